@@ -0,0 +1,67 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use csv_tx_resolver::{generate_transactions, process_reader, Engine, EngineBuilder, Transaction};
+
+const ROWS: u64 = 100_000;
+
+fn fixture() -> Vec<u8> {
+    let mut input = Vec::new();
+    generate_transactions(ROWS, &mut input).expect("writing to a Vec");
+    input
+}
+
+// parsing the way the engine's reader does it, without applying anything
+fn parse_only(input: &[u8]) -> usize {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(input);
+    reader
+        .deserialize::<Transaction>()
+        .filter(Result::is_ok)
+        .count()
+}
+
+fn throughput(c: &mut Criterion) {
+    let input = fixture();
+    let mut group = c.benchmark_group("rows");
+    group.throughput(Throughput::Elements(ROWS));
+    group.sample_size(20);
+
+    group.bench_function("parse", |b| b.iter(|| parse_only(black_box(&input))));
+    group.bench_function("parse+apply", |b| {
+        b.iter(|| process_reader(black_box(input.as_slice())).expect("generated input"))
+    });
+    group.bench_function("parse+apply --fast", |b| {
+        b.iter(|| {
+            let mut engine = EngineBuilder::new().fast_parse(true).build();
+            engine
+                .process_reader(black_box(input.as_slice()))
+                .expect("generated input");
+            engine
+        })
+    });
+    group.bench_function("parse+apply --pipeline", |b| {
+        b.iter(|| {
+            let mut engine = Engine::new();
+            engine
+                .process_reader_pipelined(black_box(input.as_slice()))
+                .expect("generated input");
+            engine
+        })
+    });
+
+    let engine = process_reader(input.as_slice()).expect("generated input");
+    let accounts = engine.accounts().len() as u64;
+    group.throughput(Throughput::Elements(accounts));
+    group.bench_function("output", |b| {
+        b.iter(|| {
+            let mut out = Vec::with_capacity(64 * accounts as usize);
+            engine.write_csv(&mut out).expect("writing to a Vec");
+            out
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, throughput);
+criterion_main!(benches);
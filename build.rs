@@ -0,0 +1,33 @@
+fn main() {
+    // the gRPC service is generated from a description here rather than from proto/resolver.proto,
+    // so building doesn't need protoc
+    #[cfg(feature = "grpc")]
+    {
+        use tonic_build::manual::{Builder, Method, Service};
+
+        let method = |name: &str, route: &str, input: &str, output: &str| {
+            Method::builder()
+                .name(name)
+                .route_name(route)
+                .input_type(format!("crate::grpc::proto::{}", input))
+                .output_type(format!("crate::grpc::proto::{}", output))
+                .codec_path("tonic::codec::ProstCodec")
+        };
+        let service = Service::builder()
+            .name("Resolver")
+            .package("csv_tx_resolver")
+            .method(
+                method(
+                    "submit_transactions",
+                    "SubmitTransactions",
+                    "Transaction",
+                    "SubmitSummary",
+                )
+                .client_streaming()
+                .build(),
+            )
+            .method(method("get_account", "GetAccount", "AccountRequest", "Account").build())
+            .build();
+        Builder::new().compile(&[service]);
+    }
+}
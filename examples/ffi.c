@@ -0,0 +1,33 @@
+/* Built and run with:
+ *   cargo build --release --features ffi
+ *   cc examples/ffi.c -Iinclude -Ltarget/release -lcsv_tx_resolver -o ffi
+ *   LD_LIBRARY_PATH=target/release ./ffi
+ */
+#include <stdio.h>
+
+#include "csv_tx_resolver.h"
+
+int main(void) {
+    CtrEngine *engine = ctr_engine_new(NULL);
+    if (engine == NULL) {
+        fprintf(stderr, "%s\n", ctr_last_error());
+        return 1;
+    }
+    ctr_engine_feed(engine, "deposit", 1, 1, "10.0", NULL);
+    ctr_engine_feed(engine, "withdrawal", 1, 2, "2.5", NULL);
+    if (ctr_engine_feed(engine, "withdrawal", 1, 3, "100.0", NULL) == 1) {
+        printf("tx 3 rejected\n");
+    }
+
+    CtrAccount account;
+    if (ctr_engine_get_account(engine, 1, NULL, &account) == 0) {
+        printf("client %u: available %s, held %s, total %s%s\n", account.client,
+               account.available, account.held, account.total, account.locked ? ", locked" : "");
+    }
+
+    char *report = ctr_engine_write_report(engine);
+    fputs(report, stdout);
+    ctr_string_free(report);
+    ctr_engine_free(engine);
+    return 0;
+}
@@ -0,0 +1,11 @@
+#![no_main]
+
+use csv_tx_resolver::{process_bytes, Error};
+use libfuzzer_sys::fuzz_target;
+
+// bad input is fine, panics and internal errors are what we're after
+fuzz_target!(|input: &[u8]| {
+    if let Err(Error::Internal(message)) = process_bytes(input) {
+        panic!("internal error: {}", message);
+    }
+});
@@ -0,0 +1,117 @@
+/* Generated by cbindgen from src/ffi.rs, don't edit by hand. */
+
+#ifndef CSV_TX_RESOLVER_H
+#define CSV_TX_RESOLVER_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Room for an amount as text, sign, 28 digits and the point included, plus the NUL.
+ */
+#define CTR_AMOUNT_LEN 32
+
+/**
+ * An engine and the rows fed to it so far, for the error report.
+ */
+typedef struct CtrEngine CtrEngine;
+
+/**
+ * A client's balances in one currency. Amounts are NUL-terminated text with four places
+ * (`"1.5000"`), so nothing is lost to floating point on the other side.
+ */
+typedef struct CtrAccount {
+  uint16_t client;
+  char available[CTR_AMOUNT_LEN];
+  char held[CTR_AMOUNT_LEN];
+  char total[CTR_AMOUNT_LEN];
+  bool locked;
+} CtrAccount;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * The message of the last call on this thread that returned -1 (or NULL), NULL if there's none.
+ * It stays valid until the next failing call on the same thread.
+ */
+const char *ctr_last_error(void);
+
+/**
+ * A new engine with the settings of `config_toml` (the config file's contents), or the defaults
+ * if it's NULL. NULL if the config doesn't parse. Free it with `ctr_engine_free`.
+ *
+ * # Safety
+ *
+ * `config_toml` is NULL or a NUL-terminated string.
+ */
+struct CtrEngine *ctr_engine_new(const char *config_toml);
+
+/**
+ * Frees an engine from `ctr_engine_new`. NULL is ignored.
+ *
+ * # Safety
+ *
+ * `engine` came from `ctr_engine_new` and isn't used again.
+ */
+void ctr_engine_free(struct CtrEngine *engine);
+
+/**
+ * Applies one record, its fields as they'd be in the csv: `type` like `"deposit"`, `amount` as
+ * decimal text and `currency` like `"EUR"`, either NULL when there's none. 0 if it was applied, 1
+ * if it was rejected (the engine's stats say why), -1 if it couldn't be read, or a strict config
+ * refused it.
+ *
+ * # Safety
+ *
+ * `engine` is a live engine, the strings are NULL or NUL-terminated.
+ */
+int ctr_engine_feed(struct CtrEngine *engine,
+                    const char *r_type,
+                    uint16_t client,
+                    uint32_t tx,
+                    const char *amount,
+                    const char *currency);
+
+/**
+ * Fills `out` with `client`'s balances in `currency` (NULL for the default one). 0 if the client
+ * has an account, 1 if not, -1 on bad arguments.
+ *
+ * # Safety
+ *
+ * `engine` is a live engine, `currency` is NULL or NUL-terminated and `out` points to a
+ * `CtrAccount`.
+ */
+int ctr_engine_get_account(const struct CtrEngine *engine,
+                           uint16_t client,
+                           const char *currency,
+                           struct CtrAccount *out);
+
+/**
+ * The report of every account as csv, like the batch tool prints it. NULL on failure. Free it
+ * with `ctr_string_free`.
+ *
+ * # Safety
+ *
+ * `engine` is a live engine.
+ */
+char *ctr_engine_write_report(const struct CtrEngine *engine);
+
+/**
+ * Frees a string from `ctr_engine_write_report`. NULL is ignored.
+ *
+ * # Safety
+ *
+ * `text` came from this library and isn't used again.
+ */
+void ctr_string_free(char *text);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CSV_TX_RESOLVER_H */
@@ -0,0 +1,4 @@
+fn main() {
+    // the link flags a Node.js addon needs, node fills the symbols in when it loads it
+    napi_build::setup();
+}
@@ -0,0 +1,11 @@
+/** The transactions engine, fed a csv in chunks. */
+export class Resolver {
+  /** Takes a config file's contents, the defaults without one. */
+  constructor(config?: string | undefined | null)
+  /** Any amount of the csv, rows can be split across writes. */
+  write(chunk: string | Buffer): void
+  /** Waits for the engine to apply everything written, throws if the run failed. */
+  end(): void
+  /** The accounts as `csv` (the default), `json` or `jsonl`, once the input has ended. */
+  report(format?: 'csv' | 'json' | 'jsonl' | undefined | null): string
+}
@@ -0,0 +1,177 @@
+use csv_tx_resolver::{Engine, EngineConfig, Error, OutputFormat, ReportOptions};
+use napi::bindgen_prelude::{Buffer, Either};
+use napi_derive::napi;
+use std::{
+    io::{self, Cursor, Read},
+    sync::mpsc,
+    thread,
+};
+
+/// How many written chunks can wait for the engine before `write` blocks.
+const PENDING_CHUNKS: usize = 64;
+
+// the engine's end of a stream: the chunks `write` sends, read back to back as one input
+struct Chunks {
+    receiver: mpsc::Receiver<Vec<u8>>,
+    current: Cursor<Vec<u8>>,
+}
+
+impl Read for Chunks {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.current.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            match self.receiver.recv() {
+                Ok(chunk) => self.current = Cursor::new(chunk),
+                // ended
+                Err(_) => return Ok(0),
+            }
+        }
+    }
+}
+
+// a run fed chunk by chunk: the engine reads the chunks on a thread of its own, so rows split
+// across chunks (or quoted newlines) are read like they would be from a file
+struct Stream {
+    sender: Option<mpsc::SyncSender<Vec<u8>>>,
+    running: Option<thread::JoinHandle<Result<Engine, Error>>>,
+    engine: Option<Engine>,
+}
+
+impl Stream {
+    fn new(mut engine: Engine) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(PENDING_CHUNKS);
+        let chunks = Chunks {
+            receiver,
+            current: Cursor::default(),
+        };
+        let running = thread::spawn(move || {
+            engine.process_reader(chunks)?;
+            Ok(engine)
+        });
+        Stream {
+            sender: Some(sender),
+            running: Some(running),
+            engine: None,
+        }
+    }
+
+    fn write(&mut self, chunk: Vec<u8>) -> Result<(), Error> {
+        let Some(sender) = &self.sender else {
+            return Err(Error::Input("write after end".to_string()));
+        };
+        // the engine only hangs up early when the run failed, `end` says why
+        if sender.send(chunk).is_err() {
+            self.end()?;
+        }
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<(), Error> {
+        self.sender = None;
+        if let Some(running) = self.running.take() {
+            let engine = running
+                .join()
+                .map_err(|_| Error::Internal("the engine panicked".to_string()))??;
+            self.engine = Some(engine);
+        }
+        Ok(())
+    }
+
+    fn report(&self, format: OutputFormat) -> Result<String, Error> {
+        let engine = match (&self.engine, &self.running) {
+            (Some(engine), _) => engine,
+            (None, Some(_)) => return Err(Error::Input("report before end".to_string())),
+            (None, None) => return Err(Error::Input("the run failed".to_string())),
+        };
+        let mut report = Vec::new();
+        engine.write_report(&ReportOptions::new(format), &mut report)?;
+        String::from_utf8(report).map_err(|err| Error::Internal(err.to_string()))
+    }
+}
+
+fn js_error(err: Error) -> napi::Error {
+    napi::Error::from_reason(err.to_string())
+}
+
+/// The engine for Node.js: `write` the transactions csv in chunks of any size (strings or
+/// Buffers), `end` the input, then get the accounts with `report`. A bad header or a `strict`
+/// rejection throws from `write` or `end`.
+#[napi]
+pub struct Resolver {
+    stream: Stream,
+}
+
+#[napi]
+impl Resolver {
+    /// Takes a config file's contents, the defaults without one.
+    #[napi(constructor)]
+    pub fn new(config: Option<String>) -> napi::Result<Self> {
+        let config = match config {
+            Some(toml) => EngineConfig::from_toml_str(&toml)
+                .map_err(|err| js_error(Error::Config(err.to_string())))?,
+            None => EngineConfig::default(),
+        };
+        Ok(Resolver {
+            stream: Stream::new(Engine::with_config(config)),
+        })
+    }
+
+    #[napi]
+    pub fn write(&mut self, chunk: Either<String, Buffer>) -> napi::Result<()> {
+        let chunk = match chunk {
+            Either::A(text) => text.into_bytes(),
+            Either::B(buffer) => buffer.to_vec(),
+        };
+        self.stream.write(chunk).map_err(js_error)
+    }
+
+    /// Waits for the engine to apply everything written.
+    #[napi]
+    pub fn end(&mut self) -> napi::Result<()> {
+        self.stream.end().map_err(js_error)
+    }
+
+    /// The accounts as `csv` (the default), `json` or `jsonl`, once the input has ended.
+    #[napi]
+    pub fn report(&self, format: Option<String>) -> napi::Result<String> {
+        let format = match format {
+            Some(format) => format.parse().map_err(|err| js_error(Error::Input(err)))?,
+            None => OutputFormat::Csv,
+        };
+        self.stream.report(format).map_err(js_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_split_across_chunks_read_like_one_file() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 2.0\n\
+                     deposit, 2, 2, 1.25\n\
+                     withdrawal, 1, 3, 0.5\n";
+        let mut stream = Stream::new(Engine::new());
+        for chunk in input.as_bytes().chunks(7) {
+            stream.write(chunk.to_vec()).unwrap();
+        }
+        assert!(stream.report(OutputFormat::Csv).is_err());
+        stream.end().unwrap();
+        assert_eq!(
+            stream.report(OutputFormat::Csv).unwrap(),
+            "client,available,held,total,locked\n\
+             1,1.5000,0.0000,1.5000,false\n\
+             2,1.2500,0.0000,1.2500,false\n"
+        );
+        assert!(stream.write(b"deposit, 3, 4, 1.0\n".to_vec()).is_err());
+
+        let mut bad = Stream::new(Engine::new());
+        bad.write(b"kind, customer\n".to_vec()).unwrap();
+        assert!(bad.end().is_err());
+        assert!(bad.report(OutputFormat::Csv).is_err());
+    }
+}
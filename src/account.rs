@@ -0,0 +1,484 @@
+use crate::amount::Amount;
+use crate::currency::Currency;
+use crate::rejection::Rejection;
+use crate::transaction::TransactionType;
+use crate::utils::four_precision_serializer;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// One currency's balances within an account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Balance {
+    pub currency: Currency,
+    pub available: Amount,
+    pub held: Amount,
+    pub total: Amount,
+}
+
+impl Balance {
+    fn new(currency: Currency) -> Self {
+        Balance {
+            currency,
+            ..Balance::default()
+        }
+    }
+}
+
+/// A client's balances, one per currency it ever moved money in. Locking is per client: a
+/// chargeback in any currency freezes all of them.
+#[derive(Debug, Clone)]
+pub struct Account {
+    client: u16,
+    // sorted by currency so the default one comes first, most accounts only ever have that one
+    balances: Vec<Balance>,
+    locked: bool,
+    // every open dispute by the disputed tx id, and what it holds. that's what its resolve or
+    // chargeback releases, never another dispute's share of held
+    disputes: HashMap<u32, Amount>,
+}
+
+/// One row of the account report: a client's balance in one currency.
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountRow {
+    pub client: u16,
+    /// Only set in reports where some account holds a currency other than the default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<Currency>,
+    #[serde(serialize_with = "four_precision_serializer")]
+    pub available: Decimal,
+    #[serde(serialize_with = "four_precision_serializer")]
+    pub held: Decimal,
+    #[serde(serialize_with = "four_precision_serializer")]
+    pub total: Decimal,
+    pub locked: bool,
+}
+
+pub type AccountMap = HashMap<u16, Account>;
+
+impl Account {
+    pub fn new(client: u16) -> Self {
+        Account {
+            client,
+            balances: Vec::new(),
+            locked: false,
+            disputes: HashMap::new(),
+        }
+    }
+
+    /// An account as a ledger backend saved it.
+    pub(crate) fn with_balances(client: u16, mut balances: Vec<Balance>, locked: bool) -> Self {
+        balances.sort_by_key(|balance| balance.currency);
+        Account {
+            client,
+            balances,
+            locked,
+            disputes: HashMap::new(),
+        }
+    }
+
+    /// Puts back a dispute that was open when the ledger was saved, `held` being what it holds.
+    pub(crate) fn reopen_dispute(&mut self, tx: u32, held: Amount) {
+        self.disputes.insert(tx, held);
+    }
+
+    pub fn client(&self) -> u16 {
+        self.client
+    }
+
+    /// Available funds in the default currency.
+    pub fn available(&self) -> Amount {
+        self.balance(Currency::DEFAULT).available
+    }
+
+    /// Held funds in the default currency.
+    pub fn held(&self) -> Amount {
+        self.balance(Currency::DEFAULT).held
+    }
+
+    /// Total funds in the default currency.
+    pub fn total(&self) -> Amount {
+        self.balance(Currency::DEFAULT).total
+    }
+
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+
+    /// The balances in `currency`, all zero if the account never had any.
+    pub fn balance(&self, currency: Currency) -> Balance {
+        match self
+            .balances
+            .binary_search_by_key(&currency, |b| b.currency)
+        {
+            Ok(i) => self.balances[i],
+            Err(_) => Balance::new(currency),
+        }
+    }
+
+    /// Every currency the account has a balance in, ordered by currency.
+    pub fn balances(&self) -> &[Balance] {
+        &self.balances
+    }
+
+    /// Whether any balance is in a currency other than the default.
+    pub fn has_currencies(&self) -> bool {
+        self.balances
+            .iter()
+            .any(|balance| !balance.currency.is_default())
+    }
+
+    /// The account's report rows, one per currency. An account that never moved money still gets
+    /// an all zero row in the default currency. `with_currency` fills in the currency column.
+    pub fn rows(&self, with_currency: bool) -> Vec<AccountRow> {
+        let row = |balance: &Balance| AccountRow {
+            client: self.client,
+            currency: with_currency.then_some(balance.currency),
+            available: balance.available.into(),
+            held: balance.held.into(),
+            total: balance.total.into(),
+            locked: self.locked,
+        };
+        match self.balances.is_empty() {
+            true => vec![row(&Balance::default())],
+            false => self.balances.iter().map(row).collect(),
+        }
+    }
+
+    fn balance_mut(&mut self, currency: Currency) -> &mut Balance {
+        let i = match self
+            .balances
+            .binary_search_by_key(&currency, |b| b.currency)
+        {
+            Ok(i) => i,
+            Err(i) => {
+                self.balances.insert(i, Balance::new(currency));
+                i
+            }
+        };
+        &mut self.balances[i]
+    }
+
+    // every change goes through here: it's worked out on a copy, and only kept if none of the sums
+    // overflowed, so a record that would overflow is rejected without touching the balance
+    fn update(
+        &mut self,
+        currency: Currency,
+        change: impl FnOnce(&mut Balance) -> Option<()>,
+    ) -> Result<(), Rejection> {
+        let mut balance = self.balance(currency);
+        change(&mut balance).ok_or(Rejection::BalanceOverflow)?;
+        *self.balance_mut(currency) = balance;
+        Ok(())
+    }
+
+    // disputes are keyed on the type of the disputed tx:
+    // - a disputed deposit holds the money that came in, out of available
+    // - a disputed withdrawal credits the money that went out back as held, available is untouched
+    pub(crate) fn dispute(
+        &mut self,
+        currency: Currency,
+        r_type: TransactionType,
+        tx: u32,
+        amount: Amount,
+    ) -> Result<(), Rejection> {
+        if self.disputes.contains_key(&tx) {
+            return Err(Rejection::AlreadyDisputed);
+        }
+        self.update(currency, |balance| {
+            balance.held = balance.held.checked_add(amount)?;
+            match r_type {
+                TransactionType::Withdrawal => balance.total = balance.total.checked_add(amount)?,
+                _ => balance.available = balance.available.checked_sub(amount)?,
+            }
+            Some(())
+        })?;
+        self.disputes.insert(tx, amount);
+        Ok(())
+    }
+
+    // what the open dispute of `tx` holds, which is all its resolve or chargeback can release.
+    // less held than that means the ledger was restored from something inconsistent, and going
+    // ahead would leave held below zero and total under available
+    fn disputed(&self, currency: Currency, tx: u32) -> Result<Amount, Rejection> {
+        let amount = *self.disputes.get(&tx).ok_or(Rejection::NotDisputed)?;
+        if self.balance(currency).held < amount {
+            return Err(Rejection::OverHeld);
+        }
+        Ok(amount)
+    }
+
+    // the engine only calls resolve/chargeback for a tx that is actually under dispute
+    // resolving means the original tx stands
+    pub(crate) fn resolve(
+        &mut self,
+        currency: Currency,
+        r_type: TransactionType,
+        tx: u32,
+    ) -> Result<(), Rejection> {
+        let amount = self.disputed(currency, tx)?;
+        self.update(currency, |balance| {
+            balance.held = balance.held.checked_sub(amount)?;
+            match r_type {
+                TransactionType::Withdrawal => balance.total = balance.total.checked_sub(amount)?,
+                _ => balance.available = balance.available.checked_add(amount)?,
+            }
+            Some(())
+        })?;
+        self.disputes.remove(&tx);
+        Ok(())
+    }
+
+    // charging back means the original tx is reversed and the account gets frozen
+    pub(crate) fn chargeback(
+        &mut self,
+        currency: Currency,
+        r_type: TransactionType,
+        tx: u32,
+    ) -> Result<(), Rejection> {
+        let amount = self.disputed(currency, tx)?;
+        self.update(currency, |balance| {
+            balance.held = balance.held.checked_sub(amount)?;
+            match r_type {
+                TransactionType::Withdrawal => {
+                    balance.available = balance.available.checked_add(amount)?
+                }
+                _ => balance.total = balance.total.checked_sub(amount)?,
+            }
+            Some(())
+        })?;
+        self.disputes.remove(&tx);
+        self.locked = true;
+        Ok(())
+    }
+
+    // admin records go through even on a locked account, that's what they're for
+    pub(crate) fn unlock(&mut self) -> Result<(), Rejection> {
+        if !self.locked {
+            return Err(Rejection::NotLocked);
+        }
+        self.locked = false;
+        Ok(())
+    }
+
+    pub(crate) fn adjust(&mut self, currency: Currency, amount: Amount) -> Result<(), Rejection> {
+        self.update(currency, |balance| {
+            balance.available = balance.available.checked_add(amount)?;
+            balance.total = balance.total.checked_add(amount)?;
+            Some(())
+        })
+    }
+
+    /// Whether a locked account still takes deposits is up to the engine's `LockedAccountPolicy`.
+    pub(crate) fn deposit(
+        &mut self,
+        currency: Currency,
+        deposit_amount: Amount,
+    ) -> Result<(), Rejection> {
+        self.update(currency, |balance| {
+            balance.total = balance.total.checked_add(deposit_amount)?;
+            balance.available = balance.available.checked_add(deposit_amount)?;
+            Some(())
+        })
+    }
+
+    // `floor` is as low as available can go, per the engine's overdraft policy, `None` for no limit
+    pub(crate) fn withdraw(
+        &mut self,
+        currency: Currency,
+        withdraw_amount: Amount,
+        floor: Option<Amount>,
+    ) -> Result<(), Rejection> {
+        // no policy lets money out of a locked account
+        if self.locked {
+            return Err(Rejection::AccountLocked);
+        }
+        // check to make sure user does not overdraft past the floor, only the withdrawal's own
+        // currency counts. everything that's available can be taken
+        let left = self
+            .balance(currency)
+            .available
+            .checked_sub(withdraw_amount);
+        if floor.is_some_and(|floor| left.is_none_or(|left| left < floor)) {
+            return Err(Rejection::InsufficientFunds);
+        }
+        self.update(currency, |balance| {
+            balance.total = balance.total.checked_sub(withdraw_amount)?;
+            balance.available = balance.available.checked_sub(withdraw_amount)?;
+            Some(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    const NONE: Currency = Currency::DEFAULT;
+    // no overdrafts
+    const FLOOR: Option<Amount> = Some(Amount::ZERO);
+
+    fn amount(amount: Decimal) -> Amount {
+        Amount::from_decimal(amount).unwrap()
+    }
+
+    #[test]
+    fn account_can_deposit() {
+        let mut account = Account::new(1);
+        account.deposit(NONE, amount(dec!(100.0))).unwrap();
+
+        assert_eq!(account.available(), dec!(100.0));
+        assert_eq!(account.total(), dec!(100.0))
+    }
+    #[test]
+    fn account_cannot_overdraft() {
+        let mut account = Account::new(1);
+        account.deposit(NONE, amount(dec!(10.0))).unwrap();
+        account.withdraw(NONE, amount(dec!(9.0)), FLOOR).unwrap();
+
+        // 1 left
+        assert_eq!(account.available(), dec!(1.0));
+        assert_eq!(account.total(), dec!(1.0));
+
+        // try to take out 2.0
+        assert_eq!(
+            account.withdraw(NONE, amount(dec!(2.0)), FLOOR),
+            Err(Rejection::InsufficientFunds)
+        );
+
+        // unchanged
+        assert_eq!(account.available(), dec!(1.0));
+        assert_eq!(account.total(), dec!(1.0));
+
+        account
+            .dispute(NONE, TransactionType::Deposit, 1, amount(dec!(0.5)))
+            .unwrap();
+
+        // 0.5 available
+        assert_eq!(account.held(), dec!(0.5));
+        assert_eq!(account.available(), dec!(0.5));
+        assert_eq!(account.total(), dec!(1.0));
+
+        // try to take out 1.0
+        assert_eq!(
+            account.withdraw(NONE, amount(dec!(1.0)), FLOOR),
+            Err(Rejection::InsufficientFunds)
+        );
+
+        // unchanged
+        assert_eq!(account.held(), dec!(0.5));
+        assert_eq!(account.available(), dec!(0.5));
+        assert_eq!(account.total(), dec!(1.0));
+    }
+
+    #[test]
+    fn account_can_withdraw_down_to_the_floor() {
+        let mut account = Account::new(1);
+        account.deposit(NONE, amount(dec!(10.0))).unwrap();
+        // all of it
+        account.withdraw(NONE, amount(dec!(10.0)), FLOOR).unwrap();
+        assert_eq!(account.available(), dec!(0.0));
+
+        // an overdraft to -5, and no further
+        let floor = Some(amount(dec!(-5.0)));
+        account.withdraw(NONE, amount(dec!(4.0)), floor).unwrap();
+        assert_eq!(
+            account.withdraw(NONE, amount(dec!(1.0001)), floor),
+            Err(Rejection::InsufficientFunds)
+        );
+        account.withdraw(NONE, amount(dec!(1.0)), floor).unwrap();
+        assert_eq!(account.available(), dec!(-5.0));
+        assert_eq!(account.total(), dec!(-5.0));
+
+        // no floor at all
+        account.withdraw(NONE, amount(dec!(100.0)), None).unwrap();
+        assert_eq!(account.available(), dec!(-105.0));
+    }
+
+    #[test]
+    fn disputes_work() {
+        let mut account = Account::new(1);
+        account.deposit(NONE, amount(dec!(10.0))).unwrap();
+        // let's pretend tx 1 had 5 in the amount
+        account
+            .dispute(NONE, TransactionType::Deposit, 1, amount(dec!(5.0)))
+            .unwrap();
+        // dispute locks 5 and reduces available
+        assert_eq!(account.held(), dec!(5.0));
+        assert_eq!(account.available(), dec!(5.0));
+        // disputing tx 2 locks another 3 and reduces available
+        account
+            .dispute(NONE, TransactionType::Deposit, 2, amount(dec!(3.0)))
+            .unwrap();
+        assert_eq!(
+            account.dispute(NONE, TransactionType::Deposit, 2, amount(dec!(3.0))),
+            Err(Rejection::AlreadyDisputed)
+        );
+
+        assert_eq!(account.held(), dec!(8.0));
+        assert_eq!(account.available(), dec!(2.0));
+        // resolving tx 1 releases just its 5 from hold and increases available
+        account.resolve(NONE, TransactionType::Deposit, 1).unwrap();
+        assert_eq!(account.held(), dec!(3.0));
+        assert_eq!(account.available(), dec!(7.0));
+        // and only once, the 3 left on hold is tx 2's
+        assert_eq!(
+            account.resolve(NONE, TransactionType::Deposit, 1),
+            Err(Rejection::NotDisputed)
+        );
+        assert_eq!(
+            account.chargeback(NONE, TransactionType::Deposit, 3),
+            Err(Rejection::NotDisputed)
+        );
+        assert_eq!(account.held(), dec!(3.0));
+        // chargeback of tx 2 removes its 3 from total and held. locks account.
+        account
+            .chargeback(NONE, TransactionType::Deposit, 2)
+            .unwrap();
+        assert!(account.locked());
+        assert_eq!(account.held(), dec!(0.0));
+        assert_eq!(account.total(), dec!(7.0));
+        // whether deposits still apply is the engine's policy (see
+        // `locked_account_policies_decide_what_gets_through`)
+        // user tries to withdraw on locked account
+        assert_eq!(
+            account.withdraw(NONE, amount(dec!(1.0)), FLOOR),
+            Err(Rejection::AccountLocked)
+        );
+        // locked account prevents withdraw
+        assert_eq!(account.total(), dec!(7.0));
+    }
+
+    #[test]
+    fn withdrawal_disputes_work() {
+        let mut account = Account::new(1);
+        account.deposit(NONE, amount(dec!(10.0))).unwrap();
+        account.withdraw(NONE, amount(dec!(4.0)), FLOOR).unwrap();
+        // disputing the 4 withdrawal puts it back as held
+        account
+            .dispute(NONE, TransactionType::Withdrawal, 2, amount(dec!(4.0)))
+            .unwrap();
+        assert_eq!(account.held(), dec!(4.0));
+        assert_eq!(account.available(), dec!(6.0));
+        assert_eq!(account.total(), dec!(10.0));
+        // resolving means the withdrawal stands
+        account
+            .resolve(NONE, TransactionType::Withdrawal, 2)
+            .unwrap();
+        assert_eq!(account.held(), dec!(0.0));
+        assert_eq!(account.available(), dec!(6.0));
+        assert_eq!(account.total(), dec!(6.0));
+        // a chargeback hands the money back and locks
+        account
+            .dispute(NONE, TransactionType::Withdrawal, 2, amount(dec!(4.0)))
+            .unwrap();
+        account
+            .chargeback(NONE, TransactionType::Withdrawal, 2)
+            .unwrap();
+        assert_eq!(account.held(), dec!(0.0));
+        assert_eq!(account.available(), dec!(10.0));
+        assert_eq!(account.total(), dec!(10.0));
+        assert!(account.locked());
+    }
+}
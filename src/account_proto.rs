@@ -0,0 +1,95 @@
+#[cfg(feature = "proto")]
+use crate::{account::AccountRow, error::Error, utils::four_places};
+#[cfg(feature = "proto")]
+use std::io::{self, Write};
+
+/// The `Account` message of proto/account.proto: one currency's balances, amounts as decimal
+/// strings with four places.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Account {
+    #[prost(uint32, tag = "1")]
+    pub client: u32,
+    #[prost(string, tag = "2")]
+    pub available: String,
+    #[prost(string, tag = "3")]
+    pub held: String,
+    #[prost(string, tag = "4")]
+    pub total: String,
+    #[prost(bool, tag = "5")]
+    pub locked: bool,
+    /// Empty for the default currency.
+    #[prost(string, tag = "6")]
+    pub currency: String,
+}
+
+#[cfg(feature = "proto")]
+impl From<&AccountRow> for Account {
+    fn from(row: &AccountRow) -> Self {
+        Account {
+            client: row.client.into(),
+            available: four_places(row.available).to_string(),
+            held: four_places(row.held).to_string(),
+            total: four_places(row.total).to_string(),
+            locked: row.locked,
+            currency: row.currency.unwrap_or_default().to_string(),
+        }
+    }
+}
+
+/// The account report as a stream of `Account` messages, each after its length as a varint, the
+/// way protobuf's `writeDelimitedTo` writes them.
+#[cfg(feature = "proto")]
+pub(crate) fn write_accounts<'a>(
+    rows: impl Iterator<Item = &'a AccountRow>,
+    mut writer: impl Write,
+) -> Result<(), Error> {
+    let mut message = Vec::new();
+    for row in rows {
+        message.clear();
+        prost::Message::encode_length_delimited(&Account::from(row), &mut message)
+            .map_err(io::Error::other)?;
+        writer.write_all(&message)?;
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "proto"))]
+mod tests {
+    use super::*;
+    use crate::{process_reader, OutputFormat, ReportOptions};
+    use prost::Message;
+
+    #[test]
+    fn proto_reports_are_length_delimited_accounts() {
+        let input = "type,client,tx,amount,currency\n\
+                     deposit,2,1,2.5,\n\
+                     deposit,1,2,1.0,EUR\n\
+                     dispute,1,2,,EUR\n";
+        let engine = process_reader(input.as_bytes()).unwrap();
+        let mut report = Vec::new();
+        engine
+            .write_report(&ReportOptions::new(OutputFormat::Proto), &mut report)
+            .unwrap();
+
+        let mut stream = report.as_slice();
+        let mut accounts = Vec::new();
+        while !stream.is_empty() {
+            accounts.push(Account::decode_length_delimited(&mut stream).unwrap());
+        }
+        let account = |client, currency: &str, available: &str, held: &str, total: &str| Account {
+            client,
+            available: available.to_string(),
+            held: held.to_string(),
+            total: total.to_string(),
+            locked: false,
+            currency: currency.to_string(),
+        };
+        assert_eq!(
+            accounts,
+            [
+                account(1, "EUR", "0.0000", "1.0000", "1.0000"),
+                account(2, "", "2.5000", "0.0000", "2.5000"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use crate::amount::Amount;
+use crate::currency::Currency;
+use crate::engine::Engine;
+use crate::error::Error;
+use crate::utils::PRECISION;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::Deserialize;
+
+/// Fees and interest for the end of a statement period, the `[accrual]` table of the config. Only
+/// the ones that are set apply, and the batch run applies them once, after its last input:
+///
+/// ```toml
+/// [accrual]
+/// maintenance_fee = 2.5
+/// withdrawal_fee = 0.25
+/// interest_rate = 0.001
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Accrual {
+    /// Charged to every account once, in the default currency.
+    pub maintenance_fee: Option<Decimal>,
+    /// Charged for every withdrawal applied during the run, in the withdrawal's currency.
+    pub withdrawal_fee: Option<Decimal>,
+    /// Paid on what's available after the fees, in every currency, rounded down to four places.
+    pub interest_rate: Option<Decimal>,
+}
+
+impl Accrual {
+    pub fn is_set(&self) -> bool {
+        self.maintenance_fee.is_some()
+            || self.withdrawal_fee.is_some()
+            || self.interest_rate.is_some()
+    }
+}
+
+// down to four places, `None` if it's past what an `Amount` holds
+fn amount(amount: Decimal) -> Option<Amount> {
+    Amount::from_decimal(amount.round_dp_with_strategy(PRECISION, RoundingStrategy::ToZero))
+}
+
+impl Engine {
+    /// Applies the config's `[accrual]` fees and interest to every account that isn't locked, as
+    /// adjustments that go into the audit log and journal. Fees come off first and never take
+    /// available below zero, a fee that isn't covered only takes what's there. Interest is paid on
+    /// what's left. Meant for the end of a run that covers one period, calling it again charges
+    /// again.
+    pub fn accrue(&mut self) -> Result<(), Error> {
+        let accrual = self.config().accrual.clone();
+        let mut accounts = self
+            .store()
+            .accounts()
+            .filter(|account| !account.locked())
+            .collect::<Vec<_>>();
+        // in client order, so the audit log is the same every run
+        accounts.sort_by_key(|account| account.client());
+        let mut booked = 0;
+        for account in accounts {
+            let client = account.client();
+            let available = |engine: &Engine, currency: Currency| {
+                engine
+                    .store()
+                    .get_account(client)
+                    .map_or(Amount::ZERO, |account| account.balance(currency).available)
+            };
+            let mut entries = Vec::new();
+            if let Some(fee) = accrual.maintenance_fee.and_then(amount) {
+                entries.push((Currency::DEFAULT, -fee));
+            }
+            if let Some(fee) = accrual.withdrawal_fee {
+                for balance in account.balances() {
+                    let made = self.withdrawals_made(client, balance.currency);
+                    let fees = fee.checked_mul(Decimal::from(made)).and_then(amount);
+                    entries.push((balance.currency, -fees.unwrap_or(Amount::MAX)));
+                }
+            }
+            for (currency, fee) in entries {
+                let charged = fee.max(-available(self, currency).max(Amount::ZERO));
+                booked += self.book_accrued(client, currency, charged);
+            }
+            if let Some(rate) = accrual.interest_rate {
+                for balance in account.balances() {
+                    let paid = available(self, balance.currency)
+                        .to_decimal()
+                        .checked_mul(rate)
+                        .and_then(amount)
+                        .filter(|paid| !paid.is_negative());
+                    if let Some(paid) = paid {
+                        booked += self.book_accrued(client, balance.currency, paid);
+                    }
+                }
+            }
+        }
+        tracing::info!(adjustments = booked, "fees and interest accrued");
+        self.flush_reports()?;
+        Ok(())
+    }
+
+    // 1 if something was booked
+    fn book_accrued(&mut self, client: u16, currency: Currency, amount: Amount) -> usize {
+        if amount == Amount::ZERO {
+            return 0;
+        }
+        match self.book(client, currency, amount) {
+            Ok(()) => 1,
+            Err(rejection) => {
+                tracing::warn!(client, %amount, %rejection, "could not book the accrual");
+                0
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{EngineBuilder, EngineConfig};
+    use rust_decimal_macros::dec;
+    use std::{fs, process};
+
+    #[test]
+    fn fees_then_interest_are_booked_as_adjustments() {
+        let config = EngineConfig::from_toml_str(
+            "[accrual]\n\
+             maintenance_fee = 2\n\
+             withdrawal_fee = 0.5\n\
+             interest_rate = 0.01\n",
+        )
+        .unwrap();
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 100.0\n\
+                     withdrawal, 1, 2, 10.0\n\
+                     withdrawal, 1, 3, 10.0\n\
+                     withdrawal, 1, 4, 1000.0\n\
+                     deposit, 2, 5, 1.0\n\
+                     deposit, 3, 6, 10.0\n\
+                     dispute, 3, 6,\n\
+                     chargeback, 3, 6,\n";
+        let mut engine = EngineBuilder::from_config(config).build();
+        engine.process_reader(input.as_bytes()).unwrap();
+        let path = std::env::temp_dir().join(format!("accrual-test-{}.csv", process::id()));
+        engine.set_audit_log(fs::File::create(&path).unwrap());
+        engine.accrue().unwrap();
+
+        let accounts = engine.accounts();
+        // 80 less 2 and twice 0.5 for the applied withdrawals, then 1% of the 77 left
+        assert_eq!(accounts[&1].total(), dec!(77.77));
+        // the fee only takes what's there, and there's nothing to pay interest on
+        assert_eq!(accounts[&2].total(), dec!(0));
+        // locked accounts are left alone
+        assert_eq!(accounts[&3].total(), dec!(0));
+        let audit = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            audit.lines().collect::<Vec<_>>(),
+            [
+                "tx,client,type,amount,available,held,total,locked,currency",
+                "0,1,adjustment,-2.0000,78.0000,0.0000,78.0000,false,",
+                "0,1,adjustment,-1.0000,77.0000,0.0000,77.0000,false,",
+                "0,1,adjustment,0.7700,77.7700,0.0000,77.7700,false,",
+                "0,2,adjustment,-1.0000,0.0000,0.0000,0.0000,false,",
+            ]
+        );
+    }
+}
@@ -0,0 +1,240 @@
+use crate::engine::Engine;
+use crate::error::Error;
+use crate::error_report::RejectedRecord;
+use crate::reader::RecordReader;
+use crate::rejection::Rejection;
+use crate::transaction::Transaction;
+use crate::utils::Instant;
+use crossbeam_channel::{Receiver, Sender};
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex, PoisonError,
+    },
+    thread,
+};
+
+/// How many records a client's mailbox holds before the reader waits for the client to catch up.
+const MAILBOX_DEPTH: usize = 256;
+
+// a record with its row and seq, on its way to the client's actor
+type Envelope = (u64, u64, Transaction);
+
+// one client's account and transactions, in an engine of its own, and the records sent to it.
+// `scheduled` is set while it's on the run queue or being run, so only one worker ever applies its
+// records and they're applied in order
+struct ClientActor {
+    engine: Mutex<Engine>,
+    mailbox: Receiver<Envelope>,
+    scheduled: AtomicBool,
+}
+
+/// The clients' actors and the run queue that hands the ones with mail to the workers.
+struct Actors {
+    addresses: HashMap<u16, (Sender<Envelope>, Arc<ClientActor>)>,
+    // clients the engine held state for that haven't had a record yet
+    waiting: HashMap<u16, Engine>,
+    // what a client nobody has heard of starts from
+    template: Engine,
+    // why a client's engine couldn't be made, if one couldn't
+    failed: Option<io::Error>,
+}
+
+impl Actors {
+    // false if the record can't be delivered: the client's engine couldn't be made, or the workers
+    // are gone, which only happens when one panicked
+    fn send(
+        &mut self,
+        run_queue: &Sender<Arc<ClientActor>>,
+        row: u64,
+        seq: u64,
+        record: Transaction,
+    ) -> bool {
+        let client = record.client;
+        if !self.addresses.contains_key(&client) {
+            let engine = match self.waiting.remove(&client) {
+                Some(engine) => engine,
+                None => match self.template.empty_like() {
+                    Ok(engine) => engine,
+                    Err(err) => {
+                        self.failed = Some(err);
+                        return false;
+                    }
+                },
+            };
+            let (sender, mailbox) = crossbeam_channel::bounded(MAILBOX_DEPTH);
+            let actor = ClientActor {
+                engine: Mutex::new(engine),
+                mailbox,
+                scheduled: AtomicBool::new(false),
+            };
+            self.addresses.insert(client, (sender, Arc::new(actor)));
+        }
+        let (sender, actor) = &self.addresses[&client];
+        if sender.send((row, seq, record)).is_err() {
+            return false;
+        }
+        // already scheduled means whoever has it looks at the mailbox again before letting go
+        if actor.scheduled.swap(true, Ordering::SeqCst) {
+            return true;
+        }
+        run_queue.send(Arc::clone(actor)).is_ok()
+    }
+
+    // every client's engine, once the workers are done with them
+    fn into_engines(self) -> impl Iterator<Item = Engine> {
+        let actors = self.addresses.into_values().map(|(_, actor)| actor);
+        actors
+            .map(|actor| {
+                let actor = Arc::into_inner(actor).expect("the workers are done");
+                actor
+                    .engine
+                    .into_inner()
+                    .unwrap_or_else(PoisonError::into_inner)
+            })
+            .chain(self.waiting.into_values())
+    }
+}
+
+impl Engine {
+    /// Like `process_reader_sharded` but every client is an actor: its account and transactions
+    /// live in an engine of their own that applies the records sent to its mailbox, in order, on
+    /// whichever of the `threads` workers picks it up. Nothing is shared between clients, so no
+    /// locks are held across them, and a client taking long holds up no one else.
+    pub fn process_reader_actors(
+        &mut self,
+        reader: impl Read,
+        threads: usize,
+    ) -> Result<(), Error> {
+        let started = Instant::now();
+        let mut custom_reader = RecordReader::new(reader, self.config())?;
+        let mut seen = self.tx_ids();
+        let waiting = self.split_clients()?;
+        let (reject_sender, rejects) = mpsc::channel::<(RejectedRecord, Rejection)>();
+        let reporting = self.has_error_report() || self.config().strict;
+        let (run_queue, runnable) = crossbeam_channel::unbounded::<Arc<ClientActor>>();
+        let mut actors = Actors {
+            addresses: HashMap::new(),
+            waiting,
+            template: self.empty_like()?,
+            failed: None,
+        };
+
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads.max(1))
+                .map(|_| {
+                    let runnable = runnable.clone();
+                    let reject_sender = reject_sender.clone();
+                    scope.spawn(move || {
+                        for actor in runnable {
+                            run(&actor, reporting, &reject_sender);
+                        }
+                    })
+                })
+                .collect();
+            drop(reject_sender);
+
+            let send = |row, seq, record| actors.send(&run_queue, row, seq, record);
+            let result = self.dispatch(&mut custom_reader, &mut seen, send, &rejects);
+            // the reader is the only one queueing actors, so hanging up lets the workers finish
+            // what's queued and stop
+            drop(run_queue);
+
+            let mut panicked = false;
+            for worker in workers {
+                panicked |= worker.join().is_err();
+            }
+            if panicked {
+                return Err(Error::Internal("actor worker panicked".to_string()));
+            }
+            Ok(result)
+        })??;
+        if let Some(err) = actors.failed.take() {
+            return Err(err.into());
+        }
+
+        for mut engine in actors.into_engines() {
+            engine.check_store()?;
+            self.merge(engine);
+        }
+        self.counters_mut().add_time(started.elapsed());
+        self.report_all(rejects.iter())?;
+        self.flush_reports()?;
+        Ok(())
+    }
+}
+
+// applies everything in the actor's mailbox. A record that comes in after the mailbox ran dry but
+// before `scheduled` is cleared isn't queued again by the reader, so it's picked up here
+fn run(actor: &ClientActor, reporting: bool, rejects: &mpsc::Sender<(RejectedRecord, Rejection)>) {
+    let mut engine = actor.engine.lock().unwrap_or_else(PoisonError::into_inner);
+    loop {
+        for (row, seq, record) in actor.mailbox.try_iter() {
+            let _span = tracing::debug_span!("record", row, client = record.client).entered();
+            if let Err(rejection) = engine.apply_at(record, seq) {
+                if reporting {
+                    let rejected = RejectedRecord::from_transaction(row, &record, rejection);
+                    let _ = rejects.send((rejected, rejection));
+                }
+            }
+        }
+        actor.scheduled.store(false, Ordering::SeqCst);
+        if actor.mailbox.is_empty() || actor.scheduled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+    }
+}
+
+/// Convenience entry point for the actor path, mirroring `process_reader`.
+pub fn process_reader_actors(reader: impl Read, threads: usize) -> Result<Engine, Error> {
+    let mut engine = Engine::new();
+    engine.process_reader_actors(reader, threads)?;
+    Ok(engine)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_reader;
+
+    // `rounds` of deposits and withdrawals for 300 clients, with some disputes and chargebacks
+    fn rounds(rounds: std::ops::Range<u32>) -> String {
+        let mut input = String::from("type, client, tx, amount\n");
+        for round in rounds {
+            for client in 0..300u32 {
+                let tx = (round * 300 + client) * 2 + 1;
+                input.push_str(&format!("deposit, {}, {}, {}.5\n", client, tx, round));
+                input.push_str(&format!("withdrawal, {}, {}, 0.75\n", client, tx + 1));
+                if round % 9 == 0 {
+                    input.push_str(&format!("dispute, {}, {},\n", client, tx));
+                }
+                if round % 18 == 0 {
+                    input.push_str(&format!("chargeback, {}, {},\n", client, tx));
+                }
+            }
+        }
+        input
+    }
+
+    #[test]
+    fn actors_match_single_threaded() {
+        let mut single = process_reader(rounds(0..20).as_bytes()).unwrap();
+        // a replayed deposit, from another client than the one that made it
+        let rest = rounds(20..40) + "deposit, 7, 1, 100.0\n";
+        single.process_reader(rest.as_bytes()).unwrap();
+
+        // the second run starts from the ledger the first one left
+        let mut actors = process_reader_actors(rounds(0..20).as_bytes(), 3).unwrap();
+        actors.process_reader_actors(rest.as_bytes(), 3).unwrap();
+
+        let (mut ours, mut theirs) = (Vec::new(), Vec::new());
+        actors.write_csv(&mut ours).unwrap();
+        single.write_csv(&mut theirs).unwrap();
+        assert!(ours == theirs);
+        assert_eq!(actors.applied(), single.applied());
+        assert_eq!(actors.skipped(), single.skipped());
+        assert_eq!(actors.stats().by_reason, single.stats().by_reason);
+    }
+}
@@ -0,0 +1,79 @@
+use crate::amount::Amount;
+use crate::rejection::Rejection;
+use crate::transaction::Transaction;
+use crate::utils::deserialize_text;
+use hmac::{Hmac, Mac};
+use serde::Deserializer;
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// HMAC-SHA256 over an admin record, hex encoded in the csv's `signature` column.
+pub type Signature = [u8; 32];
+
+// what gets signed: `type,client,tx,amount`, amount with trailing zeros dropped so 1.50 and 1.5 sign
+// the same, and empty if there is none. Amounts are rounded to four places before they're checked,
+// so sign the rounded amount. A record in any but the default currency gets `,currency` on the end,
+// so signatures made before currencies still check out.
+fn message(record: &Transaction) -> String {
+    let amount = record
+        .amount
+        .map(Amount::to_decimal)
+        .or(record.given)
+        .map_or(String::new(), |amount| amount.normalize().to_string());
+    let mut message = format!(
+        "{},{},{},{}",
+        record.r_type, record.client, record.tx, amount
+    );
+    if !record.currency.is_default() {
+        message = format!("{},{}", message, record.currency);
+    }
+    message
+}
+
+fn mac(key: &str, record: &Transaction) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key.as_bytes()).expect("hmac takes keys of any size");
+    mac.update(message(record).as_bytes());
+    mac
+}
+
+/// Signs an admin record (`unlock`/`adjustment`) with the engine's `admin_key`, for the tooling that
+/// produces them. Put the hex string in the record's `signature` column.
+pub fn admin_signature(key: &str, record: &Transaction) -> String {
+    let signature: Signature = mac(key, record).finalize().into_bytes().into();
+    signature
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Admin records only apply with a signature made with `key`. No key configured means none do.
+pub(crate) fn authorize(key: Option<&str>, record: &Transaction) -> Result<(), Rejection> {
+    match (key, &record.signature) {
+        (Some(key), Some(signature)) => mac(key, record)
+            .verify_slice(signature)
+            .map_err(|_| Rejection::Unauthorized),
+        _ => Err(Rejection::Unauthorized),
+    }
+}
+
+/// Reads the optional hex `signature` column. Empty means unsigned.
+pub fn signature_deserializer<'de, D>(deserializer: D) -> Result<Option<Signature>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_text(deserializer, parse_signature)
+}
+
+pub(crate) fn parse_signature(raw: &str) -> Result<Signature, String> {
+    let invalid = || format!("invalid signature {:?}", raw);
+    if raw.len() != 64 || !raw.is_ascii() {
+        return Err(invalid());
+    }
+    let mut signature = [0u8; 32];
+    for (byte, pair) in signature.iter_mut().zip(raw.as_bytes().chunks(2)) {
+        let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+        *byte = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+    }
+    Ok(signature)
+}
@@ -0,0 +1,171 @@
+use crate::utils::PRECISION;
+use rust_decimal::Decimal;
+use serde::{de, Deserialize, Deserializer};
+use std::{fmt, ops::Neg, str::FromStr};
+
+/// Money as a whole number of ten-thousandths, which is what every balance, hold and limit is kept
+/// in. Amounts are parsed as decimals and rounded to four places first, so nothing is lost on the
+/// way in; reports turn them back into decimals. The sums are exact and cheap, and overflow (at
+/// about 922 trillion) is checked like it was on a `Decimal`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Amount(i64);
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+    pub const MAX: Amount = Amount(i64::MAX);
+    pub const MIN: Amount = Amount(i64::MIN);
+
+    /// `units` ten-thousandths.
+    pub const fn from_units(units: i64) -> Amount {
+        Amount(units)
+    }
+
+    pub const fn units(self) -> i64 {
+        self.0
+    }
+
+    /// `amount` exactly, `None` if it has more than four places or is out of range.
+    pub fn from_decimal(amount: Decimal) -> Option<Amount> {
+        if amount.scale() > PRECISION {
+            return None;
+        }
+        let mut amount = amount;
+        amount.rescale(PRECISION);
+        (amount.scale() == PRECISION)
+            .then(|| i64::try_from(amount.mantissa()).ok().map(Amount))
+            .flatten()
+    }
+
+    /// `amount`, which has four places or fewer, with anything out of range pinned to `MAX` or
+    /// `MIN`. Those are way past `MAX_AMOUNT`, so the record is still rejected as too large.
+    pub(crate) fn saturating_from_decimal(amount: Decimal) -> Amount {
+        Amount::from_decimal(amount).unwrap_or(if amount.is_sign_negative() {
+            Amount::MIN
+        } else {
+            Amount::MAX
+        })
+    }
+
+    /// The amount as a decimal with four places.
+    pub fn to_decimal(self) -> Decimal {
+        Decimal::new(self.0, PRECISION)
+    }
+
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Amount)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+
+    pub fn saturating_add(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_add(other.0))
+    }
+
+    pub fn saturating_sub(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_sub(other.0))
+    }
+
+    pub fn abs(self) -> Amount {
+        Amount(self.0.saturating_abs())
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+}
+
+impl Neg for Amount {
+    type Output = Amount;
+
+    fn neg(self) -> Amount {
+        Amount(self.0.saturating_neg())
+    }
+}
+
+impl From<Amount> for Decimal {
+    fn from(amount: Amount) -> Decimal {
+        amount.to_decimal()
+    }
+}
+
+// what an amount in the config or on the command line has to be: a decimal that fits exactly
+fn exact(amount: Decimal) -> Result<Amount, String> {
+    Amount::from_decimal(amount)
+        .ok_or_else(|| format!("{} doesn't fit an amount with four places", amount))
+}
+
+/// A decimal with at most four places, `1000` or `0.25`.
+impl FromStr for Amount {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Amount, String> {
+        exact(Decimal::from_str(text.trim()).map_err(|err| err.to_string())?)
+    }
+}
+
+/// Config values, read like a `Decimal` and then they have to fit four places.
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        exact(<Decimal as Deserialize>::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+// comparing with a decimal, so tests don't need converting
+impl PartialEq<Decimal> for Amount {
+    fn eq(&self, other: &Decimal) -> bool {
+        self.to_decimal() == *other
+    }
+}
+
+impl PartialEq<Amount> for Decimal {
+    fn eq(&self, other: &Amount) -> bool {
+        *self == other.to_decimal()
+    }
+}
+
+/// Four places, like the reports have it: `1.5000`.
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.to_decimal().fmt(f)
+    }
+}
+
+impl fmt::Debug for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Amount({})", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn amounts_convert_exactly_or_not_at_all() {
+        let amount = Amount::from_decimal(dec!(1.5)).unwrap();
+        assert_eq!(amount.units(), 15_000);
+        assert_eq!(amount.to_string(), "1.5000");
+        assert_eq!(
+            Amount::from_decimal(dec!(-0.0001)),
+            Some(Amount::from_units(-1))
+        );
+        assert_eq!(Amount::from_decimal(dec!(1.00001)), None);
+        assert_eq!(Amount::from_decimal(dec!(1000000000000000)), None);
+        assert_eq!(
+            Amount::from_decimal(Amount::MIN.to_decimal()),
+            Some(Amount::MIN)
+        );
+
+        assert_eq!(amount, dec!(1.50));
+        assert_eq!("1.5".parse(), Ok(amount));
+        assert!("1.00001".parse::<Amount>().is_err());
+        assert!("lots".parse::<Amount>().is_err());
+        assert_eq!(-amount, Amount::from_units(-15_000));
+        assert_eq!(Amount::MAX.checked_add(Amount::from_units(1)), None);
+        assert_eq!(Amount::MIN.checked_sub(Amount::from_units(1)), None);
+        assert_eq!(Amount::MAX.saturating_add(amount), Amount::MAX);
+    }
+}
@@ -0,0 +1,98 @@
+use crate::account::AccountRow;
+use hmac::{Hmac, Mac};
+use rust_decimal::{Decimal, RoundingStrategy};
+use sha2::Sha256;
+
+/// How `ReportOptions::anonymize` disguises a report so it can be shared for tests and demos.
+/// Client ids are mapped through a keyed permutation of the u16s, the same key always mapping a
+/// client to the same id and no two clients to one. Available and held are rounded to the nearest
+/// multiple of `bucket`, and the total is what they add up to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Anonymize {
+    pub key: String,
+    pub bucket: Decimal,
+}
+
+// rounds of the Feistel network over the id's two bytes. Four make it a pseudorandom permutation
+const ROUNDS: u8 = 4;
+
+impl Anonymize {
+    /// Balances rounded to hundreds.
+    pub fn new(key: impl Into<String>) -> Self {
+        Anonymize {
+            key: key.into(),
+            bucket: Decimal::ONE_HUNDRED,
+        }
+    }
+
+    /// The id `client` goes by in the anonymized report.
+    pub fn client(&self, client: u16) -> u16 {
+        let [mut left, mut right] = client.to_be_bytes();
+        for round in 0..ROUNDS {
+            let mut mac = Hmac::<Sha256>::new_from_slice(self.key.as_bytes())
+                .expect("hmac takes keys of any size");
+            mac.update(&[round, right]);
+            let mixed = left ^ mac.finalize().into_bytes()[0];
+            (left, right) = (right, mixed);
+        }
+        u16::from_be_bytes([left, right])
+    }
+
+    fn bucketed(&self, amount: Decimal) -> Decimal {
+        if self.bucket <= Decimal::ZERO {
+            return amount;
+        }
+        (amount / self.bucket).round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero)
+            * self.bucket
+    }
+
+    pub(crate) fn row(&self, row: &mut AccountRow) {
+        row.client = self.client(row.client);
+        row.available = self.bucketed(row.available);
+        row.held = self.bucketed(row.held);
+        row.total = row.available + row.held;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{process_reader, ReportOptions};
+    use std::collections::HashSet;
+
+    #[test]
+    fn anonymized_reports_hide_ids_and_round_balances() {
+        let anonymize = Anonymize::new("demo");
+        let ids: HashSet<u16> = (0..=u16::MAX).map(|id| anonymize.client(id)).collect();
+        assert_eq!(ids.len(), 65536);
+        assert_ne!(anonymize.client(1), Anonymize::new("other").client(1));
+
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 1234.5\n\
+                     deposit, 2, 2, 80.0\n\
+                     dispute, 2, 2,\n\
+                     deposit, 3, 3, 49.99\n";
+        let engine = process_reader(input.as_bytes()).unwrap();
+        let options = ReportOptions {
+            anonymize: Some(anonymize.clone()),
+            ..ReportOptions::default()
+        };
+        let mut report = Vec::new();
+        engine.write_report(&options, &mut report).unwrap();
+        let mut expected = [
+            (anonymize.client(1), "1200.0000,0.0000,1200.0000"),
+            (anonymize.client(2), "0.0000,100.0000,100.0000"),
+            (anonymize.client(3), "0.0000,0.0000,0.0000"),
+        ];
+        // ordered by the ids they go by
+        expected.sort();
+        let expected = expected
+            .iter()
+            .map(|(client, amounts)| format!("{},{},false\n", client, amounts))
+            .collect::<String>();
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            format!("client,available,held,total,locked\n{}", expected)
+        );
+    }
+}
@@ -0,0 +1,288 @@
+use crate::config::Delimiter;
+use crate::engine::{Engine, FLUSH_EVERY};
+use crate::error::Error;
+use crate::reader::{amount_column, detect_delimiter, renamed, unformatted, Row};
+use crate::schema::check_header;
+use crate::transaction::Transaction;
+use crate::utils::Instant;
+use csv::{ByteRecord, Position, StringRecord};
+use csv_async::{AsyncReaderBuilder, Trim};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::mpsc;
+
+/// How many parsed records may sit between the reader and the engine before the reader has to wait.
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 1024;
+
+impl Engine {
+    /// Async version of `process_reader`. The csv is read on its own task and handed over through a
+    /// bounded channel, so a slow engine pushes back on the reader instead of buffering the whole file.
+    /// Rows are parsed, rejected and reported the same as with `process_reader`.
+    pub async fn process_async_reader<R>(&mut self, reader: R) -> Result<(), Error>
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        self.process_async_reader_with_capacity(reader, DEFAULT_CHANNEL_CAPACITY)
+            .await
+    }
+
+    pub async fn process_async_reader_with_capacity<R>(
+        &mut self,
+        reader: R,
+        capacity: usize,
+    ) -> Result<(), Error>
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        if self.interrupted() {
+            return Ok(());
+        }
+        let started = Instant::now();
+        // each row's raw fields, with where it is in the input, parsed on this side where the config is
+        let (sender, mut receiver) =
+            mpsc::channel::<Result<ByteRecord, csv_async::Error>>(capacity);
+
+        // the header line is read ahead to tell the delimiter, then handed back in front of the rest
+        let mut reader = reader;
+        let mut start = Vec::new();
+        if self.config().delimiter == Delimiter::Auto {
+            let mut chunk = [0; 4096];
+            while !start.contains(&b'\n') {
+                let n = reader.read(&mut chunk).await?;
+                if n == 0 {
+                    break;
+                }
+                start.extend_from_slice(&chunk[..n]);
+            }
+        }
+        let delimiter = match self.config().delimiter {
+            Delimiter::Auto => detect_delimiter(&start),
+            Delimiter::Byte(byte) => byte,
+        };
+        let reader = std::io::Cursor::new(start).chain(reader);
+        let mut deserializer = AsyncReaderBuilder::new()
+            .has_headers(true)
+            .delimiter(delimiter)
+            .trim(Trim::All)
+            .flexible(true)
+            .create_deserializer(reader);
+        let found = deserializer.headers().await.map_err(parse_error)?.clone();
+        let headers = StringRecord::from(renamed(&found, &self.config().columns));
+        check_header(&found, &headers)?;
+        let amount = amount_column(&headers, self.config().amount_notation());
+
+        let reader_task = tokio::spawn(async move {
+            let mut record = csv_async::ByteRecord::new();
+            loop {
+                // where the row starts, for its row number and the errors it gets
+                let start = deserializer.position().clone();
+                let result = match deserializer.read_byte_record(&mut record).await {
+                    Ok(false) => break,
+                    Ok(true) => {
+                        let pos = record.position().unwrap_or(&start);
+                        let mut position = Position::new();
+                        position
+                            .set_byte(pos.byte())
+                            .set_line(pos.line())
+                            .set_record(pos.record());
+                        let mut fields: ByteRecord = record.iter().collect();
+                        fields.set_position(Some(position));
+                        Ok(fields)
+                    }
+                    Err(err) => Err(err),
+                };
+                let failed = result.is_err();
+                // bad rows are only bad once parsed, an error here is the input itself failing
+                if sender.send(result).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        let mut flushed = started;
+        let mut last_tx = None;
+        while !self.past_stop(self.applied() + self.skipped(), last_tx) {
+            let Some(received) = receiver.recv().await else {
+                break;
+            };
+            let fields = received.map_err(parse_error)?;
+            let row = fields.position().map_or(0, Position::line);
+            let (raw, parsed): (_, csv::Result<Transaction>) =
+                match StringRecord::from_byte_record(fields) {
+                    Ok(raw) => {
+                        let unformatted = unformatted(&raw, amount);
+                        let parsed = unformatted
+                            .as_ref()
+                            .unwrap_or(&raw)
+                            .deserialize(Some(&headers));
+                        (raw, parsed)
+                    }
+                    // not utf-8, which the deserializer turns into the row's error
+                    Err(err) => {
+                        let fields = err.into_byte_record();
+                        let parsed = fields.deserialize(Some(headers.as_byte_record()));
+                        (StringRecord::from_byte_record_lossy(fields), parsed)
+                    }
+                };
+            last_tx = parsed.as_ref().ok().map(|record| record.tx);
+            self.apply_row(Row { row, parsed }, &headers, &raw)?;
+            // so a pipe reading --errors or --audit isn't left waiting for the end of the input
+            if flushed.elapsed() >= FLUSH_EVERY {
+                self.flush_reports()?;
+                flushed = Instant::now();
+            }
+        }
+        // a run that stopped early hangs up on the reader, which then stops too
+        drop(receiver);
+        reader_task
+            .await
+            .map_err(|err| Error::Internal(format!("reader task failed: {}", err)))?;
+        self.counters_mut().add_time(started.elapsed());
+        self.flush_reports()?;
+        Ok(())
+    }
+}
+
+// errors reading the input (not a bad row, those are rejected) end the async path, with the row when
+// the csv reader knows it
+fn parse_error(err: csv_async::Error) -> Error {
+    let row = err.position().map_or(0, |pos| pos.line());
+    let message = err.to_string();
+    match err.into_kind() {
+        csv_async::ErrorKind::Io(err) => Error::Io(err),
+        _ => Error::Parse {
+            row,
+            message,
+            line: String::new(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        generate, process_reader, Engine, EngineConfig, Error, GenerateOptions, ParseMode, StopAt,
+    };
+    use rust_decimal_macros::dec;
+    use std::{fs, path::PathBuf, process};
+
+    #[tokio::test]
+    async fn async_reader_matches_sync_reader() {
+        let input: &'static [u8] = b"type, client, tx, amount\n\
+                                     deposit, 1, 1, 5.0\n\
+                                     deposit, 2, 2, 2.0\n\
+                                     withdrawal, 1, 3, 1.5\n\
+                                     dispute, 2, 2,\n";
+        let mut engine = Engine::new();
+        // tiny channel so the reader actually has to wait on the engine
+        engine
+            .process_async_reader_with_capacity(input, 1)
+            .await
+            .unwrap();
+
+        assert_eq!(engine.accounts()[&1].total(), dec!(3.5));
+        assert_eq!(engine.accounts()[&2].held(), dec!(2.0));
+    }
+
+    #[tokio::test]
+    async fn async_reader_matches_process_reader() {
+        let options = GenerateOptions {
+            rows: 5_000,
+            clients: 50,
+            dispute_rate: 0.1,
+            seed: 7,
+        };
+        let mut input = Vec::new();
+        generate(&options, &mut input).unwrap();
+        let expected = process_reader(input.as_slice()).unwrap();
+
+        let mut engine = Engine::new();
+        engine
+            .process_async_reader_with_capacity(std::io::Cursor::new(input), 16)
+            .await
+            .unwrap();
+
+        let (mut ours, mut theirs) = (Vec::new(), Vec::new());
+        engine.write_csv(&mut ours).unwrap();
+        expected.write_csv(&mut theirs).unwrap();
+        assert!(ours == theirs);
+        assert_eq!(engine.applied(), expected.applied());
+        assert_eq!(engine.skipped(), expected.skipped());
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("async-ingest-{}-{}.csv", process::id(), name))
+    }
+
+    #[tokio::test]
+    async fn bad_rows_are_rejected_and_reported_like_process_reader() {
+        // a row that spans two lines, so only the reader knows the row numbers after it
+        let input: &'static [u8] = b"type, client, tx, amount\n\
+                                     deposit, 1, 1, 5.0\n\
+                                     deposit, nope, 2, 2.0\n\
+                                     deposit, 1, 3,\"lots\nof\"\n\
+                                     withdrawal, 1, 4, 50.0\n\
+                                     dep\xffosit, 1, 5, 1.0\n\
+                                     deposit, 1, 6, 1.0\n";
+        let (ours, theirs) = (temp_path("ours"), temp_path("theirs"));
+        let mut engine = Engine::new();
+        engine.set_error_report(fs::File::create(&ours).unwrap());
+        engine.process_async_reader(input).await.unwrap();
+        let mut expected = Engine::new();
+        expected.set_error_report(fs::File::create(&theirs).unwrap());
+        expected.process_reader(input).unwrap();
+
+        assert_eq!(engine.applied(), 2);
+        assert_eq!(engine.skipped(), 4);
+        assert_eq!(engine.accounts()[&1].total(), dec!(6.0));
+        let report = fs::read_to_string(&ours).unwrap();
+        let mut reader = csv::Reader::from_reader(report.as_bytes());
+        let rows: Vec<String> = reader
+            .records()
+            .map(|record| record.unwrap()[0].to_string())
+            .collect();
+        assert_eq!(rows, ["3", "4", "6", "7"]);
+        // the same report but for the row that isn't utf-8, which only the sync reader turns away
+        // before it's split into fields
+        let expected = fs::read_to_string(&theirs).unwrap();
+        let (ours_before, ours_bad) = report.rsplit_once("\n7,").unwrap();
+        let (theirs_before, _) = expected.rsplit_once("\n7,").unwrap();
+        assert_eq!(ours_before, theirs_before);
+        assert!(ours_bad.contains("invalid utf-8"));
+        fs::remove_file(ours).unwrap();
+        fs::remove_file(theirs).unwrap();
+    }
+
+    #[tokio::test]
+    async fn amount_notation_stop_and_strict_parsing_are_honoured() {
+        let input: &'static [u8] = b"type; client; tx; amount\n\
+                                     deposit; 1; 1; 1.234,50\n\
+                                     withdrawal; 1; 2; 0,50\n\
+                                     deposit; 1; 3; 7,00\n";
+        let config = EngineConfig {
+            formatted_amounts: true,
+            decimal_comma: true,
+            ..EngineConfig::default()
+        };
+        let mut engine = Engine::with_config(config.clone());
+        engine.set_stop(StopAt::Tx(2));
+        engine.process_async_reader(input).await.unwrap();
+        assert!(engine.stopped());
+        assert_eq!(engine.applied(), 2);
+        assert_eq!(engine.accounts()[&1].total(), dec!(1234.0));
+
+        let malformed: &'static [u8] = b"type, client, tx, amount\n\
+                                         deposit, 1, 1, 5.0\n\
+                                         deposit, nope, 2, 2.0\n";
+        let mut engine = Engine::with_config(EngineConfig {
+            parsing: ParseMode::Strict,
+            ..config
+        });
+        match engine.process_async_reader(malformed).await {
+            Err(Error::Parse { row, line, .. }) => {
+                assert_eq!(row, 3);
+                assert_eq!(line, "deposit,nope,2,2.0");
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+/// A file that only shows up at its final path once everything has been written. Writes go to a
+/// temp file next to the target and `commit` renames it into place, so a crashed or failed run
+/// never leaves a half written report behind. Dropping without committing removes the temp file.
+/// A path that's a named pipe or a device (`/dev/stdout`) is written straight into, there's
+/// nothing to rename over it. With the `cloud` feature an `s3://` or `gs://` path is an object
+/// instead, uploaded as it's written and only completed on `commit`.
+pub struct AtomicFile {
+    path: PathBuf,
+    target: Target,
+}
+
+enum Target {
+    Local {
+        tmp_path: PathBuf,
+        file: Option<BufWriter<File>>,
+    },
+    // a fifo or a device
+    Through(BufWriter<File>),
+    #[cfg(feature = "cloud")]
+    Object(Option<crate::cloud::Upload>),
+}
+
+impl AtomicFile {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        #[cfg(feature = "cloud")]
+        if crate::input::is_object_url(&path) {
+            let upload = crate::cloud::create(&path)?;
+            return Ok(AtomicFile {
+                path,
+                target: Target::Object(Some(upload)),
+            });
+        }
+        if fs::metadata(&path).is_ok_and(|metadata| !metadata.is_file() && !metadata.is_dir()) {
+            let file = fs::OpenOptions::new().write(true).open(&path)?;
+            return Ok(AtomicFile {
+                path,
+                target: Target::Through(BufWriter::new(file)),
+            });
+        }
+        let mut tmp_name = path
+            .file_name()
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "output path has no file name")
+            })?
+            .to_os_string();
+        // same directory so the rename can't cross filesystems
+        tmp_name.push(format!(".tmp-{}", process::id()));
+        let tmp_path = path.with_file_name(tmp_name);
+        let file = File::create(&tmp_path)?;
+        Ok(AtomicFile {
+            path,
+            target: Target::Local {
+                tmp_path,
+                file: Some(BufWriter::new(file)),
+            },
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Flushes, syncs and renames the temp file over the target path.
+    pub fn commit(mut self) -> io::Result<()> {
+        match &mut self.target {
+            Target::Local { tmp_path, file } => {
+                let file = file.take().expect("file is only taken on commit");
+                let file = file.into_inner().map_err(|err| err.into_error())?;
+                file.sync_all()?;
+                fs::rename(tmp_path, &self.path)
+            }
+            Target::Through(file) => file.flush(),
+            #[cfg(feature = "cloud")]
+            Target::Object(upload) => upload
+                .take()
+                .expect("upload is only taken on commit")
+                .commit(),
+        }
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match &mut self.target {
+            Target::Local { file, .. } => file.as_mut().expect("file is only taken on commit"),
+            Target::Through(file) => file,
+            #[cfg(feature = "cloud")]
+            Target::Object(upload) => upload.as_mut().expect("upload is only taken on commit"),
+        }
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer().flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        match &mut self.target {
+            Target::Local { tmp_path, file } => {
+                if file.take().is_some() {
+                    let _ = fs::remove_file(tmp_path);
+                }
+            }
+            Target::Through(_) => {}
+            // an upload that isn't committed aborts itself
+            #[cfg(feature = "cloud")]
+            Target::Object(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_visible_after_commit() {
+        let dir = std::env::temp_dir().join(format!("atomic-file-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("accounts.csv");
+
+        let mut file = AtomicFile::create(&target).unwrap();
+        file.write_all(b"client,available\n").unwrap();
+        assert!(!target.exists());
+        file.commit().unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "client,available\n");
+
+        // an abandoned write leaves the previous file alone and cleans up after itself
+        let mut file = AtomicFile::create(&target).unwrap();
+        file.write_all(b"half").unwrap();
+        drop(file);
+        assert_eq!(fs::read_to_string(&target).unwrap(), "client,available\n");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
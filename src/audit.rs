@@ -0,0 +1,150 @@
+use crate::account::Account;
+use crate::amount::Amount;
+use crate::currency::Currency;
+#[cfg(feature = "parquet")]
+use crate::parquet_writer::AuditParquet;
+use crate::transaction::{Transaction, TransactionType};
+use crate::utils::four_precision_serializer;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::{fmt, io, io::Write};
+
+/// One line of the `--audit` log: an applied record and the balances it left the account with.
+/// Replaying the log per client reproduces every intermediate balance.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AuditEntry {
+    pub tx: u32,
+    pub client: u16,
+    #[serde(rename = "type")]
+    pub r_type: TransactionType,
+    /// The amount that moved. For disputes, resolves and chargebacks that's the referenced tx's.
+    #[serde(serialize_with = "four_precision_serializer")]
+    pub amount: Decimal,
+    #[serde(serialize_with = "four_precision_serializer")]
+    pub available: Decimal,
+    #[serde(serialize_with = "four_precision_serializer")]
+    pub held: Decimal,
+    #[serde(serialize_with = "four_precision_serializer")]
+    pub total: Decimal,
+    pub locked: bool,
+    /// The currency that moved, the balances are the account's in that currency. Last so logs
+    /// from before currencies keep their column positions.
+    pub currency: Currency,
+}
+
+impl AuditEntry {
+    pub fn new(
+        record: &Transaction,
+        amount: Amount,
+        currency: Currency,
+        account: &Account,
+    ) -> Self {
+        let balance = account.balance(currency);
+        AuditEntry {
+            tx: record.tx,
+            client: record.client,
+            r_type: record.r_type,
+            amount: amount.into(),
+            available: balance.available.into(),
+            held: balance.held.into(),
+            total: balance.total.into(),
+            locked: account.locked(),
+            currency,
+        }
+    }
+}
+
+/// Sink for audit entries, csv unless it's made with `parquet`. Entries are written from
+/// `Engine::apply`, which can't fail on I/O, so the first write error is kept and handed back by the
+/// next `flush`.
+pub struct AuditLog {
+    sink: Sink,
+    client: Option<u16>,
+    failed: Option<String>,
+}
+
+enum Sink {
+    Csv(csv::Writer<Box<dyn Write + Send>>),
+    #[cfg(feature = "parquet")]
+    Parquet(AuditParquet),
+}
+
+impl AuditLog {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self::with_headers(writer, true)
+    }
+
+    /// For appending to an existing log, so the header row isn't repeated.
+    pub fn without_headers(writer: impl Write + Send + 'static) -> Self {
+        Self::with_headers(writer, false)
+    }
+
+    fn with_headers(writer: impl Write + Send + 'static, headers: bool) -> Self {
+        let writer: Box<dyn Write + Send> = Box::new(writer);
+        AuditLog {
+            sink: Sink::Csv(
+                csv::WriterBuilder::new()
+                    .has_headers(headers)
+                    .from_writer(writer),
+            ),
+            client: None,
+            failed: None,
+        }
+    }
+
+    /// A Parquet log instead, which can't be appended to and is only complete after `finish`.
+    #[cfg(feature = "parquet")]
+    pub fn parquet(writer: impl Write + Send + 'static) -> io::Result<Self> {
+        Ok(AuditLog {
+            sink: Sink::Parquet(AuditParquet::new(Box::new(writer))?),
+            client: None,
+            failed: None,
+        })
+    }
+
+    /// Only logs `client`'s entries, dropping everyone else's.
+    pub fn only_client(mut self, client: u16) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    pub fn write(&mut self, entry: &AuditEntry) {
+        if self.failed.is_some() || self.client.is_some_and(|client| client != entry.client) {
+            return;
+        }
+        let result = match &mut self.sink {
+            Sink::Csv(writer) => writer.serialize(entry).map_err(|err| err.to_string()),
+            #[cfg(feature = "parquet")]
+            Sink::Parquet(writer) => writer.write(entry).map_err(|err| err.to_string()),
+        };
+        self.failed = result.err();
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        if let Some(err) = self.failed.take() {
+            return Err(io::Error::other(format!("audit log: {}", err)));
+        }
+        match &mut self.sink {
+            Sink::Csv(writer) => writer.flush(),
+            // row groups go out as they fill up
+            #[cfg(feature = "parquet")]
+            Sink::Parquet(_) => Ok(()),
+        }
+    }
+
+    /// Flushes and, for Parquet, writes the rest and the footer. Nothing can be written after.
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.flush()?;
+        match &mut self.sink {
+            Sink::Csv(_) => Ok(()),
+            #[cfg(feature = "parquet")]
+            Sink::Parquet(writer) => writer.finish(),
+        }
+    }
+}
+
+impl fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuditLog").finish_non_exhaustive()
+    }
+}
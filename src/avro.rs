@@ -0,0 +1,256 @@
+use crate::account::AccountRow;
+use crate::error::Error;
+use crate::utils::four_places;
+use apache_avro::{schema::DecimalSchema, types::Value, Reader, Schema, Writer};
+use chrono::DateTime;
+use rust_decimal::Decimal;
+use std::io::{self, Read, Write};
+
+/// The schema of the account report's records, the one a `--format avro` file embeds and the one
+/// to register for `--format avro:<id>`. Amounts are decimals at four places like in Parquet.
+pub const ACCOUNT_AVRO_SCHEMA: &str = r#"{
+    "type": "record",
+    "name": "Account",
+    "namespace": "csv_tx_resolver",
+    "fields": [
+        {"name": "client", "type": "int"},
+        {"name": "currency", "type": "string"},
+        {"name": "available", "type": {"type": "bytes", "logicalType": "decimal", "precision": 38, "scale": 4}},
+        {"name": "held", "type": {"type": "bytes", "logicalType": "decimal", "precision": 38, "scale": 4}},
+        {"name": "total", "type": {"type": "bytes", "logicalType": "decimal", "precision": 38, "scale": 4}},
+        {"name": "locked", "type": "boolean"}
+    ]
+}"#;
+
+// what a schema registry's serializers put in front of every record: a zero and the schema's id
+const REGISTRY_MAGIC: u8 = 0;
+
+fn avro_error(err: apache_avro::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("avro: {}", err))
+}
+
+fn decimal(value: Decimal) -> Value {
+    Value::Decimal(four_places(value).mantissa().to_be_bytes().into())
+}
+
+/// The account report as Avro. An object container file with the schema embedded, or with a
+/// `schema_id` the records one after another each framed the way a schema registry's serializers
+/// do (a zero byte, then the id as four big endian bytes), ready to be produced to a topic whose
+/// schema has that id. The currency is empty for the default currency.
+pub(crate) fn write_accounts<'a>(
+    rows: impl Iterator<Item = &'a AccountRow>,
+    schema_id: Option<u32>,
+    mut writer: impl Write,
+) -> Result<(), Error> {
+    let schema = Schema::parse_str(ACCOUNT_AVRO_SCHEMA).map_err(avro_error)?;
+    let records = rows.map(|row| {
+        Value::Record(vec![
+            ("client".to_string(), Value::Int(row.client.into())),
+            (
+                "currency".to_string(),
+                Value::String(row.currency.map(|c| c.to_string()).unwrap_or_default()),
+            ),
+            ("available".to_string(), decimal(row.available)),
+            ("held".to_string(), decimal(row.held)),
+            ("total".to_string(), decimal(row.total)),
+            ("locked".to_string(), Value::Boolean(row.locked)),
+        ])
+    });
+    match schema_id {
+        None => {
+            let mut container = Writer::new(&schema, writer);
+            for record in records {
+                container.append(record).map_err(avro_error)?;
+            }
+            container.flush().map_err(avro_error)?;
+        }
+        Some(id) => {
+            for record in records {
+                let datum = apache_avro::to_avro_datum(&schema, record).map_err(avro_error)?;
+                writer.write_all(&[REGISTRY_MAGIC])?;
+                writer.write_all(&id.to_be_bytes())?;
+                writer.write_all(&datum)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// An Avro object container file as csv, a header of its record's field names and then a row per
+/// record, to be read like any other input. The schema comes from the file, so any record with
+/// fields named like our columns (or mapped onto them) will do. Decimals, timestamps (as RFC 3339)
+/// and nullable fields come out as they'd be written in a csv, with `null` left empty.
+pub(crate) fn container_to_csv(reader: impl Read) -> io::Result<Vec<u8>> {
+    let reader = Reader::new(reader).map_err(avro_error)?;
+    let Schema::Record(record) = reader.writer_schema() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "avro: the file's schema isn't a record",
+        ));
+    };
+    let scales: Vec<Option<usize>> = record.fields.iter().map(|f| scale(&f.schema)).collect();
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(record.fields.iter().map(|field| &field.name))?;
+    for value in reader {
+        let Value::Record(fields) = value.map_err(avro_error)? else {
+            continue;
+        };
+        let row = fields
+            .into_iter()
+            .zip(&scales)
+            .map(|((_, value), &scale)| field(value, scale));
+        writer.write_record(row.collect::<Vec<_>>())?;
+    }
+    writer
+        .into_inner()
+        .map_err(|err| io::Error::new(err.error().kind(), err.to_string()))
+}
+
+// the scale of a decimal field, nullable or not
+fn scale(schema: &Schema) -> Option<usize> {
+    match schema {
+        Schema::Decimal(DecimalSchema { scale, .. }) => Some(*scale),
+        Schema::Union(union) => union.variants().iter().find_map(scale),
+        _ => None,
+    }
+}
+
+fn field(value: Value, scale: Option<usize>) -> String {
+    let micros = |micros: i64| {
+        DateTime::from_timestamp_micros(micros)
+            .map_or_else(|| micros.to_string(), |when| when.to_rfc3339())
+    };
+    match value {
+        Value::Null => String::new(),
+        Value::Boolean(value) => value.to_string(),
+        Value::Int(value) => value.to_string(),
+        Value::Long(value) => value.to_string(),
+        Value::Float(value) => value.to_string(),
+        Value::Double(value) => value.to_string(),
+        Value::String(value) | Value::Enum(_, value) => value,
+        Value::Bytes(value) => String::from_utf8_lossy(&value).into_owned(),
+        Value::Union(_, value) => field(*value, scale),
+        Value::Decimal(value) => {
+            let bytes = Vec::<u8>::try_from(&value).unwrap_or_default();
+            // two's complement big endian, sign extended to an i128
+            let mantissa = match bytes.len() {
+                1..=16 => {
+                    let fill = if bytes[0] & 0x80 != 0 { 0xff } else { 0 };
+                    let mut wide = [fill; 16];
+                    wide[16 - bytes.len()..].copy_from_slice(&bytes);
+                    Some(i128::from_be_bytes(wide))
+                }
+                _ => None,
+            };
+            mantissa
+                .zip(scale.and_then(|scale| u32::try_from(scale).ok()))
+                .and_then(|(mantissa, scale)| {
+                    Decimal::try_from_i128_with_scale(mantissa, scale).ok()
+                })
+                // left unreadable, so the row is rejected rather than read as something else
+                .map_or_else(
+                    || "invalid decimal".to_string(),
+                    |amount| amount.to_string(),
+                )
+        }
+        Value::BigDecimal(value) => value.to_string(),
+        Value::TimestampMillis(millis) => micros(millis.saturating_mul(1000)),
+        Value::TimestampMicros(value) => micros(value),
+        Value::TimestampNanos(nanos) => micros(nanos / 1000),
+        other => format!("{:?}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{open_input, process_reader, OutputFormat, ReportOptions};
+    use rust_decimal_macros::dec;
+    use std::{fs, process};
+
+    #[test]
+    fn avro_containers_read_like_the_same_csv() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "Transaction", "fields": [
+                {"name": "type", "type": "string"},
+                {"name": "client", "type": "int"},
+                {"name": "tx", "type": "long"},
+                {"name": "amount", "type": ["null",
+                    {"type": "bytes", "logicalType": "decimal", "precision": 20, "scale": 4}]},
+                {"name": "timestamp", "type": ["null",
+                    {"type": "long", "logicalType": "timestamp-millis"}]}
+            ]}"#,
+        )
+        .unwrap();
+        let record = |r_type: &str, client, tx, amount: Option<i64>| {
+            let amount = match amount {
+                Some(units) => {
+                    Value::Union(1, Box::new(Value::Decimal(units.to_be_bytes().into())))
+                }
+                None => Value::Union(0, Box::new(Value::Null)),
+            };
+            Value::Record(vec![
+                ("type".to_string(), Value::String(r_type.to_string())),
+                ("client".to_string(), Value::Int(client)),
+                ("tx".to_string(), Value::Long(tx)),
+                ("amount".to_string(), amount),
+                (
+                    "timestamp".to_string(),
+                    Value::Union(1, Box::new(Value::TimestampMillis(1_714_521_600_000))),
+                ),
+            ])
+        };
+        let mut writer = Writer::new(&schema, Vec::new());
+        writer
+            .append(record("deposit", 1, 1, Some(15_000)))
+            .unwrap();
+        writer
+            .append(record("withdrawal", 1, 2, Some(2_500)))
+            .unwrap();
+        writer.append(record("dispute", 1, 1, None)).unwrap();
+        let path = std::env::temp_dir().join(format!("avro-test-{}.avro", process::id()));
+        fs::write(&path, writer.into_inner().unwrap()).unwrap();
+
+        let mut csv = String::new();
+        open_input(&path).unwrap().read_to_string(&mut csv).unwrap();
+        let engine = process_reader(open_input(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            csv,
+            "type,client,tx,amount,timestamp\n\
+             deposit,1,1,1.5000,2024-05-01T00:00:00+00:00\n\
+             withdrawal,1,2,0.2500,2024-05-01T00:00:00+00:00\n\
+             dispute,1,1,,2024-05-01T00:00:00+00:00\n"
+        );
+        let accounts = engine.accounts();
+        assert_eq!(accounts[&1].held(), dec!(1.5));
+        assert_eq!(accounts[&1].total(), dec!(1.25));
+    }
+
+    #[test]
+    fn reports_embed_or_reference_the_schema() {
+        let engine = process_reader("type,client,tx,amount\ndeposit,7,1,2.5\n".as_bytes()).unwrap();
+        let mut container = Vec::new();
+        let options = ReportOptions::new(OutputFormat::Avro(None));
+        engine.write_report(&options, &mut container).unwrap();
+        let reader = Reader::new(container.as_slice()).unwrap();
+        assert_eq!(
+            reader.writer_schema(),
+            &Schema::parse_str(ACCOUNT_AVRO_SCHEMA).unwrap()
+        );
+        let records = reader.map(Result::unwrap).collect::<Vec<_>>();
+        let Value::Record(fields) = &records[0] else {
+            panic!("not a record");
+        };
+        assert_eq!(fields[0].1, Value::Int(7));
+        assert_eq!(field(fields[2].1.clone(), Some(4)), "2.5000");
+
+        let mut framed = Vec::new();
+        let options = ReportOptions::new(OutputFormat::Avro(Some(42)));
+        engine.write_report(&options, &mut framed).unwrap();
+        assert_eq!(framed[..5], [0, 0, 0, 0, 42]);
+        let schema = Schema::parse_str(ACCOUNT_AVRO_SCHEMA).unwrap();
+        let datum = apache_avro::from_avro_datum(&schema, &mut &framed[5..], None).unwrap();
+        assert_eq!(datum, records[0]);
+    }
+}
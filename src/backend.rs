@@ -0,0 +1,42 @@
+use crate::engine::Engine;
+use crate::error::Error;
+use std::path::PathBuf;
+
+/// Somewhere the ledger (balances and stored transactions) outlives the process. A run loads it
+/// before the first record and saves it back once it's done, or at every commit point of a
+/// long-running source.
+pub trait LedgerBackend: Send {
+    /// Puts the saved ledger into `engine`. Nothing saved yet is a fresh start, not an error.
+    fn load(&mut self, engine: &mut Engine) -> Result<(), Error>;
+
+    /// Replaces what's saved with the engine's ledger, all or nothing.
+    fn save(&mut self, engine: &Engine) -> Result<(), Error>;
+}
+
+/// The json file `--state` reads and writes.
+#[derive(Debug, Clone)]
+pub struct StateFile(pub PathBuf);
+
+impl LedgerBackend for StateFile {
+    fn load(&mut self, engine: &mut Engine) -> Result<(), Error> {
+        engine.load_state_file(&self.0)
+    }
+
+    fn save(&mut self, engine: &Engine) -> Result<(), Error> {
+        engine.save_state_file(&self.0)
+    }
+}
+
+/// Opens the backend `spec` names: `json:PATH` for a state file, or `sqlite:PATH` with the `sqlite`
+/// feature.
+pub fn open_backend(spec: &str) -> Result<Box<dyn LedgerBackend>, Error> {
+    match spec.split_once(':') {
+        Some(("json", path)) => Ok(Box::new(StateFile(path.into()))),
+        #[cfg(feature = "sqlite")]
+        Some(("sqlite", path)) => Ok(Box::new(crate::sqlite::SqliteBackend::open(path)?)),
+        _ => Err(Error::Config(format!(
+            "unknown backend {:?}, expected json:PATH or sqlite:PATH",
+            spec
+        ))),
+    }
+}
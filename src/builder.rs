@@ -0,0 +1,189 @@
+use crate::amount::Amount;
+use crate::config::{
+    AccountCreation, Delimiter, EngineConfig, InputFormat, LockedAccountPolicy, NegativeAvailable,
+    Overdraft, ParseMode, Rounding,
+};
+use crate::engine::Engine;
+use crate::limits::WithdrawalLimits;
+
+/// Sets up an `Engine` one policy at a time instead of through an `EngineConfig` literal, the way
+/// csv's `ReaderBuilder` sets up a reader. Anything not set keeps `EngineConfig`'s default.
+///
+/// ```
+/// use csv_tx_resolver::{EngineBuilder, LockedAccountPolicy, NegativeAvailable, Rounding};
+///
+/// let engine = EngineBuilder::new()
+///     .negative_available(NegativeAvailable::Clamp)
+///     .max_dispute_window(Some(10_000))
+///     .locked_account_policy(LockedAccountPolicy::FreezeAll)
+///     .rounding(Rounding::HalfEven)
+///     .build();
+/// assert_eq!(engine.config().negative_available, NegativeAvailable::Clamp);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct EngineBuilder {
+    config: EngineConfig,
+}
+
+impl EngineBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts from an existing config, e.g. one read with `EngineConfig::from_toml_file`.
+    pub fn from_config(config: EngineConfig) -> Self {
+        EngineBuilder { config }
+    }
+
+    pub fn allow_withdrawal_disputes(&mut self, yes: bool) -> &mut Self {
+        self.config.allow_withdrawal_disputes = yes;
+        self
+    }
+
+    pub fn negative_available(&mut self, policy: NegativeAvailable) -> &mut Self {
+        self.config.negative_available = policy;
+        self
+    }
+
+    /// `negative_available` as the yes/no it used to be: `Allow` or `Reject`.
+    pub fn allow_negative_available(&mut self, yes: bool) -> &mut Self {
+        self.negative_available(match yes {
+            true => NegativeAvailable::Allow,
+            false => NegativeAvailable::Reject,
+        })
+    }
+
+    /// How many records later a deposit/withdrawal can still be disputed, `None` for any time.
+    pub fn max_dispute_window(&mut self, records: Option<u64>) -> &mut Self {
+        self.config.max_dispute_window = records;
+        self
+    }
+
+    /// How many days after a deposit/withdrawal (by its timestamp) it can still be disputed.
+    pub fn max_dispute_days(&mut self, days: Option<u32>) -> &mut Self {
+        self.config.max_dispute_days = days;
+        self
+    }
+
+    pub fn locked_account_policy(&mut self, policy: LockedAccountPolicy) -> &mut Self {
+        self.config.locked_accounts = policy;
+        self
+    }
+
+    pub fn account_creation(&mut self, policy: AccountCreation) -> &mut Self {
+        self.config.account_creation = policy;
+        self
+    }
+
+    pub fn overdraft(&mut self, policy: Overdraft) -> &mut Self {
+        self.config.overdraft = policy;
+        self
+    }
+
+    pub fn overdraft_limit(&mut self, limit: Amount) -> &mut Self {
+        self.config.overdraft_limit = limit;
+        self
+    }
+
+    pub fn parsing(&mut self, mode: ParseMode) -> &mut Self {
+        self.config.parsing = mode;
+        self
+    }
+
+    pub fn strict(&mut self, yes: bool) -> &mut Self {
+        self.config.strict = yes;
+        self
+    }
+
+    pub fn check_invariants(&mut self, yes: bool) -> &mut Self {
+        self.config.check_invariants = yes;
+        self
+    }
+
+    pub fn fast_parse(&mut self, yes: bool) -> &mut Self {
+        self.config.fast_parse = yes;
+        self
+    }
+
+    pub fn formatted_amounts(&mut self, yes: bool) -> &mut Self {
+        self.config.formatted_amounts = yes;
+        self
+    }
+
+    pub fn decimal_comma(&mut self, yes: bool) -> &mut Self {
+        self.config.decimal_comma = yes;
+        self
+    }
+
+    pub fn delimiter(&mut self, delimiter: Delimiter) -> &mut Self {
+        self.config.delimiter = delimiter;
+        self
+    }
+
+    pub fn input_format(&mut self, format: InputFormat) -> &mut Self {
+        self.config.input_format = format;
+        self
+    }
+
+    pub fn rounding(&mut self, rounding: Rounding) -> &mut Self {
+        self.config.rounding = rounding;
+        self
+    }
+
+    /// The secret admin records have to be signed with, see `admin_signature`.
+    pub fn admin_key(&mut self, key: impl Into<String>) -> &mut Self {
+        self.config.admin_key = Some(key.into());
+        self
+    }
+
+    pub fn withdrawal_limits(&mut self, limits: WithdrawalLimits) -> &mut Self {
+        self.config.withdrawal_limits = limits;
+        self
+    }
+
+    /// The config built up so far.
+    pub fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    pub fn build(&self) -> Engine {
+        Engine::with_config(self.config.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rejection::Rejection;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn builder_policies_reach_the_engine() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 5.0\n\
+                     withdrawal, 1, 2, 4.0\n\
+                     dispute, 1, 1,\n\
+                     deposit, 1, 3, 1.0\n\
+                     deposit, 1, 4, 1.0\n\
+                     deposit, 1, 5, 1.0\n\
+                     dispute, 1, 3,\n\
+                     dispute, 1, 5,\n";
+        let mut engine = EngineBuilder::new()
+            .allow_negative_available(false)
+            .max_dispute_window(Some(2))
+            .build();
+        engine.process_reader(input.as_bytes()).unwrap();
+
+        // tx 1 was mostly withdrawn already, tx 3 is 3 records old by the time it's disputed
+        assert_eq!(
+            engine.stats().by_reason,
+            [
+                (Rejection::InsufficientFunds, 1),
+                (Rejection::DisputeWindowClosed, 1)
+            ]
+        );
+        let account = &engine.accounts()[&1];
+        assert_eq!(account.available(), dec!(3.0));
+        assert_eq!(account.held(), dec!(1.0));
+    }
+}
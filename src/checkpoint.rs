@@ -0,0 +1,206 @@
+use crate::atomic_file::AtomicFile;
+use crate::engine::Engine;
+use crate::error::Error;
+use crate::input::read_path;
+use crate::reader::RecordReader;
+use crate::state::State;
+use csv::StringRecord;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// Where checkpoints go and how many rows apart they are.
+#[derive(Debug)]
+pub(crate) struct Checkpoints {
+    path: PathBuf,
+    every: u64,
+}
+
+impl Checkpoints {
+    pub fn new(path: PathBuf, every: u64) -> Self {
+        Checkpoints {
+            path,
+            every: every.max(1),
+        }
+    }
+
+    pub fn every(&self) -> u64 {
+        self.every
+    }
+}
+
+/// How far a checkpointed run got: which of its inputs, and where in that input the next row
+/// starts. Offsets are into the decompressed csv, so compressed input gets skipped by reading.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Progress {
+    pub input: usize,
+    pub offset: u64,
+    pub line: u64,
+    headers: Vec<String>,
+    // checkpoints from before other delimiters were all comma separated
+    #[serde(default = "comma")]
+    delimiter: u8,
+}
+
+fn comma() -> u8 {
+    b','
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Checkpoint {
+    progress: Progress,
+    applied: u64,
+    skipped: u64,
+    state: State,
+    // a two-pass run's index, so resuming it doesn't take another first pass
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    disputed: Option<HashSet<u32>>,
+    #[serde(default, skip_serializing_if = "HashSet::is_empty")]
+    passed_over: HashSet<u32>,
+}
+
+impl Engine {
+    /// Saves everything needed to carry on from where `reader` is now. The error report is flushed
+    /// first so it's at least as far along as the checkpoint.
+    pub(crate) fn checkpoint<R: Read>(
+        &mut self,
+        reader: &RecordReader<R>,
+        input: usize,
+    ) -> Result<(), Error> {
+        let Some(checkpoints) = self.checkpoints() else {
+            return Ok(());
+        };
+        let (offset, line) = reader.position();
+        let (disputed, passed_over) = self.dispute_index();
+        let checkpoint = Checkpoint {
+            progress: Progress {
+                input,
+                offset,
+                line,
+                headers: reader.headers().iter().map(str::to_owned).collect(),
+                delimiter: reader.delimiter(),
+            },
+            applied: self.applied(),
+            skipped: self.skipped(),
+            state: State::of(self),
+            disputed: disputed.cloned(),
+            passed_over: passed_over.clone(),
+        };
+        let sealed = self.seal(serde_json::to_vec(&checkpoint)?)?;
+        let mut file = AtomicFile::create(&checkpoints.path)?;
+        file.write_all(&sealed)?;
+        self.flush_reports()?;
+        file.commit()?;
+        tracing::info!(input, line, "checkpoint saved");
+        Ok(())
+    }
+
+    /// Restores the engine from a checkpoint and says where to carry on from, or `None` if there's
+    /// no checkpoint at `path` (the run never got far enough, or it finished).
+    pub fn load_checkpoint(&mut self, path: impl AsRef<Path>) -> Result<Option<Progress>, Error> {
+        let path = path.as_ref();
+        let saved = match read_path(path) {
+            Ok(saved) => saved,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let bad = |err: &dyn std::fmt::Display| {
+            Error::Config(format!("bad checkpoint {}: {}", path.display(), err))
+        };
+        let saved = self.unseal(saved).map_err(|err| bad(&err))?;
+        let checkpoint: Checkpoint = serde_json::from_slice(&saved).map_err(|err| bad(&err))?;
+        checkpoint.state.restore(self)?;
+        self.restore_counts(checkpoint.applied, checkpoint.skipped);
+        self.restore_dispute_index(checkpoint.disputed, checkpoint.passed_over);
+        tracing::info!(
+            input = checkpoint.progress.input,
+            line = checkpoint.progress.line,
+            "resuming from checkpoint"
+        );
+        Ok(Some(checkpoint.progress))
+    }
+
+    /// Processes input number `input` of a run, picking up from `resume` if given. Inputs the
+    /// checkpoint had already finished are skipped, the one it was in the middle of is read from the
+    /// checkpoint's offset and later ones are processed from the start.
+    pub fn process_input(
+        &mut self,
+        input: usize,
+        mut reader: impl Read,
+        resume: Option<&Progress>,
+    ) -> Result<(), Error> {
+        match resume {
+            Some(progress) if progress.input > input => Ok(()),
+            Some(progress) if progress.input == input => {
+                // the input has to be the same one, so skipping the already applied bytes is enough
+                let skipped = io::copy(&mut (&mut reader).take(progress.offset), &mut io::sink())?;
+                if skipped < progress.offset {
+                    return Err(Error::Input(
+                        "input is shorter than the checkpoint, is it the same file?".to_string(),
+                    ));
+                }
+                let headers = StringRecord::from(progress.headers.clone());
+                let mut custom_reader = RecordReader::resume(
+                    reader,
+                    self.config(),
+                    headers,
+                    progress.delimiter,
+                    progress.offset,
+                    progress.line,
+                );
+                self.process_rows(&mut custom_reader, input)
+            }
+            _ => {
+                let mut custom_reader = RecordReader::new(reader, self.config())?;
+                self.process_rows(&mut custom_reader, input)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, process};
+
+    const INPUT: &str = "type, client, tx, amount\n\
+                         deposit, 1, 1, 10.0\n\
+                         deposit, 2, 2, 5.0\n\
+                         dispute, 1, 1,\n\
+                         withdrawal, 2, 3, 1.5\n\
+                         resolve, 1, 1,\n\
+                         deposit, 2, 2, 1.0\n";
+
+    #[test]
+    fn resuming_from_a_checkpoint_matches_a_straight_run() {
+        let path = std::env::temp_dir().join(format!("checkpoint-test-{}", process::id()));
+        let straight = crate::process_reader(INPUT.as_bytes()).unwrap();
+
+        // the last checkpoint lands after row 4, as if the run died somewhere in the last two
+        let mut interrupted = Engine::new();
+        interrupted.set_checkpoints(&path, 4);
+        interrupted
+            .process_input(0, INPUT.as_bytes(), None)
+            .unwrap();
+
+        let mut resumed = Engine::new();
+        let progress = resumed.load_checkpoint(&path).unwrap().unwrap();
+        assert_eq!(progress.line, 5);
+        assert_eq!(resumed.applied(), 4);
+        resumed
+            .process_input(0, INPUT.as_bytes(), Some(&progress))
+            .unwrap();
+
+        assert_eq!(resumed.applied(), straight.applied());
+        assert_eq!(resumed.skipped(), straight.skipped());
+        for (client, account) in straight.accounts() {
+            let other = &resumed.accounts()[&client];
+            assert_eq!(other.available(), account.available());
+            assert_eq!(other.held(), account.held());
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
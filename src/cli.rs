@@ -0,0 +1,733 @@
+use clap::{error::ErrorKind, ArgAction, Args, CommandFactory, Parser, Subcommand};
+use csv_tx_resolver::{
+    Amount, Anonymize, Delimiter, GenerateOptions, InputFormat, OutputFormat, PartitionBy,
+    ReportOptions, SortBy, StopAt, INPUT_COLUMNS,
+};
+use rust_decimal::Decimal;
+use std::{collections::hash_map::RandomState, hash::BuildHasher, path::PathBuf, sync::LazyLock};
+use tracing::Level;
+
+// what --anonymize maps ids with when it isn't given a key: random, so they can't be mapped back,
+// but the same for every report of the run
+static RANDOM_KEY: LazyLock<String> = LazyLock::new(|| {
+    let random = RandomState::new().hash_one(std::time::SystemTime::now());
+    format!("{:016x}", random)
+});
+
+/// Resolves a csv of deposits, withdrawals and disputes into final account balances.
+///
+/// With no subcommand it behaves like `resolve`, so
+/// `csv_tx_resolver transactions.csv > accounts.csv` keeps working.
+#[derive(Debug, Parser)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub resolve: ResolveArgs,
+
+    /// Log more to stderr: -v for rejections and progress, -vv for every record, -vvv for
+    /// everything.
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Write N synthetic records to stdout instead, as a fixture for benchmarks and load tests.
+    /// Short for `generate --rows N`.
+    #[arg(long, value_name = "N", hide = true)]
+    pub bench_generate: Option<u64>,
+}
+
+impl Cli {
+    pub fn log_level(&self) -> Level {
+        match self.verbose {
+            0 => Level::WARN,
+            1 => Level::INFO,
+            2 => Level::DEBUG,
+            _ => Level::TRACE,
+        }
+    }
+
+    pub fn into_command(self) -> Command {
+        if let Some(rows) = self.bench_generate {
+            return Command::Generate(GenerateArgs::with_rows(rows));
+        }
+        self.command.unwrap_or(Command::Resolve(self.resolve))
+    }
+}
+
+/// For combinations of flags clap can't check by itself. Exits like any other usage error.
+pub fn usage_error(message: &str) -> ! {
+    Cli::command()
+        .error(ErrorKind::ArgumentConflict, message)
+        .exit()
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Apply every transaction and write the resulting accounts.
+    Resolve(ResolveArgs),
+    /// Check the whole file and list every rejected record, without writing accounts or a ledger.
+    Validate(ValidateArgs),
+    /// Process the file and print a summary of the run instead of the accounts.
+    Report(ReportArgs),
+    /// Print every change to one client's balances, with the running balances after each.
+    History(HistoryArgs),
+    /// Compare two csv account reports and print the balance deltas of the accounts that changed.
+    Diff(DiffArgs),
+    /// Write one client's statement from an `--audit` log: opening balance, every entry, closing
+    /// balance.
+    Statement(StatementArgs),
+    /// Combine two `--state` ledgers, say of runs over different partitions of the clients, into
+    /// one.
+    Merge(MergeArgs),
+    /// Print the delimiter and columns of a transactions file, what each is read as and what its
+    /// values look like.
+    Schema(SchemaArgs),
+    /// Write synthetic transactions, seeded so they're the same every time, for load tests.
+    Generate(GenerateArgs),
+    /// Run as an HTTP service: POST /transactions, GET /accounts and GET /accounts/:id.
+    #[cfg(feature = "server")]
+    Serve(ServeArgs),
+    /// Run as a gRPC service: SubmitTransactions and GetAccount (see proto/resolver.proto).
+    #[cfg(feature = "grpc")]
+    Grpc(GrpcArgs),
+}
+
+/// Where the transactions come from and how rejections are surfaced.
+#[derive(Debug, Args)]
+pub struct InputArgs {
+    /// Transactions csvs (or glob patterns), optionally .gz/.zst compressed, replayed in order against
+    /// the same accounts. Omit them or pass `-` to read from stdin.
+    pub paths: Vec<String>,
+
+    /// Read the PATHS (or stdin), or consume from a Kafka topic until stopped.
+    #[cfg(feature = "kafka")]
+    #[arg(long, value_enum, default_value_t = Source::Files)]
+    pub source: Source,
+
+    #[cfg(feature = "kafka")]
+    #[command(flatten)]
+    pub kafka: KafkaArgs,
+
+    /// Spread the work over this many threads, sharded by client.
+    #[arg(long, default_value_t = 1)]
+    pub shards: usize,
+
+    /// Parse the csv on a thread of its own while the engine applies it.
+    #[arg(long)]
+    pub pipeline: bool,
+
+    /// Read the inputs twice: first for the tx ids that get disputed, then to apply them keeping
+    /// only those transactions in memory. Needs files, not stdin.
+    #[arg(long)]
+    pub two_pass: bool,
+
+    /// Make every client an actor with its own mailbox, run on this many worker threads.
+    #[cfg(feature = "actors")]
+    #[arg(long, value_name = "THREADS")]
+    pub actors: Option<usize>,
+
+    /// Log every rejected record to stderr.
+    #[arg(long)]
+    pub warn: bool,
+
+    /// Fail on the first row that doesn't parse, has an unknown type or bad amount, or references a
+    /// tx that isn't there, printing its row number and line. Same as `strict = true` in the config.
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Check the ledger's invariants after every record and fail on the first one that breaks.
+    /// Same as `check_invariants = true` in the config.
+    #[arg(long)]
+    pub check_invariants: bool,
+
+    /// Parse rows with a hand-rolled parser instead of serde, for big files. Same rows, same
+    /// rejections, only faster. Same as `fast_parse = true` in the config.
+    #[arg(long)]
+    pub fast: bool,
+
+    /// Read amounts like `1,234.50` or `$12.00`, without the thousands separators and currency
+    /// symbol. Same as `formatted_amounts = true` in the config.
+    #[arg(long)]
+    pub formatted_amounts: bool,
+
+    /// Read amounts with a decimal comma, `12,3456`. Same as `decimal_comma = true` in the config.
+    #[arg(long)]
+    pub decimal_comma: bool,
+
+    /// The field separator: a character, `tab`, or `auto` to tell comma, tab, semicolon or pipe
+    /// from the header line. Same as `delimiter` in the config, auto by default.
+    #[arg(long, value_name = "CHAR")]
+    pub delimiter: Option<Delimiter>,
+
+    /// `csv`, or `jsonl` for a JSON object per line with the same fields and no header. Same as
+    /// `input_format` in the config.
+    #[arg(long, value_name = "FORMAT")]
+    pub input_format: Option<InputFormat>,
+
+    /// Reject deposits, withdrawals and adjustments over this amount. Same as `max_amount` in the
+    /// config.
+    #[arg(long, value_name = "AMOUNT")]
+    pub max_amount: Option<Amount>,
+
+    /// Flag (but apply) deposits, withdrawals and adjustments of at least this amount. Same as
+    /// `suspicious_amount` in the config.
+    #[arg(long, value_name = "AMOUNT")]
+    pub suspicious_amount: Option<Amount>,
+
+    /// Only read the records of the clients listed in this file (ids separated by whitespace or
+    /// commas, `#` for comments), passing over everyone else's. Same as `include_clients` in the
+    /// config.
+    #[arg(long, value_name = "PATH")]
+    pub include_clients: Option<PathBuf>,
+
+    /// Pass over the records of the clients listed in this file, on top of the config's
+    /// `exclude_clients`.
+    #[arg(long, value_name = "PATH")]
+    pub exclude_clients: Option<PathBuf>,
+
+    /// Read the input's FROM column as our TO column, e.g. `--column customer_id=client`. Can be
+    /// given more than once, on top of the config's `[columns]`.
+    #[arg(long = "column", value_name = "FROM=TO", value_parser = column_alias)]
+    pub columns: Vec<(String, String)>,
+
+    /// Write a csv of every rejected record with its row number and reason.
+    #[arg(long, value_name = "PATH")]
+    pub errors: Option<PathBuf>,
+
+    /// Serve Prometheus metrics on `GET /metrics` at this address while the run goes on. Only for
+    /// the runs that don't end, `--follow` and `--source kafka`; `serve` has its own.
+    #[cfg(feature = "server")]
+    #[arg(long, value_name = "ADDR")]
+    pub metrics: Option<String>,
+
+    /// Read input files through a memory map rather than a read buffer, for very large files on
+    /// machines with RAM to spare. Files must not be rewritten while the run reads them.
+    #[cfg(feature = "mmap")]
+    #[arg(long)]
+    pub mmap: bool,
+
+    /// Show a progress bar on stderr: bytes and rows read, throughput and, for files, an ETA.
+    #[arg(long)]
+    pub progress: bool,
+
+    /// Write a summary of the run (counts per type and rejection reason, held funds, throughput)
+    /// here once it's done. `-` prints it to stderr.
+    #[arg(long, value_name = "PATH")]
+    pub stats: Option<String>,
+
+    /// Write a SHA-256 of the final ledger here once it's done, the same for any run over the same
+    /// input and config, to check one run against another. `-` prints it to stderr.
+    #[arg(long, value_name = "PATH")]
+    pub state_hash: Option<String>,
+
+    /// Append every applied change (tx, client, type, amount and the resulting balances) to this
+    /// csv, so balances can be replayed and checked independently.
+    #[arg(long, value_name = "PATH")]
+    pub audit: Option<PathBuf>,
+
+    /// Write `--audit` as csv or as Parquet. A Parquet log can't be appended to, so it's written
+    /// from scratch every run.
+    #[cfg(feature = "parquet")]
+    #[arg(long, value_enum, default_value_t = AuditFormat::Csv)]
+    pub audit_format: AuditFormat,
+
+    /// Append every movement of money to this csv as a double-entry journal line (the account
+    /// debited, the account credited and the amount), to reconcile against a general ledger.
+    #[arg(long, value_name = "PATH")]
+    pub ledger: Option<PathBuf>,
+
+    /// Start from the ledger saved here by a previous run. `resolve` writes the new ledger back to it,
+    /// `validate` and `report` only read it.
+    #[arg(long, value_name = "PATH")]
+    pub state: Option<PathBuf>,
+
+    /// Keep the ledger in a backend instead of a `--state` file: `json:PATH` is the same as
+    /// `--state PATH`, `sqlite:PATH` (with the sqlite feature) keeps it in a SQLite database.
+    #[arg(long, value_name = "SPEC", conflicts_with = "state")]
+    pub backend: Option<String>,
+
+    /// Encrypt the `--state` file and checkpoints with the AES-256 key in this file (64 hex
+    /// digits), and decrypt them with it. Without it, a key in CSV_TX_RESOLVER_STATE_KEY is used.
+    #[cfg(feature = "encrypt")]
+    #[arg(long, value_name = "PATH")]
+    pub state_key_file: Option<PathBuf>,
+
+    /// Start from the accounts of a csv report a previous run wrote, instead of a saved ledger.
+    /// Nothing from before it can be disputed, it has no transactions.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["state", "backend"])]
+    pub opening_balances: Option<PathBuf>,
+
+    /// Periodically save the whole run here, so an interrupted run can carry on with `--resume`.
+    /// Removed once the run completes.
+    #[arg(long, value_name = "PATH")]
+    pub checkpoint: Option<PathBuf>,
+
+    /// Rows between checkpoints.
+    #[arg(long, value_name = "ROWS", default_value_t = 1_000_000)]
+    pub checkpoint_every: u64,
+
+    /// Carry on from the `--checkpoint` file instead of starting over, if there is one. The inputs
+    /// have to be the same as the interrupted run's.
+    #[arg(long, requires = "checkpoint")]
+    pub resume: bool,
+
+    /// Keep at most this many stored transactions in memory, spilling the rest to a temporary
+    /// on-disk store. Each shard gets this limit.
+    #[cfg(feature = "spill")]
+    #[arg(long, value_name = "TXS")]
+    pub spill_after: Option<usize>,
+
+    /// Where the spilled transactions go, the system temp dir by default.
+    #[cfg(feature = "spill")]
+    #[arg(long, value_name = "DIR", requires = "spill_after")]
+    pub spill_dir: Option<PathBuf>,
+
+    /// TOML file with engine policies (dispute, locked account, parsing and rounding rules).
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Stop right after the first record with this tx id and report the balances as they were
+    /// then. A `resolve` that stops doesn't save the ledger back.
+    #[arg(long, value_name = "TX", conflicts_with = "until_row")]
+    pub until_tx: Option<u32>,
+
+    /// Like `--until-tx` but stop after this many records, counted over all the inputs without
+    /// their headers.
+    #[arg(long, value_name = "N")]
+    pub until_row: Option<u64>,
+}
+
+impl InputArgs {
+    /// Where `--until-tx`/`--until-row` stop the replay, if anywhere.
+    pub fn stop_at(&self) -> Option<StopAt> {
+        match (self.until_tx, self.until_row) {
+            (Some(tx), _) => Some(StopAt::Tx(tx)),
+            (None, Some(records)) => Some(StopAt::Records(records)),
+            (None, None) => None,
+        }
+    }
+
+    /// Whether `--audit` is written as Parquet, which only works for runs that get to the end.
+    pub fn parquet_audit(&self) -> bool {
+        #[cfg(feature = "parquet")]
+        return self.audit.is_some() && self.audit_format == AuditFormat::Parquet;
+        #[cfg(not(feature = "parquet"))]
+        false
+    }
+
+    /// Whether `--mmap` is on, never without the mmap feature.
+    pub fn mmap(&self) -> bool {
+        #[cfg(feature = "mmap")]
+        return self.mmap;
+        #[cfg(not(feature = "mmap"))]
+        false
+    }
+}
+
+#[cfg(feature = "parquet")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum AuditFormat {
+    Csv,
+    Parquet,
+}
+
+#[cfg(feature = "kafka")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Source {
+    Files,
+    Kafka,
+}
+
+/// Only used with `--source kafka`.
+#[cfg(feature = "kafka")]
+#[derive(Debug, Args)]
+pub struct KafkaArgs {
+    #[arg(long, value_name = "HOSTS", default_value = "localhost:9092")]
+    pub kafka_brokers: String,
+
+    #[arg(long, value_name = "TOPIC", default_value = "transactions")]
+    pub kafka_topic: String,
+
+    /// Consumer group, whose committed offsets a restart picks up from.
+    #[arg(long, value_name = "GROUP", default_value = "csv_tx_resolver")]
+    pub kafka_group: String,
+
+    /// Most messages applied before offsets are committed (and `--state` is saved).
+    #[arg(long, value_name = "MESSAGES", default_value_t = 1000)]
+    pub kafka_batch: usize,
+
+    /// How long to wait for a batch to fill up before committing what's there.
+    #[arg(long, value_name = "MS", default_value_t = 1000)]
+    pub kafka_batch_ms: u64,
+
+    /// Write the accounts here every `--snapshot-every` seconds, stdout if not given.
+    #[arg(long, value_name = "PATH")]
+    pub snapshot: Option<PathBuf>,
+
+    #[arg(long, value_name = "SECS", default_value_t = 60)]
+    pub snapshot_every: u64,
+}
+
+#[derive(Debug, Args)]
+pub struct ResolveArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    /// Output format: csv, json, jsonl, (with the parquet feature) parquet, (with the proto
+    /// feature) proto or (with the avro feature) avro, or avro:<schema id> for records framed for a
+    /// schema registry.
+    #[arg(long, default_value_t = OutputFormat::Csv)]
+    pub format: OutputFormat,
+
+    /// Write the csv report with decimal commas and semicolons between the fields, for European
+    /// spreadsheets.
+    #[arg(long)]
+    pub output_decimal_comma: bool,
+
+    /// Disguise the report for sharing: client ids mapped through a keyed hash and balances
+    /// rounded to --anonymize-bucket.
+    #[arg(long)]
+    pub anonymize: bool,
+
+    /// The key --anonymize maps client ids with, the same key mapping them the same way every
+    /// time. A random one for each run if not given.
+    #[arg(long, value_name = "KEY", requires = "anonymize")]
+    pub anonymize_key: Option<String>,
+
+    /// What --anonymize rounds balances to the nearest multiple of.
+    #[arg(long, value_name = "AMOUNT", default_value = "100")]
+    pub anonymize_bucket: Decimal,
+
+    /// Order accounts by client, total or available (ascending).
+    #[arg(long, value_name = "COLUMN", default_value = "client")]
+    pub sort_by: SortBy,
+
+    /// Write the report here instead of stdout, renamed into place once it's complete.
+    #[arg(short, long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+
+    /// Split the report over this many files next to `--output`, `accounts-000.csv` and on for
+    /// `--output accounts.csv`, with every client's rows in one of them.
+    #[arg(long, value_name = "FILES", requires = "output", value_parser = clap::value_parser!(u32).range(1..=65536))]
+    pub partition_output: Option<u32>,
+
+    /// Sign the report with this ed25519 key (PKCS#8 PEM), the detached signature going next to
+    /// every file written as `<file>.sig`. Without it, a key in CSV_TX_RESOLVER_SIGN_KEY is used.
+    #[cfg(feature = "sign")]
+    #[arg(long, value_name = "PATH", requires = "output")]
+    pub sign_key: Option<PathBuf>,
+
+    /// Which clients go in which `--partition-output` file: contiguous client ranges, or client id
+    /// modulo the number of files.
+    #[arg(
+        long,
+        value_name = "HOW",
+        default_value = "range",
+        requires = "partition_output"
+    )]
+    pub partition_by: PartitionBy,
+
+    /// Only report locked accounts.
+    #[arg(long)]
+    pub only_locked: bool,
+
+    /// Only report rows with at least this total, e.g. 0.0001 to leave out empty accounts.
+    #[arg(long, value_name = "AMOUNT")]
+    pub min_total: Option<Decimal>,
+
+    /// Only report these clients, comma separated.
+    #[arg(long, value_name = "IDS", value_delimiter = ',')]
+    pub clients: Option<Vec<u16>>,
+
+    /// Keep the input file open like `tail -f`, applying rows as they're appended, and write the
+    /// report again on SIGUSR1 or every `--report-every` seconds. Runs until it's interrupted.
+    #[arg(long)]
+    pub follow: bool,
+
+    /// How often the followed file is checked for new rows.
+    #[arg(long, value_name = "MS", default_value_t = 500, requires = "follow")]
+    pub follow_poll_ms: u64,
+
+    #[arg(long, value_name = "SECS", requires = "follow")]
+    pub report_every: Option<u64>,
+
+    /// On SIGHUP write a snapshot of the report to a new file in this directory, named for when
+    /// it was taken, and keep following.
+    #[arg(long, value_name = "DIR", requires = "follow")]
+    pub snapshot_dir: Option<PathBuf>,
+}
+
+impl ResolveArgs {
+    pub fn report_options(&self) -> ReportOptions {
+        ReportOptions {
+            format: self.format,
+            sort_by: self.sort_by,
+            only_locked: self.only_locked,
+            min_total: self.min_total,
+            clients: self.clients.clone(),
+            decimal_comma: self.output_decimal_comma,
+            anonymize: self.anonymize(),
+        }
+    }
+
+    fn anonymize(&self) -> Option<Anonymize> {
+        if !self.anonymize {
+            return None;
+        }
+        if self.anonymize_bucket <= Decimal::ZERO {
+            usage_error("--anonymize-bucket has to be more than zero");
+        }
+        let key = self
+            .anonymize_key
+            .clone()
+            .unwrap_or_else(|| RANDOM_KEY.clone());
+        Some(Anonymize {
+            key,
+            bucket: self.anonymize_bucket,
+        })
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ValidateArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ReportArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct HistoryArgs {
+    /// The client to follow through the input.
+    #[arg(long)]
+    pub client: u16,
+
+    #[command(flatten)]
+    pub input: InputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct DiffArgs {
+    /// The earlier report, e.g. yesterday's or one made under the old rules.
+    pub old: PathBuf,
+    /// The report to compare it with.
+    pub new: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct StatementArgs {
+    /// The client the statement is for.
+    #[arg(long)]
+    pub client: u16,
+
+    /// Write here instead of stdout, renamed into place once it's complete.
+    #[arg(short, long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+
+    /// The audit log written by `--audit`.
+    pub audit: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct MergeArgs {
+    /// Write the merged ledger here instead of stdout, renamed into place once it's complete.
+    #[arg(short, long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+
+    /// One of the ledgers.
+    pub a: PathBuf,
+    /// The other one.
+    pub b: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct SchemaArgs {
+    /// How many rows to look at for the kinds of values.
+    #[arg(long, default_value_t = 1000)]
+    pub rows: u64,
+
+    /// TOML file with engine policies, for its `delimiter` and `[columns]`.
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Like `resolve --delimiter`.
+    #[arg(long, value_name = "CHAR")]
+    pub delimiter: Option<Delimiter>,
+
+    /// Like `resolve --column`.
+    #[arg(long = "column", value_name = "FROM=TO", value_parser = column_alias)]
+    pub columns: Vec<(String, String)>,
+
+    /// The transactions file, optionally .gz/.zst compressed, `-` for stdin.
+    pub path: String,
+}
+
+#[derive(Debug, Args)]
+pub struct GenerateArgs {
+    /// How many client ids to spread the records over.
+    #[arg(long, default_value_t = 10_000, value_parser = clap::value_parser!(u32).range(1..=65536))]
+    pub clients: u32,
+
+    /// How many records to write.
+    #[arg(long, default_value_t = 1_000_000)]
+    pub rows: u64,
+
+    /// The share of records that dispute a recent deposit, up to 0.5. About as many again resolve
+    /// or charge one back.
+    #[arg(long, default_value_t = 0.02, value_parser = dispute_rate)]
+    pub dispute_rate: f64,
+
+    /// Another seed gives another file with the same shape.
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// Write here instead of stdout, renamed into place once it's complete.
+    #[arg(short, long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+}
+
+impl GenerateArgs {
+    /// `generate --rows ROWS` with every other option left at its default, to stdout.
+    pub fn with_rows(rows: u64) -> Self {
+        let defaults = GenerateOptions::default();
+        GenerateArgs {
+            clients: defaults.clients,
+            rows,
+            dispute_rate: defaults.dispute_rate,
+            seed: defaults.seed,
+            output: None,
+        }
+    }
+
+    pub fn options(&self) -> GenerateOptions {
+        GenerateOptions {
+            rows: self.rows,
+            clients: self.clients,
+            dispute_rate: self.dispute_rate,
+            seed: self.seed,
+        }
+    }
+}
+
+fn column_alias(given: &str) -> Result<(String, String), String> {
+    match given.split_once('=') {
+        Some((from, to)) if INPUT_COLUMNS.contains(&to.trim()) => {
+            Ok((from.trim().to_string(), to.trim().to_string()))
+        }
+        Some(_) => Err(format!(
+            "expected one of {} after =",
+            INPUT_COLUMNS.join(", ")
+        )),
+        None => Err("expected FROM=TO".to_string()),
+    }
+}
+
+fn dispute_rate(given: &str) -> Result<f64, String> {
+    match given.parse::<f64>() {
+        Ok(rate) if (0.0..=0.5).contains(&rate) => Ok(rate),
+        _ => Err("expected a number from 0 to 0.5".to_string()),
+    }
+}
+
+#[cfg(feature = "server")]
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    /// Address to listen on.
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+    pub listen: String,
+
+    /// On SIGHUP or `POST /admin/snapshot` write a snapshot of the accounts to a new file in this
+    /// directory, named for when it was taken.
+    #[arg(long, value_name = "DIR")]
+    pub snapshot_dir: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub service: ServiceArgs,
+}
+
+#[cfg(feature = "grpc")]
+#[derive(Debug, Args)]
+pub struct GrpcArgs {
+    /// Address to listen on.
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:50051")]
+    pub listen: std::net::SocketAddr,
+
+    #[command(flatten)]
+    pub service: ServiceArgs,
+}
+
+/// How a long-lived service's engine is set up.
+#[cfg(any(feature = "server", feature = "grpc"))]
+#[derive(Debug, Args)]
+pub struct ServiceArgs {
+    /// Log every rejected record to stderr.
+    #[arg(long)]
+    pub warn: bool,
+
+    /// Write a csv of every rejected record with its row number (within its request) and reason.
+    #[arg(long, value_name = "PATH")]
+    pub errors: Option<PathBuf>,
+
+    /// Append every applied change to this csv, like `resolve --audit`.
+    #[arg(long, value_name = "PATH")]
+    pub audit: Option<PathBuf>,
+
+    /// TOML file with engine policies, like `resolve --config`.
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Command {
+        let args = std::iter::once("csv_tx_resolver").chain(args.iter().copied());
+        Cli::try_parse_from(args).unwrap().into_command()
+    }
+
+    #[test]
+    fn flags_check_out() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn no_subcommand_is_resolve() {
+        let Command::Resolve(args) = parse(&["transactions.csv", "--strict"]) else {
+            panic!("not resolve");
+        };
+        assert_eq!(args.input.paths, ["transactions.csv"]);
+        assert!(args.input.strict);
+
+        let Command::Resolve(args) = parse(&["resolve", "-", "-o", "accounts.csv"]) else {
+            panic!("not resolve");
+        };
+        assert_eq!(args.input.paths, ["-"]);
+        assert_eq!(args.output, Some(PathBuf::from("accounts.csv")));
+
+        assert!(matches!(
+            parse(&["validate", "a.csv"]),
+            Command::Validate(_)
+        ));
+        assert!(matches!(parse(&["report"]), Command::Report(_)));
+    }
+
+    #[test]
+    fn every_v_logs_more() {
+        let level = |args: &[&str]| {
+            let args = std::iter::once("csv_tx_resolver").chain(args.iter().copied());
+            Cli::try_parse_from(args).unwrap().log_level()
+        };
+        assert_eq!(level(&["a.csv"]), Level::WARN);
+        assert_eq!(level(&["-v", "a.csv"]), Level::INFO);
+        // after a subcommand too
+        assert_eq!(level(&["validate", "-vv", "a.csv"]), Level::DEBUG);
+        assert_eq!(level(&["-vvvv", "a.csv"]), Level::TRACE);
+    }
+}
@@ -0,0 +1,228 @@
+use bytes::Bytes;
+use futures::StreamExt;
+use object_store::{
+    aws::AmazonS3Builder, buffered::BufWriter, gcp::GoogleCloudStorageBuilder, path::Path,
+    ObjectStore,
+};
+use std::{
+    io::{self, Read, Write},
+    sync::{mpsc, Arc, LazyLock},
+};
+use tokio::{io::AsyncWriteExt, runtime::Runtime, sync::mpsc as channel};
+
+// chunks read ahead of the parser, and what's written before it's handed to the upload
+const CHUNKS_AHEAD: usize = 8;
+const UPLOAD_CHUNK: usize = 8 << 20;
+
+// object stores only have an async API, their requests run here
+static RUNTIME: LazyLock<Runtime> = LazyLock::new(|| {
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .thread_name("object-store")
+        .enable_all()
+        .build()
+        .expect("the object store runtime starts")
+});
+
+/// The store and key `url` names: `s3://bucket/key` or `gs://bucket/key`, with the credentials and
+/// region the environment has for them (`AWS_ACCESS_KEY_ID`, `AWS_REGION`,
+/// `GOOGLE_APPLICATION_CREDENTIALS` and the like).
+fn store(url: &str) -> io::Result<(Arc<dyn ObjectStore>, Path)> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return Err(invalid(format!("{} isn't an object url", url)));
+    };
+    let key = match rest.split_once('/') {
+        Some((_, key)) if !key.is_empty() => key,
+        _ => return Err(invalid(format!("{} has no key after the bucket", url))),
+    };
+    let store: Arc<dyn ObjectStore> = match scheme {
+        "s3" => Arc::new(AmazonS3Builder::from_env().with_url(url).build()?),
+        "gs" => Arc::new(
+            GoogleCloudStorageBuilder::from_env()
+                .with_url(url)
+                .build()?,
+        ),
+        other => return Err(invalid(format!("unknown object store {}://", other))),
+    };
+    Ok((store, Path::from(key)))
+}
+
+/// The object at `url`, streamed as it downloads. A missing object is a `NotFound` error, like a
+/// missing file.
+pub(crate) fn open(url: &std::path::Path) -> io::Result<Box<dyn Read + Send>> {
+    let (store, path) = store(&url.to_string_lossy())?;
+    Ok(Box::new(ObjectReader::open(store, path)?))
+}
+
+/// An upload to `url` that only completes on `commit`.
+pub(crate) fn create(url: &std::path::Path) -> io::Result<Upload> {
+    let (store, path) = store(&url.to_string_lossy())?;
+    Ok(Upload::start(store, path))
+}
+
+struct ObjectReader {
+    chunks: channel::Receiver<io::Result<Bytes>>,
+    chunk: Bytes,
+}
+
+impl ObjectReader {
+    fn open(store: Arc<dyn ObjectStore>, path: Path) -> io::Result<Self> {
+        let (opened, found) = mpsc::sync_channel(1);
+        let (send, chunks) = channel::channel(CHUNKS_AHEAD);
+        RUNTIME.spawn(async move {
+            let mut stream = match store.get(&path).await {
+                Ok(got) => {
+                    let _ = opened.send(Ok(()));
+                    got.into_stream()
+                }
+                Err(err) => {
+                    let _ = opened.send(Err(io::Error::from(err)));
+                    return;
+                }
+            };
+            while let Some(chunk) = stream.next().await {
+                let failed = chunk.is_err();
+                // the reader is gone, or there's nothing after an error
+                if send.send(chunk.map_err(io::Error::from)).await.is_err() || failed {
+                    return;
+                }
+            }
+        });
+        found
+            .recv()
+            .map_err(|_| io::Error::other("the object store runtime stopped"))??;
+        Ok(ObjectReader {
+            chunks,
+            chunk: Bytes::new(),
+        })
+    }
+}
+
+impl Read for ObjectReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.chunks.blocking_recv() {
+                Some(chunk) => self.chunk = chunk?,
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len());
+        buf[..n].copy_from_slice(&self.chunk.split_to(n));
+        Ok(n)
+    }
+}
+
+enum Part {
+    Data(Vec<u8>),
+    Commit,
+}
+
+/// Writes to an object in chunks, uploading (in parts, for big ones) as it goes. The object only
+/// shows up once `commit` completes the upload, dropping it without one aborts it.
+pub(crate) struct Upload {
+    parts: Option<channel::Sender<Part>>,
+    done: mpsc::Receiver<io::Result<()>>,
+    buffer: Vec<u8>,
+}
+
+impl Upload {
+    fn start(store: Arc<dyn ObjectStore>, path: Path) -> Self {
+        let (parts, mut received) = channel::channel(2);
+        let (finished, done) = mpsc::sync_channel(1);
+        RUNTIME.spawn(async move {
+            let mut writer = BufWriter::new(store, path);
+            let mut result = Ok(());
+            loop {
+                match received.recv().await {
+                    Some(Part::Data(data)) if result.is_ok() => {
+                        result = writer.write_all(&data).await;
+                    }
+                    Some(Part::Data(_)) => {}
+                    Some(Part::Commit) if result.is_ok() => {
+                        let _ = finished.send(writer.shutdown().await);
+                        return;
+                    }
+                    Some(Part::Commit) => break,
+                    None => {
+                        let _ = writer.abort().await;
+                        return;
+                    }
+                }
+            }
+            let _ = writer.abort().await;
+            let _ = finished.send(result);
+        });
+        Upload {
+            parts: Some(parts),
+            done,
+            buffer: Vec::new(),
+        }
+    }
+
+    fn send(&mut self, part: Part) -> io::Result<()> {
+        let gone = || io::Error::other("the upload stopped");
+        self.parts
+            .as_ref()
+            .ok_or_else(gone)?
+            .blocking_send(part)
+            .map_err(|_| gone())
+    }
+
+    /// Uploads what's left and completes the upload.
+    pub fn commit(mut self) -> io::Result<()> {
+        let rest = std::mem::take(&mut self.buffer);
+        self.send(Part::Data(rest))?;
+        self.send(Part::Commit)?;
+        self.done
+            .recv()
+            .map_err(|_| io::Error::other("the upload stopped"))?
+    }
+}
+
+impl Write for Upload {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= UPLOAD_CHUNK {
+            let full = std::mem::take(&mut self.buffer);
+            self.send(Part::Data(full))?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::memory::InMemory;
+
+    #[test]
+    fn objects_only_show_up_once_committed() {
+        let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let path = Path::from("reports/accounts.csv");
+        let missing = ObjectReader::open(store.clone(), path.clone())
+            .err()
+            .unwrap();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+
+        let mut dropped = Upload::start(store.clone(), path.clone());
+        dropped.write_all(b"half a report").unwrap();
+        drop(dropped);
+        assert!(ObjectReader::open(store.clone(), path.clone()).is_err());
+
+        let report = "client,available,held,total,locked\n".repeat(300_000);
+        let mut upload = Upload::start(store.clone(), path.clone());
+        upload.write_all(report.as_bytes()).unwrap();
+        upload.commit().unwrap();
+        let mut read = String::new();
+        ObjectReader::open(store, path)
+            .unwrap()
+            .read_to_string(&mut read)
+            .unwrap();
+        assert_eq!(read, report);
+    }
+}
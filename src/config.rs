@@ -0,0 +1,531 @@
+use crate::accrual::Accrual;
+use crate::amount::Amount;
+use crate::error::Error;
+use crate::limits::WithdrawalLimits;
+use crate::transaction::TransactionType;
+use crate::utils::AmountNotation;
+use rust_decimal::RoundingStrategy;
+use serde::{Deserialize, Deserializer};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs,
+    path::Path,
+    str::FromStr,
+};
+
+/// Policy knobs for the engine, so different customers' rules can be expressed without code
+/// changes. Every field has a default matching the engine's built-in behaviour, so a config file
+/// only needs the knobs it changes:
+///
+/// ```toml
+/// allow_withdrawal_disputes = false
+/// negative_available = "clamp"
+/// max_dispute_window = 10000
+/// max_dispute_days = 120
+/// locked_accounts = "freeze-all"
+/// account_creation = "deposits"
+/// overdraft = "allow-to-limit"
+/// overdraft_limit = 50
+/// parsing = "strict"
+/// strict = true
+/// rounding = "half-even"
+/// admin_key = "change me"
+/// check_invariants = true
+/// fast_parse = true
+/// formatted_amounts = true
+/// decimal_comma = true
+/// delimiter = ";"
+/// input_format = "jsonl"
+/// max_amount = 1000000000
+/// suspicious_amount = 1000000
+/// include_clients = [1, 2, 3]
+/// exclude_clients = [2]
+///
+/// [withdrawal_limits]
+/// max_amount = 1000
+///
+/// [accrual]
+/// maintenance_fee = 2.5
+///
+/// [columns]
+/// txn_type = "type"
+/// customer_id = "client"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineConfig {
+    /// Whether a withdrawal can be disputed at all, or only deposits.
+    pub allow_withdrawal_disputes: bool,
+    /// What happens when a dispute (or a negative adjustment) would take available below zero, say
+    /// when the disputed deposit was already withdrawn. `allow_negative_available = true/false`
+    /// still works, as `allow` and `reject`.
+    #[serde(alias = "allow_negative_available")]
+    pub negative_available: NegativeAvailable,
+    /// How many records later a deposit/withdrawal can still be disputed, counted over the whole
+    /// run. `None` means any time.
+    pub max_dispute_window: Option<u64>,
+    /// How many days after a deposit/withdrawal it can still be disputed, going by the records'
+    /// `timestamp` column the way card networks set chargeback windows. A dispute or referenced tx
+    /// without a timestamp isn't held to it. `None` means any time.
+    pub max_dispute_days: Option<u32>,
+    pub locked_accounts: LockedAccountPolicy,
+    pub account_creation: AccountCreation,
+    pub overdraft: Overdraft,
+    /// How far below zero `Overdraft::AllowToLimit` lets a withdrawal take available.
+    pub overdraft_limit: Amount,
+    pub parsing: ParseMode,
+    /// Fail the run on the first record that's invalid input (see `Rejection::is_invalid_input`),
+    /// not just on rows that don't parse. Implies strict parsing.
+    pub strict: bool,
+    /// How amounts with more than four decimal places get brought down to four.
+    pub rounding: Rounding,
+    /// Secret that `unlock` and `adjustment` records must be signed with (see `admin_signature`).
+    /// Without one they're all rejected.
+    pub admin_key: Option<String>,
+    pub withdrawal_limits: WithdrawalLimits,
+    pub accrual: Accrual,
+    /// Check `Account::check_invariants` (and that only chargebacks lock accounts) after every
+    /// applied record, failing the run on the first one that breaks. Costs a lookup per record.
+    pub check_invariants: bool,
+    /// Parse csv rows by hand instead of through serde, for throughput on big files. Rows are read
+    /// the same either way, it only changes how fast.
+    pub fast_parse: bool,
+    /// Read amounts written for people, `1,234.50` or `$12.00`, taking out the currency symbol and
+    /// the thousands separators before parsing. Commas only count as separators between groups of
+    /// three digits, so `1,5` is still no amount.
+    pub formatted_amounts: bool,
+    /// Read amounts with a decimal comma, `12,3456`, the way European exports write them. With
+    /// `formatted_amounts` points are then the thousands separators, `1.234,50`.
+    pub decimal_comma: bool,
+    /// What separates the fields of the input, guessed from the header line unless it's set.
+    pub delimiter: Delimiter,
+    pub input_format: InputFormat,
+    /// Header names of the input to read as one of our columns, `txn_type = "type"` for an export
+    /// that calls `type` that. Names that aren't mapped are read as they are.
+    #[serde(deserialize_with = "column_aliases")]
+    pub columns: BTreeMap<String, String>,
+    /// Deposits, withdrawals and adjustments moving more than this are rejected as too large, on
+    /// top of `MAX_AMOUNT`. `None` means only `MAX_AMOUNT` holds.
+    pub max_amount: Option<Amount>,
+    /// Deposits, withdrawals and adjustments moving at least this much are applied but flagged, in
+    /// the stats and with `--warn` on stderr.
+    pub suspicious_amount: Option<Amount>,
+    /// Only these clients' records are read, everyone else's are passed over as if they weren't in
+    /// the input. `None` means every client.
+    pub include_clients: Option<BTreeSet<u16>>,
+    /// These clients' records are passed over, even if `include_clients` has them.
+    pub exclude_clients: BTreeSet<u16>,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig {
+            allow_withdrawal_disputes: true,
+            negative_available: NegativeAvailable::default(),
+            max_dispute_window: None,
+            max_dispute_days: None,
+            locked_accounts: LockedAccountPolicy::default(),
+            account_creation: AccountCreation::default(),
+            overdraft: Overdraft::default(),
+            overdraft_limit: Amount::ZERO,
+            parsing: ParseMode::default(),
+            strict: false,
+            rounding: Rounding::default(),
+            admin_key: None,
+            withdrawal_limits: WithdrawalLimits::default(),
+            accrual: Accrual::default(),
+            check_invariants: false,
+            fast_parse: false,
+            formatted_amounts: false,
+            decimal_comma: false,
+            delimiter: Delimiter::default(),
+            input_format: InputFormat::default(),
+            columns: BTreeMap::new(),
+            max_amount: None,
+            suspicious_amount: None,
+            include_clients: None,
+            exclude_clients: BTreeSet::new(),
+        }
+    }
+}
+
+impl EngineConfig {
+    pub(crate) fn amount_notation(&self) -> AmountNotation {
+        AmountNotation {
+            formatted: self.formatted_amounts,
+            decimal_comma: self.decimal_comma,
+        }
+    }
+
+    pub fn from_toml_str(toml: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(toml)
+    }
+
+    pub fn from_toml_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+        EngineConfig::from_toml_str(&contents)
+            .map_err(|err| Error::Config(format!("bad config {}: {}", path.display(), err)))
+    }
+
+    /// Whether `client`'s records are read, going by `include_clients` and `exclude_clients`.
+    pub fn takes_client(&self, client: u16) -> bool {
+        self.include_clients
+            .as_ref()
+            .is_none_or(|clients| clients.contains(&client))
+            && !self.exclude_clients.contains(&client)
+    }
+
+    /// The lowest a withdrawal can take available to, `None` if there's no floor.
+    pub(crate) fn withdrawal_floor(&self) -> Option<Amount> {
+        match self.overdraft {
+            Overdraft::None => Some(Amount::ZERO),
+            Overdraft::AllowToLimit => Some(-self.overdraft_limit.max(Amount::ZERO)),
+            Overdraft::AllowNegative => None,
+        }
+    }
+}
+
+/// The client ids in a `--include-clients`/`--exclude-clients` file: separated by whitespace or
+/// commas, with `#` starting a comment that runs to the end of the line.
+pub fn read_client_list(path: impl AsRef<Path>) -> Result<BTreeSet<u16>, Error> {
+    let path = path.as_ref();
+    parse_client_list(&fs::read_to_string(path)?)
+        .map_err(|err| Error::Config(format!("bad client list {}: {}", path.display(), err)))
+}
+
+fn parse_client_list(text: &str) -> Result<BTreeSet<u16>, String> {
+    text.lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .flat_map(|line| line.split(|c: char| c == ',' || c.is_whitespace()))
+        .filter(|id| !id.is_empty())
+        .map(|id| {
+            id.parse()
+                .map_err(|_| format!("{:?} isn't a client id", id))
+        })
+        .collect()
+}
+
+/// How the input's records are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InputFormat {
+    /// A header line and then a row per record.
+    #[default]
+    Csv,
+    /// A JSON object per line, keyed by column name, and no header.
+    Jsonl,
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(InputFormat::Csv),
+            "jsonl" => Ok(InputFormat::Jsonl),
+            other => Err(format!("unknown input format {:?}", other)),
+        }
+    }
+}
+
+/// The columns a transactions csv can have.
+pub const INPUT_COLUMNS: [&str; 7] = [
+    "type",
+    "client",
+    "tx",
+    "amount",
+    "currency",
+    "timestamp",
+    "signature",
+];
+
+// mapping onto a column that doesn't exist is a typo, better caught when the config is read
+fn column_aliases<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<String, String>, D::Error> {
+    let columns = BTreeMap::<String, String>::deserialize(deserializer)?;
+    match columns
+        .values()
+        .find(|column| !INPUT_COLUMNS.contains(&column.as_str()))
+    {
+        Some(column) => Err(serde::de::Error::custom(format!(
+            "unknown column {:?}, expected one of {}",
+            column,
+            INPUT_COLUMNS.join(", ")
+        ))),
+        None => Ok(columns),
+    }
+}
+
+/// What a locked (charged back) account still accepts. Admin records (`unlock`, `adjustment`) go
+/// through whatever the policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LockedAccountPolicy {
+    /// Deposits and withdrawals are refused but disputes, resolves and chargebacks still apply.
+    #[default]
+    DisputesOnly,
+    /// Only withdrawals are refused, money can still come in and disputes still apply.
+    FreezeDebitsOnly,
+    /// Nothing touches a locked account anymore.
+    #[serde(alias = "read-only")]
+    FreezeAll,
+}
+
+impl LockedAccountPolicy {
+    /// Whether a locked account still takes a record of this type.
+    pub fn allows(self, r_type: TransactionType) -> bool {
+        match r_type {
+            TransactionType::Deposit => self == LockedAccountPolicy::FreezeDebitsOnly,
+            TransactionType::Withdrawal => false,
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback => {
+                self != LockedAccountPolicy::FreezeAll
+            }
+            TransactionType::Unlock | TransactionType::Adjustment | TransactionType::Unknown => {
+                true
+            }
+        }
+    }
+}
+
+/// Which records open an account for a client that doesn't have one yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AccountCreation {
+    /// The client's first record, whatever it is and even if it's rejected, so a stray dispute
+    /// shows up as an all zero account in the report.
+    #[default]
+    AnyRecord,
+    /// Only a deposit (or signed adjustment) that applies. Other records for a client without an
+    /// account are rejected as they would be anyway, but leave no account behind.
+    Deposits,
+}
+
+impl AccountCreation {
+    /// Whether a record of this type for a client without an account leaves one behind.
+    pub fn creates(self, r_type: TransactionType, applied: bool) -> bool {
+        match self {
+            AccountCreation::AnyRecord => true,
+            AccountCreation::Deposits => {
+                applied
+                    && matches!(
+                        r_type,
+                        TransactionType::Deposit | TransactionType::Adjustment
+                    )
+            }
+        }
+    }
+}
+
+/// How far below zero a withdrawal can take available, for products that permit overdrafts.
+/// Disputes that overdraw go by `NegativeAvailable` instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Overdraft {
+    /// Up to everything that's available, not a cent more.
+    #[default]
+    None,
+    /// Down to minus `overdraft_limit`.
+    AllowToLimit,
+    /// As far as the withdrawal goes, only `withdrawal_limits` hold it back.
+    AllowNegative,
+}
+
+/// What a dispute that would take available below zero does. Every one that goes ahead anyway is
+/// counted in `Stats`, rejected ones like any other rejection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NegativeAvailable {
+    /// Hold the whole amount and let available go negative.
+    #[default]
+    Allow,
+    /// Hold only what's still available, so available stops at zero. The resolve or chargeback
+    /// later releases just that. Negative adjustments have nothing to clamp, they're rejected.
+    Clamp,
+    /// Reject the dispute for insufficient funds.
+    Reject,
+}
+
+impl<'de> Deserialize<'de> for NegativeAvailable {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // the old `allow_negative_available` bool or one of the names
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Given {
+            Allowed(bool),
+            Named(Named),
+        }
+        #[derive(Deserialize)]
+        #[serde(rename_all = "kebab-case")]
+        enum Named {
+            Allow,
+            Clamp,
+            Reject,
+        }
+        Ok(match Given::deserialize(deserializer)? {
+            Given::Allowed(true) | Given::Named(Named::Allow) => NegativeAvailable::Allow,
+            Given::Named(Named::Clamp) => NegativeAvailable::Clamp,
+            Given::Allowed(false) | Given::Named(Named::Reject) => NegativeAvailable::Reject,
+        })
+    }
+}
+
+/// The field separator of the input: one ASCII character (`tab` or `\t` for tabs), or `auto` to
+/// go with whichever of comma, tab, semicolon and pipe the header line has most of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Delimiter {
+    #[default]
+    Auto,
+    Byte(u8),
+}
+
+impl Delimiter {
+    /// The separator for input that comes without a header to guess from.
+    pub fn or_comma(self) -> u8 {
+        match self {
+            Delimiter::Auto => b',',
+            Delimiter::Byte(byte) => byte,
+        }
+    }
+}
+
+impl FromStr for Delimiter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Delimiter::Auto),
+            "tab" | "\\t" => Ok(Delimiter::Byte(b'\t')),
+            _ if s.len() == 1 && s.is_ascii() && s != "\"" && s != "\n" && s != "\r" => {
+                Ok(Delimiter::Byte(s.as_bytes()[0]))
+            }
+            other => Err(format!("invalid delimiter {:?}", other)),
+        }
+    }
+}
+
+impl fmt::Display for Delimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Delimiter::Auto => f.write_str("auto"),
+            Delimiter::Byte(b'\t') => f.write_str("tab"),
+            Delimiter::Byte(byte) => write!(f, "{}", *byte as char),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Delimiter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ParseMode {
+    /// Rows that don't parse are skipped and reported.
+    #[default]
+    Lenient,
+    /// The first row that doesn't parse fails the whole run.
+    Strict,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rounding {
+    /// Drop the extra digits, never round up.
+    #[default]
+    Truncate,
+    HalfUp,
+    HalfEven,
+}
+
+impl Rounding {
+    pub fn strategy(self) -> RoundingStrategy {
+        match self {
+            Rounding::Truncate => RoundingStrategy::ToZero,
+            Rounding::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            Rounding::HalfEven => RoundingStrategy::MidpointNearestEven,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_only_needs_the_knobs_it_changes() {
+        assert_eq!(
+            EngineConfig::from_toml_str("").unwrap(),
+            EngineConfig::default()
+        );
+
+        let config = EngineConfig::from_toml_str(
+            "allow_withdrawal_disputes = false\n\
+             locked_accounts = \"freeze-debits-only\"\n\
+             parsing = \"strict\"\n\
+             rounding = \"half-even\"\n",
+        )
+        .unwrap();
+        assert!(!config.allow_withdrawal_disputes);
+        assert_eq!(
+            config.locked_accounts,
+            LockedAccountPolicy::FreezeDebitsOnly
+        );
+        assert_eq!(config.parsing, ParseMode::Strict);
+        assert_eq!(config.rounding, Rounding::HalfEven);
+
+        let config = EngineConfig::from_toml_str("negative_available = \"clamp\"").unwrap();
+        assert_eq!(config.negative_available, NegativeAvailable::Clamp);
+        // the old yes/no
+        let config = EngineConfig::from_toml_str("allow_negative_available = false").unwrap();
+        assert_eq!(config.negative_available, NegativeAvailable::Reject);
+
+        // what freeze-all was called before there were more than two policies
+        let config = EngineConfig::from_toml_str("locked_accounts = \"read-only\"").unwrap();
+        assert_eq!(config.locked_accounts, LockedAccountPolicy::FreezeAll);
+
+        let config = EngineConfig::from_toml_str("delimiter = \"tab\"").unwrap();
+        assert_eq!(config.delimiter, Delimiter::Byte(b'\t'));
+        let config = EngineConfig::from_toml_str("delimiter = \";\"").unwrap();
+        assert_eq!(config.delimiter, Delimiter::Byte(b';'));
+        assert!(EngineConfig::from_toml_str("delimiter = \";;\"").is_err());
+
+        let config = EngineConfig::from_toml_str("[columns]\ncustomer_id = \"client\"").unwrap();
+        assert_eq!(config.columns["customer_id"], "client");
+        assert!(EngineConfig::from_toml_str("[columns]\ncustomer_id = \"customer\"").is_err());
+
+        let config =
+            EngineConfig::from_toml_str("include_clients = [1, 2]\nexclude_clients = [2]").unwrap();
+        assert!(config.takes_client(1));
+        assert!(!config.takes_client(2) && !config.takes_client(3));
+        assert_eq!(
+            parse_client_list("1, 2\n# unit b\n3 4 # four\n").unwrap(),
+            BTreeSet::from([1, 2, 3, 4])
+        );
+        assert!(parse_client_list("1\nalice\n").is_err());
+
+        assert!(EngineConfig::from_toml_str("rounding = \"sideways\"").is_err());
+        assert!(EngineConfig::from_toml_str("overdraft = true").is_err());
+        let config =
+            EngineConfig::from_toml_str("overdraft = \"allow-to-limit\"\noverdraft_limit = 50")
+                .unwrap();
+        assert_eq!(
+            config.withdrawal_floor(),
+            Some(Amount::from_units(-500_000))
+        );
+        assert_eq!(
+            EngineConfig::default().withdrawal_floor(),
+            Some(Amount::ZERO)
+        );
+
+        // amounts are read into `Amount`s, so they have to fit four places
+        let config = EngineConfig::from_toml_str("max_amount = \"1000.5\"").unwrap();
+        assert_eq!(config.max_amount, Some(Amount::from_units(10_005_000)));
+        assert!(EngineConfig::from_toml_str("max_amount = \"1000.00005\"").is_err());
+        assert!(EngineConfig::from_toml_str("[withdrawal_limits]\nmax_total = 1e30").is_err());
+    }
+}
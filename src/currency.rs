@@ -0,0 +1,84 @@
+use crate::utils::deserialize_text;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, str::FromStr};
+
+/// A currency code from the optional `currency` column, up to `Currency::MAX_LEN` ASCII letters or
+/// digits, kept uppercase. Records without one (or files without the column) are in the default
+/// currency, which is just the empty code.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Currency([u8; Currency::MAX_LEN]);
+
+impl Currency {
+    pub const MAX_LEN: usize = 8;
+
+    /// The currency of records that don't name one.
+    pub const DEFAULT: Currency = Currency([0; Currency::MAX_LEN]);
+
+    pub fn as_str(&self) -> &str {
+        let len = self
+            .0
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(Self::MAX_LEN);
+        // only ever filled with ascii
+        std::str::from_utf8(&self.0[..len]).unwrap_or_default()
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == Self::DEFAULT
+    }
+
+    // the fixed size form the spill store keeps on disk
+    #[cfg(feature = "spill")]
+    pub(crate) fn to_bytes(self) -> [u8; Currency::MAX_LEN] {
+        self.0
+    }
+
+    #[cfg(feature = "spill")]
+    pub(crate) fn from_bytes(bytes: [u8; Currency::MAX_LEN]) -> Self {
+        Currency(bytes)
+    }
+}
+
+impl FromStr for Currency {
+    type Err = String;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        let code = code.trim();
+        if code.len() > Self::MAX_LEN || !code.bytes().all(|byte| byte.is_ascii_alphanumeric()) {
+            return Err(format!("invalid currency {:?}", code));
+        }
+        let mut bytes = [0; Self::MAX_LEN];
+        bytes[..code.len()].copy_from_slice(code.to_ascii_uppercase().as_bytes());
+        Ok(Currency(bytes))
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.is_default() {
+            true => f.write_str("Currency(default)"),
+            false => write!(f, "Currency({})", self.as_str()),
+        }
+    }
+}
+
+impl Serialize for Currency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+// an empty field is the default currency, same as a missing column
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let given = deserialize_text(deserializer, str::parse)?;
+        Ok(given.unwrap_or_default())
+    }
+}
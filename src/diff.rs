@@ -0,0 +1,164 @@
+use crate::currency::Currency;
+use crate::error::Error;
+use crate::utils::{amount_deserializer, four_precision_serializer};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, io::Read, io::Write};
+
+/// How an account's row changed between two reports, besides its balances.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BalanceChange {
+    /// Only in the new report.
+    New,
+    /// Only in the old report.
+    Gone,
+    /// Locked now, wasn't before.
+    Locked,
+    Unlocked,
+}
+
+impl fmt::Display for BalanceChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BalanceChange::New => "new",
+            BalanceChange::Gone => "gone",
+            BalanceChange::Locked => "locked",
+            BalanceChange::Unlocked => "unlocked",
+        })
+    }
+}
+
+/// One (client, currency) row that differs between two reports: new minus old for every balance.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AccountDelta {
+    pub client: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<Currency>,
+    #[serde(serialize_with = "four_precision_serializer")]
+    pub available: Decimal,
+    #[serde(serialize_with = "four_precision_serializer")]
+    pub held: Decimal,
+    #[serde(serialize_with = "four_precision_serializer")]
+    pub total: Decimal,
+    pub change: Option<BalanceChange>,
+}
+
+// a row of a csv report as `write_report` writes it, with or without the currency column
+#[derive(Debug, Clone, Default, Deserialize)]
+pub(crate) struct ReportRow {
+    pub client: u16,
+    #[serde(default)]
+    pub currency: Currency,
+    #[serde(deserialize_with = "amount_deserializer")]
+    pub available: Option<Decimal>,
+    #[serde(deserialize_with = "amount_deserializer")]
+    pub held: Option<Decimal>,
+    #[serde(deserialize_with = "amount_deserializer")]
+    pub total: Option<Decimal>,
+    pub locked: bool,
+}
+
+pub(crate) fn read_report(
+    reader: impl Read,
+) -> Result<BTreeMap<(u16, Currency), ReportRow>, Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    reader
+        .deserialize()
+        .map(|row| {
+            let row: ReportRow = row?;
+            Ok(((row.client, row.currency), row))
+        })
+        .collect()
+}
+
+/// Compares two csv account reports, say yesterday's and today's or the same input under old and
+/// new rules, and returns the rows that differ, ordered by client and currency.
+pub fn diff_reports(old: impl Read, new: impl Read) -> Result<Vec<AccountDelta>, Error> {
+    let mut old = read_report(old)?;
+    let new = read_report(new)?;
+    let with_currency = old.keys().chain(new.keys()).any(|(_, c)| !c.is_default());
+
+    let mut deltas = Vec::new();
+    let delta = |key: (u16, Currency), old: &ReportRow, new: &ReportRow, change| {
+        let amount = |amount: Option<Decimal>| amount.unwrap_or_default();
+        AccountDelta {
+            client: key.0,
+            currency: with_currency.then_some(key.1),
+            available: amount(new.available) - amount(old.available),
+            held: amount(new.held) - amount(old.held),
+            total: amount(new.total) - amount(old.total),
+            change,
+        }
+    };
+    for (key, new) in &new {
+        let delta = match old.remove(key) {
+            None => delta(*key, &ReportRow::default(), new, Some(BalanceChange::New)),
+            Some(old) => {
+                let change = match (old.locked, new.locked) {
+                    (false, true) => Some(BalanceChange::Locked),
+                    (true, false) => Some(BalanceChange::Unlocked),
+                    _ => None,
+                };
+                delta(*key, &old, new, change)
+            }
+        };
+        let moved = !delta.available.is_zero() || !delta.held.is_zero() || !delta.total.is_zero();
+        if moved || delta.change.is_some() {
+            deltas.push(delta);
+        }
+    }
+    for (key, old) in old {
+        deltas.push(delta(
+            key,
+            &old,
+            &ReportRow::default(),
+            Some(BalanceChange::Gone),
+        ));
+    }
+    deltas.sort_by_key(|delta| (delta.client, delta.currency));
+    Ok(deltas)
+}
+
+/// Writes `diff_reports`' deltas as csv.
+pub fn write_deltas(deltas: &[AccountDelta], writer: impl Write) -> Result<(), Error> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for delta in deltas {
+        writer.serialize(delta)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_changed_accounts_show_up() {
+        let old = "client,available,held,total,locked\n\
+                   1,10.0000,0.0000,10.0000,false\n\
+                   2,5.0000,0.0000,5.0000,false\n\
+                   3,1.0000,0.0000,1.0000,false\n\
+                   4,0.0000,0.0000,0.0000,false\n";
+        let new = "client,available,held,total,locked\n\
+                   1,7.5000,2.5000,10.0000,false\n\
+                   2,0.0000,0.0000,0.0000,true\n\
+                   4,0.0000,0.0000,0.0000,false\n\
+                   5,3.0000,0.0000,3.0000,false\n";
+        let deltas = diff_reports(old.as_bytes(), new.as_bytes()).unwrap();
+
+        let mut out = Vec::new();
+        write_deltas(&deltas, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client,available,held,total,change\n\
+             1,-2.5000,2.5000,0.0000,\n\
+             2,-5.0000,0.0000,-5.0000,locked\n\
+             3,-1.0000,0.0000,-1.0000,gone\n\
+             5,3.0000,0.0000,3.0000,new\n"
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use crate::engine::Engine;
+use crate::error::Error;
+#[cfg(feature = "encrypt")]
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Nonce,
+};
+#[cfg(feature = "encrypt")]
+use std::fmt;
+
+// what an encrypted state file or checkpoint starts with, ahead of the nonce. Plain ones are json
+// and start with `{`
+const SEALED: &[u8] = b"ctr-aes256gcm1\n";
+#[cfg(feature = "encrypt")]
+const NONCE_LEN: usize = 12;
+
+/// The environment variable a state key is read from when there's no `--state-key-file`.
+#[cfg(feature = "encrypt")]
+pub const STATE_KEY_ENV: &str = "CSV_TX_RESOLVER_STATE_KEY";
+
+/// The AES-256 key state files and checkpoints are encrypted with, 32 bytes written as 64 hex
+/// digits (`openssl rand -hex 32`). It never shows up in `Debug` output.
+#[cfg(feature = "encrypt")]
+#[derive(Clone)]
+pub struct StateKey([u8; 32]);
+
+#[cfg(feature = "encrypt")]
+impl StateKey {
+    /// The key from its hex digits, surrounding whitespace ignored, so a key file with a newline
+    /// at the end will do.
+    pub fn from_hex(hex: &str) -> Result<Self, Error> {
+        let hex = hex.trim();
+        let invalid = || Error::Config("a state key is 64 hex digits".to_string());
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut key = [0u8; 32];
+        for (byte, pair) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+            *byte = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+        }
+        Ok(StateKey(key))
+    }
+}
+
+#[cfg(feature = "encrypt")]
+impl fmt::Debug for StateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("StateKey(..)")
+    }
+}
+
+impl Engine {
+    /// A saved state or checkpoint as it goes on disk: encrypted if the engine has a state key,
+    /// behind a marker and a fresh nonce, or as it is if not.
+    pub(crate) fn seal(&self, plain: Vec<u8>) -> Result<Vec<u8>, Error> {
+        #[cfg(feature = "encrypt")]
+        if let Some(key) = self.state_key() {
+            let cipher = Aes256Gcm::new(&key.0.into());
+            let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+            let sealed = cipher
+                .encrypt(&nonce, plain.as_slice())
+                .map_err(|_| Error::Internal("could not encrypt the state".to_string()))?;
+            return Ok([SEALED, nonce.as_slice(), &sealed].concat());
+        }
+        Ok(plain)
+    }
+
+    /// What `seal` wrote, back as it was. A file saved without a key reads as it is with one
+    /// too, so turning encryption on doesn't strand the ledger saved before.
+    pub(crate) fn unseal(&self, saved: Vec<u8>) -> Result<Vec<u8>, Error> {
+        let Some(sealed) = saved.strip_prefix(SEALED) else {
+            return Ok(saved);
+        };
+        #[cfg(feature = "encrypt")]
+        {
+            let Some(key) = self.state_key() else {
+                return Err(Error::Config(
+                    "it's encrypted and there's no state key to open it".to_string(),
+                ));
+            };
+            if sealed.len() < NONCE_LEN {
+                return Err(Error::Config("it's encrypted but cut short".to_string()));
+            }
+            let (nonce, sealed) = sealed.split_at(NONCE_LEN);
+            Aes256Gcm::new(&key.0.into())
+                .decrypt(Nonce::from_slice(nonce), sealed)
+                .map_err(|_| {
+                    Error::Config(
+                        "can't decrypt it, the key is wrong or it was changed".to_string(),
+                    )
+                })
+        }
+        #[cfg(not(feature = "encrypt"))]
+        {
+            let _ = sealed;
+            Err(Error::Config(
+                "it's encrypted, which needs the encrypt feature".to_string(),
+            ))
+        }
+    }
+}
+
+#[cfg(all(test, feature = "encrypt"))]
+mod tests {
+    use super::*;
+    use crate::process_reader;
+    use std::{fs, process};
+
+    #[test]
+    fn encrypted_state_needs_its_key() {
+        let key = StateKey::from_hex(&"2a".repeat(32)).unwrap();
+        let mut engine =
+            process_reader("type,client,tx,amount\ndeposit,1,1,2.5\n".as_bytes()).unwrap();
+        engine.set_state_key(key.clone());
+        let path = std::env::temp_dir().join(format!("encrypted-state-{}.json", process::id()));
+        engine.save_state_file(&path).unwrap();
+        let saved = fs::read(&path).unwrap();
+        assert!(saved.starts_with(SEALED));
+        assert!(!String::from_utf8_lossy(&saved).contains("2.5"));
+
+        let mut loaded = Engine::new();
+        loaded.set_state_key(key);
+        loaded.load_state_file(&path).unwrap();
+        assert_eq!(loaded.state_hash().unwrap(), engine.state_hash().unwrap());
+        assert!(Engine::new().load_state_file(&path).is_err());
+        let mut wrong = Engine::new();
+        wrong.set_state_key(StateKey::from_hex(&"2b".repeat(32)).unwrap());
+        assert!(wrong.load_state_file(&path).is_err());
+        fs::remove_file(&path).unwrap();
+        assert!(StateKey::from_hex("2a").is_err());
+    }
+}
@@ -0,0 +1,1954 @@
+use crate::account::{Account, AccountMap};
+use crate::admin;
+use crate::amount::Amount;
+use crate::audit::{AuditEntry, AuditLog};
+use crate::checkpoint::Checkpoints;
+use crate::config::{EngineConfig, NegativeAvailable, ParseMode};
+use crate::currency::Currency;
+use crate::error::Error;
+use crate::error_report::{ErrorReport, RejectedRecord};
+use crate::events::{EngineEvent, Subscribers};
+use crate::invariants::{self, InvariantViolation};
+use crate::journal::{Journal, JournalEntry};
+use crate::limits::Velocity;
+use crate::reader::{csv_line, RecordReader, Row};
+use crate::rejection::Rejection;
+use crate::sharded::shard_for;
+use crate::stats::Counters;
+use crate::store::LedgerStore;
+use crate::transaction::{DisputeStatus, Transaction, TransactionMap, TransactionType};
+use crate::utils::{round_to_precision, Instant};
+use csv::StringRecord;
+use std::{
+    collections::HashSet,
+    io::{self, Read, Write},
+    mem,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+pub(crate) const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+// how long side reports go at most without being flushed while rows are processed
+pub(crate) const FLUSH_EVERY: Duration = Duration::from_secs(1);
+
+/// Where a replay stops early, to see the balances as they were at that point of the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopAt {
+    /// Right after the first record with this tx id.
+    Tx(u32),
+    /// After this many records of the run, applied or rejected.
+    Records(u64),
+}
+
+#[derive(Debug, Default)]
+pub struct Engine {
+    config: EngineConfig,
+    store: Box<dyn LedgerStore>,
+    applied: u64,
+    skipped: u64,
+    warn: bool,
+    errors: Option<ErrorReport>,
+    checkpoints: Option<Checkpoints>,
+    audit: Option<Arc<Mutex<AuditLog>>>,
+    journal: Option<Arc<Mutex<Journal>>>,
+    subscribers: Subscribers,
+    counters: Counters,
+    withdrawals: Velocity,
+    stop_at: Option<StopAt>,
+    stopped: bool,
+    interrupt: Option<Arc<AtomicBool>>,
+    interrupted: bool,
+    // the first `check_invariants` failure, for `check_store` to hand out
+    broken: Option<InvariantViolation>,
+    // a two-pass run's index of the txs disputes refer to, only those get stored. The ids of the
+    // others are kept so duplicates are still caught
+    disputed: Option<Arc<HashSet<u32>>>,
+    passed_over: HashSet<u32>,
+    #[cfg(feature = "encrypt")]
+    state_key: Option<crate::encryption::StateKey>,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_config(config: EngineConfig) -> Self {
+        Engine {
+            config,
+            ..Engine::default()
+        }
+    }
+
+    /// An engine that keeps its ledger in `store` instead of in memory.
+    pub fn with_store(config: EngineConfig, store: impl LedgerStore + 'static) -> Self {
+        Engine {
+            config,
+            store: Box::new(store),
+            ..Engine::default()
+        }
+    }
+
+    pub fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    pub fn store(&self) -> &dyn LedgerStore {
+        self.store.as_ref()
+    }
+
+    /// A copy of every account, by client.
+    pub fn accounts(&self) -> AccountMap {
+        self.store
+            .accounts()
+            .map(|account| (account.client(), account))
+            .collect()
+    }
+
+    pub fn account(&self, client: u16) -> Option<Account> {
+        self.store.get_account(client)
+    }
+
+    /// Every stored transaction, in no particular order.
+    pub fn transactions(&self) -> Box<dyn Iterator<Item = Transaction> + '_> {
+        self.store.transactions()
+    }
+
+    pub fn transaction(&self, tx: u32) -> Option<Transaction> {
+        self.store.get_transaction(tx)
+    }
+
+    /// Number of records that were applied to the ledger.
+    pub fn applied(&self) -> u64 {
+        self.applied
+    }
+
+    /// Number of records that were rejected instead of applied.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// When on, every rejected record is logged to stderr (stdout stays clean for the csv).
+    pub fn set_warnings(&mut self, warn: bool) {
+        self.warn = warn;
+    }
+
+    /// Every rejected record (with its row number and reason) gets written to `writer` as csv.
+    pub fn set_error_report(&mut self, writer: impl Write + Send + 'static) {
+        self.errors = Some(ErrorReport::new(writer));
+    }
+
+    /// Every applied record gets written to `writer` along with the balances it left behind.
+    pub fn set_audit_log(&mut self, writer: impl Write + Send + 'static) {
+        self.audit = Some(Arc::new(Mutex::new(AuditLog::new(writer))));
+    }
+
+    /// Like `set_audit_log` but only for `client`: every change to its balances, in order, with
+    /// the balances after each one. Takes the place of the audit log.
+    pub fn set_history(&mut self, client: u16, writer: impl Write + Send + 'static) {
+        let log = AuditLog::new(writer).only_client(client);
+        self.audit = Some(Arc::new(Mutex::new(log)));
+    }
+
+    /// Calls `subscriber` with an event for every record as it's applied or rejected, and for
+    /// every fee or interest payment `accrue` books. It's called from inside the processing loop,
+    /// so a slow sink slows the run down: hand the events to a channel or a buffer instead.
+    pub fn subscribe(&mut self, subscriber: impl FnMut(&EngineEvent) + Send + 'static) {
+        self.subscribers.add(subscriber);
+    }
+
+    /// Like `set_audit_log` but for adding to a log earlier runs started.
+    pub fn continue_audit_log(&mut self, writer: impl Write + Send + 'static) {
+        self.audit = Some(Arc::new(Mutex::new(AuditLog::without_headers(writer))));
+    }
+
+    /// Like `set_audit_log` but written as Parquet. The file is only complete once
+    /// `finish_audit_log` is called.
+    #[cfg(feature = "parquet")]
+    pub fn set_audit_log_parquet(
+        &mut self,
+        writer: impl Write + Send + 'static,
+    ) -> Result<(), Error> {
+        self.audit = Some(Arc::new(Mutex::new(AuditLog::parquet(writer)?)));
+        Ok(())
+    }
+
+    /// Flushes the audit log for good, writing the footer of a Parquet one.
+    pub fn finish_audit_log(&mut self) -> Result<(), Error> {
+        if let Some(audit) = &self.audit {
+            audit.lock().expect("audit log poisoned").finish()?;
+        }
+        Ok(())
+    }
+
+    /// Every applied record that moves money gets written to `writer` as a double-entry journal
+    /// line, the account it debits and the one it credits.
+    pub fn set_journal(&mut self, writer: impl Write + Send + 'static) {
+        self.journal = Some(Arc::new(Mutex::new(Journal::new(writer))));
+    }
+
+    /// Like `set_journal` but for adding to a journal earlier runs started.
+    pub fn continue_journal(&mut self, writer: impl Write + Send + 'static) {
+        self.journal = Some(Arc::new(Mutex::new(Journal::without_headers(writer))));
+    }
+
+    /// Keeps at most `limit` stored transactions in memory and spills the rest to a temporary
+    /// on-disk store under `dir` (the system temp dir if `None`), so inputs with billions of
+    /// transactions fit a fixed memory budget. Each shard of a sharded run gets its own store with
+    /// the same limit.
+    #[cfg(feature = "spill")]
+    pub fn set_spill_limit(
+        &mut self,
+        limit: usize,
+        dir: Option<&std::path::Path>,
+    ) -> io::Result<()> {
+        use crate::store::{MemoryStore, TransactionStore};
+
+        let mut store = MemoryStore::with_transactions(TransactionStore::with_spill(limit, dir)?);
+        for account in self.store.accounts() {
+            store.put_account(account);
+        }
+        for transaction in self.store.transactions() {
+            store.put_transaction(transaction);
+        }
+        self.store = Box::new(store);
+        Ok(())
+    }
+
+    /// Every `every` rows the whole engine (ledger, counters and how far into the input it got) is
+    /// saved to `path`, so an interrupted run can pick up from there with `load_checkpoint`. Only
+    /// the single-threaded `process_reader`/`process_input` take checkpoints.
+    pub fn set_checkpoints(&mut self, path: impl Into<PathBuf>, every: u64) {
+        self.checkpoints = Some(Checkpoints::new(path.into(), every));
+    }
+
+    pub(crate) fn checkpoints(&self) -> Option<&Checkpoints> {
+        self.checkpoints.as_ref()
+    }
+
+    /// State files and checkpoints get encrypted with `key` when saved, and ones encrypted with it
+    /// can be loaded.
+    #[cfg(feature = "encrypt")]
+    pub fn set_state_key(&mut self, key: crate::encryption::StateKey) {
+        self.state_key = Some(key);
+    }
+
+    #[cfg(feature = "encrypt")]
+    pub(crate) fn state_key(&self) -> Option<&crate::encryption::StateKey> {
+        self.state_key.as_ref()
+    }
+
+    fn checkpoint_due(&self, rows: u64) -> bool {
+        self.checkpoints
+            .as_ref()
+            .is_some_and(|checkpoints| rows >= checkpoints.every())
+    }
+
+    /// Stops reading input at `at`, leaving the ledger as it was right there. Inputs after the one
+    /// it's reached in aren't read at all.
+    pub fn set_stop(&mut self, at: StopAt) {
+        self.stop_at = Some(at);
+    }
+
+    /// Whether the run got to its `set_stop` boundary.
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Stops the run after the record it's on once `flag` is set, by a signal handler say. Like at
+    /// a `set_stop` boundary the inputs after that aren't read, and a run with checkpoints saves
+    /// one right there, so resuming carries on with the next record.
+    pub fn set_interrupt(&mut self, flag: Arc<AtomicBool>) {
+        self.interrupt = Some(flag);
+    }
+
+    /// Whether the run stopped because its `set_interrupt` flag was set.
+    pub fn interrupted(&self) -> bool {
+        self.interrupted
+    }
+
+    pub(crate) fn check_interrupt(&mut self) -> bool {
+        self.interrupted |= self
+            .interrupt
+            .as_ref()
+            .is_some_and(|flag| flag.load(Ordering::Relaxed));
+        self.interrupted
+    }
+
+    // `records` is how many the run has seen so far, `tx` the last one's
+    pub(crate) fn past_stop(&mut self, records: u64, tx: Option<u32>) -> bool {
+        self.stopped |= match self.stop_at {
+            Some(StopAt::Tx(at)) => tx == Some(at),
+            Some(StopAt::Records(at)) => records >= at,
+            None => false,
+        };
+        self.check_interrupt() || self.stopped
+    }
+
+    /// Like `set_error_report` but for adding to a report an earlier run started.
+    pub fn continue_error_report(&mut self, writer: impl Write + Send + 'static) {
+        self.errors = Some(ErrorReport::without_headers(writer));
+    }
+
+    pub(crate) fn counters(&self) -> &Counters {
+        &self.counters
+    }
+
+    pub(crate) fn counters_mut(&mut self) -> &mut Counters {
+        &mut self.counters
+    }
+
+    pub(crate) fn has_error_report(&self) -> bool {
+        self.errors.is_some()
+    }
+
+    /// Reads csv records from any reader and applies them in order. Rows that don't parse are
+    /// skipped and reported like any other rejection, unless parsing is strict, in which case the
+    /// first one fails the run. Otherwise only I/O errors stop it.
+    pub fn process_reader(&mut self, reader: impl Read) -> Result<(), Error> {
+        // TODO: try tokio_codec::FramedRead
+        let mut custom_reader = RecordReader::new(reader, &self.config)?;
+        self.process_rows(&mut custom_reader, 0)
+    }
+
+    /// Applies every row `custom_reader` still has. `input` is which of the run's inputs this is,
+    /// only used to label checkpoints.
+    pub(crate) fn process_rows<R: Read>(
+        &mut self,
+        custom_reader: &mut RecordReader<R>,
+        input: usize,
+    ) -> Result<(), Error> {
+        // an interrupted run already has its checkpoint, the inputs after it aren't touched
+        if self.interrupted {
+            return Ok(());
+        }
+        let started = Instant::now();
+        let mut flushed = started;
+        let mut since_checkpoint = 0;
+        let mut last_tx = None;
+        loop {
+            if self.past_stop(self.applied + self.skipped, last_tx) {
+                break;
+            }
+            // between rows, so the checkpoint's offset is exactly where the next one starts
+            if self.checkpoint_due(since_checkpoint) {
+                self.checkpoint(custom_reader, input)?;
+                since_checkpoint = 0;
+            }
+            let Some(row) = custom_reader.next_row()? else {
+                break;
+            };
+            since_checkpoint += 1;
+            last_tx = row.parsed.as_ref().ok().map(|record| record.tx);
+            self.apply_row(row, custom_reader.headers(), custom_reader.raw())?;
+            // so a pipe reading --errors or --audit isn't left waiting for the end of the input
+            if flushed.elapsed() >= FLUSH_EVERY {
+                self.flush_reports()?;
+                flushed = Instant::now();
+            }
+        }
+        if self.interrupted && self.checkpoints.is_some() {
+            self.checkpoint(custom_reader, input)?;
+        }
+        self.counters.add_time(started.elapsed());
+        self.flush_reports()?;
+        Ok(())
+    }
+
+    /// Applies one row, parsed from `raw`, and reports it if it's rejected. Only errors if the run
+    /// has to stop.
+    pub(crate) fn apply_row(
+        &mut self,
+        row: Row,
+        headers: &StringRecord,
+        raw: &StringRecord,
+    ) -> Result<(), Error> {
+        if self.filters_out(&row, headers, raw) {
+            return Ok(());
+        }
+        let _span = tracing::debug_span!("record", row = row.row).entered();
+        let record = match row.parsed {
+            Ok(record) => record,
+            Err(err) => return self.reject_malformed(row.row, headers, raw, &err),
+        };
+        let result = self.apply(record);
+        self.check_store()?;
+        if let Err(rejection) = result {
+            self.report(&RejectedRecord::from_raw(row.row, headers, raw, rejection))?;
+            self.fail_if_strict(row.row, rejection, || csv_line(raw))?;
+        }
+        Ok(())
+    }
+
+    /// Whether `row` is of a client the config leaves out, counting it if it is. A row whose client
+    /// can't be read isn't, it's rejected like any other bad row.
+    pub(crate) fn filters_out(
+        &mut self,
+        row: &Row,
+        headers: &StringRecord,
+        raw: &StringRecord,
+    ) -> bool {
+        if self.config.include_clients.is_none() && self.config.exclude_clients.is_empty() {
+            return false;
+        }
+        let client = match &row.parsed {
+            Ok(record) => Some(record.client),
+            Err(_) => headers
+                .iter()
+                .position(|name| name == "client")
+                .and_then(|i| raw.get(i))
+                .and_then(|client| client.parse().ok()),
+        };
+        self.filters_out_client(client)
+    }
+
+    // `filters_out` for a record that's already parsed, or whose client isn't known
+    pub(crate) fn filters_out_client(&mut self, client: Option<u16>) -> bool {
+        let out = client.is_some_and(|client| !self.config.takes_client(client));
+        if out {
+            self.counters.filter_out();
+        }
+        out
+    }
+
+    /// Applies a single transaction to the ledger. Rejected records don't move any balances.
+    pub fn apply(&mut self, record: Transaction) -> Result<(), Rejection> {
+        let seq = self.applied + self.skipped;
+        self.apply_at(record, seq)
+    }
+
+    /// `apply` for a record that was `seq`th in the run. Shards only see some of the records, so
+    /// they're told.
+    pub(crate) fn apply_at(&mut self, record: Transaction, seq: u64) -> Result<(), Rejection> {
+        let mut record = self.prepare(record);
+        record.seq = seq;
+        self.counters.record(record.r_type);
+        let was_locked = self.config.check_invariants
+            && self
+                .store
+                .get_account(record.client)
+                .is_some_and(|account| account.locked());
+        let result = self.try_apply(record);
+        match result {
+            Ok(()) => {
+                if self.config.check_invariants {
+                    self.check_invariants(&record, was_locked);
+                }
+                self.applied += 1;
+                tracing::debug!(
+                    tx = record.tx,
+                    client = record.client,
+                    r_type = %record.r_type,
+                    amount = ?record.amount,
+                    "applied"
+                );
+                self.log_applied(&record);
+                if record.r_type.is_stored()
+                    && self
+                        .config
+                        .suspicious_amount
+                        .is_some_and(|at| record.amount().abs() >= at)
+                {
+                    self.report_suspicious(&record);
+                }
+            }
+            Err(rejection) => self.reject(&record, rejection),
+        }
+        result
+    }
+
+    // a dispute of `disputed` that available didn't cover went ahead anyway
+    fn report_overdrawn(&mut self, disputed: &Transaction) {
+        self.counters.overdrawn_dispute(disputed.held.is_some());
+        let action = match disputed.held {
+            Some(held) => format!("clamped to hold {}", held),
+            None => "took available below zero".to_string(),
+        };
+        tracing::info!(tx = disputed.tx, client = disputed.client, %action, "overdrawn dispute");
+        if self.warn {
+            eprintln!("dispute of tx {}: {}", disputed.tx, action);
+        }
+    }
+
+    // a record that went ahead with an amount over `suspicious_amount`
+    fn report_suspicious(&mut self, record: &Transaction) {
+        self.counters.suspicious_amount();
+        tracing::warn!(tx = record.tx, client = record.client, amount = %record.amount(), "suspicious amount");
+        if self.warn {
+            eprintln!(
+                "{} tx {}: suspicious amount {}",
+                record.r_type,
+                record.tx,
+                record.amount()
+            );
+        }
+    }
+
+    pub(crate) fn reject(&mut self, record: &Transaction, rejection: Rejection) {
+        let tx = record.tx;
+        self.skipped += 1;
+        self.counters.reject(rejection);
+        tracing::info!(tx, %rejection, "rejected");
+        if self.warn {
+            eprintln!("skipping tx {}: {}", tx, rejection);
+        }
+        self.subscribers.emit(&EngineEvent::Rejected {
+            client: Some(record.client),
+            tx: Some(tx),
+            reason: rejection,
+        });
+    }
+
+    /// Counts and reports a row that never made it to a `Transaction`. With strict parsing this is
+    /// where the run ends.
+    pub(crate) fn reject_malformed(
+        &mut self,
+        row: u64,
+        headers: &StringRecord,
+        raw: &StringRecord,
+        err: &csv::Error,
+    ) -> Result<(), Error> {
+        self.skipped += 1;
+        self.counters.reject(Rejection::Malformed);
+        tracing::info!(row, error = %err, "malformed record");
+        if self.warn {
+            eprintln!("skipping row {}: {}: {}", row, Rejection::Malformed, err);
+        }
+        self.subscribers.emit(&EngineEvent::Rejected {
+            client: None,
+            tx: None,
+            reason: Rejection::Malformed,
+        });
+        let reason = format!("{}: {}", Rejection::Malformed, err);
+        self.report(&RejectedRecord::from_raw(row, headers, raw, reason))?;
+        if self.config.parsing == ParseMode::Strict || self.config.strict {
+            self.flush_reports()?;
+            return Err(Error::Parse {
+                row,
+                message: err.to_string(),
+                line: csv_line(raw),
+            });
+        }
+        Ok(())
+    }
+
+    /// With `strict` on, a rejection that means the input is bad ends the run right here.
+    pub(crate) fn fail_if_strict(
+        &mut self,
+        row: u64,
+        rejection: Rejection,
+        line: impl FnOnce() -> String,
+    ) -> Result<(), Error> {
+        if !self.config.strict || !rejection.is_invalid_input() {
+            return Ok(());
+        }
+        self.flush_reports()?;
+        Err(Error::Policy {
+            row,
+            rejection,
+            line: line(),
+        })
+    }
+
+    /// Writes to the error report, if there is one. Counting already happened in `reject`.
+    pub(crate) fn report(&mut self, rejected: &RejectedRecord) -> csv::Result<()> {
+        match &mut self.errors {
+            Some(errors) => errors.write(rejected),
+            None => Ok(()),
+        }
+    }
+
+    /// Flushes the error report, the audit log and the journal, whichever are set.
+    pub(crate) fn flush_reports(&mut self) -> std::io::Result<()> {
+        if let Some(errors) = &mut self.errors {
+            errors.flush()?;
+        }
+        if let Some(audit) = &self.audit {
+            audit.lock().expect("audit log poisoned").flush()?;
+        }
+        if let Some(journal) = &self.journal {
+            journal.lock().expect("journal poisoned").flush()?;
+        }
+        Ok(())
+    }
+
+    fn log_applied(&self, record: &Transaction) {
+        if self.audit.is_none() && self.journal.is_none() && self.subscribers.is_empty() {
+            return;
+        }
+        // the referenced tx for the dispute family, the record itself otherwise
+        let stored = self.store.get_transaction(record.tx);
+        let (amount, currency) = stored.map_or((record.amount(), record.currency), |tx| {
+            (tx.disputed_amount(), tx.currency)
+        });
+        if let Some(event) = EngineEvent::applied(record, amount.to_decimal(), currency) {
+            self.subscribers.emit(&event);
+        }
+        if let Some(journal) = &self.journal {
+            let referenced = stored.as_ref().filter(|_| !record.r_type.is_stored());
+            if let Some(entry) = JournalEntry::new(record, referenced) {
+                journal.lock().expect("journal poisoned").write(&entry);
+            }
+        }
+        let Some(audit) = &self.audit else {
+            return;
+        };
+        let Some(account) = self.store.get_account(record.client) else {
+            return;
+        };
+        let entry = AuditEntry::new(record, amount, currency, &account);
+        audit.lock().expect("audit log poisoned").write(&entry);
+    }
+
+    /// Brings the amount the input gave down to four places per the configured rounding, before
+    /// anything checks it, and turns it into the `Amount` the rest of the engine works with. A
+    /// record that has one already is left as it is.
+    pub(crate) fn prepare(&self, mut record: Transaction) -> Transaction {
+        if let (None, Some(given)) = (record.amount, record.given) {
+            let rounded = round_to_precision(given, self.config.rounding.strategy());
+            record.amount = Some(Amount::saturating_from_decimal(rounded));
+        }
+        record
+    }
+
+    /// `Transaction::check` plus the caps and admin signatures from the config, everything that
+    /// doesn't need the ledger. The sharded reader runs it before it takes a tx id, so a rejected
+    /// record can be resent.
+    pub(crate) fn check_record(&self, record: &Transaction) -> Result<(), Rejection> {
+        record.check()?;
+        if record.r_type.is_stored()
+            && self
+                .config
+                .max_amount
+                .is_some_and(|max| record.amount().abs() > max)
+        {
+            return Err(Rejection::AmountTooLarge);
+        }
+        if record.r_type.is_admin() {
+            admin::authorize(self.config.admin_key.as_deref(), record)?;
+        }
+        Ok(())
+    }
+
+    fn try_apply(&mut self, record: Transaction) -> Result<(), Rejection> {
+        self.check_record(&record)?;
+        // a replayed file must not credit the same deposit twice
+        if record.r_type.is_stored()
+            && (self.store.contains_transaction(record.tx) || self.passed_over.contains(&record.tx))
+        {
+            return Err(Rejection::DuplicateTx);
+        }
+        // by default the account is there from its first record on, even if that one gets rejected
+        let existing = self.store.get_account(record.client);
+        let opened = existing.is_none();
+        let mut account = existing.unwrap_or_else(|| Account::new(record.client));
+        let result = self.apply_to(&record, &mut account);
+        if !opened
+            || self
+                .config
+                .account_creation
+                .creates(record.r_type, result.is_ok())
+        {
+            self.store.put_account(account);
+        }
+        result
+    }
+
+    fn apply_to(&mut self, record: &Transaction, account: &mut Account) -> Result<(), Rejection> {
+        match record.r_type {
+            TransactionType::Deposit | TransactionType::Withdrawal => {
+                let locked_out =
+                    account.locked() && !self.config.locked_accounts.allows(record.r_type);
+                let result = if locked_out {
+                    Err(Rejection::AccountLocked)
+                } else if record.r_type == TransactionType::Deposit {
+                    account.deposit(record.currency, record.amount())
+                } else {
+                    let limits = &self.config.withdrawal_limits;
+                    self.withdrawals
+                        .check(limits, record)
+                        .and_then(|()| {
+                            let floor = self.config.withdrawal_floor();
+                            account.withdraw(record.currency, record.amount(), floor)
+                        })
+                        .inspect(|()| {
+                            self.withdrawals.record(limits, record);
+                            if self.config.accrual.withdrawal_fee.is_some() {
+                                self.withdrawals.count(record);
+                            }
+                        })
+                };
+                self.save(record, result.is_ok());
+                result?;
+            }
+            TransactionType::Adjustment => {
+                // nothing to clamp an adjustment to, so clamping rejects it
+                if self.config.negative_available != NegativeAvailable::Allow
+                    && account.balance(record.currency).available < -record.amount()
+                {
+                    self.save(record, false);
+                    return Err(Rejection::InsufficientFunds);
+                }
+                let result = account.adjust(record.currency, record.amount());
+                self.save(record, result.is_ok());
+                result?;
+            }
+            TransactionType::Unlock => account.unlock()?,
+            // everything below is a dispute, resolve or chargeback
+            _ if account.locked() && !self.config.locked_accounts.allows(record.r_type) => {
+                return Err(Rejection::AccountLocked);
+            }
+            TransactionType::Dispute => {
+                let mut referenced_tx = referenced(self.store.as_ref(), record)?;
+                if !matches!(
+                    referenced_tx.r_type,
+                    TransactionType::Deposit | TransactionType::Withdrawal
+                ) {
+                    return Err(Rejection::NotDisputable);
+                }
+                if referenced_tx.r_type == TransactionType::Withdrawal
+                    && !self.config.allow_withdrawal_disputes
+                {
+                    return Err(Rejection::WithdrawalDispute);
+                }
+                if let Some(window) = self.config.max_dispute_window {
+                    if record.seq.saturating_sub(referenced_tx.seq) > window {
+                        return Err(Rejection::DisputeWindowClosed);
+                    }
+                }
+                if let (Some(days), Some(filed), Some(made)) = (
+                    self.config.max_dispute_days,
+                    record.timestamp,
+                    referenced_tx.timestamp,
+                ) {
+                    if filed.saturating_sub(made) > i64::from(days) * SECONDS_PER_DAY {
+                        return Err(Rejection::DisputeWindowClosed);
+                    }
+                }
+                // only a disputed deposit comes out of available
+                let available = account.balance(referenced_tx.currency).available;
+                let overdrawn = referenced_tx.r_type == TransactionType::Deposit
+                    && available < referenced_tx.amount();
+                if overdrawn {
+                    match self.config.negative_available {
+                        NegativeAvailable::Allow => {}
+                        NegativeAvailable::Clamp => {
+                            referenced_tx.held = Some(available.max(Amount::ZERO))
+                        }
+                        NegativeAvailable::Reject => return Err(Rejection::InsufficientFunds),
+                    }
+                }
+                referenced_tx.open_dispute()?;
+                account.dispute(
+                    referenced_tx.currency,
+                    referenced_tx.r_type,
+                    referenced_tx.tx,
+                    referenced_tx.disputed_amount(),
+                )?;
+                if overdrawn {
+                    self.report_overdrawn(&referenced_tx);
+                }
+                self.store.put_transaction(referenced_tx);
+            }
+            TransactionType::Resolve => {
+                let mut referenced_tx = referenced(self.store.as_ref(), record)?;
+                referenced_tx.close_dispute(DisputeStatus::Resolved)?;
+                account.resolve(
+                    referenced_tx.currency,
+                    referenced_tx.r_type,
+                    referenced_tx.tx,
+                )?;
+                self.store.put_transaction(referenced_tx);
+            }
+            TransactionType::Chargeback => {
+                let mut referenced_tx = referenced(self.store.as_ref(), record)?;
+                referenced_tx.close_dispute(DisputeStatus::ChargedBack)?;
+                account.chargeback(
+                    referenced_tx.currency,
+                    referenced_tx.r_type,
+                    referenced_tx.tx,
+                )?;
+                self.store.put_transaction(referenced_tx);
+            }
+            TransactionType::Unknown => unreachable!("unknown records are rejected by check()"),
+        }
+        Ok(())
+    }
+
+    // stores a deposit/withdrawal/adjustment for the disputes that might follow, unless a two-pass
+    // run knows none will
+    fn save(&mut self, record: &Transaction, applied: bool) {
+        match &self.disputed {
+            Some(disputed) if !disputed.contains(&record.tx) => {
+                self.passed_over.insert(record.tx);
+            }
+            _ => {
+                record.save(self.store.as_mut(), applied);
+            }
+        }
+    }
+
+    /// A fresh engine with the same settings but none of the state.
+    pub(crate) fn empty_like(&self) -> io::Result<Engine> {
+        Ok(Engine {
+            config: self.config.clone(),
+            warn: self.warn,
+            store: self.store.empty_like()?,
+            // shards share the one audit log, so per client it's still in order
+            audit: self.audit.clone(),
+            journal: self.journal.clone(),
+            subscribers: self.subscribers.clone(),
+            disputed: self.disputed.clone(),
+            ..Engine::default()
+        })
+    }
+
+    fn check_invariants(&mut self, record: &Transaction, was_locked: bool) {
+        let Some(account) = self.store.get_account(record.client) else {
+            return;
+        };
+        if let Err(violation) = invariants::check_after(&account, record, was_locked) {
+            self.broken.get_or_insert(violation);
+        }
+    }
+
+    /// Surfaces a failure of the on-disk transaction store, or a broken invariant, if there was
+    /// one.
+    pub(crate) fn check_store(&mut self) -> Result<(), Error> {
+        self.store.take_error()?;
+        match self.broken.take() {
+            Some(violation) => Err(Error::Internal(format!("invariant broken: {}", violation))),
+            None => Ok(()),
+        }
+    }
+
+    /// The tx id of every deposit, withdrawal and adjustment seen so far, stored or not.
+    pub(crate) fn tx_ids(&self) -> HashSet<u32> {
+        self.transactions()
+            .map(|tx| tx.tx)
+            .chain(self.passed_over.iter().copied())
+            .collect()
+    }
+
+    /// The two-pass index and the ids of the txs it left out, see `scan_disputes`.
+    pub(crate) fn dispute_index(&self) -> (Option<&HashSet<u32>>, &HashSet<u32>) {
+        (self.disputed.as_deref(), &self.passed_over)
+    }
+
+    /// What the withdrawal limits have counted so far.
+    pub(crate) fn velocity(&self) -> &Velocity {
+        &self.withdrawals
+    }
+
+    pub(crate) fn velocity_mut(&mut self) -> &mut Velocity {
+        &mut self.withdrawals
+    }
+
+    /// How many withdrawals `client` made in `currency` this run, if there's a fee for them.
+    pub(crate) fn withdrawals_made(&self, client: u16, currency: Currency) -> u64 {
+        self.withdrawals.made(client, currency)
+    }
+
+    /// Books `amount` on `client`'s balance in `currency` as an adjustment the engine made itself,
+    /// a fee or interest, into the audit log and journal like any applied record. It's not stored
+    /// as a transaction, so its tx id (0) can't clash with the input's.
+    pub(crate) fn book(
+        &mut self,
+        client: u16,
+        currency: Currency,
+        amount: Amount,
+    ) -> Result<(), Rejection> {
+        let mut account = self
+            .store
+            .get_account(client)
+            .unwrap_or_else(|| Account::new(client));
+        account.adjust(currency, amount)?;
+        let record = Transaction {
+            r_type: TransactionType::Adjustment,
+            client,
+            tx: 0,
+            amount: Some(amount),
+            given: None,
+            currency,
+            timestamp: None,
+            status: DisputeStatus::Normal,
+            seq: 0,
+            signature: None,
+            held: None,
+        };
+        if let Some(journal) = &self.journal {
+            if let Some(entry) = JournalEntry::new(&record, None) {
+                journal.lock().expect("journal poisoned").write(&entry);
+            }
+        }
+        if let Some(audit) = &self.audit {
+            let entry = AuditEntry::new(&record, amount, currency, &account);
+            audit.lock().expect("audit log poisoned").write(&entry);
+        }
+        if let Some(event) = EngineEvent::applied(&record, amount.to_decimal(), currency) {
+            self.subscribers.emit(&event);
+        }
+        self.store.put_account(account);
+        Ok(())
+    }
+
+    pub(crate) fn dispute_index_mut(&mut self) -> &mut HashSet<u32> {
+        Arc::make_mut(self.disputed.get_or_insert_default())
+    }
+
+    pub(crate) fn restore_dispute_index(
+        &mut self,
+        disputed: Option<HashSet<u32>>,
+        passed_over: HashSet<u32>,
+    ) {
+        self.disputed = disputed.map(Arc::new);
+        self.passed_over = passed_over;
+    }
+
+    pub(crate) fn restore_counts(&mut self, applied: u64, skipped: u64) {
+        self.applied = applied;
+        self.skipped = skipped;
+    }
+
+    /// Swaps in previously saved ledger state, e.g. yesterday's closing balances.
+    pub(crate) fn restore(&mut self, mut accounts: AccountMap, transactions: TransactionMap) {
+        self.store.clear();
+        // the disputes an account has open are the ones its disputed transactions say
+        for transaction in transactions.values() {
+            if let (DisputeStatus::Disputed, Some(account)) =
+                (transaction.status, accounts.get_mut(&transaction.client))
+            {
+                account.reopen_dispute(transaction.tx, transaction.disputed_amount());
+            }
+        }
+        for account in accounts.into_values() {
+            self.store.put_account(account);
+        }
+        for transaction in transactions.into_values() {
+            self.store.put_transaction(transaction);
+        }
+    }
+
+    /// Moves all state out into `shards` engines, partitioned by `shard_for(client)`.
+    pub(crate) fn split(&mut self, shards: usize) -> io::Result<Vec<Engine>> {
+        let mut parts = (0..shards)
+            .map(|_| self.empty_like())
+            .collect::<io::Result<Vec<Engine>>>()?;
+        // swapped for an empty store with the same settings, so merging back spills like before
+        let empty = self.store.empty_like()?;
+        let store = mem::replace(&mut self.store, empty);
+        for (part, withdrawals) in parts.iter_mut().zip(self.withdrawals.split(shards)) {
+            part.withdrawals = withdrawals;
+        }
+        for account in store.accounts() {
+            parts[shard_for(account.client(), shards)]
+                .store
+                .put_account(account);
+        }
+        for transaction in store.transactions() {
+            parts[shard_for(transaction.client, shards)]
+                .store
+                .put_transaction(transaction);
+        }
+        Ok(parts)
+    }
+
+    /// Moves all state out into an engine per client that has any.
+    #[cfg(feature = "actors")]
+    pub(crate) fn split_clients(&mut self) -> io::Result<std::collections::HashMap<u16, Engine>> {
+        use std::collections::{hash_map::Entry, HashMap};
+
+        let mut parts = HashMap::new();
+        let empty = self.store.empty_like()?;
+        let store = mem::replace(&mut self.store, empty);
+        fn part<'a>(
+            parts: &'a mut HashMap<u16, Engine>,
+            client: u16,
+            template: &Engine,
+        ) -> io::Result<&'a mut Engine> {
+            Ok(match parts.entry(client) {
+                Entry::Occupied(part) => part.into_mut(),
+                Entry::Vacant(entry) => entry.insert(template.empty_like()?),
+            })
+        }
+        for account in store.accounts() {
+            part(&mut parts, account.client(), self)?
+                .store
+                .put_account(account);
+        }
+        for transaction in store.transactions() {
+            part(&mut parts, transaction.client, self)?
+                .store
+                .put_transaction(transaction);
+        }
+        for (client, withdrawals) in self.withdrawals.split_clients() {
+            if let Some(part) = parts.get_mut(&client) {
+                part.withdrawals = withdrawals;
+            }
+        }
+        Ok(parts)
+    }
+
+    /// Folds another engine's state into this one. Used to stitch shards back together, so the two
+    /// engines are expected to hold disjoint sets of clients.
+    pub(crate) fn merge(&mut self, other: Engine) {
+        for account in other.store.accounts() {
+            self.store.put_account(account);
+        }
+        for transaction in other.store.transactions() {
+            self.store.put_transaction(transaction);
+        }
+        self.withdrawals.merge(other.withdrawals);
+        self.passed_over.extend(other.passed_over);
+        self.broken = self.broken.or(other.broken);
+        self.applied += other.applied;
+        self.skipped += other.skipped;
+        self.counters.merge(other.counters);
+    }
+}
+
+/// Looks up the tx a dispute/resolve/chargeback points at. It has to exist and belong to the same
+/// client, otherwise client 2 could dispute client 1's deposit and have it applied to their account.
+/// A record that names a currency has to name the referenced tx's, one without means whatever the
+/// tx was in.
+fn referenced(store: &dyn LedgerStore, record: &Transaction) -> Result<Transaction, Rejection> {
+    let referenced_tx = store
+        .get_transaction(record.tx)
+        .ok_or(Rejection::UnknownTx)?;
+    if referenced_tx.client != record.client {
+        return Err(Rejection::ClientMismatch);
+    }
+    if !record.currency.is_default() && record.currency != referenced_tx.currency {
+        return Err(Rejection::CurrencyMismatch);
+    }
+    Ok(referenced_tx)
+}
+
+/// Convenience entry point: build a fresh engine and feed it everything in `reader`.
+pub fn process_reader(reader: impl Read) -> Result<Engine, Error> {
+    let mut engine = Engine::new();
+    engine.process_reader(reader)?;
+    Ok(engine)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AccountCreation, LockedAccountPolicy};
+    use crate::{admin_signature, Balance, Currency, EngineBuilder};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use std::collections::HashMap;
+
+    #[test]
+    fn unknown_types_are_skipped() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 5.0\n\
+                     refund, 1, 2, 3.0\n\
+                     teleport, 2, 3, 1.0\n";
+        let engine = process_reader(input.as_bytes()).unwrap();
+
+        assert_eq!(engine.skipped(), 2);
+        assert_eq!(engine.accounts().len(), 1);
+        assert_eq!(engine.accounts()[&1].total(), dec!(5.0));
+    }
+
+    #[test]
+    fn duplicate_tx_ids_are_rejected() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 5.0\n\
+                     deposit, 1, 1, 5.0\n\
+                     withdrawal, 2, 1, 1.0\n\
+                     deposit, 2, 2, 1.0\n";
+        let engine = process_reader(input.as_bytes()).unwrap();
+
+        assert_eq!(engine.skipped(), 2);
+        assert_eq!(engine.accounts()[&1].total(), dec!(5.0));
+        assert_eq!(engine.accounts()[&2].total(), dec!(1.0));
+    }
+
+    #[test]
+    fn disputes_follow_the_state_machine() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 5.0\n\
+                     deposit, 1, 2, 3.0\n\
+                     resolve, 1, 1,\n\
+                     dispute, 1, 1,\n\
+                     dispute, 1, 1,\n\
+                     resolve, 1, 2,\n\
+                     resolve, 1, 1,\n\
+                     chargeback, 1, 1,\n\
+                     dispute, 1, 1,\n\
+                     dispute, 1, 99,\n";
+        let engine = process_reader(input.as_bytes()).unwrap();
+        let account = &engine.accounts()[&1];
+
+        // only the first dispute and the first resolve of tx 1 did anything
+        assert_eq!(engine.skipped(), 6);
+        assert_eq!(account.held(), dec!(0));
+        assert_eq!(account.available(), dec!(8.0));
+        assert!(!account.locked());
+        assert_eq!(
+            engine.transaction(1).unwrap().status,
+            DisputeStatus::Resolved
+        );
+        assert_eq!(engine.transaction(2).unwrap().status, DisputeStatus::Normal);
+    }
+
+    #[test]
+    fn disputes_must_come_from_the_owning_client() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 5.0\n\
+                     deposit, 2, 2, 1.0\n\
+                     dispute, 2, 1,\n\
+                     chargeback, 2, 1,\n";
+        let engine = process_reader(input.as_bytes()).unwrap();
+
+        assert_eq!(engine.skipped(), 2);
+        assert_eq!(engine.accounts()[&1].held(), dec!(0));
+        assert_eq!(engine.accounts()[&2].held(), dec!(0));
+        assert_eq!(engine.accounts()[&2].available(), dec!(1.0));
+        assert_eq!(engine.transaction(1).unwrap().status, DisputeStatus::Normal);
+    }
+
+    #[test]
+    fn disputed_withdrawals_credit_the_funds_back() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 10.0\n\
+                     withdrawal, 1, 2, 4.0\n\
+                     withdrawal, 1, 3, 40.0\n\
+                     dispute, 1, 3,\n\
+                     dispute, 1, 2,\n";
+        let engine = process_reader(input.as_bytes()).unwrap();
+        let account = &engine.accounts()[&1];
+
+        // the 40 withdrawal never happened so there is nothing to dispute
+        assert_eq!(engine.skipped(), 2);
+        assert_eq!(
+            engine.transaction(3).unwrap().status,
+            DisputeStatus::NotApplied
+        );
+        // the 4 comes back as held, available stays where it was
+        assert_eq!(account.available(), dec!(6.0));
+        assert_eq!(account.held(), dec!(4.0));
+        assert_eq!(account.total(), dec!(10.0));
+    }
+
+    #[test]
+    fn non_positive_amounts_are_rejected() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 10.0\n\
+                     deposit, 1, 2, -500.0\n\
+                     withdrawal, 1, 3, -5.0\n\
+                     deposit, 1, 4, 0\n\
+                     deposit, 1, 5,\n\
+                     deposit, 1, 2, 1.0\n";
+        let engine = process_reader(input.as_bytes()).unwrap();
+
+        assert_eq!(engine.skipped(), 4);
+        // a zero deposit and one without an amount are told apart
+        assert_eq!(
+            engine.stats().by_reason,
+            [(Rejection::InvalidAmount, 3), (Rejection::MissingAmount, 1)]
+        );
+        assert_eq!(engine.accounts()[&1].total(), dec!(11.0));
+        // the bad row didn't use up tx 2
+        assert_eq!(engine.transaction(2).unwrap().amount(), dec!(1.0));
+    }
+
+    #[test]
+    fn config_policies_are_enforced() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 10.00005\n\
+                     withdrawal, 1, 2, 4.0\n\
+                     dispute, 1, 2,\n\
+                     dispute, 1, 1,\n\
+                     chargeback, 1, 1,\n\
+                     deposit, 1, 3, 5.0\n\
+                     dispute, 1, 3,\n";
+        let config = EngineConfig {
+            allow_withdrawal_disputes: false,
+            locked_accounts: LockedAccountPolicy::FreezeAll,
+            rounding: crate::config::Rounding::HalfUp,
+            ..EngineConfig::default()
+        };
+        let mut engine = Engine::with_config(config);
+        engine.process_reader(input.as_bytes()).unwrap();
+        let account = &engine.accounts()[&1];
+
+        // withdrawal dispute refused, then the locked account refuses the deposit and the dispute
+        assert_eq!(engine.skipped(), 3);
+        assert_eq!(engine.transaction(1).unwrap().amount(), dec!(10.0001));
+        assert_eq!(account.total(), dec!(-4.0));
+        assert!(account.locked());
+    }
+
+    #[test]
+    fn locked_account_policies_decide_what_gets_through() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 10.0\n\
+                     deposit, 1, 2, 3.0\n\
+                     dispute, 1, 1,\n\
+                     chargeback, 1, 1,\n\
+                     deposit, 1, 3, 5.0\n\
+                     withdrawal, 1, 4, 1.0\n\
+                     dispute, 1, 2,\n";
+        let run = |policy| {
+            let mut engine = EngineBuilder::new().locked_account_policy(policy).build();
+            engine.process_reader(input.as_bytes()).unwrap();
+            let account = &engine.accounts()[&1];
+            assert!(account.locked());
+            (
+                engine.skipped(),
+                account.total().to_decimal(),
+                account.held().to_decimal(),
+            )
+        };
+
+        // the withdrawal is always refused
+        assert_eq!(
+            run(LockedAccountPolicy::DisputesOnly),
+            (2, dec!(3.0), dec!(3.0))
+        );
+        assert_eq!(
+            run(LockedAccountPolicy::FreezeDebitsOnly),
+            (1, dec!(8.0), dec!(3.0))
+        );
+        assert_eq!(
+            run(LockedAccountPolicy::FreezeAll),
+            (3, dec!(3.0), dec!(0.0))
+        );
+    }
+
+    #[test]
+    fn accounts_are_only_opened_by_deposits_when_asked() {
+        let input = "type, client, tx, amount\n\
+                     dispute, 2, 9,\n\
+                     withdrawal, 3, 10, 1.0\n\
+                     deposit, 4, 11, 0.0\n\
+                     deposit, 1, 1, 5.0\n\
+                     dispute, 1, 7,\n";
+        let run = |policy| {
+            let mut engine = EngineBuilder::new().account_creation(policy).build();
+            engine.process_reader(input.as_bytes()).unwrap();
+            assert_eq!(engine.skipped(), 4);
+            let mut clients = engine.accounts().into_keys().collect::<Vec<_>>();
+            clients.sort();
+            clients
+        };
+        // the zero deposit never gets as far as the account
+        assert_eq!(run(AccountCreation::AnyRecord), [1, 2, 3]);
+        assert_eq!(run(AccountCreation::Deposits), [1]);
+    }
+
+    #[test]
+    fn disputes_past_available_follow_the_policy() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 10.0\n\
+                     withdrawal, 1, 2, 8.0\n\
+                     dispute, 1, 1,\n";
+        let run = |policy| {
+            let mut engine = EngineBuilder::new().negative_available(policy).build();
+            engine.process_reader(input.as_bytes()).unwrap();
+            engine
+        };
+        let balances = |engine: &Engine| {
+            let account = &engine.accounts()[&1];
+            let [available, held, total] = [account.available(), account.held(), account.total()];
+            (
+                available.to_decimal(),
+                held.to_decimal(),
+                total.to_decimal(),
+            )
+        };
+
+        let allowed = run(NegativeAvailable::Allow);
+        assert_eq!(balances(&allowed), (dec!(-8.0), dec!(10.0), dec!(2.0)));
+        assert_eq!(allowed.stats().overdrawn_disputes, 1);
+
+        let rejected = run(NegativeAvailable::Reject);
+        assert_eq!(balances(&rejected), (dec!(2.0), dec!(0.0), dec!(2.0)));
+        assert_eq!(
+            rejected.stats().by_reason,
+            [(Rejection::InsufficientFunds, 1)]
+        );
+
+        // only what was left gets held, and the chargeback takes back just that, also after a
+        // restart
+        let clamped = run(NegativeAvailable::Clamp);
+        assert_eq!(balances(&clamped), (dec!(0.0), dec!(2.0), dec!(2.0)));
+        assert_eq!(clamped.stats().clamped_disputes, 1);
+        let mut state = Vec::new();
+        clamped.save_state(&mut state).unwrap();
+        let mut restarted = EngineBuilder::new()
+            .negative_available(NegativeAvailable::Clamp)
+            .build();
+        restarted.load_state(state.as_slice()).unwrap();
+        restarted
+            .process_reader("type, client, tx, amount\nchargeback, 1, 1,\n".as_bytes())
+            .unwrap();
+        assert_eq!(balances(&restarted), (dec!(0.0), dec!(0.0), dec!(0.0)));
+        assert!(restarted.accounts()[&1].locked());
+    }
+
+    #[test]
+    fn open_disputes_release_only_their_own_hold() {
+        let balances = |engine: &Engine| {
+            let account = &engine.accounts()[&1];
+            (
+                account.available().to_decimal(),
+                account.held().to_decimal(),
+                account.total().to_decimal(),
+            )
+        };
+        let two_disputes = "type, client, tx, amount\n\
+                            deposit, 1, 1, 5.0\n\
+                            deposit, 1, 2, 3.0\n\
+                            dispute, 1, 1,\n\
+                            dispute, 1, 2,\n";
+
+        // resolving tx 1 leaves the 3.0 tx 2 holds, and its chargeback takes just that
+        let resolved = format!("{}resolve, 1, 1,\n", two_disputes);
+        let mut engine = process_reader(resolved.as_bytes()).unwrap();
+        assert_eq!(balances(&engine), (dec!(5.0), dec!(3.0), dec!(8.0)));
+        assert_eq!(
+            engine.transaction(2).unwrap().status,
+            DisputeStatus::Disputed
+        );
+        engine
+            .process_reader("type, client, tx, amount\nchargeback, 1, 2,\n".as_bytes())
+            .unwrap();
+        assert_eq!(balances(&engine), (dec!(5.0), dec!(0.0), dec!(5.0)));
+        assert!(engine.accounts()[&1].locked());
+
+        // the other way round, charging back tx 1 takes its 5.0 and leaves tx 2's hold
+        let charged_back = format!("{}chargeback, 1, 1,\n", two_disputes);
+        let mut engine = process_reader(charged_back.as_bytes()).unwrap();
+        assert_eq!(balances(&engine), (dec!(0.0), dec!(3.0), dec!(3.0)));
+        engine
+            .process_reader("type, client, tx, amount\nresolve, 1, 2,\n".as_bytes())
+            .unwrap();
+        assert_eq!(balances(&engine), (dec!(3.0), dec!(0.0), dec!(3.0)));
+
+        // clamped holds differ from the amounts, tx 1 holds nothing and tx 2 the 2.0 left
+        let clamped = "type, client, tx, amount\n\
+                       deposit, 1, 1, 10.0\n\
+                       deposit, 1, 2, 4.0\n\
+                       withdrawal, 1, 3, 12.0\n\
+                       dispute, 1, 2,\n\
+                       dispute, 1, 1,\n\
+                       resolve, 1, 1,\n";
+        let mut engine = EngineBuilder::new()
+            .negative_available(NegativeAvailable::Clamp)
+            .build();
+        engine.process_reader(clamped.as_bytes()).unwrap();
+        assert_eq!(engine.skipped(), 0);
+        assert_eq!(balances(&engine), (dec!(0.0), dec!(2.0), dec!(2.0)));
+    }
+
+    #[test]
+    fn open_disputes_survive_a_saved_state() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 5.0\n\
+                     deposit, 1, 2, 3.0\n\
+                     dispute, 1, 1,\n\
+                     dispute, 1, 2,\n";
+        let engine = process_reader(input.as_bytes()).unwrap();
+        let mut state = Vec::new();
+        engine.save_state(&mut state).unwrap();
+
+        let mut restarted = Engine::new();
+        restarted.load_state(state.as_slice()).unwrap();
+        assert_eq!(restarted.accounts()[&1].held(), dec!(8.0));
+        restarted
+            .process_reader(
+                "type, client, tx, amount\n\
+                 resolve, 1, 2,\n\
+                 dispute, 1, 1,\n\
+                 chargeback, 1, 1,\n"
+                    .as_bytes(),
+            )
+            .unwrap();
+        // both were still open: tx 2 resolved, tx 1 couldn't be disputed again but charged back
+        assert_eq!(restarted.skipped(), 1);
+        assert_eq!(
+            restarted.stats().by_reason,
+            [(Rejection::AlreadyDisputed, 1)]
+        );
+        let account = &restarted.accounts()[&1];
+        assert_eq!(account.available(), dec!(3.0));
+        assert_eq!(account.held(), dec!(0));
+        assert_eq!(account.total(), dec!(3.0));
+        assert!(account.locked());
+    }
+
+    #[test]
+    fn strict_parsing_fails_on_the_first_bad_row() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 5.0\n\
+                     deposit, 1, 2, lots\n\
+                     deposit, 1, 3, 5.0\n";
+        let config = EngineConfig {
+            parsing: ParseMode::Strict,
+            ..EngineConfig::default()
+        };
+        let mut engine = Engine::with_config(config);
+        let err = engine.process_reader(input.as_bytes()).unwrap_err();
+
+        assert!(matches!(err, Error::Parse { row: 3, .. }));
+        assert!(err.to_string().starts_with("row 3:"));
+        assert_eq!(engine.accounts()[&1].total(), dec!(5.0));
+    }
+
+    #[test]
+    fn ragged_rows_without_an_amount_column_still_parse() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 5.0\n\
+                     dispute, 1, 1\n\
+                     deposit, 1, 2\n\
+                     withdrawal, 1\n\
+                     chargeback, 1, 1\n";
+        let mut engine = Engine::new();
+        engine.process_reader(input.as_bytes()).unwrap();
+
+        // disputes don't need the amount, deposits/withdrawals still do
+        assert_eq!(engine.skipped(), 2);
+        assert_eq!(
+            engine.stats().by_reason,
+            [(Rejection::Malformed, 1), (Rejection::MissingAmount, 1)]
+        );
+        assert_eq!(engine.accounts()[&1].total(), dec!(0));
+        assert!(engine.accounts()[&1].locked());
+    }
+
+    #[test]
+    fn strict_runs_fail_on_bad_references_but_not_on_overdrafts() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 5.0\n\
+                     withdrawal, 1, 2, 50.0\n\
+                     dispute, 1, 9,\n\
+                     deposit, 1, 3, 5.0\n";
+        let config = EngineConfig {
+            strict: true,
+            ..EngineConfig::default()
+        };
+        let mut engine = Engine::with_config(config);
+        let err = engine.process_reader(input.as_bytes()).unwrap_err();
+
+        assert!(matches!(
+            err,
+            Error::Policy {
+                row: 4,
+                rejection: Rejection::UnknownTx,
+                ..
+            }
+        ));
+        assert!(err.to_string().ends_with(": dispute,1,9,"));
+        assert_eq!(engine.skipped(), 2);
+        assert_eq!(engine.accounts()[&1].total(), dec!(5.0));
+    }
+
+    /// Write half of a shared buffer so the test can read back what the engine reported.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn rejected_records_are_reported_with_row_and_reason() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 5.0\n\
+                     deposit, 1, 2, lots\n\
+                     refund, 1, 3, 3.0\n\
+                     deposit, 1, 1, 5.0\n\
+                     dispute, 1, 42,\n\
+                     withdrawal, 1, 4, 50.0\n\
+                     dispute, 1\n\
+                     deposit, 1, 5, 1.0\n";
+        let buffer = SharedBuffer::default();
+        let mut engine = Engine::new();
+        engine.set_error_report(buffer.clone());
+        engine.process_reader(input.as_bytes()).unwrap();
+
+        assert_eq!(engine.skipped(), 6);
+        assert_eq!(engine.accounts()[&1].total(), dec!(6.0));
+
+        let report = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[0], "row,type,client,tx,amount,reason");
+        assert!(lines[1].starts_with("3,deposit,1,2,lots,\"malformed record:"));
+        assert_eq!(lines[2], "4,refund,1,3,3.0,unknown transaction type");
+        assert_eq!(lines[3], "5,deposit,1,1,5.0,duplicate transaction id");
+        assert_eq!(
+            lines[4],
+            "6,dispute,1,42,,referenced transaction does not exist"
+        );
+        assert_eq!(
+            lines[5],
+            "7,withdrawal,1,4,50.0,insufficient available funds"
+        );
+        assert!(lines[6].starts_with("8,dispute,1,,,\"malformed record:"));
+        assert_eq!(lines.len(), 7);
+    }
+
+    #[test]
+    fn audit_log_has_every_applied_change() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 5.0\n\
+                     withdrawal, 1, 2, 50.0\n\
+                     withdrawal, 1, 3, 1.5\n\
+                     dispute, 1, 1,\n\
+                     chargeback, 1, 1,\n";
+        let buffer = SharedBuffer::default();
+        let mut engine = Engine::new();
+        engine.set_audit_log(buffer.clone());
+        engine.process_reader(input.as_bytes()).unwrap();
+
+        let log = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "tx,client,type,amount,available,held,total,locked,currency",
+                "1,1,deposit,5.0000,5.0000,0.0000,5.0000,false,",
+                "3,1,withdrawal,1.5000,3.5000,0.0000,3.5000,false,",
+                "1,1,dispute,5.0000,-1.5000,5.0000,3.5000,false,",
+                "1,1,chargeback,5.0000,-1.5000,0.0000,-1.5000,true,",
+            ]
+        );
+    }
+
+    #[test]
+    fn journal_balances_and_adds_up_to_the_accounts() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 10.0\n\
+                     withdrawal, 1, 2, 4.0\n\
+                     withdrawal, 1, 3, 40.0\n\
+                     dispute, 1, 1,\n\
+                     resolve, 1, 1,\n\
+                     dispute, 1, 2,\n\
+                     chargeback, 1, 2,\n\
+                     deposit, 2, 4, 3.0\n\
+                     dispute, 2, 4,\n\
+                     chargeback, 2, 4,\n";
+        let buffer = SharedBuffer::default();
+        let mut engine = Engine::new();
+        engine.set_journal(buffer.clone());
+        engine.process_reader(input.as_bytes()).unwrap();
+
+        let journal = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = journal.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "tx,client,type,currency,debit,credit,amount",
+                "1,1,deposit,,clearing,available,10.0000",
+                "2,1,withdrawal,,available,clearing,4.0000",
+                "1,1,dispute,,available,held,10.0000",
+                "1,1,resolve,,held,available,10.0000",
+                "2,1,dispute,,clearing,held,4.0000",
+                "2,1,chargeback,,held,available,4.0000",
+                "4,2,deposit,,clearing,available,3.0000",
+                "4,2,dispute,,available,held,3.0000",
+                "4,2,chargeback,,held,clearing,3.0000",
+            ]
+        );
+
+        // credits less debits per client account come back to the balances
+        let mut net: HashMap<(&str, &str), Decimal> = HashMap::new();
+        for line in &lines[1..] {
+            let fields: Vec<&str> = line.split(',').collect();
+            let amount: Decimal = fields[6].parse().unwrap();
+            *net.entry((fields[1], fields[5])).or_default() += amount;
+            *net.entry((fields[1], fields[4])).or_default() -= amount;
+        }
+        for (client, account) in engine.accounts() {
+            let client = client.to_string();
+            let amount = |name| {
+                net.get(&(client.as_str(), name))
+                    .copied()
+                    .unwrap_or_default()
+            };
+            assert_eq!(amount("available"), account.available());
+            assert_eq!(amount("held"), account.held());
+        }
+    }
+
+    #[test]
+    fn odd_amounts_and_overflows_are_rejected() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, NaN\n\
+                     deposit, 1, 2, inf\n\
+                     deposit, 1, 3, -infinity\n\
+                     deposit, 1, 4, 1e400\n\
+                     deposit, 1, 5, 100000000000000000000000000000000\n\
+                     deposit, 1, 6, 10.0\n";
+        let mut engine = Engine::new();
+        engine.process_reader(input.as_bytes()).unwrap();
+        assert_eq!(engine.stats().by_reason, [(Rejection::Malformed, 5)]);
+        assert_eq!(engine.accounts()[&1].total(), dec!(10.0));
+
+        // a ledger from elsewhere can already be at the limit, the record is refused instead
+        let full = Balance {
+            available: Amount::MAX,
+            total: Amount::MAX,
+            ..Balance::default()
+        };
+        let accounts = AccountMap::from([(2, Account::with_balances(2, vec![full], false))]);
+        engine.restore(accounts, TransactionMap::new());
+        let input = "type, client, tx, amount\n\
+                     deposit, 2, 7, 1.0\n";
+        engine.process_reader(input.as_bytes()).unwrap();
+        assert!(engine
+            .stats()
+            .by_reason
+            .contains(&(Rejection::BalanceOverflow, 1)));
+        assert_eq!(engine.accounts()[&2].total(), Amount::MAX);
+    }
+
+    #[test]
+    fn disputes_cannot_release_more_than_is_held() {
+        // a ledger from elsewhere that holds less than its open dispute is for
+        let short = Balance {
+            available: Amount::from_units(10_000),
+            held: Amount::from_units(10_000),
+            total: Amount::from_units(20_000),
+            ..Balance::default()
+        };
+        let disputed = Transaction {
+            r_type: TransactionType::Deposit,
+            client: 1,
+            tx: 1,
+            amount: Some(Amount::from_units(50_000)),
+            given: None,
+            currency: Currency::DEFAULT,
+            timestamp: None,
+            status: DisputeStatus::Disputed,
+            seq: 0,
+            signature: None,
+            held: None,
+        };
+        let mut engine = Engine::new();
+        engine.restore(
+            AccountMap::from([(1, Account::with_balances(1, vec![short], false))]),
+            TransactionMap::from([(1, disputed)]),
+        );
+        let buffer = SharedBuffer::default();
+        engine.set_error_report(buffer.clone());
+        let input = "type, client, tx, amount
+                     chargeback, 1, 1,
+                     resolve, 1, 1,
+";
+        engine.process_reader(input.as_bytes()).unwrap();
+
+        assert_eq!(engine.stats().by_reason, [(Rejection::OverHeld, 2)]);
+        let account = &engine.accounts()[&1];
+        assert_eq!(
+            (account.held(), account.total(), account.locked()),
+            (short.held, short.total, false)
+        );
+        assert_eq!(
+            engine.transaction(1).unwrap().status,
+            DisputeStatus::Disputed
+        );
+        let report = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(report.contains("2,chargeback,1,1,,dispute is for more than the account holds"));
+    }
+
+    #[test]
+    fn history_only_has_the_one_client() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 5.0\n\
+                     deposit, 2, 2, 3.0\n\
+                     withdrawal, 2, 3, 1.0\n\
+                     dispute, 1, 1,\n\
+                     dispute, 2, 2,\n";
+        let buffer = SharedBuffer::default();
+        let mut engine = Engine::new();
+        engine.set_history(2, buffer.clone());
+        engine.process_reader(input.as_bytes()).unwrap();
+
+        let log = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "tx,client,type,amount,available,held,total,locked,currency",
+                "2,2,deposit,3.0000,3.0000,0.0000,3.0000,false,",
+                "3,2,withdrawal,1.0000,2.0000,0.0000,2.0000,false,",
+                "2,2,dispute,3.0000,-1.0000,3.0000,2.0000,false,",
+            ]
+        );
+    }
+
+    #[test]
+    fn admin_records_need_a_valid_signature() {
+        let key = "ops secret";
+        let sign = |r_type, tx, amount| {
+            let record = Transaction {
+                r_type,
+                client: 1,
+                tx,
+                amount: None,
+                given: amount,
+                status: DisputeStatus::Normal,
+                seq: 0,
+                currency: Currency::DEFAULT,
+                timestamp: None,
+                signature: None,
+                held: None,
+            };
+            admin_signature(key, &record)
+        };
+        let input = format!(
+            "type, client, tx, amount, signature\n\
+             deposit, 1, 1, 5.0,\n\
+             dispute, 1, 1,,\n\
+             chargeback, 1, 1,,\n\
+             unlock, 1, 2,,\n\
+             unlock, 1, 3,, {}\n\
+             adjustment, 1, 4, -1.25, {}\n\
+             adjustment, 1, 5, 100.0, {}\n\
+             dispute, 1, 4,,\n\
+             deposit, 1, 6, 2.0,\n",
+            sign(TransactionType::Unlock, 3, None),
+            sign(TransactionType::Adjustment, 4, Some(dec!(-1.25))),
+            // signed for a different amount
+            sign(TransactionType::Adjustment, 5, Some(dec!(1.0))),
+        );
+
+        let mut unsigned = Engine::new();
+        unsigned.process_reader(input.as_bytes()).unwrap();
+        assert!(unsigned.accounts()[&1].locked());
+
+        let config = EngineConfig {
+            admin_key: Some(key.to_string()),
+            ..EngineConfig::default()
+        };
+        let mut engine = Engine::with_config(config);
+        engine.process_reader(input.as_bytes()).unwrap();
+        let account = &engine.accounts()[&1];
+        assert!(!account.locked());
+        // 5.0 charged back, then -1.25 and +2.0
+        assert_eq!(account.total(), dec!(0.75));
+        // unsigned unlock, forged adjustment, dispute of an adjustment
+        assert_eq!(engine.skipped(), 3);
+    }
+
+    #[test]
+    fn balances_are_kept_per_currency() {
+        let input = "type, client, tx, amount, currency\n\
+                     deposit, 1, 1, 10.0,\n\
+                     deposit, 1, 2, 5.0, eur\n\
+                     withdrawal, 1, 3, 7.0, EUR\n\
+                     withdrawal, 1, 4, 7.0,\n\
+                     dispute, 1, 2,, USD\n\
+                     dispute, 1, 2,, EUR\n\
+                     deposit, 2, 5, 1.0,\n\
+                     deposit, 2, 6, 1.0, dollars!\n";
+        let engine = process_reader(input.as_bytes()).unwrap();
+        assert_eq!(
+            engine.stats().by_reason,
+            [
+                (Rejection::Malformed, 1),
+                (Rejection::CurrencyMismatch, 1),
+                (Rejection::InsufficientFunds, 1),
+            ]
+        );
+        assert_eq!(
+            engine.transaction(2).unwrap().status,
+            DisputeStatus::Disputed
+        );
+
+        let mut out = Vec::new();
+        engine.write_csv(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client,currency,available,held,total,locked\n\
+             1,,3.0000,0.0000,3.0000,false\n\
+             1,EUR,0.0000,5.0000,5.0000,false\n\
+             2,,1.0000,0.0000,1.0000,false\n"
+        );
+        let eur: Currency = "eur".parse().unwrap();
+        assert_eq!(engine.accounts()[&1].balance(eur).held, dec!(5.0));
+    }
+
+    #[test]
+    fn disputes_past_the_day_window_are_rejected() {
+        let input = "type, client, tx, amount, timestamp\n\
+                     deposit, 1, 1, 5.0, 2024-01-01\n\
+                     deposit, 1, 2, 5.0, 2024-01-01T23:00:00+02:00\n\
+                     deposit, 1, 3, 5.0, 1704067200\n\
+                     deposit, 1, 4, 5.0,\n\
+                     dispute, 1, 1,, 2024-01-31T00:00:00Z\n\
+                     dispute, 1, 2,, 2024-01-31T22:00:00Z\n\
+                     dispute, 1, 3,, 2024-02-01\n\
+                     dispute, 1, 4,, 2030-01-01\n\
+                     deposit, 1, 5, 5.0, yesterday\n";
+        let config = EngineConfig {
+            max_dispute_days: Some(30),
+            ..EngineConfig::default()
+        };
+        let mut engine = Engine::with_config(config);
+        engine.process_reader(input.as_bytes()).unwrap();
+
+        // tx 2 was made at 21:00 UTC, so 30 days and an hour before its dispute. tx 4 has no
+        // timestamp to go by
+        assert_eq!(
+            engine.stats().by_reason,
+            [
+                (Rejection::DisputeWindowClosed, 2),
+                (Rejection::Malformed, 1)
+            ]
+        );
+        assert_eq!(
+            engine.transaction(1).unwrap().status,
+            DisputeStatus::Disputed
+        );
+        assert_eq!(
+            engine.transaction(4).unwrap().status,
+            DisputeStatus::Disputed
+        );
+        assert_eq!(engine.transaction(3).unwrap().timestamp, Some(1704067200));
+    }
+
+    #[test]
+    fn replay_stops_at_the_boundary() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 10.0\n\
+                     withdrawal, 1, 2, 2.5\n\
+                     dispute, 1, 1,\n\
+                     chargeback, 1, 1,\n";
+        let mut engine = Engine::new();
+        engine.set_stop(StopAt::Tx(2));
+        engine.process_reader(input.as_bytes()).unwrap();
+        assert!(engine.stopped());
+        assert_eq!(engine.applied(), 2);
+        assert_eq!(engine.accounts()[&1].available(), dec!(7.5));
+
+        // the rest of the input, or a later one, isn't read either
+        engine.process_reader(input.as_bytes()).unwrap();
+        assert_eq!(engine.applied(), 2);
+
+        let mut engine = Engine::new();
+        engine.set_stop(StopAt::Records(3));
+        engine.process_reader_sharded(input.as_bytes(), 2).unwrap();
+        let account = &engine.accounts()[&1];
+        assert_eq!(account.held(), dec!(10.0));
+        assert!(!account.locked());
+    }
+
+    #[test]
+    fn interrupted_runs_stop_after_the_record_and_resume_from_there() {
+        // sets the flag once the parser comes back for more than its first buffer
+        struct Interrupting<'a>(&'a [u8], u32, Arc<AtomicBool>);
+        impl Read for Interrupting<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.1 += 1;
+                if self.1 > 1 {
+                    self.2.store(true, Ordering::Relaxed);
+                }
+                self.0.read(buf)
+            }
+        }
+        let mut input = Vec::new();
+        crate::generate_transactions(5_000, &mut input).unwrap();
+        let plain = process_reader(input.as_slice()).unwrap();
+
+        let path = std::env::temp_dir().join(format!("interrupt-test-{}", std::process::id()));
+        let flag = Arc::new(AtomicBool::new(false));
+        let mut interrupted = Engine::new();
+        interrupted.set_interrupt(flag.clone());
+        interrupted.set_checkpoints(&path, 1_000_000);
+        let reader = Interrupting(input.as_slice(), 0, flag);
+        interrupted.process_input(0, reader, None).unwrap();
+        assert!(interrupted.interrupted() && !interrupted.stopped());
+        let seen = interrupted.applied() + interrupted.skipped();
+        assert!(seen > 0 && seen < plain.applied() + plain.skipped());
+        // a later input isn't read, and the checkpoint stays where the run stopped
+        interrupted
+            .process_input(1, input.as_slice(), None)
+            .unwrap();
+        assert_eq!(interrupted.applied() + interrupted.skipped(), seen);
+
+        let mut resumed = Engine::new();
+        let progress = resumed.load_checkpoint(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        resumed
+            .process_input(0, input.as_slice(), progress.as_ref())
+            .unwrap();
+        let (mut ours, mut theirs) = (Vec::new(), Vec::new());
+        resumed.write_csv(&mut ours).unwrap();
+        plain.write_csv(&mut theirs).unwrap();
+        assert_eq!(ours, theirs);
+        assert_eq!(resumed.skipped(), plain.skipped());
+    }
+
+    #[test]
+    fn amounts_over_the_cap_are_rejected_and_big_ones_flagged() {
+        let config = EngineConfig {
+            max_amount: "1000".parse().ok(),
+            suspicious_amount: "100".parse().ok(),
+            ..EngineConfig::default()
+        };
+        let mut engine = Engine::with_config(config);
+        // 12 exported as 12e11
+        engine
+            .process_reader(
+                "type, client, tx, amount\n\
+                 deposit, 1, 1, 1200000000000\n\
+                 deposit, 1, 2, 500\n\
+                 deposit, 1, 3, 12\n\
+                 withdrawal, 1, 4, 1000.0001\n\
+                 withdrawal, 1, 5, 100\n"
+                    .as_bytes(),
+            )
+            .unwrap();
+        assert_eq!(engine.accounts()[&1].available(), dec!(412));
+        let stats = engine.stats();
+        assert_eq!(stats.by_reason, [(Rejection::AmountTooLarge, 2)]);
+        assert_eq!(stats.suspicious_amounts, 2);
+    }
+
+    #[test]
+    fn other_clients_records_are_passed_over() {
+        let config = EngineConfig {
+            include_clients: Some([1, 2].into()),
+            exclude_clients: [2].into(),
+            ..EngineConfig::default()
+        };
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 5.0\n\
+                     deposit, 2, 2, 3.0\n\
+                     deposit, 3, 3, lots\n\
+                     deposit, 1, 4, lots\n\
+                     dispute, 1, 2,\n";
+        let mut engine = Engine::with_config(config.clone());
+        engine.process_reader(input.as_bytes()).unwrap();
+        assert_eq!(engine.accounts().keys().collect::<Vec<_>>(), [&1]);
+        let stats = engine.stats();
+        assert_eq!((stats.records, stats.filtered_out), (3, 2));
+        // the dispute's tx went with client 2
+        assert_eq!(
+            stats.by_reason,
+            [(Rejection::Malformed, 1), (Rejection::UnknownTx, 1)]
+        );
+
+        let mut sharded = Engine::with_config(config);
+        sharded.process_reader_sharded(input.as_bytes(), 2).unwrap();
+        assert_eq!(sharded.stats().filtered_out, 2);
+        assert_eq!(sharded.applied(), engine.applied());
+    }
+}
@@ -0,0 +1,101 @@
+use crate::rejection::Rejection;
+use std::{fmt, io};
+
+/// Everything that can stop a run, by class, so callers can tell a broken input from a broken disk.
+/// Single rejected records never end up here, those are counted and reported (see `Rejection`).
+#[derive(Debug)]
+pub enum Error {
+    /// Reading input or writing output failed.
+    Io(io::Error),
+    /// The input isn't csv that can be read at all, e.g. a broken header.
+    Csv(csv::Error),
+    /// A row didn't parse and parsing is strict. `line` is the row as it was in the file, empty
+    /// when the reader couldn't say.
+    Parse {
+        row: u64,
+        message: String,
+        line: String,
+    },
+    /// A record was rejected and the run is strict about rejections.
+    Policy {
+        row: u64,
+        rejection: Rejection,
+        line: String,
+    },
+    /// The inputs given can't be used: a bad glob, no matching files, not the checkpoint's input, a
+    /// header without the columns records need.
+    Input(String),
+    /// A config, state or checkpoint file that can't be used.
+    Config(String),
+    /// A bug, e.g. a shard worker panicked.
+    Internal(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Csv(err) => write!(f, "{}", err),
+            Error::Parse { row, message, line } => write_row(f, *row, message, line),
+            Error::Policy {
+                row,
+                rejection,
+                line,
+            } => write_row(f, *row, rejection, line),
+            Error::Input(message) | Error::Config(message) | Error::Internal(message) => {
+                f.write_str(message)
+            }
+        }
+    }
+}
+
+fn write_row(
+    f: &mut fmt::Formatter<'_>,
+    row: u64,
+    reason: impl fmt::Display,
+    line: &str,
+) -> fmt::Result {
+    write!(f, "row {}: {}", row, reason)?;
+    if !line.is_empty() {
+        write!(f, ": {}", line)?;
+    }
+    Ok(())
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Csv(err) => Some(err),
+            Error::Policy { rejection, .. } => Some(rejection),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(err: csv::Error) -> Self {
+        // the csv crate wraps I/O errors too, those are still I/O errors
+        if !err.is_io_error() {
+            return Error::Csv(err);
+        }
+        match err.into_kind() {
+            csv::ErrorKind::Io(err) => Error::Io(err),
+            kind => Error::Internal(format!("{:?}", kind)),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Io(err.into())
+    }
+}
@@ -0,0 +1,105 @@
+use crate::transaction::Transaction;
+use csv::StringRecord;
+use serde::Serialize;
+use std::{fmt, io::Write};
+
+/// One line of the `--errors` report: where the record was, what it said, and why it was skipped.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RejectedRecord {
+    pub row: u64,
+    #[serde(rename = "type")]
+    pub r_type: String,
+    pub client: String,
+    pub tx: String,
+    pub amount: String,
+    pub reason: String,
+}
+
+impl RejectedRecord {
+    /// Built from the raw csv fields, so even rows that failed to parse show what was in the file.
+    pub fn from_raw(
+        row: u64,
+        headers: &StringRecord,
+        raw: &StringRecord,
+        reason: impl fmt::Display,
+    ) -> Self {
+        let field = |name: &str| {
+            headers
+                .iter()
+                .position(|header| header == name)
+                .and_then(|index| raw.get(index))
+                .unwrap_or("")
+                .to_string()
+        };
+        RejectedRecord {
+            row,
+            r_type: field("type"),
+            client: field("client"),
+            tx: field("tx"),
+            amount: field("amount"),
+            reason: reason.to_string(),
+        }
+    }
+
+    /// The record's fields as a csv line, for error messages.
+    pub fn line(&self) -> String {
+        [&self.r_type, &self.client, &self.tx, &self.amount]
+            .map(String::as_str)
+            .join(",")
+    }
+
+    pub fn from_transaction(row: u64, record: &Transaction, reason: impl fmt::Display) -> Self {
+        RejectedRecord {
+            row,
+            r_type: record.r_type.to_string(),
+            client: record.client.to_string(),
+            tx: record.tx.to_string(),
+            // as the input had it, like `from_raw`
+            amount: match (record.given, record.amount) {
+                (Some(given), _) => given.to_string(),
+                (None, amount) => amount.map_or(String::new(), |amount| amount.to_string()),
+            },
+            reason: reason.to_string(),
+        }
+    }
+}
+
+/// CSV sink for rejected records.
+pub struct ErrorReport {
+    writer: csv::Writer<Box<dyn Write + Send>>,
+}
+
+impl ErrorReport {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        let writer: Box<dyn Write + Send> = Box::new(writer);
+        ErrorReport {
+            writer: csv::WriterBuilder::new()
+                .has_headers(true)
+                .from_writer(writer),
+        }
+    }
+
+    /// For adding to an existing report (e.g. a resumed run), so the header row isn't repeated.
+    pub fn without_headers(writer: impl Write + Send + 'static) -> Self {
+        let writer: Box<dyn Write + Send> = Box::new(writer);
+        ErrorReport {
+            writer: csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(writer),
+        }
+    }
+
+    pub fn write(&mut self, record: &RejectedRecord) -> csv::Result<()> {
+        self.writer.serialize(record)
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl fmt::Debug for ErrorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErrorReport").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,222 @@
+use crate::currency::Currency;
+use crate::rejection::Rejection;
+use crate::transaction::{Transaction, TransactionType};
+use rust_decimal::Decimal;
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+/// What a record did to the ledger, handed to every `Engine::subscribe` callback as it happens.
+/// Amounts are the ones that moved: for the dispute family that's what the dispute holds, which
+/// an overdrawn dispute may have clamped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EngineEvent {
+    Deposited {
+        client: u16,
+        tx: u32,
+        amount: Decimal,
+        currency: Currency,
+    },
+    Withdrew {
+        client: u16,
+        tx: u32,
+        amount: Decimal,
+        currency: Currency,
+    },
+    DisputeOpened {
+        client: u16,
+        tx: u32,
+        amount: Decimal,
+        currency: Currency,
+    },
+    DisputeResolved {
+        client: u16,
+        tx: u32,
+        amount: Decimal,
+        currency: Currency,
+    },
+    /// The account is locked from here on.
+    ChargedBack {
+        client: u16,
+        tx: u32,
+        amount: Decimal,
+        currency: Currency,
+    },
+    /// An `adjustment` record, or fees and interest from `Engine::accrue` (with tx 0).
+    Adjusted {
+        client: u16,
+        tx: u32,
+        amount: Decimal,
+        currency: Currency,
+    },
+    Unlocked {
+        client: u16,
+        tx: u32,
+    },
+    /// A record that wasn't applied. A row that couldn't be read has no client or tx.
+    Rejected {
+        client: Option<u16>,
+        tx: Option<u32>,
+        reason: Rejection,
+    },
+}
+
+impl EngineEvent {
+    // `record` once it's applied, `amount` and `currency` being what it moved
+    pub(crate) fn applied(
+        record: &Transaction,
+        amount: Decimal,
+        currency: Currency,
+    ) -> Option<Self> {
+        let (client, tx) = (record.client, record.tx);
+        let event = match record.r_type {
+            TransactionType::Deposit => EngineEvent::Deposited {
+                client,
+                tx,
+                amount,
+                currency,
+            },
+            TransactionType::Withdrawal => EngineEvent::Withdrew {
+                client,
+                tx,
+                amount,
+                currency,
+            },
+            TransactionType::Dispute => EngineEvent::DisputeOpened {
+                client,
+                tx,
+                amount,
+                currency,
+            },
+            TransactionType::Resolve => EngineEvent::DisputeResolved {
+                client,
+                tx,
+                amount,
+                currency,
+            },
+            TransactionType::Chargeback => EngineEvent::ChargedBack {
+                client,
+                tx,
+                amount,
+                currency,
+            },
+            TransactionType::Adjustment => EngineEvent::Adjusted {
+                client,
+                tx,
+                amount,
+                currency,
+            },
+            TransactionType::Unlock => EngineEvent::Unlocked { client, tx },
+            TransactionType::Unknown => return None,
+        };
+        Some(event)
+    }
+}
+
+type Subscriber = Arc<Mutex<dyn FnMut(&EngineEvent) + Send>>;
+
+/// The engine's subscribers. Shards and actors share them like they share the audit log, so a
+/// subscriber sees every record of a client in order but clients interleaved.
+#[derive(Clone, Default)]
+pub(crate) struct Subscribers(Vec<Subscriber>);
+
+impl Subscribers {
+    pub fn add(&mut self, subscriber: impl FnMut(&EngineEvent) + Send + 'static) {
+        self.0.push(Arc::new(Mutex::new(subscriber)));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn emit(&self, event: &EngineEvent) {
+        for subscriber in &self.0 {
+            (subscriber.lock().expect("subscriber poisoned"))(event);
+        }
+    }
+}
+
+impl fmt::Debug for Subscribers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Subscribers").field(&self.0.len()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{open_input, Engine};
+    use rust_decimal_macros::dec;
+    use std::sync::mpsc;
+
+    #[test]
+    fn every_record_is_an_event() {
+        let input = "type,client,tx,amount\n\
+                     deposit,1,1,5.0\n\
+                     withdrawal,1,2,1.5\n\
+                     deposit,1,3,2.0\n\
+                     dispute,1,1,\n\
+                     withdrawal,1,4,100\n\
+                     resolve,1,1,\n\
+                     dispute,1,3,\n\
+                     chargeback,1,3,\n\
+                     not,a,row\n";
+        let (sender, events) = mpsc::channel();
+        let mut engine = Engine::new();
+        engine.subscribe(move |event| sender.send(*event).unwrap());
+        engine.process_reader(input.as_bytes()).unwrap();
+        drop(engine);
+
+        let event = |r_type, tx, amount| {
+            let record = Transaction {
+                r_type,
+                client: 1,
+                tx,
+                amount: None,
+                given: None,
+                currency: Currency::DEFAULT,
+                timestamp: None,
+                status: Default::default(),
+                seq: 0,
+                signature: None,
+                held: None,
+            };
+            EngineEvent::applied(&record, amount, Currency::DEFAULT).unwrap()
+        };
+        assert_eq!(
+            events.iter().collect::<Vec<_>>(),
+            [
+                event(TransactionType::Deposit, 1, dec!(5)),
+                event(TransactionType::Withdrawal, 2, dec!(1.5)),
+                event(TransactionType::Deposit, 3, dec!(2)),
+                event(TransactionType::Dispute, 1, dec!(5)),
+                EngineEvent::Rejected {
+                    client: Some(1),
+                    tx: Some(4),
+                    reason: Rejection::InsufficientFunds,
+                },
+                event(TransactionType::Resolve, 1, dec!(5)),
+                event(TransactionType::Dispute, 3, dec!(2)),
+                event(TransactionType::Chargeback, 3, dec!(2)),
+                EngineEvent::Rejected {
+                    client: None,
+                    tx: None,
+                    reason: Rejection::Malformed,
+                },
+            ]
+        );
+
+        // shards share the subscribers
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/data/transactions.csv");
+        let (sender, events) = mpsc::channel();
+        let mut engine = Engine::new();
+        engine.subscribe(move |event| sender.send(*event).unwrap());
+        engine
+            .process_reader_sharded(open_input(path).unwrap(), 4)
+            .unwrap();
+        let records = engine.applied() + engine.skipped();
+        drop(engine);
+        assert_eq!(events.iter().count() as u64, records);
+    }
+}
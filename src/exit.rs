@@ -0,0 +1,32 @@
+use csv_tx_resolver::Error;
+
+// process exit codes, so whatever runs the resolver can branch on how the run ended
+
+/// Everything applied, nothing rejected.
+pub const OK: i32 = 0;
+/// The run finished but some records were rejected (see `--errors`).
+pub const REJECTED: i32 = 1;
+// 2 is a bad command line, clap exits with that itself
+/// The input couldn't be parsed: unreadable csv, or a bad row with strict parsing.
+pub const PARSE: i32 = 3;
+/// A record was rejected and the run was strict about it.
+pub const POLICY: i32 = 4;
+/// Reading or writing a file failed.
+pub const IO: i32 = 5;
+/// A config, state or checkpoint file, or the inputs given, can't be used.
+pub const CONFIG: i32 = 6;
+/// A bug.
+pub const INTERNAL: i32 = 70;
+/// SIGINT or SIGTERM stopped the run, what it wrote is partial. 128 + SIGINT, like a shell reports
+/// a ^C.
+pub const INTERRUPTED: i32 = 130;
+
+pub fn for_error(err: &Error) -> i32 {
+    match err {
+        Error::Io(_) => IO,
+        Error::Csv(_) | Error::Parse { .. } => PARSE,
+        Error::Policy { .. } => POLICY,
+        Error::Input(_) | Error::Config(_) => CONFIG,
+        Error::Internal(_) => INTERNAL,
+    }
+}
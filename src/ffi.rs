@@ -0,0 +1,312 @@
+use crate::amount::Amount;
+use crate::config::EngineConfig;
+use crate::currency::Currency;
+use crate::engine::Engine;
+use crate::reader::{default_headers, Row};
+use csv::StringRecord;
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_int, CStr, CString},
+    ptr,
+};
+
+/// Room for an amount as text, sign, 28 digits and the point included, plus the NUL.
+pub const CTR_AMOUNT_LEN: usize = 32;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// An engine and the rows fed to it so far, for the error report.
+pub struct CtrEngine {
+    engine: Engine,
+    row: u64,
+}
+
+/// A client's balances in one currency. Amounts are NUL-terminated text with four places
+/// (`"1.5000"`), so nothing is lost to floating point on the other side.
+#[repr(C)]
+pub struct CtrAccount {
+    pub client: u16,
+    pub available: [c_char; CTR_AMOUNT_LEN],
+    pub held: [c_char; CTR_AMOUNT_LEN],
+    pub total: [c_char; CTR_AMOUNT_LEN],
+    pub locked: bool,
+}
+
+fn fail(message: impl ToString) -> c_int {
+    let message = message.to_string().replace('\0', " ");
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(message).ok());
+    -1
+}
+
+// a C string argument, `None` for NULL
+unsafe fn text<'a>(value: *const c_char, name: &str) -> Result<Option<&'a str>, String> {
+    if value.is_null() {
+        return Ok(None);
+    }
+    CStr::from_ptr(value)
+        .to_str()
+        .map(Some)
+        .map_err(|_| format!("{} isn't UTF-8", name))
+}
+
+fn copy_amount(to: &mut [c_char; CTR_AMOUNT_LEN], amount: Amount) {
+    let amount = amount.to_string();
+    // an i64 at four places is at most 21 characters
+    for (to, byte) in to.iter_mut().zip(amount.bytes().take(CTR_AMOUNT_LEN - 1)) {
+        *to = byte as c_char;
+    }
+    to[amount.len().min(CTR_AMOUNT_LEN - 1)] = 0;
+}
+
+/// The message of the last call on this thread that returned -1 (or NULL), NULL if there's none.
+/// It stays valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn ctr_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// A new engine with the settings of `config_toml` (the config file's contents), or the defaults
+/// if it's NULL. NULL if the config doesn't parse. Free it with `ctr_engine_free`.
+///
+/// # Safety
+///
+/// `config_toml` is NULL or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ctr_engine_new(config_toml: *const c_char) -> *mut CtrEngine {
+    let config = match text(config_toml, "config_toml") {
+        Ok(None) => EngineConfig::default(),
+        Ok(Some(toml)) => match EngineConfig::from_toml_str(toml) {
+            Ok(config) => config,
+            Err(err) => {
+                fail(err);
+                return ptr::null_mut();
+            }
+        },
+        Err(err) => {
+            fail(err);
+            return ptr::null_mut();
+        }
+    };
+    let engine = CtrEngine {
+        engine: Engine::with_config(config),
+        row: 0,
+    };
+    Box::into_raw(Box::new(engine))
+}
+
+/// Frees an engine from `ctr_engine_new`. NULL is ignored.
+///
+/// # Safety
+///
+/// `engine` came from `ctr_engine_new` and isn't used again.
+#[no_mangle]
+pub unsafe extern "C" fn ctr_engine_free(engine: *mut CtrEngine) {
+    if !engine.is_null() {
+        drop(Box::from_raw(engine));
+    }
+}
+
+/// Applies one record, its fields as they'd be in the csv: `type` like `"deposit"`, `amount` as
+/// decimal text and `currency` like `"EUR"`, either NULL when there's none. 0 if it was applied, 1
+/// if it was rejected (the engine's stats say why), -1 if it couldn't be read, or a strict config
+/// refused it.
+///
+/// # Safety
+///
+/// `engine` is a live engine, the strings are NULL or NUL-terminated.
+#[no_mangle]
+pub unsafe extern "C" fn ctr_engine_feed(
+    engine: *mut CtrEngine,
+    r_type: *const c_char,
+    client: u16,
+    tx: u32,
+    amount: *const c_char,
+    currency: *const c_char,
+) -> c_int {
+    let Some(handle) = engine.as_mut() else {
+        return fail("engine is NULL");
+    };
+    let fields = (
+        text(r_type, "type"),
+        text(amount, "amount"),
+        text(currency, "currency"),
+    );
+    let (r_type, amount, currency) = match fields {
+        (Ok(r_type), Ok(amount), Ok(currency)) => (r_type, amount, currency),
+        (Err(err), _, _) | (_, Err(err), _) | (_, _, Err(err)) => return fail(err),
+    };
+    // through the same deserializer as a csv row, so it takes and rejects what a file would
+    let headers = default_headers();
+    let raw = StringRecord::from(vec![
+        r_type.unwrap_or_default().to_string(),
+        client.to_string(),
+        tx.to_string(),
+        amount.unwrap_or_default().to_string(),
+        String::new(),
+        currency.unwrap_or_default().to_string(),
+        String::new(),
+    ]);
+    handle.row += 1;
+    let row = Row {
+        row: handle.row,
+        parsed: raw.deserialize(Some(&headers)),
+    };
+    let engine = &mut handle.engine;
+    let applied = engine.applied();
+    if let Err(err) = engine.apply_row(row, &headers, &raw) {
+        return fail(err);
+    }
+    match engine.applied() > applied {
+        true => 0,
+        false => 1,
+    }
+}
+
+/// Fills `out` with `client`'s balances in `currency` (NULL for the default one). 0 if the client
+/// has an account, 1 if not, -1 on bad arguments.
+///
+/// # Safety
+///
+/// `engine` is a live engine, `currency` is NULL or NUL-terminated and `out` points to a
+/// `CtrAccount`.
+#[no_mangle]
+pub unsafe extern "C" fn ctr_engine_get_account(
+    engine: *const CtrEngine,
+    client: u16,
+    currency: *const c_char,
+    out: *mut CtrAccount,
+) -> c_int {
+    let (Some(handle), Some(out)) = (engine.as_ref(), out.as_mut()) else {
+        return fail("engine or out is NULL");
+    };
+    let currency = match text(currency, "currency") {
+        Ok(None) => Currency::default(),
+        Ok(Some(code)) => match code.parse() {
+            Ok(currency) => currency,
+            Err(err) => return fail(err),
+        },
+        Err(err) => return fail(err),
+    };
+    let Some(account) = handle.engine.account(client) else {
+        return 1;
+    };
+    let balance = account.balance(currency);
+    out.client = client;
+    copy_amount(&mut out.available, balance.available);
+    copy_amount(&mut out.held, balance.held);
+    copy_amount(&mut out.total, balance.total);
+    out.locked = account.locked();
+    0
+}
+
+/// The report of every account as csv, like the batch tool prints it. NULL on failure. Free it
+/// with `ctr_string_free`.
+///
+/// # Safety
+///
+/// `engine` is a live engine.
+#[no_mangle]
+pub unsafe extern "C" fn ctr_engine_write_report(engine: *const CtrEngine) -> *mut c_char {
+    let Some(handle) = engine.as_ref() else {
+        fail("engine is NULL");
+        return ptr::null_mut();
+    };
+    let mut report = Vec::new();
+    if let Err(err) = handle.engine.write_csv(&mut report) {
+        fail(err);
+        return ptr::null_mut();
+    }
+    // csv quotes fields rather than putting NULs in them
+    match CString::new(report) {
+        Ok(report) => report.into_raw(),
+        Err(err) => {
+            fail(err);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Frees a string from `ctr_engine_write_report`. NULL is ignored.
+///
+/// # Safety
+///
+/// `text` came from this library and isn't used again.
+#[no_mangle]
+pub unsafe extern "C" fn ctr_string_free(text: *mut c_char) {
+    if !text.is_null() {
+        drop(CString::from_raw(text));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c(text: &str) -> CString {
+        CString::new(text).unwrap()
+    }
+
+    #[test]
+    fn c_api_feeds_and_reports() {
+        unsafe {
+            let engine = ctr_engine_new(ptr::null());
+            let (deposit, withdrawal) = (c("deposit"), c("withdrawal"));
+            let none = ptr::null();
+            assert_eq!(
+                ctr_engine_feed(engine, deposit.as_ptr(), 1, 1, c("2.5").as_ptr(), none),
+                0
+            );
+            assert_eq!(
+                ctr_engine_feed(engine, withdrawal.as_ptr(), 1, 2, c("9.0").as_ptr(), none),
+                1
+            );
+            assert_eq!(
+                ctr_engine_feed(engine, deposit.as_ptr(), 1, 3, c("x").as_ptr(), none),
+                1
+            );
+
+            let mut account = std::mem::zeroed::<CtrAccount>();
+            assert_eq!(ctr_engine_get_account(engine, 1, none, &mut account), 0);
+            let available = CStr::from_ptr(account.available.as_ptr());
+            assert_eq!(available.to_str().unwrap(), "2.5000");
+            assert!(!account.locked);
+            assert_eq!(ctr_engine_get_account(engine, 2, none, &mut account), 1);
+            assert_eq!(
+                ctr_engine_get_account(engine, 1, c("??").as_ptr(), &mut account),
+                -1
+            );
+            assert!(!ctr_last_error().is_null());
+
+            let report = ctr_engine_write_report(engine);
+            assert_eq!(
+                CStr::from_ptr(report).to_str().unwrap(),
+                "client,available,held,total,locked\n1,2.5000,0.0000,2.5000,false\n"
+            );
+            ctr_string_free(report);
+            ctr_engine_free(engine);
+
+            assert!(ctr_engine_new(c("strict = 3").as_ptr()).is_null());
+        }
+    }
+
+    #[test]
+    fn header_declares_every_function() {
+        let header = include_str!("../include/csv_tx_resolver.h");
+        let functions = include_str!("ffi.rs")
+            .lines()
+            .filter_map(|line| line.split(" extern \"C\" fn ").nth(1))
+            .filter_map(|rest| rest.split('(').next())
+            .collect::<Vec<_>>();
+        assert_eq!(functions.len(), 7);
+        for function in functions {
+            assert!(
+                header.contains(&format!(" {}(", function))
+                    || header.contains(&format!("*{}(", function)),
+                "{} isn't in the header, regenerate it with cbindgen",
+                function
+            );
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use crate::engine::Engine;
+use crate::error::Error;
+use crate::reader::RecordReader;
+use csv::StringRecord;
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+    thread,
+    time::Duration,
+};
+
+impl Engine {
+    /// Applies `path` like `tail -f`: once the end is reached it checks for appended rows every
+    /// `poll` and applies those too. Only complete lines are read, so a row that's still being
+    /// written is picked up once its newline is there (a quoted field spanning lines can still get
+    /// split).
+    ///
+    /// `idle` is called every time the engine has caught up with the file, and stops the following
+    /// by returning false. An interrupt (see `set_interrupt`) stops it too, after a last `idle`.
+    pub fn follow(
+        &mut self,
+        path: impl AsRef<Path>,
+        poll: Duration,
+        mut idle: impl FnMut(&Engine) -> Result<bool, Error>,
+    ) -> Result<(), Error> {
+        let path = path.as_ref();
+        let mut file = File::open(path)?;
+        let mut headers: Option<(StringRecord, u8)> = None;
+        let (mut offset, mut line) = (0, 0);
+        loop {
+            let end = complete_lines_end(&mut file, offset)?;
+            if end > offset {
+                file.seek(SeekFrom::Start(offset))?;
+                let chunk = (&mut file).take(end - offset);
+                let mut custom_reader = match &headers {
+                    None => RecordReader::new(chunk, self.config())?,
+                    Some((headers, delimiter)) => RecordReader::resume(
+                        chunk,
+                        self.config(),
+                        headers.clone(),
+                        *delimiter,
+                        offset,
+                        line,
+                    ),
+                };
+                self.process_rows(&mut custom_reader, 0)?;
+                (offset, line) = custom_reader.position();
+                headers.get_or_insert_with(|| {
+                    (custom_reader.headers().clone(), custom_reader.delimiter())
+                });
+                // so the error report is current whenever the engine has caught up
+                self.flush_reports()?;
+            }
+            let interrupted = self.check_interrupt();
+            if !idle(self)? || interrupted {
+                return Ok(());
+            }
+            thread::sleep(poll);
+        }
+    }
+}
+
+/// Where the last complete line after `offset` ends, `offset` itself if there's none yet.
+fn complete_lines_end(file: &mut File, offset: u64) -> Result<u64, Error> {
+    let len = file.metadata()?.len();
+    if len < offset {
+        return Err(Error::Input(
+            "the followed file got shorter, was it truncated or rotated?".to_string(),
+        ));
+    }
+    // scan back from the end, a block at a time
+    let mut end = len;
+    let mut block = [0; 8192];
+    while end > offset {
+        let start = end.saturating_sub(block.len() as u64).max(offset);
+        let block = &mut block[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(block)?;
+        if let Some(newline) = block.iter().rposition(|&byte| byte == b'\n') {
+            return Ok(start + newline as u64 + 1);
+        }
+        end = start;
+    }
+    Ok(offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use std::{fs, io::Write, process};
+
+    #[test]
+    fn appended_rows_are_picked_up() {
+        let path = std::env::temp_dir().join(format!("follow-test-{}.csv", process::id()));
+        fs::write(
+            &path,
+            "type, client, tx, amount\ndeposit, 1, 1, 5.0\ndeposit, 1,",
+        )
+        .unwrap();
+
+        let mut engine = Engine::new();
+        let mut rounds = 0;
+        engine
+            .follow(&path, Duration::ZERO, |engine| {
+                rounds += 1;
+                match rounds {
+                    // the half written row waits for its newline
+                    1 => {
+                        assert_eq!(engine.applied(), 1);
+                        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+                        file.write_all(b" 2, 1.5\nwithdrawal, 1, 3, 2.0\n").unwrap();
+                        Ok(true)
+                    }
+                    _ => Ok(false),
+                }
+            })
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(engine.applied(), 3);
+        assert_eq!(engine.skipped(), 0);
+        assert_eq!(engine.accounts()[&1].total(), dec!(4.5));
+    }
+}
@@ -0,0 +1,68 @@
+use crate::account::AccountRow;
+use crate::builder::EngineBuilder;
+use crate::error::Error;
+use crate::output::{rows, SortBy};
+use crate::stats::Stats;
+use std::io;
+
+/// What `process_bytes` ends up with: the report's rows, ordered by client, and the run's stats.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub accounts: Vec<AccountRow>,
+    pub stats: Stats,
+}
+
+/// Entry point for fuzzers (see `fuzz/`): any bytes at all go through the parser and the engine,
+/// with the invariants checked after every record, and then get written out as a csv report.
+/// Whatever the input, this returns instead of panicking, and an `Error::Internal` means a bug.
+pub fn process_bytes(input: &[u8]) -> Result<Report, Error> {
+    let mut engine = EngineBuilder::new().check_invariants(true).build();
+    engine.process_reader(input)?;
+    // the writers are part of what gets thrown at
+    engine.write_csv(io::sink())?;
+    Ok(Report {
+        accounts: rows(&engine, SortBy::Client),
+        stats: engine.stats(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MAX_AMOUNT;
+    use proptest::prelude::*;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn huge_amounts_are_rejected_instead_of_overflowing() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 79228162514264337593543950335\n\
+                     deposit, 1, 2, 100000000000000\n\
+                     deposit, 1, 3, 100000000000000\n\
+                     deposit, 1, 5, 100000000000000.0001\n\
+                     adjustment, 1, 4, -79228162514264337593543950335\n";
+        let report = process_bytes(input.as_bytes()).unwrap();
+        assert_eq!(report.stats.applied, 2);
+        assert_eq!(
+            report.accounts[0].total,
+            MAX_AMOUNT.to_decimal() * Decimal::TWO
+        );
+    }
+
+    proptest! {
+        // a cheap stand-in for the fuzz target that runs with the other tests
+        #[test]
+        fn arbitrary_bytes_never_break_the_engine(
+            input in prop::collection::vec(
+                prop_oneof![any::<u8>(), prop::sample::select(b"0123456789,.-\n deposit".to_vec())],
+                0..512,
+            )
+        ) {
+            let mut bytes = b"type,client,tx,amount\n".to_vec();
+            bytes.extend(input);
+            if let Err(err) = process_bytes(&bytes) {
+                prop_assert!(!matches!(err, Error::Internal(_)), "{}", err);
+            }
+        }
+    }
+}
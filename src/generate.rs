@@ -0,0 +1,202 @@
+use std::{
+    collections::VecDeque,
+    io::{self, BufWriter, Write},
+};
+
+// how many recent deposits withdrawals and disputes pick from
+const RECENT: usize = 1_000;
+// one settled dispute in this many is a chargeback, so only a few accounts get locked
+const CHARGEBACK_ONE_IN: u64 = 500;
+// rolls are out of this many, the rates are turned into shares of it
+const ROLLS: u64 = 1_000_000;
+
+/// What `generate` writes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenerateOptions {
+    /// Records to write, the header not counted.
+    pub rows: u64,
+    /// Client ids are picked from `0..clients`, so at most 65536.
+    pub clients: u32,
+    /// The share of records that open a dispute. About as many again settle one, so at most 0.5.
+    pub dispute_rate: f64,
+    /// The same seed (and options) always gives the same file.
+    pub seed: u64,
+}
+
+impl Default for GenerateOptions {
+    fn default() -> Self {
+        GenerateOptions {
+            rows: 1_000_000,
+            clients: 10_000,
+            dispute_rate: 0.02,
+            seed: 0,
+        }
+    }
+}
+
+/// `generate` with the default options and `rows` records, for benchmarks.
+pub fn generate_transactions(rows: u64, writer: impl Write) -> io::Result<()> {
+    let options = GenerateOptions {
+        rows,
+        ..GenerateOptions::default()
+    };
+    generate(&options, writer)
+}
+
+/// Writes synthetic records for benchmarks and load tests: mostly deposits and withdrawals of
+/// clients that recently deposited, with disputes of recent deposits that later get resolved or,
+/// now and then, charged back.
+pub fn generate(options: &GenerateOptions, writer: impl Write) -> io::Result<()> {
+    if !(1..=65536).contains(&options.clients) || !(0.0..=0.5).contains(&options.dispute_rate) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "clients must be 1 to 65536 and the dispute rate 0 to 0.5",
+        ));
+    }
+    let disputes_share = (options.dispute_rate * ROLLS as f64) as u64;
+    // what isn't a dispute is 70% deposits and 30% withdrawals
+    let deposits_share = (ROLLS - 2 * disputes_share) * 7 / 10;
+    let clients = u64::from(options.clients);
+
+    let mut writer = BufWriter::new(writer);
+    writeln!(writer, "type,client,tx,amount")?;
+    let mut rng = XorShift::new(options.seed);
+    let mut next_tx: u32 = 1;
+    // (tx, client)
+    let mut deposits: VecDeque<(u32, u64)> = VecDeque::with_capacity(RECENT);
+    let mut disputes: VecDeque<(u32, u64)> = VecDeque::new();
+    for _ in 0..options.rows {
+        let roll = rng.next() % ROLLS;
+        let recent = match deposits.len() {
+            0 => None,
+            len => Some((rng.next() % len as u64) as usize),
+        };
+        match recent {
+            Some(index) if roll < 2 * disputes_share => match disputes.pop_front() {
+                Some((tx, client)) if roll >= disputes_share => {
+                    let r_type = match rng.next() % CHARGEBACK_ONE_IN {
+                        0 => "chargeback",
+                        _ => "resolve",
+                    };
+                    writeln!(writer, "{},{},{},", r_type, client, tx)?;
+                }
+                // nothing open to settle, so open something. it's out of the recent deposits from
+                // then on so it isn't disputed twice
+                popped => {
+                    disputes.extend(popped);
+                    let (tx, client) = deposits.remove(index).expect("picked from deposits");
+                    writeln!(writer, "dispute,{},{},", client, tx)?;
+                    disputes.push_back((tx, client));
+                }
+            },
+            Some(index) if roll >= 2 * disputes_share + deposits_share => {
+                let client = deposits[index].1;
+                let cents = rng.next() % 5_000;
+                writeln!(
+                    writer,
+                    "withdrawal,{},{},{}.{:02}",
+                    client,
+                    next_tx,
+                    cents / 100,
+                    cents % 100
+                )?;
+                next_tx = next_tx.checked_add(1).ok_or_else(out_of_ids)?;
+            }
+            _ => {
+                let client = rng.next() % clients;
+                let cents = rng.next() % 100_000;
+                writeln!(
+                    writer,
+                    "deposit,{},{},{}.{:02}",
+                    client,
+                    next_tx,
+                    cents / 100,
+                    cents % 100
+                )?;
+                if deposits.len() == RECENT {
+                    deposits.pop_front();
+                }
+                deposits.push_back((next_tx, client));
+                next_tx = next_tx.checked_add(1).ok_or_else(out_of_ids)?;
+            }
+        }
+    }
+    writer.flush()
+}
+
+fn out_of_ids() -> io::Error {
+    io::Error::other("more deposits and withdrawals than there are tx ids")
+}
+
+// no rand dependency for this, it only has to look random and be repeatable
+struct XorShift(u64);
+
+impl XorShift {
+    // splitmix the seed so nearby seeds don't start out alike, and xorshift never gets a zero
+    fn new(seed: u64) -> Self {
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        XorShift((z ^ (z >> 31)) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        // xorshift64*, the plain low bits are too alike from one call to the next
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{process_reader, TransactionType};
+
+    #[test]
+    fn generated_input_is_mostly_applied() {
+        let mut input = Vec::new();
+        generate_transactions(10_000, &mut input).unwrap();
+        assert_eq!(input.iter().filter(|&&byte| byte == b'\n').count(), 10_001);
+
+        let engine = process_reader(input.as_slice()).unwrap();
+        let stats = engine.stats();
+        assert_eq!(stats.records, 10_000);
+        assert!(stats.applied > 9_000, "{}", stats);
+    }
+
+    #[test]
+    fn options_shape_the_input() {
+        let options = GenerateOptions {
+            rows: 20_000,
+            clients: 50,
+            dispute_rate: 0.1,
+            seed: 7,
+        };
+        let mut input = Vec::new();
+        generate(&options, &mut input).unwrap();
+        let mut again = Vec::new();
+        generate(&options, &mut again).unwrap();
+        assert_eq!(input, again);
+
+        let engine = process_reader(input.as_slice()).unwrap();
+        let stats = engine.stats();
+        assert!(stats.accounts <= 50, "{}", stats);
+        let disputes = stats
+            .by_type
+            .iter()
+            .find(|(t, _)| *t == TransactionType::Dispute);
+        assert!(
+            disputes.is_some_and(|&(_, n)| (1_500..2_500).contains(&n)),
+            "{}",
+            stats
+        );
+        assert!(stats.locked_accounts > 0);
+
+        let seeded = GenerateOptions { seed: 8, ..options };
+        let mut other = Vec::new();
+        generate(&seeded, &mut other).unwrap();
+        assert_ne!(input, other);
+    }
+}
@@ -0,0 +1,267 @@
+use crate::currency::Currency;
+use crate::engine::Engine;
+use crate::error::Error;
+use crate::reader::{default_headers, Row};
+use crate::shared::SharedEngine;
+use csv::StringRecord;
+use proto::resolver_server::{Resolver, ResolverServer};
+use std::{io, net::SocketAddr};
+use tonic::{Request, Response, Status, Streaming};
+
+/// The messages and generated service of proto/resolver.proto (and its account.proto).
+pub mod proto {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Transaction {
+        #[prost(string, tag = "1")]
+        pub r#type: String,
+        #[prost(uint32, tag = "2")]
+        pub client: u32,
+        #[prost(uint32, tag = "3")]
+        pub tx: u32,
+        /// Decimal string, empty if there is none.
+        #[prost(string, tag = "4")]
+        pub amount: String,
+        #[prost(string, tag = "5")]
+        pub signature: String,
+        /// Empty for the default currency.
+        #[prost(string, tag = "6")]
+        pub currency: String,
+        /// Like the csv column, empty if there is none.
+        #[prost(string, tag = "7")]
+        pub timestamp: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubmitSummary {
+        #[prost(uint64, tag = "1")]
+        pub applied: u64,
+        #[prost(uint64, tag = "2")]
+        pub rejected: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct AccountRequest {
+        #[prost(uint32, tag = "1")]
+        pub client: u32,
+        #[prost(string, tag = "2")]
+        pub currency: String,
+    }
+
+    pub use crate::account_proto::Account;
+
+    include!(concat!(env!("OUT_DIR"), "/csv_tx_resolver.Resolver.rs"));
+}
+
+impl proto::Transaction {
+    fn to_record(&self) -> StringRecord {
+        StringRecord::from(vec![
+            self.r#type.clone(),
+            self.client.to_string(),
+            self.tx.to_string(),
+            self.amount.clone(),
+            self.signature.clone(),
+            self.currency.clone(),
+            self.timestamp.clone(),
+        ])
+    }
+}
+
+/// The engine as a tonic service. Every `SubmitTransactions` stream is applied in order like one
+/// csv file, rejections end up in the engine's error report as usual.
+#[derive(Debug, Clone)]
+pub struct ResolverService {
+    engine: SharedEngine,
+}
+
+impl ResolverService {
+    pub fn new(engine: SharedEngine) -> Self {
+        ResolverService { engine }
+    }
+
+    pub fn into_server(self) -> ResolverServer<Self> {
+        ResolverServer::new(self)
+    }
+
+    fn engine(&self) -> std::sync::MutexGuard<'_, Engine> {
+        self.engine.lock()
+    }
+}
+
+#[tonic::async_trait]
+impl Resolver for ResolverService {
+    async fn submit_transactions(
+        &self,
+        request: Request<Streaming<proto::Transaction>>,
+    ) -> Result<Response<proto::SubmitSummary>, Status> {
+        // a message goes through the same deserializer as a csv row, so both inputs accept (and
+        // reject) exactly the same records
+        let headers = default_headers();
+        let mut stream = request.into_inner();
+        let mut summary = proto::SubmitSummary::default();
+        // rows count from 1 within each stream
+        let mut row = 0;
+        while let Some(message) = stream.message().await? {
+            row += 1;
+            let raw = message.to_record();
+            let parsed = raw.deserialize(Some(&headers));
+            let mut engine = self.engine();
+            let (applied, rejected) = (engine.applied(), engine.skipped());
+            let result = engine.apply_row(Row { row, parsed }, &headers, &raw);
+            summary.applied += engine.applied() - applied;
+            summary.rejected += engine.skipped() - rejected;
+            result.map_err(status)?;
+        }
+        self.engine()
+            .flush_reports()
+            .map_err(|err| status(err.into()))?;
+        Ok(Response::new(summary))
+    }
+
+    async fn get_account(
+        &self,
+        request: Request<proto::AccountRequest>,
+    ) -> Result<Response<proto::Account>, Status> {
+        let request = request.into_inner();
+        let client = u16::try_from(request.client)
+            .map_err(|_| Status::invalid_argument("client ids go up to 65535"))?;
+        let currency: Currency = request.currency.parse().map_err(Status::invalid_argument)?;
+        let engine = self.engine();
+        let account = engine
+            .account(client)
+            .ok_or_else(|| Status::not_found("no such account"))?;
+        let balance = account.balance(currency);
+        Ok(Response::new(proto::Account {
+            client: account.client().into(),
+            available: balance.available.to_string(),
+            held: balance.held.to_string(),
+            total: balance.total.to_string(),
+            locked: account.locked(),
+            currency: currency.to_string(),
+        }))
+    }
+}
+
+fn status(err: Error) -> Status {
+    match err {
+        Error::Csv(_) | Error::Parse { .. } | Error::Policy { .. } => {
+            Status::invalid_argument(err.to_string())
+        }
+        _ => Status::internal(err.to_string()),
+    }
+}
+
+/// Serves `engine` over gRPC on `addr` until the server fails.
+pub async fn serve_grpc(engine: SharedEngine, addr: SocketAddr) -> Result<(), Error> {
+    tracing::info!(%addr, "listening");
+    tonic::transport::Server::builder()
+        .add_service(ResolverService::new(engine).into_server())
+        .serve(addr)
+        .await
+        .map_err(|err| Error::Io(io::Error::other(err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::resolver_client::ResolverClient;
+    use tokio_stream::wrappers::TcpListenerStream;
+
+    fn transaction(r#type: &str, tx: u32, amount: &str) -> proto::Transaction {
+        proto::Transaction {
+            r#type: r#type.to_string(),
+            client: 1,
+            tx,
+            amount: amount.to_string(),
+            signature: String::new(),
+            currency: String::new(),
+            timestamp: String::new(),
+        }
+    }
+
+    // serves `engine` on a free local port and connects to it
+    async fn connect(engine: SharedEngine) -> ResolverClient<tonic::transport::Channel> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tonic::transport::Server::builder()
+            .add_service(ResolverService::new(engine).into_server())
+            .serve_with_incoming(TcpListenerStream::new(listener));
+        tokio::spawn(server);
+        ResolverClient::connect(format!("http://{}", addr))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn streamed_transactions_are_applied() {
+        let mut client = connect(SharedEngine::new(Engine::new())).await;
+        let stream = tokio_stream::iter([
+            transaction("deposit", 1, "5.0"),
+            transaction("withdrawal", 2, "50"),
+            transaction("dispute", 1, ""),
+        ]);
+        let summary = client
+            .submit_transactions(stream)
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(summary.applied, 2);
+        assert_eq!(summary.rejected, 1);
+
+        let request = proto::AccountRequest {
+            client: 1,
+            ..Default::default()
+        };
+        let account = client.get_account(request).await.unwrap().into_inner();
+        assert_eq!(account.available, "0.0000");
+        assert_eq!(account.held, "5.0000");
+
+        let missing = client
+            .get_account(proto::AccountRequest {
+                client: 2,
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(missing.unwrap_err().code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn submitted_stream_matches_process_reader() {
+        let options = crate::GenerateOptions {
+            rows: 2_000,
+            clients: 50,
+            dispute_rate: 0.1,
+            seed: 7,
+        };
+        let mut input = Vec::new();
+        crate::generate(&options, &mut input).unwrap();
+        let expected = crate::process_reader(input.as_slice()).unwrap();
+
+        // the generated csv has no quoting, every line is `type,client,tx,amount`
+        let messages = String::from_utf8(input)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|line| {
+                let fields = line.split(',').collect::<Vec<_>>();
+                proto::Transaction {
+                    client: fields[1].parse().unwrap(),
+                    ..transaction(fields[0], fields[2].parse().unwrap(), fields[3])
+                }
+            })
+            .collect::<Vec<_>>();
+        let engine = SharedEngine::new(Engine::new());
+        let mut client = connect(engine.clone()).await;
+        let summary = client
+            .submit_transactions(tokio_stream::iter(messages))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(summary.applied, expected.applied());
+        assert_eq!(summary.rejected, expected.skipped());
+
+        let (mut ours, mut theirs) = (Vec::new(), Vec::new());
+        engine.lock().write_csv(&mut ours).unwrap();
+        expected.write_csv(&mut theirs).unwrap();
+        assert!(ours == theirs);
+    }
+}
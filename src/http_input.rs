@@ -0,0 +1,198 @@
+use reqwest::{
+    blocking::{Client, Response},
+    header, StatusCode,
+};
+use std::{
+    io::{self, Read},
+    thread,
+    time::Duration,
+};
+
+// tries in a row before a dropped download is given up on, waiting twice as long each time
+const ATTEMPTS: u32 = 5;
+const FIRST_WAIT: Duration = Duration::from_millis(500);
+
+/// A file on a web server read as it downloads. A connection that drops or stalls is reopened
+/// with a `Range` request for the rest, up to `ATTEMPTS` times in a row. With an `ETag` or
+/// `Last-Modified` to go by, a file that changed in between is an error rather than the rest of
+/// another file. A server that can't send ranges sends it all again, and what was read already is
+/// skipped.
+pub(crate) struct HttpReader {
+    client: Client,
+    url: String,
+    response: Response,
+    // what the first response said it was, for `If-Range`
+    validator: Option<header::HeaderValue>,
+    read: u64,
+}
+
+impl HttpReader {
+    pub fn open(url: &str) -> io::Result<Self> {
+        let client = Client::builder()
+            .connect_timeout(Duration::from_secs(30))
+            .build()
+            .map_err(io::Error::other)?;
+        let response = retried(|| get(&client, url, None))?;
+        let validator = [header::ETAG, header::LAST_MODIFIED]
+            .into_iter()
+            .find_map(|name| response.headers().get(name).cloned());
+        Ok(HttpReader {
+            client,
+            url: url.to_string(),
+            response,
+            validator,
+            read: 0,
+        })
+    }
+
+    // a response for what's left after `self.read` bytes
+    fn reopen(&self) -> io::Result<Response> {
+        let mut response = get(
+            &self.client,
+            &self.url,
+            Some((self.read, self.validator.as_ref())),
+        )?;
+        match response.status() {
+            StatusCode::PARTIAL_CONTENT => Ok(response),
+            _ if self.validator.is_some() => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} changed while it was being read", self.url),
+            )),
+            // no ranges, so the whole file again
+            _ => {
+                io::copy(&mut (&mut response).take(self.read), &mut io::sink())?;
+                Ok(response)
+            }
+        }
+    }
+}
+
+fn get(
+    client: &Client,
+    url: &str,
+    from: Option<(u64, Option<&header::HeaderValue>)>,
+) -> io::Result<Response> {
+    let mut request = client.get(url);
+    if let Some((from, validator)) = from {
+        request = request.header(header::RANGE, format!("bytes={}-", from));
+        if let Some(validator) = validator {
+            request = request.header(header::IF_RANGE, validator);
+        }
+    }
+    let response = request.send().map_err(io::Error::other)?;
+    match response.status() {
+        status if status.is_success() => Ok(response),
+        StatusCode::NOT_FOUND => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} isn't there", url),
+        )),
+        status => Err(io::Error::other(format!("{} answered {}", url, status))),
+    }
+}
+
+// `attempt` until it works, giving up after `ATTEMPTS` or on an answer that won't change
+fn retried<T>(mut attempt: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let mut wait = FIRST_WAIT;
+    for _ in 1..ATTEMPTS {
+        match attempt() {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                tracing::warn!(%err, "download failed, trying again");
+                thread::sleep(wait);
+                wait *= 2;
+            }
+            done => return done,
+        }
+    }
+    attempt()
+}
+
+impl Read for HttpReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut dropped = match self.response.read(buf) {
+            Ok(n) => {
+                self.read += n as u64;
+                return Ok(n);
+            }
+            Err(err) => err,
+        };
+        let mut wait = FIRST_WAIT;
+        for _ in 0..ATTEMPTS {
+            tracing::warn!(url = %self.url, at = self.read, err = %dropped, "download dropped, resuming");
+            thread::sleep(wait);
+            wait *= 2;
+            match self.reopen().and_then(|mut response| {
+                let n = response.read(buf)?;
+                Ok((response, n))
+            }) {
+                Ok((response, n)) => {
+                    self.response = response;
+                    self.read += n as u64;
+                    return Ok(n);
+                }
+                Err(err) if err.kind() == io::ErrorKind::InvalidData => return Err(err),
+                Err(err) => dropped = err,
+            }
+        }
+        Err(dropped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+    };
+
+    #[test]
+    fn dropped_downloads_carry_on_where_they_broke_off() {
+        let body = "type,client,tx,amount\n".to_string() + &"deposit,1,1,1.0\n".repeat(10_000);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/transactions.csv", listener.local_addr().unwrap());
+        let served = body.clone();
+        let server = thread::spawn(move || {
+            let mut ranges = Vec::new();
+            for (i, stream) in listener.incoming().take(2).enumerate() {
+                let mut stream = stream.unwrap();
+                let mut from = 0;
+                for line in BufReader::new(&stream).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(range) = line.to_ascii_lowercase().strip_prefix("range: bytes=") {
+                        from = range.trim_end_matches('-').parse().unwrap();
+                    }
+                }
+                ranges.push(from);
+                let rest = &served.as_bytes()[from..];
+                let status = if from == 0 {
+                    "200 OK"
+                } else {
+                    "206 Partial Content"
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\ncontent-length: {}\r\netag: \"v1\"\r\nconnection: close\r\n\r\n",
+                    status,
+                    rest.len()
+                )
+                .unwrap();
+                // the first answer breaks off a third of the way in
+                let sent = if i == 0 { rest.len() / 3 } else { rest.len() };
+                stream.write_all(&rest[..sent]).unwrap();
+            }
+            ranges
+        });
+
+        let mut read = String::new();
+        HttpReader::open(&url)
+            .unwrap()
+            .read_to_string(&mut read)
+            .unwrap();
+        assert_eq!(read, body);
+        let ranges = server.join().unwrap();
+        assert_eq!(ranges, [0, body.len() / 3]);
+    }
+}
@@ -0,0 +1,322 @@
+use crate::error::Error;
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression of an input stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Guess from the file name: `.gz` / `.zst`.
+    pub fn from_extension(path: &Path) -> Option<Compression> {
+        match path.extension()?.to_str()? {
+            "gz" | "gzip" => Some(Compression::Gzip),
+            "zst" | "zstd" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Guess from the first bytes of the stream.
+    pub fn sniff(head: &[u8]) -> Compression {
+        if head.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if head.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Opens a transactions file, decompressing it on the fly if the extension or the magic bytes say
+/// it's gzip or zstd. Nothing is ever decompressed to disk, so multi-GB archives stream straight in.
+/// An `.xlsx` workbook (with the `xlsx` feature) is read as its first sheet turned into csv, an
+/// `.avro` container (with the `avro` feature) as its records.
+pub fn open_input(path: impl AsRef<Path>) -> io::Result<Box<dyn Read + Send>> {
+    let path = path.as_ref();
+    decode_input(path, open_path(path)?)
+}
+
+/// The bytes at `path` as they are, not decompressed: a local file or, with the `cloud` feature,
+/// an `s3://bucket/key` or `gs://bucket/key` object streamed as it downloads. With the `http`
+/// feature an `https://` url is downloaded the same way.
+pub fn open_path(path: impl AsRef<Path>) -> io::Result<Box<dyn Read + Send>> {
+    let path = path.as_ref();
+    if is_object_url(path) {
+        return open_object(path);
+    }
+    if is_http_url(path) {
+        return open_http(path);
+    }
+    Ok(Box::new(File::open(path)?))
+}
+
+/// Everything at `path`, like `fs::read` but for objects too.
+pub(crate) fn read_path(path: &Path) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    open_path(path)?.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Whether `path` names an object in a bucket rather than a local file.
+pub(crate) fn is_object_url(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| path.starts_with("s3://") || path.starts_with("gs://"))
+}
+
+// http:// and https:// urls
+fn is_http_url(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| path.starts_with("http://") || path.starts_with("https://"))
+}
+
+#[cfg(feature = "http")]
+fn open_http(path: &Path) -> io::Result<Box<dyn Read + Send>> {
+    Ok(Box::new(crate::http_input::HttpReader::open(
+        &path.to_string_lossy(),
+    )?))
+}
+
+#[cfg(not(feature = "http"))]
+fn open_http(_: &Path) -> io::Result<Box<dyn Read + Send>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "http:// and https:// inputs need the http cargo feature",
+    ))
+}
+
+#[cfg(feature = "cloud")]
+fn open_object(path: &Path) -> io::Result<Box<dyn Read + Send>> {
+    crate::cloud::open(path)
+}
+
+#[cfg(not(feature = "cloud"))]
+fn open_object(_: &Path) -> io::Result<Box<dyn Read + Send>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "s3:// and gs:// paths need the cloud cargo feature",
+    ))
+}
+
+/// `open_input` for a file that's already open, or wrapped (say to count the bytes read off disk).
+/// `path` only gives the extension.
+pub fn decode_input(
+    path: &Path,
+    reader: impl Read + Send + 'static,
+) -> io::Result<Box<dyn Read + Send>> {
+    decode_buffered(path, BufReader::new(reader))
+}
+
+fn decode_buffered(
+    path: &Path,
+    mut reader: impl BufRead + Send + 'static,
+) -> io::Result<Box<dyn Read + Send>> {
+    if is_spreadsheet(path) {
+        return spreadsheet(reader);
+    }
+    if has_extension(path, "avro") {
+        return avro_container(reader);
+    }
+    let compression = match Compression::from_extension(path) {
+        Some(compression) => compression,
+        None => Compression::sniff(reader.fill_buf()?),
+    };
+    decoder(compression, reader)
+}
+
+fn has_extension(path: &Path, wanted: &str) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extension.eq_ignore_ascii_case(wanted))
+}
+
+// whether the file name says it's an Excel workbook: `.xlsx` / `.xlsm`
+fn is_spreadsheet(path: &Path) -> bool {
+    has_extension(path, "xlsx") || has_extension(path, "xlsm")
+}
+
+#[cfg(feature = "xlsx")]
+fn spreadsheet(reader: impl Read) -> io::Result<Box<dyn Read + Send>> {
+    Ok(Box::new(io::Cursor::new(crate::xlsx::sheet_to_csv(
+        reader,
+    )?)))
+}
+
+#[cfg(not(feature = "xlsx"))]
+fn spreadsheet(_: impl Read) -> io::Result<Box<dyn Read + Send>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        ".xlsx input needs the xlsx cargo feature",
+    ))
+}
+
+#[cfg(feature = "avro")]
+fn avro_container(reader: impl Read) -> io::Result<Box<dyn Read + Send>> {
+    Ok(Box::new(io::Cursor::new(crate::avro::container_to_csv(
+        reader,
+    )?)))
+}
+
+#[cfg(not(feature = "avro"))]
+fn avro_container(_: impl Read) -> io::Result<Box<dyn Read + Send>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        ".avro input needs the avro cargo feature",
+    ))
+}
+
+/// Maps a transactions file into memory, to be read without a copy into a read buffer.
+///
+/// The file mustn't be truncated or rewritten while it's mapped, or reading it may crash the
+/// process. Appending is fine (the map just doesn't see the new end), which is all a growing
+/// transaction log does.
+#[cfg(feature = "mmap")]
+pub fn map_file(path: impl AsRef<Path>) -> io::Result<io::Cursor<memmap2::Mmap>> {
+    let file = File::open(path)?;
+    // SAFETY: see above, the file is only ever read and nothing in here changes it
+    let map = unsafe { memmap2::Mmap::map(&file)? };
+    Ok(io::Cursor::new(map))
+}
+
+/// `open_input` through `map_file`: plain csv is parsed straight out of the page cache, compressed
+/// files are decompressed out of it.
+#[cfg(feature = "mmap")]
+pub fn map_input(path: impl AsRef<Path>) -> io::Result<Box<dyn Read + Send>> {
+    let path = path.as_ref();
+    decode_buffered(path, map_file(path)?)
+}
+
+/// Wraps any reader (stdin, a socket...) so that gzip/zstd input is transparently decompressed.
+/// Detection only peeks at the magic bytes, nothing is consumed.
+pub fn decompress(reader: impl Read + Send + 'static) -> io::Result<Box<dyn Read + Send>> {
+    let mut reader = BufReader::new(reader);
+    let compression = Compression::sniff(reader.fill_buf()?);
+    decoder(compression, reader)
+}
+
+/// Turns the command line inputs into the list of files to replay, in order. Plain paths are kept
+/// as given, glob patterns (`daily/2022-*.csv`) expand sorted by name, so date-stamped files come out
+/// oldest first. A pattern that matches nothing is an error rather than a silently empty batch.
+pub fn expand_inputs<S: AsRef<str>>(inputs: &[S]) -> Result<Vec<PathBuf>, Error> {
+    let mut paths = Vec::new();
+    for input in inputs {
+        let input = input.as_ref();
+        // urls are taken as they are, there's no listing them (and `?` starts a query)
+        let url = is_object_url(Path::new(input)) || is_http_url(Path::new(input));
+        if !input.contains(['*', '?', '[']) || url {
+            paths.push(PathBuf::from(input));
+            continue;
+        }
+        let mut matched = glob::glob(input)
+            .map_err(|err| Error::Input(format!("bad pattern {}: {}", input, err)))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| Error::Io(err.into()))?;
+        if matched.is_empty() {
+            return Err(Error::Input(format!("no files match {}", input)));
+        }
+        matched.sort();
+        paths.extend(matched);
+    }
+    Ok(paths)
+}
+
+fn decoder<R: BufRead + Send + 'static>(
+    compression: Compression,
+    reader: R,
+) -> io::Result<Box<dyn Read + Send>> {
+    match compression {
+        Compression::None => Ok(Box::new(reader)),
+        #[cfg(feature = "gzip")]
+        // concatenated members are common in rotated logs, so read all of them
+        Compression::Gzip => Ok(Box::new(flate2::bufread::MultiGzDecoder::new(reader))),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Ok(Box::new(zstd::stream::read::Decoder::with_buffer(reader)?)),
+        #[allow(unreachable_patterns)]
+        other => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{:?} input needs the matching cargo feature", other),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, io::Write, process};
+
+    const CSV: &str = "type, client, tx, amount\ndeposit, 1, 1, 1.0\n";
+
+    fn read_all(mut reader: Box<dyn Read + Send>) -> String {
+        let mut out = String::new();
+        reader.read_to_string(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn globs_expand_in_name_order() {
+        let dir = std::env::temp_dir().join(format!("expand-inputs-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for day in ["2022-01-03", "2022-01-01", "2022-01-02"] {
+            fs::write(dir.join(format!("{}.csv", day)), CSV).unwrap();
+        }
+        let first = dir.join("first.csv");
+        let pattern = dir.join("2022-*.csv");
+        let inputs = [first.to_str().unwrap(), pattern.to_str().unwrap()];
+
+        let paths = expand_inputs(&inputs).unwrap();
+        let names: Vec<_> = paths.iter().map(|p| p.file_name().unwrap()).collect();
+        assert_eq!(
+            names,
+            [
+                "first.csv",
+                "2022-01-01.csv",
+                "2022-01-02.csv",
+                "2022-01-03.csv"
+            ]
+        );
+        assert!(expand_inputs(&[dir.join("nope-*.csv").to_str().unwrap()]).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(all(feature = "gzip", feature = "zstd"))]
+    #[test]
+    fn plain_gzip_and_zstd_are_detected_by_magic_bytes() {
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(CSV.as_bytes()).unwrap();
+        let gz = gz.finish().unwrap();
+        let zst = zstd::encode_all(CSV.as_bytes(), 0).unwrap();
+
+        assert_eq!(Compression::sniff(&gz), Compression::Gzip);
+        assert_eq!(Compression::sniff(&zst), Compression::Zstd);
+        assert_eq!(read_all(decompress(io::Cursor::new(gz)).unwrap()), CSV);
+        assert_eq!(read_all(decompress(io::Cursor::new(zst)).unwrap()), CSV);
+        assert_eq!(read_all(decompress(CSV.as_bytes()).unwrap()), CSV);
+    }
+
+    #[cfg(all(feature = "mmap", feature = "gzip"))]
+    #[test]
+    fn mapped_files_read_like_opened_ones() {
+        let dir = std::env::temp_dir().join(format!("map-input-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(CSV.as_bytes()).unwrap();
+        fs::write(dir.join("plain.csv"), CSV).unwrap();
+        fs::write(dir.join("packed.csv"), gz.finish().unwrap()).unwrap();
+        fs::write(dir.join("empty.csv"), "").unwrap();
+
+        assert_eq!(read_all(map_input(dir.join("plain.csv")).unwrap()), CSV);
+        assert_eq!(read_all(map_input(dir.join("packed.csv")).unwrap()), CSV);
+        assert_eq!(read_all(map_input(dir.join("empty.csv")).unwrap()), "");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,170 @@
+use crate::account::Account;
+use crate::currency::Currency;
+use crate::transaction::{Transaction, TransactionType};
+use std::{error::Error, fmt};
+
+/// A rule of the ledger a balance broke. The engine never lets input do this, so any of these is a
+/// bug.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvariantViolation {
+    /// `total` isn't `available + held`.
+    TotalMismatch {
+        client: u16,
+        currency: Currency,
+    },
+    NegativeHeld {
+        client: u16,
+        currency: Currency,
+    },
+    /// The account got locked by something other than a chargeback.
+    LockedWithoutChargeback {
+        client: u16,
+        tx: u32,
+    },
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvariantViolation::TotalMismatch { client, currency } => write!(
+                f,
+                "client {} {:?}: total is not available + held",
+                client, currency
+            ),
+            InvariantViolation::NegativeHeld { client, currency } => {
+                write!(f, "client {} {:?}: held is negative", client, currency)
+            }
+            InvariantViolation::LockedWithoutChargeback { client, tx } => write!(
+                f,
+                "client {} was locked by tx {}, which isn't a chargeback",
+                client, tx
+            ),
+        }
+    }
+}
+
+impl Error for InvariantViolation {}
+
+impl Account {
+    /// Checks every balance: total is available + held and held isn't negative. Available can go
+    /// negative (a dispute after a withdrawal), so that's allowed.
+    pub fn check_invariants(&self) -> Result<(), InvariantViolation> {
+        for balance in self.balances() {
+            let (client, currency) = (self.client(), balance.currency);
+            if balance.available.checked_add(balance.held) != Some(balance.total) {
+                return Err(InvariantViolation::TotalMismatch { client, currency });
+            }
+            if balance.held.is_negative() {
+                return Err(InvariantViolation::NegativeHeld { client, currency });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `Account::check_invariants` for the account `record` was applied to, plus that only a chargeback
+/// locks one. `was_locked` is whether it was locked before.
+pub(crate) fn check_after(
+    account: &Account,
+    record: &Transaction,
+    was_locked: bool,
+) -> Result<(), InvariantViolation> {
+    account.check_invariants()?;
+    if account.locked() && !was_locked && record.r_type != TransactionType::Chargeback {
+        return Err(InvariantViolation::LockedWithoutChargeback {
+            client: account.client(),
+            tx: record.tx,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{process_reader_sharded, Amount, Balance, EngineBuilder};
+    use proptest::prelude::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn broken_balances_are_caught() {
+        let amount = |amount| Amount::from_decimal(amount).unwrap();
+        let balance = |available, held, total| Balance {
+            currency: Currency::DEFAULT,
+            available: amount(available),
+            held: amount(held),
+            total: amount(total),
+        };
+        let fine = Account::with_balances(1, vec![balance(dec!(-1), dec!(3), dec!(2))], false);
+        assert_eq!(fine.check_invariants(), Ok(()));
+        let off = Account::with_balances(1, vec![balance(dec!(1), dec!(3), dec!(2))], false);
+        assert!(matches!(
+            off.check_invariants(),
+            Err(InvariantViolation::TotalMismatch { client: 1, .. })
+        ));
+        let negative = Account::with_balances(1, vec![balance(dec!(3), dec!(-1), dec!(2))], false);
+        assert!(matches!(
+            negative.check_invariants(),
+            Err(InvariantViolation::NegativeHeld { client: 1, .. })
+        ));
+    }
+
+    // a few clients and tx ids so that disputes, duplicates and cross-client references actually
+    // hit something, and amounts with up to six places to go through rounding
+    fn record() -> impl Strategy<Value = String> {
+        let r_type = prop::sample::select(vec![
+            "deposit",
+            "deposit",
+            "withdrawal",
+            "dispute",
+            "resolve",
+            "chargeback",
+        ]);
+        (r_type, 0..4u16, 0..40u32, 0..2_000_000u64).prop_map(|(r_type, client, tx, micros)| {
+            match r_type {
+                "deposit" | "withdrawal" => format!(
+                    "{}, {}, {}, {}.{:06}",
+                    r_type,
+                    client,
+                    tx,
+                    micros / 1_000_000,
+                    micros % 1_000_000
+                ),
+                _ => format!("{}, {}, {},", r_type, client, tx),
+            }
+        })
+    }
+
+    /// A csv transaction stream, header included.
+    fn stream() -> impl Strategy<Value = String> {
+        prop::collection::vec(record(), 0..200).prop_map(|records| {
+            let mut input = String::from("type, client, tx, amount\n");
+            for record in records {
+                input.push_str(&record);
+                input.push('\n');
+            }
+            input
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn no_stream_breaks_the_invariants(input in stream()) {
+            let mut engine = EngineBuilder::new().check_invariants(true).build();
+            engine.process_reader(input.as_bytes()).unwrap();
+            for account in engine.accounts().values() {
+                prop_assert!(account.check_invariants().is_ok());
+            }
+
+            let sharded = process_reader_sharded(input.as_bytes(), 3).unwrap();
+            let balances = |accounts: Vec<Account>| {
+                accounts.iter().map(|a| (a.client(), a.balances().to_vec(), a.locked())).collect::<Vec<_>>()
+            };
+            let mut single: Vec<_> = engine.accounts().into_values().collect();
+            let mut other: Vec<_> = sharded.accounts().into_values().collect();
+            single.sort_by_key(Account::client);
+            other.sort_by_key(Account::client);
+            prop_assert_eq!(balances(single), balances(other));
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use crate::amount::Amount;
+use crate::currency::Currency;
+use crate::transaction::{Transaction, TransactionType};
+use crate::utils::four_precision_serializer;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::{fmt, io, io::Write};
+
+/// The general ledger accounts money moves between. `Available` and `Held` are the client's (the
+/// entry's `client` column says whose), what the ledger owes them. `Clearing` is the money itself,
+/// coming in with deposits and going out with withdrawals and chargebacks, and `Adjustments` is
+/// where manual corrections are booked against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LedgerAccount {
+    Available,
+    Held,
+    Clearing,
+    Adjustments,
+}
+
+/// One line of the `--ledger` journal: `amount` debited from one account and credited to another, so
+/// every line balances on its own.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct JournalEntry {
+    pub tx: u32,
+    pub client: u16,
+    #[serde(rename = "type")]
+    pub r_type: TransactionType,
+    pub currency: Currency,
+    pub debit: LedgerAccount,
+    pub credit: LedgerAccount,
+    /// Always positive, a negative adjustment swaps the accounts instead.
+    #[serde(serialize_with = "four_precision_serializer")]
+    pub amount: Decimal,
+}
+
+impl JournalEntry {
+    /// The entry for `record`, just applied. `referenced` is the tx a dispute, resolve or chargeback
+    /// was about, as it is after the record. Unlocks and moves of nothing don't get one.
+    pub fn new(record: &Transaction, referenced: Option<&Transaction>) -> Option<Self> {
+        use LedgerAccount::*;
+        let (debit, credit, amount, currency) = match (record.r_type, referenced) {
+            (TransactionType::Deposit, _) => {
+                (Clearing, Available, record.amount(), record.currency)
+            }
+            (TransactionType::Withdrawal, _) => {
+                (Available, Clearing, record.amount(), record.currency)
+            }
+            (TransactionType::Adjustment, _) if record.amount() < Amount::ZERO => {
+                (Available, Adjustments, -record.amount(), record.currency)
+            }
+            (TransactionType::Adjustment, _) => {
+                (Adjustments, Available, record.amount(), record.currency)
+            }
+            (
+                TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback,
+                Some(tx),
+            ) => {
+                // a disputed withdrawal comes back as held, a disputed deposit moves off available
+                let withdrawal = tx.r_type == TransactionType::Withdrawal;
+                let (debit, credit) = match (record.r_type, withdrawal) {
+                    (TransactionType::Dispute, false) => (Available, Held),
+                    (TransactionType::Dispute, true) => (Clearing, Held),
+                    (TransactionType::Resolve, false) => (Held, Available),
+                    (TransactionType::Resolve, true) => (Held, Clearing),
+                    (_, false) => (Held, Clearing),
+                    (_, true) => (Held, Available),
+                };
+                (debit, credit, tx.disputed_amount(), tx.currency)
+            }
+            _ => return None,
+        };
+        (amount != Amount::ZERO).then_some(JournalEntry {
+            tx: record.tx,
+            client: record.client,
+            r_type: record.r_type,
+            currency,
+            debit,
+            credit,
+            amount: amount.into(),
+        })
+    }
+}
+
+/// Csv sink for journal entries. Like the audit log it's written from `Engine::apply`, so the
+/// first write error is kept for the next `flush`.
+pub struct Journal {
+    writer: csv::Writer<Box<dyn Write + Send>>,
+    failed: Option<String>,
+}
+
+impl Journal {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self::with_headers(writer, true)
+    }
+
+    /// For appending to an existing journal, so the header row isn't repeated.
+    pub fn without_headers(writer: impl Write + Send + 'static) -> Self {
+        Self::with_headers(writer, false)
+    }
+
+    fn with_headers(writer: impl Write + Send + 'static, headers: bool) -> Self {
+        let writer: Box<dyn Write + Send> = Box::new(writer);
+        Journal {
+            writer: csv::WriterBuilder::new()
+                .has_headers(headers)
+                .from_writer(writer),
+            failed: None,
+        }
+    }
+
+    pub fn write(&mut self, entry: &JournalEntry) {
+        if self.failed.is_none() {
+            self.failed = self
+                .writer
+                .serialize(entry)
+                .err()
+                .map(|err| err.to_string());
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        if let Some(err) = self.failed.take() {
+            return Err(io::Error::other(format!("ledger journal: {}", err)));
+        }
+        self.writer.flush()
+    }
+}
+
+impl fmt::Debug for Journal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Journal").finish_non_exhaustive()
+    }
+}
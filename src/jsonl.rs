@@ -0,0 +1,220 @@
+use crate::config::INPUT_COLUMNS;
+use serde_json::value::RawValue;
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    io::{self, BufRead, BufReader, Read, Write},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+// the csv writer's output, shared so it can be read out while the one writer stays open
+#[derive(Clone, Default)]
+struct Out(Arc<Mutex<Vec<u8>>>);
+
+impl Out {
+    fn buffer(&self) -> MutexGuard<'_, Vec<u8>> {
+        self.0.lock().expect("jsonl buffer poisoned")
+    }
+}
+
+impl Write for Out {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// JSON Lines input read as csv, one object per line becoming a record with our columns in the
+/// order of `INPUT_COLUMNS` (and no header line). Keys go through the config's `columns` like a
+/// header's names would, ones that aren't our columns are ignored. Strings are unquoted, numbers
+/// kept as they're written so amounts aren't rounded through a float, and `null` is left empty.
+/// A line that isn't a JSON object comes out as a record of just that line, for the error report,
+/// and `is_object` tells them apart. Blank lines are skipped.
+pub(crate) struct JsonLines<R> {
+    reader: BufReader<R>,
+    columns: BTreeMap<String, String>,
+    line: Vec<u8>,
+    // one writer for the whole stream, each line's record is read out of `out` before the next
+    writer: csv::Writer<Out>,
+    out: Out,
+    at: usize,
+    // (csv bytes, input bytes, input lines, whether it was an object) by the end of each line not
+    // yet forgotten
+    ends: VecDeque<(u64, u64, u64, bool)>,
+    written: u64,
+    read: u64,
+    lines: u64,
+}
+
+impl<R: Read> JsonLines<R> {
+    pub fn new(reader: R, columns: &BTreeMap<String, String>) -> Self {
+        let out = Out::default();
+        JsonLines {
+            reader: BufReader::new(reader),
+            columns: columns.clone(),
+            line: Vec::new(),
+            writer: csv::WriterBuilder::new()
+                .buffer_capacity(256)
+                .flexible(true)
+                .from_writer(out.clone()),
+            out,
+            at: 0,
+            ends: VecDeque::new(),
+            written: 0,
+            read: 0,
+            lines: 0,
+        }
+    }
+
+    /// Where in the input the record `byte` bytes into the csv starts, as a byte offset and
+    /// 1-based line number.
+    pub fn input_position(&self, byte: u64) -> (u64, u64) {
+        let ends = self.ends.partition_point(|&(written, ..)| written <= byte);
+        match ends {
+            0 => (0, 1),
+            n => (self.ends[n - 1].1, self.ends[n - 1].2 + 1),
+        }
+    }
+
+    /// Whether the record `byte` bytes into the csv came from a JSON object, rather than a line
+    /// that wasn't one.
+    pub fn is_object(&self, byte: u64) -> bool {
+        let ends = self.ends.partition_point(|&(written, ..)| written <= byte);
+        self.ends.get(ends).is_none_or(|&(.., object)| object)
+    }
+
+    /// Drops the line ends before `byte` bytes into the csv, once nothing before it is asked
+    /// about again.
+    pub fn forget_before(&mut self, byte: u64) {
+        while self.ends.len() > 1 && self.ends[1].0 <= byte {
+            self.ends.pop_front();
+        }
+    }
+
+    // writes the line out as a csv record, true unless it wasn't a JSON object
+    fn convert(&mut self) -> io::Result<bool> {
+        let line = self.line.trim_ascii();
+        if line.is_empty() {
+            return Ok(true);
+        }
+        let object = match serde_json::from_slice::<HashMap<String, &RawValue>>(line) {
+            Ok(object) => {
+                let mut record = vec![String::new(); INPUT_COLUMNS.len()];
+                for (key, value) in object {
+                    let name = self.columns.get(&key).unwrap_or(&key);
+                    if let Some(i) = INPUT_COLUMNS.iter().position(|column| column == name) {
+                        record[i] = field(value);
+                    }
+                }
+                self.writer.write_record(&record)?;
+                true
+            }
+            Err(_) => {
+                let line = String::from_utf8_lossy(line).replace('\r', " ");
+                self.writer.write_record([line])?;
+                false
+            }
+        };
+        self.writer.flush()?;
+        Ok(object)
+    }
+}
+
+// a value as the csv field it'd be, on one line
+fn field(value: &RawValue) -> String {
+    let text = value.get();
+    let field = match text {
+        "null" => String::new(),
+        _ if text.starts_with('"') => serde_json::from_str(text).unwrap_or_default(),
+        _ => text.to_string(),
+    };
+    field.replace(['\r', '\n'], " ")
+}
+
+impl<R: Read> Read for JsonLines<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.at == self.out.buffer().len() {
+            self.out.buffer().clear();
+            self.at = 0;
+            self.line.clear();
+            let read = self.reader.read_until(b'\n', &mut self.line)?;
+            if read == 0 {
+                return Ok(0);
+            }
+            let object = self.convert()?;
+            self.read += read as u64;
+            self.written += self.out.buffer().len() as u64;
+            self.lines += 1;
+            self.ends
+                .push_back((self.written, self.read, self.lines, object));
+        }
+        let out = self.out.buffer();
+        let n = buf.len().min(out.len() - self.at);
+        buf[..n].copy_from_slice(&out[self.at..self.at + n]);
+        self.at += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::EngineConfig;
+    use crate::reader::RecordReader;
+    use crate::{process_reader, EngineBuilder};
+
+    #[test]
+    fn json_lines_read_like_the_same_csv() {
+        let csv = "type, client, tx, amount\n\
+                   deposit, 1, 1, 1.5\n\
+                   deposit, 2, 2, 2.0\n\
+                   withdrawal, 1, 3, 0.25\n\
+                   dispute, 2, 2,\n";
+        let jsonl = "{\"type\": \"deposit\", \"client\": 1, \"tx\": 1, \"amount\": 1.5}\n\
+                     {\"kind\": \"deposit\", \"client\": 2, \"tx\": 2, \"amount\": \"2.0\", \"note\": {}}\n\
+                     \n\
+                     not json\n\
+                     {\"type\": \"withdrawal\", \"client\": 1, \"tx\": 3, \"amount\": 0.25}\n\
+                     {\"type\": \"dispute\", \"client\": 2, \"tx\": 2, \"amount\": null}\n\
+                     {\"type\": \"deposit\", \"client\": \"x\", \"tx\": 4, \"amount\": 1}";
+        let config = EngineConfig::from_toml_str(
+            "input_format = \"jsonl\"\n\
+             [columns]\n\
+             kind = \"type\"\n",
+        )
+        .unwrap();
+
+        let report = |engine: &crate::Engine| {
+            let mut report = Vec::new();
+            engine.write_csv(&mut report).unwrap();
+            String::from_utf8(report).unwrap()
+        };
+        let mut engine = EngineBuilder::from_config(config.clone()).build();
+        engine.process_reader(jsonl.as_bytes()).unwrap();
+        assert_eq!(
+            report(&engine),
+            report(&process_reader(csv.as_bytes()).unwrap())
+        );
+        assert_eq!(engine.skipped(), 2);
+
+        // rows are numbered by line, and positions are in the input rather than the csv
+        let mut reader = RecordReader::new(jsonl.as_bytes(), &config).unwrap();
+        let mut bad = Vec::new();
+        while let Some(row) = reader.next_row().unwrap() {
+            if let Err(err) = row.parsed {
+                bad.push((row.row, err.to_string()));
+            }
+            if row.row == 5 {
+                let end = jsonl.match_indices('\n').nth(4).unwrap().0 as u64 + 1;
+                assert_eq!(reader.position(), (end, 5));
+            }
+        }
+        assert_eq!(bad.len(), 2);
+        assert_eq!(bad[0], (4, "line 4 is not a JSON object".to_string()));
+        assert_eq!(bad[1].0, 7);
+        assert!(bad[1].1.starts_with("CSV deserialize error"));
+        assert_eq!(reader.position(), (jsonl.len() as u64, 7));
+    }
+}
@@ -0,0 +1,131 @@
+use crate::engine::Engine;
+use crate::error::Error;
+use crate::reader::{default_headers, Row};
+use csv::{StringRecord, Trim};
+use rdkafka::{
+    config::ClientConfig,
+    consumer::{BaseConsumer, CommitMode, Consumer},
+    error::KafkaError,
+    Message,
+};
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+/// Where `Engine::consume_kafka` reads from and how often it settles up.
+#[derive(Debug, Clone)]
+pub struct KafkaOptions {
+    /// `bootstrap.servers`, e.g. `localhost:9092`.
+    pub brokers: String,
+    pub topic: String,
+    /// Consumer group, whose committed offsets are where a restarted consumer picks up.
+    pub group: String,
+    /// Most messages applied before offsets are committed.
+    pub batch_size: usize,
+    /// How long to wait for more messages before committing a smaller batch.
+    pub batch_timeout: Duration,
+    /// How often `snapshot` is called.
+    pub snapshot_every: Duration,
+}
+
+impl Engine {
+    /// Applies transactions from a Kafka topic until the consumer fails. Every message holds one or
+    /// more csv records without a header (`type,client,tx,amount[,signature]`), so the message's
+    /// offset is the row number in the error report.
+    ///
+    /// Messages are applied in batches. After each one `before_commit` gets the engine (to save the
+    /// ledger, say) and only then are the offsets committed, so nothing is committed that wasn't
+    /// applied and saved. Records redelivered after a crash in between are rejected as duplicates.
+    /// Every `snapshot_every`, `snapshot` gets the engine too, e.g. to write the accounts out.
+    pub fn consume_kafka(
+        &mut self,
+        options: &KafkaOptions,
+        mut before_commit: impl FnMut(&Engine) -> Result<(), Error>,
+        mut snapshot: impl FnMut(&Engine) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let consumer: BaseConsumer = ClientConfig::new()
+            .set("bootstrap.servers", &options.brokers)
+            .set("group.id", &options.group)
+            .set("enable.auto.commit", "false")
+            .set("auto.offset.reset", "earliest")
+            .create()
+            .map_err(kafka_error)?;
+        consumer.subscribe(&[&options.topic]).map_err(kafka_error)?;
+        tracing::info!(topic = options.topic, group = options.group, "consuming");
+
+        let mut last_snapshot = Instant::now();
+        loop {
+            let started = Instant::now();
+            let mut messages = 0;
+            while messages < options.batch_size {
+                let Some(remaining) = options.batch_timeout.checked_sub(started.elapsed()) else {
+                    break;
+                };
+                let Some(message) = consumer.poll(remaining) else {
+                    break;
+                };
+                let message = message.map_err(kafka_error)?;
+                messages += 1;
+                let row = u64::try_from(message.offset()).unwrap_or_default();
+                self.apply_payload(message.payload().unwrap_or_default(), row)?;
+            }
+            if messages > 0 {
+                self.counters_mut().add_time(started.elapsed());
+                self.flush_reports()?;
+                before_commit(self)?;
+                consumer
+                    .commit_consumer_state(CommitMode::Sync)
+                    .map_err(kafka_error)?;
+                tracing::info!(messages, "committed batch");
+            }
+            if last_snapshot.elapsed() >= options.snapshot_every {
+                snapshot(self)?;
+                last_snapshot = Instant::now();
+            }
+        }
+    }
+
+    /// Applies the csv records of one message.
+    pub(crate) fn apply_payload(&mut self, payload: &[u8], row: u64) -> Result<(), Error> {
+        let headers = default_headers();
+        // messages have no header to tell the delimiter from
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .delimiter(self.config().delimiter.or_comma())
+            .flexible(true)
+            .trim(Trim::All)
+            .from_reader(payload);
+        let mut raw = StringRecord::new();
+        while reader.read_record(&mut raw)? {
+            let parsed = raw.deserialize(Some(&headers));
+            self.apply_row(Row { row, parsed }, &headers, &raw)?;
+        }
+        Ok(())
+    }
+}
+
+fn kafka_error(err: KafkaError) -> Error {
+    Error::Io(io::Error::other(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn payloads_are_headerless_csv() {
+        let mut engine = Engine::new();
+        engine.apply_payload(b"deposit, 1, 1, 5.0\n", 0).unwrap();
+        engine
+            .apply_payload(b"withdrawal,1,2,1.5\ndispute,1,1", 1)
+            .unwrap();
+        // redelivered
+        engine.apply_payload(b"deposit, 1, 1, 5.0\n", 0).unwrap();
+
+        assert_eq!(engine.applied(), 3);
+        assert_eq!(engine.skipped(), 1);
+        assert_eq!(engine.accounts()[&1].held(), dec!(5.0));
+    }
+}
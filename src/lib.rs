@@ -0,0 +1,141 @@
+mod account;
+#[cfg(any(feature = "grpc", feature = "proto"))]
+mod account_proto;
+mod accrual;
+#[cfg(feature = "actors")]
+mod actor;
+mod admin;
+mod amount;
+mod anonymize;
+#[cfg(feature = "arrow")]
+mod arrow_batches;
+#[cfg(feature = "async")]
+mod async_ingest;
+mod atomic_file;
+mod audit;
+#[cfg(feature = "avro")]
+mod avro;
+mod backend;
+mod builder;
+mod checkpoint;
+#[cfg(feature = "cloud")]
+mod cloud;
+mod config;
+mod currency;
+mod diff;
+mod encryption;
+mod engine;
+mod error;
+mod error_report;
+mod events;
+#[cfg(feature = "ffi")]
+mod ffi;
+mod follow;
+mod fuzz;
+mod generate;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "http")]
+mod http_input;
+mod input;
+mod invariants;
+mod journal;
+mod jsonl;
+#[cfg(feature = "kafka")]
+mod kafka;
+mod limits;
+mod metrics;
+mod opening;
+mod output;
+mod packed;
+#[cfg(feature = "parquet")]
+mod parquet_writer;
+mod pipeline;
+mod reader;
+mod rejection;
+mod schema;
+#[cfg(feature = "server")]
+mod server;
+mod sharded;
+mod shared;
+#[cfg(feature = "sign")]
+mod signing;
+mod snapshot;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod state;
+mod statement;
+mod stats;
+mod store;
+mod transaction;
+mod two_pass;
+mod utils;
+#[cfg(feature = "wasm")]
+mod wasm;
+#[cfg(feature = "xlsx")]
+mod xlsx;
+
+pub use account::{Account, AccountMap, AccountRow, Balance};
+pub use accrual::Accrual;
+#[cfg(feature = "actors")]
+pub use actor::process_reader_actors;
+pub use admin::{admin_signature, signature_deserializer, Signature};
+pub use amount::Amount;
+pub use anonymize::Anonymize;
+#[cfg(feature = "arrow")]
+pub use arrow_batches::{account_schema, ARROW_BATCH_ROWS};
+#[cfg(feature = "async")]
+pub use async_ingest::DEFAULT_CHANNEL_CAPACITY;
+pub use atomic_file::AtomicFile;
+pub use audit::{AuditEntry, AuditLog};
+#[cfg(feature = "avro")]
+pub use avro::ACCOUNT_AVRO_SCHEMA;
+pub use backend::{open_backend, LedgerBackend, StateFile};
+pub use builder::EngineBuilder;
+pub use checkpoint::Progress;
+pub use config::{
+    read_client_list, AccountCreation, Delimiter, EngineConfig, InputFormat, LockedAccountPolicy,
+    NegativeAvailable, Overdraft, ParseMode, Rounding, INPUT_COLUMNS,
+};
+pub use currency::Currency;
+pub use diff::{diff_reports, write_deltas, AccountDelta, BalanceChange};
+#[cfg(feature = "encrypt")]
+pub use encryption::{StateKey, STATE_KEY_ENV};
+pub use engine::{process_reader, Engine, StopAt};
+pub use error::{Error, Result};
+pub use error_report::{ErrorReport, RejectedRecord};
+pub use events::EngineEvent;
+pub use fuzz::{process_bytes, Report};
+pub use generate::{generate, generate_transactions, GenerateOptions};
+#[cfg(feature = "grpc")]
+pub use grpc::{serve_grpc, ResolverService};
+pub use input::{decode_input, decompress, expand_inputs, open_input, open_path, Compression};
+#[cfg(feature = "mmap")]
+pub use input::{map_file, map_input};
+pub use invariants::InvariantViolation;
+pub use journal::{Journal, JournalEntry, LedgerAccount};
+#[cfg(feature = "kafka")]
+pub use kafka::KafkaOptions;
+pub use limits::WithdrawalLimits;
+#[cfg(feature = "server")]
+pub use metrics::MetricsServer;
+pub use output::{OutputFormat, PartitionBy, ReportOptions, SortBy};
+pub use rejection::Rejection;
+pub use schema::{infer_schema, ColumnKind, ColumnSchema, Schema};
+#[cfg(feature = "server")]
+pub use server::serve;
+pub use sharded::{process_reader_sharded, shard_for};
+pub use shared::SharedEngine;
+#[cfg(feature = "sign")]
+pub use signing::{sign_report, verify_report, SIGN_KEY_ENV};
+pub use snapshot::Snapshots;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteBackend;
+pub use state::merge_states;
+pub use statement::{statement, write_statement, StatementEntry, StatementLine};
+pub use stats::Stats;
+pub use store::{LedgerStore, MemoryStore, TransactionStore};
+pub use transaction::{DisputeStatus, Transaction, TransactionMap, TransactionType, MAX_AMOUNT};
+pub use utils::{
+    amount_deserializer, four_precision_serializer, round_to_precision, timestamp_deserializer,
+};
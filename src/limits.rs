@@ -0,0 +1,264 @@
+use crate::amount::Amount;
+use crate::currency::Currency;
+use crate::engine::SECONDS_PER_DAY;
+use crate::rejection::Rejection;
+use crate::sharded::shard_for;
+use crate::transaction::Transaction;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Caps on what each client can withdraw, the `[withdrawal_limits]` table of the config. They hold
+/// per client and currency, and only the ones that are set apply:
+///
+/// ```toml
+/// [withdrawal_limits]
+/// max_amount = 1000
+/// max_count = 5
+/// max_total = 2500
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WithdrawalLimits {
+    /// The largest single withdrawal.
+    pub max_amount: Option<Amount>,
+    /// How many withdrawals a client can make in a day.
+    pub max_count: Option<u64>,
+    /// How much a client can withdraw in a day, all withdrawals together.
+    pub max_total: Option<Amount>,
+}
+
+impl WithdrawalLimits {
+    fn tracks_velocity(&self) -> bool {
+        self.max_count.is_some() || self.max_total.is_some()
+    }
+}
+
+/// The calendar day (UTC) a unix timestamp is on, as days since 1970-01-01.
+fn day_of(timestamp: i64) -> i64 {
+    timestamp.div_euclid(SECONDS_PER_DAY)
+}
+
+/// The withdrawals a client made on its latest day, in one currency. Days go by the record's
+/// `timestamp`; one without a timestamp counts towards the day before it, so an input without
+/// timestamps is all one day.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Daily {
+    /// Days since 1970-01-01, `None` until a dated withdrawal comes along.
+    pub day: Option<i64>,
+    pub count: u64,
+    pub total: Amount,
+}
+
+/// What the engine remembers to enforce `WithdrawalLimits`, and to charge per-withdrawal fees.
+/// The daily counts are saved with the state file, the fee counts are per run.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Velocity {
+    daily: HashMap<(u16, Currency), Daily>,
+    // every withdrawal applied in the run, only counted when there's a fee for them
+    made: HashMap<(u16, Currency), u64>,
+}
+
+impl Velocity {
+    /// Whether `record`, a withdrawal, stays within `limits`.
+    pub(crate) fn check(
+        &self,
+        limits: &WithdrawalLimits,
+        record: &Transaction,
+    ) -> Result<(), Rejection> {
+        let amount = record.amount();
+        if limits.max_amount.is_some_and(|max| amount > max) {
+            return Err(Rejection::OverWithdrawalLimit);
+        }
+        if !limits.tracks_velocity() {
+            return Ok(());
+        }
+        let Some(daily) = self.daily.get(&(record.client, record.currency)) else {
+            return Ok(());
+        };
+        // a withdrawal on another day than the last one starts that day's count
+        let today = match record.timestamp.map(day_of) {
+            Some(day) if daily.day != Some(day) => Daily::default(),
+            _ => *daily,
+        };
+        if limits.max_count.is_some_and(|max| today.count >= max) {
+            return Err(Rejection::TooManyWithdrawals);
+        }
+        if limits
+            .max_total
+            .is_some_and(|max| today.total.saturating_add(amount) > max)
+        {
+            return Err(Rejection::OverWithdrawalTotal);
+        }
+        Ok(())
+    }
+
+    /// Counts `record`, a withdrawal that was applied.
+    pub(crate) fn record(&mut self, limits: &WithdrawalLimits, record: &Transaction) {
+        if !limits.tracks_velocity() {
+            return;
+        }
+        let daily = self
+            .daily
+            .entry((record.client, record.currency))
+            .or_default();
+        if let Some(day) = record.timestamp.map(day_of) {
+            if daily.day != Some(day) {
+                *daily = Daily {
+                    day: Some(day),
+                    ..Daily::default()
+                };
+            }
+        }
+        daily.count += 1;
+        daily.total = daily.total.saturating_add(record.amount());
+    }
+
+    /// Every client's latest day of withdrawals, for the state file.
+    pub(crate) fn days(&self) -> impl Iterator<Item = (u16, Currency, Daily)> + '_ {
+        self.daily
+            .iter()
+            .map(|(&(client, currency), &daily)| (client, currency, daily))
+    }
+
+    /// Swaps in the days a state file kept, replacing what was counted so far.
+    pub(crate) fn restore_days(&mut self, days: impl IntoIterator<Item = (u16, Currency, Daily)>) {
+        self.daily = days
+            .into_iter()
+            .map(|(client, currency, daily)| ((client, currency), daily))
+            .collect();
+    }
+
+    /// Counts `record`, an applied withdrawal, towards the withdrawal fees.
+    pub(crate) fn count(&mut self, record: &Transaction) {
+        *self
+            .made
+            .entry((record.client, record.currency))
+            .or_default() += 1;
+    }
+
+    /// How many withdrawals `count` counted for `client` in `currency`.
+    pub(crate) fn made(&self, client: u16, currency: Currency) -> u64 {
+        self.made.get(&(client, currency)).copied().unwrap_or(0)
+    }
+
+    /// Moves everything out into `shards` parts, partitioned like the engine's clients.
+    pub(crate) fn split(&mut self, shards: usize) -> Vec<Velocity> {
+        let mut parts = vec![Velocity::default(); shards];
+        for ((client, currency), daily) in self.daily.drain() {
+            parts[shard_for(client, shards)]
+                .daily
+                .insert((client, currency), daily);
+        }
+        for ((client, currency), made) in self.made.drain() {
+            parts[shard_for(client, shards)]
+                .made
+                .insert((client, currency), made);
+        }
+        parts
+    }
+
+    /// Moves everything out, one part per client.
+    #[cfg(feature = "actors")]
+    pub(crate) fn split_clients(&mut self) -> HashMap<u16, Velocity> {
+        let mut parts: HashMap<u16, Velocity> = HashMap::new();
+        for ((client, currency), daily) in self.daily.drain() {
+            parts
+                .entry(client)
+                .or_default()
+                .daily
+                .insert((client, currency), daily);
+        }
+        for ((client, currency), made) in self.made.drain() {
+            parts
+                .entry(client)
+                .or_default()
+                .made
+                .insert((client, currency), made);
+        }
+        parts
+    }
+
+    pub(crate) fn merge(&mut self, other: Velocity) {
+        self.daily.extend(other.daily);
+        self.made.extend(other.made);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Engine, EngineBuilder, EngineConfig, Rejection};
+    use rust_decimal_macros::dec;
+
+    fn limited() -> Engine {
+        let config = EngineConfig::from_toml_str(
+            "[withdrawal_limits]\n\
+             max_amount = 50\n\
+             max_count = 2\n\
+             max_total = \"60.5\"\n",
+        )
+        .unwrap();
+        EngineBuilder::from_config(config).build()
+    }
+
+    #[test]
+    fn withdrawals_over_the_daily_limits_are_rejected() {
+        let input = "type, client, tx, amount, timestamp\n\
+                     deposit, 1, 1, 500.0, 2024-03-01T08:00:00Z\n\
+                     withdrawal, 1, 2, 50.5, 2024-03-01T09:00:00Z\n\
+                     withdrawal, 1, 3, 40.0, 2024-03-01T10:00:00Z\n\
+                     withdrawal, 1, 4, 30.0, 2024-03-01T11:00:00Z\n\
+                     withdrawal, 1, 5, 20.5, 2024-03-01T12:00:00Z\n\
+                     withdrawal, 1, 6, 0.5, 2024-03-01T23:59:59Z\n\
+                     deposit, 2, 7, 500.0, 2024-03-01T08:00:00Z\n\
+                     withdrawal, 2, 8, 40.0, 2024-03-01T13:00:00Z\n\
+                     withdrawal, 1, 9, 30.0, 2024-03-02T00:00:00Z\n\
+                     withdrawal, 1, 10, 31.0,\n";
+        let mut engine = limited();
+        engine.process_reader(input.as_bytes()).unwrap();
+
+        // the day is full after tx 5, tx 9 is on the next one and client 2 is counted on its own.
+        // tx 10 has no timestamp, so it's on tx 9's day and goes past its total
+        assert_eq!(
+            engine.stats().by_reason,
+            [
+                (Rejection::OverWithdrawalTotal, 2),
+                (Rejection::OverWithdrawalLimit, 1),
+                (Rejection::TooManyWithdrawals, 1),
+            ]
+        );
+        assert_eq!(engine.accounts()[&1].total(), dec!(409.5));
+        assert_eq!(engine.accounts()[&2].total(), dec!(460.0));
+    }
+
+    #[test]
+    fn the_days_withdrawals_carry_over_in_the_state() {
+        let mut morning = limited();
+        morning
+            .process_reader(
+                "type, client, tx, amount, timestamp\n\
+                 deposit, 1, 1, 500.0, 2024-03-01\n\
+                 withdrawal, 1, 2, 40.0, 2024-03-01T09:00:00Z\n\
+                 withdrawal, 1, 3, 20.0, 2024-03-01T10:00:00Z\n"
+                    .as_bytes(),
+            )
+            .unwrap();
+        let mut state = Vec::new();
+        morning.save_state(&mut state).unwrap();
+
+        let mut afternoon = limited();
+        afternoon.load_state(state.as_slice()).unwrap();
+        afternoon
+            .process_reader(
+                "type, client, tx, amount, timestamp\n\
+                 withdrawal, 1, 4, 1.0, 2024-03-01T15:00:00Z\n\
+                 withdrawal, 1, 5, 1.0, 2024-03-02T09:00:00Z\n"
+                    .as_bytes(),
+            )
+            .unwrap();
+        assert_eq!(
+            afternoon.stats().by_reason,
+            [(Rejection::TooManyWithdrawals, 1)]
+        );
+        assert_eq!(afternoon.accounts()[&1].total(), dec!(439.0));
+    }
+}
@@ -1,300 +1,805 @@
-use csv::Trim;
-use rust_decimal::prelude::*;
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use std::{collections::HashMap, env, error::Error, io, process};
-
-#[derive(Debug, Deserialize)]
-pub struct Transaction {
-    // I could either escape type like r#type or rename it bc it's a reserved word
-    #[serde(rename = "type")]
-    r_type: String,
-    client: u16,
-    tx: u32,
-    #[serde(deserialize_with = "four_precision_deserializer")]
-    amount: f64,
-}
-
-#[derive(Debug, Deserialize, Serialize)]
-pub struct Account {
-    client: u16,
-    #[serde(serialize_with = "four_precision_serializer")]
-    available: f64,
-    #[serde(serialize_with = "four_precision_serializer")]
-    held: f64,
-    #[serde(serialize_with = "four_precision_serializer")]
-    total: f64,
-    locked: bool,
-}
-
-pub type AccountMap = HashMap<u16, Account>;
-pub type TransactionMap = HashMap<u32, Transaction>;
+mod cli;
+mod exit;
+mod progress;
+
+use clap::Parser;
+use cli::{Cli, Command, InputArgs, ResolveArgs};
+use csv_tx_resolver::{
+    decode_input, decompress, expand_inputs, open_backend, open_input, open_path, read_client_list,
+    AtomicFile, Engine, EngineConfig, Error, LedgerBackend, ParseMode, Progress, ReportOptions,
+    Snapshots, StateFile,
+};
+use progress::InputProgress;
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    io::Read,
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, LazyLock,
+    },
+};
+
+/// Set by the first SIGINT or SIGTERM: the run stops after the record it's on and writes what it
+/// has. Another one while that's going on exits right away.
+static INTERRUPT: LazyLock<Arc<AtomicBool>> = LazyLock::new(Arc::default);
 
 fn main() {
-    // get the filename argument
-    let arg: String = env::args().nth(1).expect("No csv file path given!");
-
-    if let Err(err) = read_from_file(&arg) {
-        println!("Could not read from file: {}", err);
-        process::exit(1);
-    }
-}
-
-fn read_from_file(path: &String) -> Result<(), Box<dyn Error>> {
-    let mut accounts: AccountMap = HashMap::new();
-    let mut transactions: TransactionMap = HashMap::new();
-
-    // TODO: try tokio_codec::FramedRead
-    let mut custom_reader = csv::ReaderBuilder::new()
-        .has_headers(true)
-        .trim(Trim::All)
-        .from_path(path)?;
-
-    for result in custom_reader.deserialize() {
-        let record: Transaction = result?;
-        record.save(&mut transactions);
-        let account_id = record.create_account_if_not_exists(&mut accounts);
-        let account = accounts.entry(account_id);
-        if record.r_type == "deposit" {
-            account.and_modify(|this_account| this_account.deposit(record.amount));
-        } else if record.r_type == "withdrawal" {
-            account.and_modify(|this_account| this_account.withdraw(record.amount));
-        } else if record.r_type == "dispute" {
-            let referenced_tx_opt = transactions.get(&record.tx);
-            match referenced_tx_opt {
-                Some(referenced_tx) => {
-                    account.and_modify(|this_account| this_account.dispute(referenced_tx.amount));
-                }
-                None => (), // ignore none case. TX does not exist
-            }
-        } else if record.r_type == "resolve" {
-            let referenced_tx_opt = transactions.get(&record.tx);
-            match referenced_tx_opt {
-                Some(referenced_tx) => {
-                    account.and_modify(|this_account| this_account.resolve(referenced_tx.amount));
-                }
-                None => (), // ignore none case. TX does not exist
-            }
-        } else if record.r_type == "chargeback" {
-            let referenced_tx_opt = transactions.get(&record.tx);
-            match referenced_tx_opt {
-                Some(referenced_tx) => {
-                    account
-                        .and_modify(|this_account| this_account.chargeback(referenced_tx.amount));
-                }
-                None => (), // ignore none case. TX does not exist
+    let cli = Cli::parse();
+    tracing_subscriber::fmt()
+        .with_max_level(cli.log_level())
+        .with_writer(io::stderr)
+        .init();
+
+    let result = match cli.into_command() {
+        Command::Resolve(args) => resolve(&args),
+        Command::Validate(args) => validate(&args.input),
+        Command::Report(args) => report(&args.input),
+        Command::History(args) => history(&args),
+        Command::Diff(args) => diff(&args),
+        Command::Statement(args) => statement(&args),
+        Command::Merge(args) => merge(&args),
+        Command::Schema(args) => schema(&args),
+        Command::Generate(args) => generate(&args),
+        #[cfg(feature = "server")]
+        Command::Serve(args) => serve(&args),
+        #[cfg(feature = "grpc")]
+        Command::Grpc(args) => grpc(&args),
+    };
+
+    match result {
+        Ok(_) if INTERRUPT.load(Ordering::Relaxed) => process::exit(exit::INTERRUPTED),
+        Ok(0) => process::exit(exit::OK),
+        Ok(_) => process::exit(exit::REJECTED),
+        // whatever read our output went away (`| head`), nothing to tell it
+        Err(Error::Io(err)) if err.kind() == io::ErrorKind::BrokenPipe => process::exit(exit::IO),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            process::exit(exit::for_error(&err));
+        }
+    }
+}
+
+// every subcommand hands back how many records were rejected, for the exit code
+
+fn resolve(args: &ResolveArgs) -> Result<u64, Error> {
+    // --sign-key itself requires --output, a key in the environment is only seen here
+    #[cfg(feature = "sign")]
+    if args.output.is_none() && std::env::var_os(csv_tx_resolver::SIGN_KEY_ENV).is_some() {
+        cli::usage_error("a signed report needs --output, the signature goes next to it");
+    }
+    if args.follow {
+        return follow(args);
+    }
+    let engine = run(&args.input, engine_config(&args.input)?)?;
+    // a replay that stopped partway isn't where the ledger is at
+    let partway = engine.stopped() || engine.interrupted();
+    if let (Some(mut ledger), false) = (ledger(&args.input)?, partway) {
+        ledger.save(&engine)?;
+    }
+    if !engine.interrupted() {
+        write_report(&engine, args, args.output.as_deref())?;
+        return Ok(engine.skipped());
+    }
+    // next to the report rather than over it, so nothing picks it up for a finished one
+    let output = args.output.as_deref().map(partial_path);
+    write_report(&engine, args, output.as_deref())?;
+    match &output {
+        Some(path) => eprintln!("partial report written to {}", path.display()),
+        None => eprintln!("the report on stdout is partial"),
+    }
+    Ok(engine.skipped())
+}
+
+/// `accounts.csv` as `accounts.partial.csv`.
+fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(".partial");
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+fn write_report(engine: &Engine, args: &ResolveArgs, output: Option<&Path>) -> Result<(), Error> {
+    let options = args.report_options();
+    let written = match (output, args.partition_output) {
+        (Some(path), Some(parts)) => {
+            let parts = parts as usize;
+            // nothing is renamed into place until every part is written
+            let files = engine.write_partitioned(&options, parts, args.partition_by, |i| {
+                Ok(AtomicFile::create(partition_path(path, i, parts))?)
+            })?;
+            for file in files {
+                file.commit()?;
             }
+            (0..parts).map(|i| partition_path(path, i, parts)).collect()
+        }
+        (Some(path), None) => {
+            let mut file = AtomicFile::create(path)?;
+            engine.write_report(&options, &mut file)?;
+            file.commit()?;
+            vec![path.to_path_buf()]
         }
+        (None, _) => {
+            engine.write_report(&options, io::stdout())?;
+            Vec::new()
+        }
+    };
+    sign_reports(args, &written)?;
+    Ok(())
+}
+
+/// Writes `accounts.csv.sig` next to every report file, with the `--sign-key` (or the key in its
+/// environment variable) if there is one.
+#[cfg(feature = "sign")]
+fn sign_reports(args: &ResolveArgs, written: &[PathBuf]) -> Result<(), Error> {
+    let key = match &args.sign_key {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))?,
+        None => match std::env::var(csv_tx_resolver::SIGN_KEY_ENV) {
+            Ok(key) => key,
+            Err(_) => return Ok(()),
+        },
+    };
+    for path in written {
+        let mut report = Vec::new();
+        open_path(path)?.read_to_end(&mut report)?;
+        let signature = csv_tx_resolver::sign_report(&key, &report)?;
+        let mut name = path.as_os_str().to_os_string();
+        name.push(".sig");
+        let mut file = AtomicFile::create(PathBuf::from(name))?;
+        io::Write::write_all(&mut file, &signature)?;
+        file.commit()?;
     }
-    csv_stdout(&accounts)?;
     Ok(())
 }
 
-pub fn four_precision_deserializer<'de, D>(deserializer: D) -> Result<f64, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let given_f64: f64 = Option::deserialize(deserializer)?.unwrap_or(0.0);
-    // could panic on unwrap
-    let chopped_decimal = Decimal::from_f64(given_f64)
-        .unwrap()
-        .round_dp_with_strategy(4, RoundingStrategy::ToZero);
-    let chopped_f64 = Decimal::to_f64(&chopped_decimal).unwrap_or(0.0);
-    Ok(chopped_f64)
-}
-
-fn four_precision_serializer<S>(data: &f64, serializer: S) -> Result<S::Ok, S::Error>
-where
-    S: Serializer,
-{
-    // I should assume up to 4 precision. If given more than 4 precision, drop the extra.
-    let chopped_decimal = Decimal::from_f64(*data)
-        .unwrap()
-        .round_dp_with_strategy(4, RoundingStrategy::ToZero);
-    let chopped_f64 = Decimal::to_f64(&chopped_decimal).unwrap();
-    serializer.serialize_f64(chopped_f64)
-}
-
-fn csv_stdout(accounts: &AccountMap) -> Result<(), Box<dyn Error>> {
-    let mut writer = csv::WriterBuilder::new()
-        .has_headers(true)
-        .from_writer(io::stdout());
-    for (_, account) in accounts.iter() {
-        writer.serialize(account)?;
-    }
-    writer.flush()?;
+#[cfg(not(feature = "sign"))]
+fn sign_reports(_: &ResolveArgs, _: &[PathBuf]) -> Result<(), Error> {
     Ok(())
 }
 
-impl Transaction {
-    fn save(&self, transactions: &mut TransactionMap) -> u32 {
-        // only save on withdrawal or deposit
-        if self.r_type == "withdrawal" || self.r_type == "deposit" {
-            transactions.insert(
-                self.tx,
-                Transaction {
-                    r_type: self.r_type.clone(),
-                    amount: self.amount,
-                    client: self.client,
-                    tx: self.tx,
-                },
-            );
+/// `accounts.csv` as the `i`th of `parts` files, `accounts-007.csv`. At least three digits, more
+/// if there are more files, so they sort in order.
+fn partition_path(path: &Path, i: usize, parts: usize) -> PathBuf {
+    let width = (parts - 1).max(1).ilog10() as usize + 1;
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!("-{:0width$}", i, width = width.max(3)));
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+/// `resolve --follow`: never gets to the end of its file, so the report (and the ledger) are
+/// written whenever they're asked for instead, and once more when it's interrupted.
+fn follow(args: &ResolveArgs) -> Result<u64, Error> {
+    use std::time::{Duration, Instant};
+
+    let [path] = args.input.paths.as_slice() else {
+        cli::usage_error("--follow takes exactly one file");
+    };
+    if path == "-" || args.input.checkpoint.is_some() || args.input.shards > 1 {
+        cli::usage_error("--follow needs a file, drop stdin/--checkpoint/--shards");
+    }
+    if args.input.parquet_audit() {
+        cli::usage_error("a Parquet --audit needs a run that ends, use csv with --follow");
+    }
+    if args.input.stop_at().is_some() {
+        cli::usage_error("--follow doesn't stop, drop --until-tx/--until-row");
+    }
+    if args.input.two_pass {
+        cli::usage_error("--follow never gets to the end of its file, drop --two-pass");
+    }
+    if args.input.mmap() {
+        cli::usage_error("--follow reads what gets appended, which a map doesn't see, drop --mmap");
+    }
+    let (mut engine, _) = open_engine(&args.input, engine_config(&args.input)?)?;
+    let mut ledger = ledger(&args.input)?;
+    let update_metrics = metrics_server(&args.input)?;
+    catch_interrupts(&mut engine)?;
+    let snapshots = snapshots(args.snapshot_dir.as_deref(), args.report_options())?;
+
+    let requested = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    signal_hook::flag::register(signal_hook::consts::SIGUSR1, requested.clone())?;
+    let every = args.report_every.map(Duration::from_secs);
+    let mut last_report = Instant::now();
+
+    engine.follow(path, Duration::from_millis(args.follow_poll_ms), |engine| {
+        update_metrics(engine);
+        if let Some(snapshots) = &snapshots {
+            snapshots.write_requested(engine);
         }
-        self.tx
-    }
-    fn create_account_if_not_exists(&self, accounts: &mut AccountMap) -> u16 {
-        let account_opt = accounts.get(&self.client);
-        match account_opt {
-            Some(_) => self.client,
-            None => {
-                accounts.insert(
-                    self.client,
-                    Account {
-                        available: 0.0,
-                        client: self.client,
-                        held: 0.0,
-                        locked: false,
-                        total: 0.0,
-                    },
-                );
-                self.client
+        let due = every.is_some_and(|every| last_report.elapsed() >= every);
+        if requested.swap(false, Ordering::Relaxed) || due || engine.interrupted() {
+            if let Some(ledger) = &mut ledger {
+                ledger.save(engine)?;
             }
+            write_report(engine, args, args.output.as_deref())?;
+            last_report = Instant::now();
         }
+        Ok(true)
+    })?;
+    Ok(engine.skipped())
+}
+
+/// Preflights the input: every record is parsed and checked against the ledger it builds up, but
+/// nothing is written except the problems. Those go to `--errors`, or to stdout as the same csv,
+/// and a summary goes to stderr.
+fn validate(args: &InputArgs) -> Result<u64, Error> {
+    if args.audit.is_some() || args.ledger.is_some() || args.checkpoint.is_some() {
+        cli::usage_error("validate doesn't write anything, drop --audit/--ledger/--checkpoint");
+    }
+    if args.state_hash.is_some() {
+        cli::usage_error("validate doesn't keep the ledger it checks against, drop --state-hash");
+    }
+    #[cfg(feature = "kafka")]
+    if args.source == cli::Source::Kafka {
+        cli::usage_error("validate needs input that ends, not --source kafka");
+    }
+    // the point is to see every problem, not just the first
+    let config = EngineConfig {
+        strict: false,
+        parsing: ParseMode::Lenient,
+        ..engine_config(args)?
+    };
+    let (mut engine, _) = open_engine(args, config)?;
+    if args.errors.is_none() {
+        engine.set_error_report(io::stdout());
     }
+    run_inputs(&mut engine, args, &None)?;
+    if engine.skipped() > 0 {
+        eprintln!(
+            "{} of {} records rejected",
+            engine.skipped(),
+            engine.skipped() + engine.applied()
+        );
+    } else {
+        eprintln!("{} records ok", engine.applied());
+    }
+    Ok(engine.skipped())
+}
+
+fn report(args: &InputArgs) -> Result<u64, Error> {
+    let engine = run(args, engine_config(args)?)?;
+    print!("{}", engine.stats());
+    Ok(engine.skipped())
 }
 
-impl Account {
-    fn dispute(&mut self, amount: f64) {
-        self.held = self.held + amount;
-        self.available = self.total - self.held;
+/// The client's slice of the audit log, on stdout. Its rejected records are in `--errors`.
+fn history(args: &cli::HistoryArgs) -> Result<u64, Error> {
+    if args.input.audit.is_some() || args.input.checkpoint.is_some() {
+        cli::usage_error(
+            "history is written like --audit from the start, drop --audit/--checkpoint",
+        );
+    }
+    #[cfg(feature = "kafka")]
+    if args.input.source == cli::Source::Kafka {
+        cli::usage_error("history needs input that ends, not --source kafka");
+    }
+    if args.input.state_hash.is_some() {
+        cli::usage_error("history only writes the client's log, drop --state-hash");
     }
+    let (mut engine, _) = open_engine(&args.input, engine_config(&args.input)?)?;
+    engine.set_history(args.client, io::stdout());
+    run_inputs(&mut engine, &args.input, &None)?;
+    Ok(engine.skipped())
+}
+
+/// Exits like `diff`: 0 if the reports agree, 1 if some account changed.
+fn diff(args: &cli::DiffArgs) -> Result<u64, Error> {
+    let open = |path: &Path| {
+        open_path(path)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))
+    };
+    let deltas = csv_tx_resolver::diff_reports(open(&args.old)?, open(&args.new)?)?;
+    csv_tx_resolver::write_deltas(&deltas, io::stdout())?;
+    Ok(deltas.len() as u64)
+}
 
-    fn resolve(&mut self, amount: f64) {
-        // ignore if not in dispute. aka nothing is held
-        if self.held > 0.0 {
-            self.held = self.held - amount;
-            self.available = self.total - self.held;
+fn statement(args: &cli::StatementArgs) -> Result<u64, Error> {
+    let audit = open_path(&args.audit)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", args.audit.display(), err)))?;
+    let lines = csv_tx_resolver::statement(audit, args.client)?;
+    match &args.output {
+        Some(path) => {
+            let mut file = AtomicFile::create(path)?;
+            csv_tx_resolver::write_statement(&lines, &mut file)?;
+            file.commit()?;
         }
+        None => csv_tx_resolver::write_statement(&lines, io::stdout())?,
     }
+    Ok(0)
+}
 
-    fn chargeback(&mut self, amount: f64) {
-        // ignore if not in dispute. aka nothing is held
-        if self.held > 0.0 {
-            self.held = self.held - amount;
-            self.total = self.total - amount;
-            self.locked = true;
+fn merge(args: &cli::MergeArgs) -> Result<u64, Error> {
+    let open = |path: &Path| {
+        open_path(path)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))
+    };
+    let (a, b) = (open(&args.a)?, open(&args.b)?);
+    match &args.output {
+        Some(path) => {
+            let mut file = AtomicFile::create(path)?;
+            csv_tx_resolver::merge_states(a, b, &mut file)?;
+            file.commit()?;
         }
+        None => csv_tx_resolver::merge_states(a, b, io::stdout())?,
     }
+    Ok(0)
+}
 
-    fn deposit(&mut self, deposit_amount: f64) {
-        // locked should prevent deposits and withdrawals
-        if !self.locked {
-            self.total = self.total + deposit_amount;
-            self.available = self.available + deposit_amount;
+/// Exits 1 if the header wouldn't do for a run.
+fn schema(args: &cli::SchemaArgs) -> Result<u64, Error> {
+    let mut config = load_config(args.config.as_deref())?;
+    if let Some(delimiter) = args.delimiter {
+        config.delimiter = delimiter;
+    }
+    config.columns.extend(args.columns.iter().cloned());
+    let reader = match args.path.as_str() {
+        "-" => decompress(io::stdin())?,
+        path => open_input(path)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path, err)))?,
+    };
+    let schema = csv_tx_resolver::infer_schema(reader, &config, args.rows)?;
+    print!("{}", schema);
+    Ok(u64::from(schema.problem.is_some()))
+}
+
+fn generate(args: &cli::GenerateArgs) -> Result<u64, Error> {
+    match &args.output {
+        Some(path) => {
+            let mut file = AtomicFile::create(path)?;
+            csv_tx_resolver::generate(&args.options(), &mut file)?;
+            file.commit()?;
         }
-        
+        None => csv_tx_resolver::generate(&args.options(), io::stdout())?,
+    }
+    Ok(0)
+}
+
+#[cfg(feature = "server")]
+fn serve(args: &cli::ServeArgs) -> Result<u64, Error> {
+    let mut engine = service_engine(&args.service)?;
+    let snapshots = snapshots(args.snapshot_dir.as_deref(), Default::default())?;
+    csv_tx_resolver::serve(&mut engine, &args.listen, snapshots.as_ref())?;
+    Ok(engine.skipped())
+}
+
+#[cfg(feature = "grpc")]
+fn grpc(args: &cli::GrpcArgs) -> Result<u64, Error> {
+    let engine = csv_tx_resolver::SharedEngine::new(service_engine(&args.service)?);
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(csv_tx_resolver::serve_grpc(engine.clone(), args.listen))?;
+    let skipped = engine.lock().skipped();
+    Ok(skipped)
+}
+
+#[cfg(any(feature = "server", feature = "grpc"))]
+fn service_engine(args: &cli::ServiceArgs) -> Result<Engine, Error> {
+    let mut engine = Engine::with_config(load_config(args.config.as_deref())?);
+    engine.set_warnings(args.warn);
+    if let Some(path) = &args.errors {
+        engine.set_error_report(File::create(path)?);
+    }
+    if let Some(path) = &args.audit {
+        open_audit_log(&mut engine, path)?;
+    }
+    Ok(engine)
+}
+
+/// Where the ledger is kept between runs, `--state` or `--backend`.
+fn ledger(args: &InputArgs) -> Result<Option<Box<dyn LedgerBackend>>, Error> {
+    match (&args.state, &args.backend) {
+        (Some(path), _) => Ok(Some(Box::new(StateFile(path.clone())))),
+        (None, Some(spec)) => open_backend(spec).map(Some),
+        (None, None) => Ok(None),
+    }
+}
+
+/// The `--state-key-file` key, or the one in its environment variable.
+#[cfg(feature = "encrypt")]
+fn state_key(args: &InputArgs) -> Result<Option<csv_tx_resolver::StateKey>, Error> {
+    let hex = match &args.state_key_file {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))?,
+        None => match std::env::var(csv_tx_resolver::STATE_KEY_ENV) {
+            Ok(hex) => hex,
+            Err(_) => return Ok(None),
+        },
+    };
+    csv_tx_resolver::StateKey::from_hex(&hex).map(Some)
+}
+
+fn load_config(path: Option<&Path>) -> Result<EngineConfig, Error> {
+    match path {
+        Some(path) => EngineConfig::from_toml_file(path),
+        None => Ok(EngineConfig::default()),
+    }
+}
+
+fn open_audit_log(engine: &mut Engine, path: &Path) -> Result<(), Error> {
+    // append only, the header goes in when the log is first created
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    if file.metadata()?.len() == 0 {
+        engine.set_audit_log(file);
+    } else {
+        engine.continue_audit_log(file);
+    }
+    Ok(())
+}
+
+/// `--config` with the flags that override it.
+fn engine_config(args: &InputArgs) -> Result<EngineConfig, Error> {
+    let mut config = load_config(args.config.as_deref())?;
+    config.strict |= args.strict;
+    config.check_invariants |= args.check_invariants;
+    config.fast_parse |= args.fast;
+    config.formatted_amounts |= args.formatted_amounts;
+    config.decimal_comma |= args.decimal_comma;
+    if let Some(delimiter) = args.delimiter {
+        config.delimiter = delimiter;
+    }
+    if let Some(format) = args.input_format {
+        config.input_format = format;
+    }
+    config.columns.extend(args.columns.iter().cloned());
+    if let Some(max) = args.max_amount {
+        config.max_amount = Some(max);
     }
+    if let Some(at) = args.suspicious_amount {
+        config.suspicious_amount = Some(at);
+    }
+    if let Some(path) = &args.include_clients {
+        config.include_clients = Some(read_client_list(path)?);
+    }
+    if let Some(path) = &args.exclude_clients {
+        config.exclude_clients.extend(read_client_list(path)?);
+    }
+    Ok(config)
+}
 
-    fn withdraw(&mut self, withdraw_amount: f64) {
-        // check to make sure user does not overdraft
-        // locked should prevent deposits and withdrawals
-        if withdraw_amount < self.available && !self.locked {
-            self.total = self.total - withdraw_amount;
-            self.available = self.available - withdraw_amount;
+/// Sets the engine up from the flags, returning where an interrupted run left off if it's resumed.
+fn open_engine(
+    args: &InputArgs,
+    config: EngineConfig,
+) -> Result<(Engine, Option<Progress>), Error> {
+    let mut engine = Engine::with_config(config);
+    engine.set_warnings(args.warn);
+    #[cfg(feature = "encrypt")]
+    if let Some(key) = state_key(args)? {
+        engine.set_state_key(key);
+    }
+    #[cfg(feature = "spill")]
+    if let Some(limit) = args.spill_after {
+        engine.set_spill_limit(limit, args.spill_dir.as_deref())?;
+    }
+    if args.checkpoint.is_some() && args.shards > 1 {
+        cli::usage_error("--checkpoint only works single-threaded, drop --shards");
+    }
+    if args.pipeline && (args.shards > 1 || args.checkpoint.is_some()) {
+        cli::usage_error("--pipeline is a single ordered stream, drop --shards/--checkpoint");
+    }
+    #[cfg(feature = "actors")]
+    if args.actors.is_some() && (args.shards > 1 || args.pipeline || args.checkpoint.is_some()) {
+        cli::usage_error("--actors runs its own workers, drop --shards/--pipeline/--checkpoint");
+    }
+    #[cfg(all(feature = "actors", feature = "spill"))]
+    if args.actors.is_some() && args.spill_after.is_some() {
+        cli::usage_error("--actors keeps every client in memory, drop --spill-after");
+    }
+    if args.two_pass && (args.state.is_some() || args.backend.is_some()) {
+        cli::usage_error(
+            "--two-pass doesn't keep every transaction to save, drop --state/--backend",
+        );
+    }
+    if args.parquet_audit() && args.resume {
+        cli::usage_error("a Parquet --audit can't be carried on, drop --resume");
+    }
+
+    // a checkpoint already holds the ledger the run started from, so that isn't loaded again
+    let progress = match (&args.checkpoint, args.resume) {
+        (Some(path), true) => engine.load_checkpoint(path)?,
+        _ => None,
+    };
+    if let (Some(mut ledger), None) = (ledger(args)?, &progress) {
+        ledger.load(&mut engine)?;
+    }
+    if let (Some(path), None) = (&args.opening_balances, &progress) {
+        engine.load_opening_balances(open_path(path)?)?;
+    }
+    if let Some(path) = &args.errors {
+        if progress.is_some() {
+            engine.continue_error_report(OpenOptions::new().append(true).open(path)?);
+        } else {
+            engine.set_error_report(File::create(path)?);
         }
     }
+    match &args.audit {
+        #[cfg(feature = "parquet")]
+        Some(path) if args.parquet_audit() => engine.set_audit_log_parquet(File::create(path)?)?,
+        Some(path) => open_audit_log(&mut engine, path)?,
+        None => {}
+    }
+    if let Some(path) = &args.ledger {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        if file.metadata()?.len() == 0 {
+            engine.set_journal(file);
+        } else {
+            engine.continue_journal(file);
+        }
+    }
+    if let Some(path) = &args.checkpoint {
+        engine.set_checkpoints(path, args.checkpoint_every);
+    }
+    if let Some(at) = args.stop_at() {
+        engine.set_stop(at);
+    }
+    Ok((engine, progress))
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+fn run(args: &InputArgs, config: EngineConfig) -> Result<Engine, Error> {
+    let (mut engine, progress) = open_engine(args, config)?;
+
+    #[cfg(feature = "kafka")]
+    if args.source == cli::Source::Kafka {
+        if args.checkpoint.is_some() || args.shards > 1 {
+            cli::usage_error("--source kafka commits offsets instead, drop --checkpoint/--shards");
+        }
+        if args.stop_at().is_some() || args.two_pass {
+            cli::usage_error("--source kafka doesn't stop, drop --until-tx/--until-row/--two-pass");
+        }
+        if args.parquet_audit() {
+            cli::usage_error(
+                "a Parquet --audit needs a run that ends, use csv with --source kafka",
+            );
+        }
+        if args.input_format == Some(csv_tx_resolver::InputFormat::Jsonl) {
+            cli::usage_error("--source kafka reads csv messages, drop --input-format jsonl");
+        }
+        consume_kafka(&mut engine, args)?;
+        return Ok(engine);
+    }
+
+    run_inputs(&mut engine, args, &progress)?;
+    // fees and interest are for a period that's over, not one that was cut short
+    if engine.config().accrual.is_set() && !engine.interrupted() && !engine.stopped() {
+        engine.accrue()?;
+    }
+    engine.finish_audit_log()?;
+    if engine.interrupted() {
+        // the checkpoint is where the run stopped, for --resume to carry on from
+        return Ok(engine);
+    }
+    if args.stop_at().is_some() && !engine.stopped() {
+        tracing::warn!("never got to --until-tx/--until-row, the whole input was replayed");
+    }
+    // after accrual, the last change to the ledger, and only for one that's final
+    match (args.state_hash.as_deref(), engine.stopped()) {
+        (Some(_), true) => tracing::warn!("no --state-hash, the replay stopped partway"),
+        (Some("-"), false) => eprintln!("{}", engine.state_hash()?),
+        (Some(path), false) => fs::write(path, format!("{}\n", engine.state_hash()?))?,
+        (None, _) => {}
+    }
+
+    // the run made it to the end, nothing left to resume
+    if let Some(path) = &args.checkpoint {
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(Error::Io(err)),
+            _ => {}
+        }
+    }
+    Ok(engine)
+}
 
-    #[test]
-    fn account_can_deposit() {
-        let mut account = Account {
-            available: 0.0,
-            client: 1,
-            held: 0.0,
-            locked: false,
-            total: 0.0
+/// Applies the PATHS (or stdin) and writes `--stats`.
+fn run_inputs(
+    engine: &mut Engine,
+    args: &InputArgs,
+    progress: &Option<Progress>,
+) -> Result<(), Error> {
+    #[cfg(feature = "server")]
+    if args.metrics.is_some() {
+        cli::usage_error("--metrics is for runs that don't end, use --follow or --source kafka");
+    }
+    // no path or "-" means read from stdin so we can sit in a pipeline
+    // either way gzip/zstd input is decompressed on the fly
+    let stdin = args.paths.is_empty() || args.paths == ["-"];
+    let paths = match stdin {
+        true => Vec::new(),
+        false => expand_inputs(&args.paths)?,
+    };
+    catch_interrupts(engine)?;
+    let bar = args.progress.then(|| {
+        // a file that isn't there fails below with a better error, a pipe has no size to go by
+        let mut total = (!stdin).then_some(0);
+        for metadata in paths.iter().filter_map(|path| fs::metadata(path).ok()) {
+            total = total
+                .filter(|_| metadata.is_file())
+                .map(|total| total + metadata.len());
+        }
+        InputProgress::new(total)
+    });
+    if args.two_pass {
+        if stdin {
+            cli::usage_error("--two-pass reads its inputs twice, it needs files rather than stdin");
+        }
+        if paths.iter().any(|path| is_pipe(path)) {
+            cli::usage_error(
+                "--two-pass reads its inputs twice, a named pipe can only be read once",
+            );
+        }
+        // a resumed run has its index from the checkpoint
+        if !engine.scanned_disputes() {
+            for path in &paths {
+                let with_path = |err: io::Error| {
+                    io::Error::new(err.kind(), format!("{}: {}", path.display(), err))
+                };
+                engine.scan_disputes(open_input(path).map_err(with_path)?)?;
+            }
+        }
+    }
+    if stdin {
+        let reader = match &bar {
+            Some(bar) => bar.rows(decompress(bar.bytes(io::stdin()))?),
+            None => decompress(io::stdin())?,
         };
-        account.deposit(100.0);
-
-        assert_eq!(account.available, 100.0);
-        assert_eq!(account.total, 100.0)
-    }
-    #[test]
-    fn account_cannot_overdraft() {
-        let mut account = Account {
-            available: 10.0,
-            client: 1,
-            held: 0.0,
-            locked: false,
-            total: 10.0
+        run_reader(engine, 0, reader, args, progress)?;
+    }
+    // every file is applied on top of the previous ones, like one long stream
+    for (input, path) in paths.iter().enumerate() {
+        let with_path =
+            |err: io::Error| io::Error::new(err.kind(), format!("{}: {}", path.display(), err));
+        let reader = match &bar {
+            Some(bar) => {
+                let file = open_file(path, args.mmap()).map_err(with_path)?;
+                bar.rows(decode_input(path, bar.bytes(file)).map_err(with_path)?)
+            }
+            #[cfg(feature = "mmap")]
+            None if args.mmap() && !is_pipe(path) => {
+                csv_tx_resolver::map_input(path).map_err(with_path)?
+            }
+            None => open_input(path).map_err(with_path)?,
         };
-        account.withdraw(9.0);
+        tracing::info!(input, path = %path.display(), "processing");
+        run_reader(engine, input, reader, args, progress)?;
+    }
+    if let Some(bar) = bar {
+        bar.finish();
+    }
+    if engine.interrupted() {
+        let records = engine.applied() + engine.skipped();
+        eprintln!("interrupted after {} records", records);
+    }
+    match args.stats.as_deref() {
+        Some("-") => eprint!("{}", engine.stats()),
+        Some(path) => fs::write(path, engine.stats().to_string())?,
+        None => {}
+    }
+    Ok(())
+}
 
-        // 1 left
-        assert_eq!(account.available, 1.0);
-        assert_eq!(account.total, 1.0);
+/// Has SIGINT and SIGTERM stop `engine` gracefully (see `INTERRUPT`).
+fn catch_interrupts(engine: &mut Engine) -> Result<(), Error> {
+    #[cfg(unix)]
+    for signal in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+        // only exits if the flag is set already, so it has to go in first
+        signal_hook::flag::register_conditional_shutdown(
+            signal,
+            exit::INTERRUPTED,
+            INTERRUPT.clone(),
+        )?;
+        signal_hook::flag::register(signal, INTERRUPT.clone())?;
+    }
+    engine.set_interrupt(INTERRUPT.clone());
+    Ok(())
+}
 
-        // try to take out 2.0
-        account.withdraw(2.0);
+/// `--snapshot-dir`, with SIGHUP asking for one.
+fn snapshots(dir: Option<&Path>, options: ReportOptions) -> Result<Option<Snapshots>, Error> {
+    let Some(dir) = dir else {
+        return Ok(None);
+    };
+    if !dir.is_dir() {
+        return Err(Error::Config(format!(
+            "--snapshot-dir {} isn't a directory",
+            dir.display()
+        )));
+    }
+    let snapshots = Snapshots::new(dir, options);
+    #[cfg(unix)]
+    signal_hook::flag::register(signal_hook::consts::SIGHUP, snapshots.flag())?;
+    Ok(Some(snapshots))
+}
 
-        // unchanged
-        assert_eq!(account.available, 1.0);
-        assert_eq!(account.total, 1.0);
+type MetricsUpdate = Box<dyn Fn(&Engine)>;
 
-        account.dispute(0.5);
+/// Starts the `--metrics` listener and hands back what keeps it current, which does nothing if
+/// there's no listener.
+#[allow(unused_variables)]
+fn metrics_server(args: &InputArgs) -> Result<MetricsUpdate, Error> {
+    #[cfg(feature = "server")]
+    if let Some(addr) = &args.metrics {
+        let server = csv_tx_resolver::MetricsServer::start(addr)?;
+        return Ok(Box::new(move |engine| server.update(engine)));
+    }
+    Ok(Box::new(|_| {}))
+}
 
-        // 0.5 available
-        assert_eq!(account.held, 0.5);
-        assert_eq!(account.available, 0.5);
-        assert_eq!(account.total, 1.0);
+#[cfg(feature = "kafka")]
+fn consume_kafka(engine: &mut Engine, args: &InputArgs) -> Result<(), Error> {
+    use std::time::Duration;
 
-        // try to take out 1.0
-        account.withdraw(1.0);
+    let kafka = &args.kafka;
+    let options = csv_tx_resolver::KafkaOptions {
+        brokers: kafka.kafka_brokers.clone(),
+        topic: kafka.kafka_topic.clone(),
+        group: kafka.kafka_group.clone(),
+        batch_size: kafka.kafka_batch,
+        batch_timeout: Duration::from_millis(kafka.kafka_batch_ms),
+        snapshot_every: Duration::from_secs(kafka.snapshot_every),
+    };
+    // the ledger is saved before offsets are committed, so a restart with the same one carries on
+    let mut ledger = ledger(args)?;
+    let update_metrics = metrics_server(args)?;
+    let save = |engine: &Engine| {
+        update_metrics(engine);
+        match &mut ledger {
+            Some(ledger) => ledger.save(engine),
+            None => Ok(()),
+        }
+    };
+    let snapshot = |engine: &Engine| {
+        let options = csv_tx_resolver::ReportOptions::default();
+        match &kafka.snapshot {
+            Some(path) => {
+                let mut file = AtomicFile::create(path)?;
+                engine.write_report(&options, &mut file)?;
+                file.commit()?;
+            }
+            None => engine.write_report(&options, io::stdout())?,
+        }
+        Ok(())
+    };
+    engine.consume_kafka(&options, save, snapshot)
+}
 
-        // unchanged
-        assert_eq!(account.held, 0.5);
-        assert_eq!(account.available, 0.5);
-        assert_eq!(account.total, 1.0);
+// an input file for the progress bar to count, mapped with `--mmap` unless it's a pipe
+fn open_file(path: &Path, mmap: bool) -> io::Result<Box<dyn Read + Send>> {
+    if mmap && !is_pipe(path) {
+        #[cfg(feature = "mmap")]
+        return Ok(Box::new(csv_tx_resolver::map_file(path)?));
     }
+    open_path(path)
+}
 
-    #[test]
-    fn disputes_work() {
-        let mut account = Account {
-            available: 10.0,
-            client: 1,
-            held: 0.0,
-            locked: false,
-            total: 10.0
-        };
-        // let's pretend the tx had 5 in the amount
-        account.dispute(5.0);
-        // dispute locks 5 and reduces available
-        assert_eq!(account.held, 5.0);
-        assert_eq!(account.available, 5.0);
-        // dispute locks another 3 and reduces available
-        account.dispute(3.0);
-
-        assert_eq!(account.held, 8.0);
-        assert_eq!(account.available, 2.0);
-        // resolve releases 3 from hold and increases available
-        account.resolve(5.0);
-        assert_eq!(account.held, 3.0);
-        assert_eq!(account.available, 7.0);
-        // chargeback removes 2 from total and reduces held. locks account.
-        account.chargeback(2.0);
-        assert_eq!(account.locked, true);
-        assert_eq!(account.total, 8.0);
-        // user tries to deposit on locked account
-        account.deposit(1.0);
-        // locked account prevents deposit
-        assert_eq!(account.total, 8.0);
-        // user tries to withdraw on locked account
-        account.withdraw(1.0);
-        // locked account prevents withdraw
-        assert_eq!(account.total, 8.0);
-    }
-
-}
\ No newline at end of file
+// a named pipe or a device, which can't be mapped or read twice
+fn is_pipe(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| !metadata.is_file() && !metadata.is_dir())
+}
+
+fn run_reader(
+    engine: &mut Engine,
+    input: usize,
+    reader: impl Read + Send,
+    args: &InputArgs,
+    progress: &Option<Progress>,
+) -> Result<(), Error> {
+    #[cfg(feature = "actors")]
+    if let Some(threads) = args.actors {
+        return engine.process_reader_actors(reader, threads);
+    }
+    if args.shards > 1 {
+        engine.process_reader_sharded(reader, args.shards)
+    } else if args.pipeline {
+        engine.process_reader_pipelined(reader)
+    } else {
+        engine.process_input(input, reader, progress.as_ref())
+    }
+}
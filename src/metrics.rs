@@ -0,0 +1,183 @@
+use crate::engine::Engine;
+#[cfg(feature = "server")]
+use crate::error::Error;
+use std::{fmt::Write, time::Duration};
+#[cfg(feature = "server")]
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+};
+
+// upper bounds in seconds, prometheus style. A batch is anything from one posted record to a
+// whole file
+const BUCKETS: [f64; 10] = [0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 60.0];
+
+/// How long batches of input took, in `BUCKETS`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Histogram {
+    // not cumulative, the rendering adds them up
+    counts: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    pub fn observe(&mut self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        if let Some(bucket) = BUCKETS.iter().position(|&bound| secs <= bound) {
+            self.counts[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum += secs;
+    }
+}
+
+impl Engine {
+    /// The run's counters in the Prometheus text format: records by type, rejections by reason,
+    /// accounts, locked accounts and how long each batch of input took to process. Like `stats`,
+    /// it only counts what happened in this process.
+    pub fn metrics(&self) -> String {
+        let stats = self.stats();
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for (labels, value) in samples {
+                let _ = writeln!(out, "{}{} {}", name, labels, value);
+            }
+        };
+        let by_type: Vec<_> = stats
+            .by_type
+            .iter()
+            .map(|(r_type, n)| (format!("{{type=\"{}\"}}", r_type), n.to_string()))
+            .collect();
+        metric(
+            "resolver_records_total",
+            "counter",
+            "Records read, by transaction type.",
+            &by_type,
+        );
+        metric(
+            "resolver_records_applied_total",
+            "counter",
+            "Records applied to the ledger.",
+            &[(String::new(), stats.applied.to_string())],
+        );
+        let by_reason: Vec<_> = stats
+            .by_reason
+            .iter()
+            .map(|(rejection, n)| {
+                (
+                    format!("{{reason=\"{}\"}}", rejection.name()),
+                    n.to_string(),
+                )
+            })
+            .collect();
+        metric(
+            "resolver_rejections_total",
+            "counter",
+            "Records rejected, by reason.",
+            &by_reason,
+        );
+        metric(
+            "resolver_accounts",
+            "gauge",
+            "Accounts in the ledger.",
+            &[(String::new(), stats.accounts.to_string())],
+        );
+        metric(
+            "resolver_locked_accounts",
+            "gauge",
+            "Accounts locked by a chargeback.",
+            &[(String::new(), stats.locked_accounts.to_string())],
+        );
+
+        let batches = self.counters().batches();
+        let mut cumulative = 0;
+        let mut samples: Vec<_> = BUCKETS
+            .iter()
+            .zip(batches.counts)
+            .map(|(bound, count)| {
+                cumulative += count;
+                (
+                    format!("_bucket{{le=\"{}\"}}", bound),
+                    cumulative.to_string(),
+                )
+            })
+            .collect();
+        samples.push((
+            "_bucket{le=\"+Inf\"}".to_string(),
+            batches.count.to_string(),
+        ));
+        samples.push(("_sum".to_string(), batches.sum.to_string()));
+        samples.push(("_count".to_string(), batches.count.to_string()));
+        metric(
+            "resolver_batch_duration_seconds",
+            "histogram",
+            "Time spent processing a batch of input (a file, a followed chunk, a request or a Kafka batch).",
+            &samples,
+        );
+        out
+    }
+}
+
+/// Serves `GET /metrics` on its own thread, for the long-running modes that have no HTTP server of
+/// their own (`--follow`, `--source kafka`). The engine isn't shared with it, so it serves whatever
+/// the last `update` rendered.
+#[cfg(feature = "server")]
+#[derive(Debug, Clone)]
+pub struct MetricsServer {
+    text: Arc<Mutex<String>>,
+}
+
+#[cfg(feature = "server")]
+impl MetricsServer {
+    pub fn start(addr: &str) -> Result<Self, Error> {
+        let server = crate::server::listen(addr)?;
+        let text = Arc::new(Mutex::new(String::new()));
+        let served = text.clone();
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let response = match request.url() {
+                    "/metrics" => {
+                        let text = served.lock().expect("metrics poisoned").clone();
+                        crate::server::metrics_response(text)
+                    }
+                    _ => tiny_http::Response::from_string("not found").with_status_code(404),
+                };
+                if let Err(err) = request.respond(response) {
+                    tracing::info!(error = %err, "could not respond");
+                }
+            }
+        });
+        Ok(MetricsServer { text })
+    }
+
+    pub fn update(&self, engine: &Engine) {
+        *self.text.lock().expect("metrics poisoned") = engine.metrics();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::process_reader;
+
+    #[test]
+    fn metrics_are_in_the_prometheus_format() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 5.0\n\
+                     withdrawal, 1, 2, 9.0\n\
+                     dispute, 1, 1,\n\
+                     chargeback, 1, 1,\n";
+        let metrics = process_reader(input.as_bytes()).unwrap().metrics();
+        let lines: Vec<&str> = metrics.lines().collect();
+
+        assert!(lines.contains(&"# TYPE resolver_records_total counter"));
+        assert!(lines.contains(&"resolver_records_total{type=\"deposit\"} 1"));
+        assert!(lines.contains(&"resolver_records_applied_total 3"));
+        assert!(lines.contains(&"resolver_rejections_total{reason=\"insufficient_funds\"} 1"));
+        assert!(lines.contains(&"resolver_locked_accounts 1"));
+        assert!(lines.contains(&"resolver_batch_duration_seconds_bucket{le=\"+Inf\"} 1"));
+        assert!(lines.contains(&"resolver_batch_duration_seconds_count 1"));
+    }
+}
@@ -0,0 +1,106 @@
+use crate::account::{Account, AccountMap, Balance};
+use crate::amount::Amount;
+use crate::diff::read_report;
+use crate::engine::Engine;
+use crate::error::Error;
+use crate::transaction::TransactionMap;
+use rust_decimal::Decimal;
+use std::{collections::HashMap, io::Read};
+
+impl Engine {
+    /// Starts from the accounts in a csv report that a previous run wrote, replacing whatever the
+    /// engine held. The report can be with or without the currency column. This lets today's
+    /// file settle on top of yesterday's closing balances without a state file. A report has no
+    /// transactions, so nothing from before it can be disputed. Whatever it shows as held stays
+    /// held, since the disputes holding it aren't there to resolve or charge back. A row whose
+    /// total isn't available plus held is an error.
+    pub fn load_opening_balances(&mut self, reader: impl Read) -> Result<(), Error> {
+        let mut balances: HashMap<u16, (Vec<Balance>, bool)> = HashMap::new();
+        for ((client, currency), row) in read_report(reader)? {
+            let amount = |amount: Option<Decimal>| {
+                let amount = amount.unwrap_or_default();
+                Amount::from_decimal(amount).ok_or_else(|| {
+                    Error::Input(format!(
+                        "opening balance {} of client {} doesn't fit four places",
+                        amount, client
+                    ))
+                })
+            };
+            let balance = Balance {
+                currency,
+                available: amount(row.available)?,
+                held: amount(row.held)?,
+                total: amount(row.total)?,
+            };
+            if balance.available.checked_add(balance.held) != Some(balance.total) {
+                return Err(Error::Input(format!(
+                    "opening balances of client {} don't add up: {} available and {} held, {} total",
+                    client, balance.available, balance.held, balance.total
+                )));
+            }
+            let (account, locked) = balances.entry(client).or_default();
+            *locked |= row.locked;
+            account.push(balance);
+        }
+        let accounts: AccountMap = balances
+            .into_iter()
+            .map(|(client, (balances, locked))| {
+                (client, Account::with_balances(client, balances, locked))
+            })
+            .collect();
+        self.restore(accounts, TransactionMap::new());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{process_reader, Engine};
+
+    #[test]
+    fn yesterdays_report_opens_todays_run() {
+        let yesterday = process_reader(
+            "type, client, tx, amount, currency\n\
+             deposit, 1, 1, 10.0,\n\
+             deposit, 1, 2, 3.0, EUR\n\
+             deposit, 2, 3, 5.0,\n\
+             dispute, 2, 3,,\n\
+             chargeback, 2, 3,,\n\
+             deposit, 3, 4, 1.5,\n\
+             dispute, 3, 4,,\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let mut report = Vec::new();
+        yesterday.write_csv(&mut report).unwrap();
+
+        let mut engine = Engine::new();
+        engine.load_opening_balances(report.as_slice()).unwrap();
+        engine
+            .process_reader(
+                "type, client, tx, amount, currency\n\
+                 withdrawal, 1, 5, 4.0,\n\
+                 withdrawal, 1, 6, 1.0, EUR\n\
+                 deposit, 2, 7, 1.0,\n\
+                 dispute, 1, 1,,\n\
+                 deposit, 3, 8, 1.0,\n"
+                    .as_bytes(),
+            )
+            .unwrap();
+        let mut report = Vec::new();
+        engine.write_csv(&mut report).unwrap();
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            "client,currency,available,held,total,locked\n\
+             1,,6.0000,0.0000,6.0000,false\n\
+             1,EUR,2.0000,0.0000,2.0000,false\n\
+             2,,0.0000,0.0000,0.0000,true\n\
+             3,,1.0000,1.5000,2.5000,false\n"
+        );
+        // the dispute refers to a tx from before the report
+        assert_eq!(engine.skipped(), 2);
+
+        let bad = "client,available,held,total,locked\n1,1.0,0.0,2.0,false\n";
+        assert!(Engine::new().load_opening_balances(bad.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,597 @@
+use crate::account::{Account, AccountRow};
+use crate::anonymize::Anonymize;
+use crate::currency::Currency;
+use crate::engine::Engine;
+use crate::error::Error;
+use crate::utils::four_places;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::{collections::HashSet, fmt, io::Write, str::FromStr};
+
+/// Reports with at least this many rows are serialized in parallel.
+#[cfg(feature = "parallel")]
+const PARALLEL_ROWS: usize = 16_384;
+/// Rows per parallel serialization task.
+#[cfg(feature = "parallel")]
+const CHUNK_ROWS: usize = 4096;
+
+/// How the final account report gets written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    /// One JSON array of account rows.
+    Json,
+    /// One JSON account row object per line.
+    Jsonl,
+    /// A Parquet file, amounts as DECIMAL(38, 4).
+    #[cfg(feature = "parquet")]
+    Parquet,
+    /// Avro: `avro` for a container file with the schema, `avro:<id>` for records framed with a
+    /// schema registry id instead.
+    #[cfg(feature = "avro")]
+    Avro(Option<u32>),
+    /// `Account` messages of proto/account.proto, each after its length as a varint.
+    #[cfg(feature = "proto")]
+    Proto,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            #[cfg(feature = "parquet")]
+            "parquet" => Ok(OutputFormat::Parquet),
+            #[cfg(feature = "proto")]
+            "proto" => Ok(OutputFormat::Proto),
+            #[cfg(feature = "avro")]
+            "avro" => Ok(OutputFormat::Avro(None)),
+            #[cfg(feature = "avro")]
+            _ if s.starts_with("avro:") => match s["avro:".len()..].parse() {
+                Ok(id) => Ok(OutputFormat::Avro(Some(id))),
+                Err(_) => Err(format!("invalid schema id in {:?}", s)),
+            },
+            other => Err(format!("unknown output format {:?}", other)),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Json => "json",
+            OutputFormat::Jsonl => "jsonl",
+            #[cfg(feature = "parquet")]
+            OutputFormat::Parquet => "parquet",
+            #[cfg(feature = "avro")]
+            OutputFormat::Avro(_) => "avro",
+            #[cfg(feature = "proto")]
+            OutputFormat::Proto => "proto",
+        })
+    }
+}
+
+/// Which column the report is ordered by. Always ascending, ties broken by client id, so two runs
+/// over the same input produce byte-identical output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortBy {
+    #[default]
+    Client,
+    Total,
+    Available,
+}
+
+impl FromStr for SortBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "client" => Ok(SortBy::Client),
+            "total" => Ok(SortBy::Total),
+            "available" => Ok(SortBy::Available),
+            other => Err(format!("unknown sort column {:?}", other)),
+        }
+    }
+}
+
+/// Knobs for `Engine::write_report`. The filters apply to each row, so an account with several
+/// currencies can have some of its rows left out.
+#[derive(Debug, Clone, Default)]
+pub struct ReportOptions {
+    pub format: OutputFormat,
+    pub sort_by: SortBy,
+    /// Only rows of locked accounts.
+    pub only_locked: bool,
+    /// Only rows with at least this total.
+    pub min_total: Option<Decimal>,
+    /// Only these clients' rows, everyone's if `None`.
+    pub clients: Option<Vec<u16>>,
+    /// Write a csv report with decimal commas (`1,5000`) and semicolons between the fields, the way
+    /// European spreadsheets read it. The other formats aren't affected.
+    pub decimal_comma: bool,
+    /// Disguise clients and balances for a shareable report. Filters still go by the real ones.
+    pub anonymize: Option<Anonymize>,
+}
+
+impl ReportOptions {
+    pub fn new(format: OutputFormat) -> Self {
+        ReportOptions {
+            format,
+            ..ReportOptions::default()
+        }
+    }
+}
+
+/// How `Engine::write_partitioned` spreads the clients over the files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PartitionBy {
+    /// Contiguous client ranges with about as many clients in each file, the lowest ids first.
+    #[default]
+    Range,
+    /// Client id modulo the number of files, so a client's file doesn't depend on who else is in
+    /// the report.
+    Modulo,
+}
+
+impl FromStr for PartitionBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "range" => Ok(PartitionBy::Range),
+            "modulo" => Ok(PartitionBy::Modulo),
+            other => Err(format!("unknown partitioning {:?}", other)),
+        }
+    }
+}
+
+impl Engine {
+    /// Writes every account as described by `options`, one row per currency it holds. Amounts keep
+    /// the four place invariant in every format; in JSON they are strings (`"1.5000"`) so no
+    /// consumer reads them back as floats.
+    pub fn write_report(&self, options: &ReportOptions, writer: impl Write) -> Result<(), Error> {
+        let rows = selected(self, options);
+        write_rows(rows.iter().collect(), options, writer)
+    }
+
+    /// `write_report` split over `parts` reports, each a complete one in `options.format` written
+    /// to `open(i)` for the `i`th, and each client's rows all in the same one. Every part is
+    /// opened, even one without rows. Hands the writers back once they're all written.
+    pub fn write_partitioned<W: Write>(
+        &self,
+        options: &ReportOptions,
+        parts: usize,
+        by: PartitionBy,
+        mut open: impl FnMut(usize) -> Result<W, Error>,
+    ) -> Result<Vec<W>, Error> {
+        let parts = parts.max(1);
+        let rows = selected(self, options);
+        let mut clients: Vec<u16> = rows.iter().map(|row| row.client).collect();
+        clients.sort_unstable();
+        clients.dedup();
+        let part_of = |client: u16| match by {
+            // clients.len() is at most 65536, so this can't overflow
+            PartitionBy::Range => {
+                clients.binary_search(&client).unwrap_or(0) * parts / clients.len()
+            }
+            PartitionBy::Modulo => usize::from(client) % parts,
+        };
+        // same order as the whole report within each part
+        let mut split: Vec<Vec<&AccountRow>> = vec![Vec::new(); parts];
+        for row in &rows {
+            split[part_of(row.client)].push(row);
+        }
+        split
+            .into_iter()
+            .enumerate()
+            .map(|(i, rows)| {
+                let mut writer = open(i)?;
+                write_rows(rows, options, &mut writer)?;
+                Ok(writer)
+            })
+            .collect()
+    }
+
+    /// Writes every account as csv, ordered by client, to the given writer.
+    pub fn write_csv(&self, writer: impl Write) -> Result<(), Error> {
+        self.write_report(&ReportOptions::default(), writer)
+    }
+}
+
+// the rows `options` filters leave in the report, anonymized if it asks for that
+fn selected(engine: &Engine, options: &ReportOptions) -> Vec<AccountRow> {
+    let mut rows = rows(engine, options.sort_by);
+    let clients: Option<HashSet<u16>> = options
+        .clients
+        .as_ref()
+        .map(|c| c.iter().copied().collect());
+    rows.retain(|row| {
+        (!options.only_locked || row.locked)
+            && options.min_total.is_none_or(|min| row.total >= min)
+            && clients.as_ref().is_none_or(|c| c.contains(&row.client))
+    });
+    if let Some(anonymize) = &options.anonymize {
+        rows.iter_mut().for_each(|row| anonymize.row(row));
+        // in the real ids' order they'd give them away
+        sort(&mut rows, options.sort_by);
+    }
+    rows
+}
+
+fn write_rows(
+    rows: Vec<&AccountRow>,
+    options: &ReportOptions,
+    writer: impl Write,
+) -> Result<(), Error> {
+    let format = options.format;
+    if options.decimal_comma && format == OutputFormat::Csv {
+        return write_decimal_comma_csv(rows.into_iter(), writer);
+    }
+    #[cfg(feature = "parallel")]
+    if rows.len() >= PARALLEL_ROWS
+        && matches!(
+            format,
+            OutputFormat::Csv | OutputFormat::Json | OutputFormat::Jsonl
+        )
+    {
+        return write_chunked(&rows, format, writer);
+    }
+    let rows = rows.into_iter();
+    match format {
+        OutputFormat::Csv => write_csv(rows, writer),
+        OutputFormat::Json => write_json(rows, writer),
+        OutputFormat::Jsonl => write_jsonl(rows, writer),
+        #[cfg(feature = "parquet")]
+        OutputFormat::Parquet => crate::parquet_writer::write_accounts(rows, writer),
+        #[cfg(feature = "avro")]
+        OutputFormat::Avro(schema_id) => crate::avro::write_accounts(rows, schema_id, writer),
+        #[cfg(feature = "proto")]
+        OutputFormat::Proto => crate::account_proto::write_accounts(rows, writer),
+    }
+}
+
+// HashMap order changes between runs, so the writer always sorts. Rows only get a currency column
+// when some account holds more than the default currency, so single currency reports look the same
+// as before currencies
+pub(crate) fn rows(engine: &Engine, sort_by: SortBy) -> Vec<AccountRow> {
+    let accounts: Vec<Account> = engine.store().accounts().collect();
+    let with_currency = accounts.iter().any(Account::has_currencies);
+    let mut rows: Vec<AccountRow> = accounts
+        .iter()
+        .flat_map(|account| account.rows(with_currency))
+        .collect();
+    sort(&mut rows, sort_by);
+    rows
+}
+
+fn sort(rows: &mut [AccountRow], sort_by: SortBy) {
+    match sort_by {
+        SortBy::Client => rows.sort_by_key(|row| (row.client, row.currency)),
+        SortBy::Total => rows.sort_by_key(|row| (row.total, row.client, row.currency)),
+        SortBy::Available => rows.sort_by_key(|row| (row.available, row.client, row.currency)),
+    }
+}
+
+fn write_csv<'a>(
+    rows: impl Iterator<Item = &'a AccountRow>,
+    writer: impl Write,
+) -> Result<(), Error> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(writer);
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// `AccountRow` with its amounts as decimal comma text
+#[derive(Serialize)]
+struct DecimalCommaRow {
+    client: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency: Option<Currency>,
+    available: String,
+    held: String,
+    total: String,
+    locked: bool,
+}
+
+fn write_decimal_comma_csv<'a>(
+    rows: impl Iterator<Item = &'a AccountRow>,
+    writer: impl Write,
+) -> Result<(), Error> {
+    let comma = |amount: Decimal| four_places(amount).to_string().replace('.', ",");
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(true)
+        .delimiter(b';')
+        .from_writer(writer);
+    for row in rows {
+        writer.serialize(DecimalCommaRow {
+            client: row.client,
+            currency: row.currency,
+            available: comma(row.available),
+            held: comma(row.held),
+            total: comma(row.total),
+            locked: row.locked,
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_json<'a>(
+    rows: impl Iterator<Item = &'a AccountRow>,
+    mut writer: impl Write,
+) -> Result<(), Error> {
+    let rows: Vec<&AccountRow> = rows.collect();
+    serde_json::to_writer(&mut writer, &rows)?;
+    writeln!(writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_jsonl<'a>(
+    rows: impl Iterator<Item = &'a AccountRow>,
+    mut writer: impl Write,
+) -> Result<(), Error> {
+    for row in rows {
+        serde_json::to_writer(&mut writer, row)?;
+        writeln!(writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// big reports are serialized a chunk per rayon task, into buffers that are then written out in
+// order, so the bytes are the same as writing the rows one by one
+#[cfg(feature = "parallel")]
+fn write_chunked(
+    rows: &[&AccountRow],
+    format: OutputFormat,
+    mut writer: impl Write,
+) -> Result<(), Error> {
+    use rayon::prelude::*;
+
+    let json = format == OutputFormat::Json;
+    let chunks = rows
+        .par_chunks(CHUNK_ROWS)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut buffer = Vec::with_capacity(64 * chunk.len());
+            if format == OutputFormat::Csv {
+                // only the first chunk starts with the header
+                let mut csv = csv::WriterBuilder::new()
+                    .has_headers(i == 0)
+                    .from_writer(&mut buffer);
+                for row in chunk {
+                    csv.serialize(row)?;
+                }
+                csv.flush()?;
+            } else {
+                for (j, row) in chunk.iter().enumerate() {
+                    if json && (i, j) != (0, 0) {
+                        buffer.push(b',');
+                    }
+                    serde_json::to_writer(&mut buffer, row)?;
+                    if !json {
+                        buffer.push(b'\n');
+                    }
+                }
+            }
+            Ok(buffer)
+        })
+        .collect::<Result<Vec<Vec<u8>>, Error>>()?;
+
+    if json {
+        writer.write_all(b"[")?;
+    }
+    for chunk in chunks {
+        writer.write_all(&chunk)?;
+    }
+    if json {
+        writer.write_all(b"]\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{process_reader, EngineBuilder};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn json_keeps_four_places_as_strings() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 1.5\n";
+        let engine = process_reader(input.as_bytes()).unwrap();
+
+        let mut json = Vec::new();
+        engine
+            .write_report(&ReportOptions::new(OutputFormat::Json), &mut json)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "[{\"client\":1,\"available\":\"1.5000\",\"held\":\"0.0000\",\"total\":\"1.5000\",\"locked\":false}]\n"
+        );
+
+        let mut jsonl = Vec::new();
+        engine
+            .write_report(&ReportOptions::new(OutputFormat::Jsonl), &mut jsonl)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(jsonl).unwrap(),
+            "{\"client\":1,\"available\":\"1.5000\",\"held\":\"0.0000\",\"total\":\"1.5000\",\"locked\":false}\n"
+        );
+    }
+
+    #[test]
+    fn decimal_commas_go_in_and_come_out() {
+        let input = "type;client;tx;amount\n\
+                     deposit;1;1;12,3456\n\
+                     deposit;1;2;1.234,50\n\
+                     withdrawal;1;3;0,5\n\
+                     deposit;2;4;1.5\n\
+                     deposit;2;5;12.34,5\n";
+        let mut engine = EngineBuilder::new()
+            .decimal_comma(true)
+            .formatted_amounts(true)
+            .build();
+        engine.process_reader(input.as_bytes()).unwrap();
+        let options = ReportOptions {
+            decimal_comma: true,
+            ..ReportOptions::default()
+        };
+        let mut out = Vec::new();
+        engine.write_report(&options, &mut out).unwrap();
+        // plain decimal text is read as it is, misplaced separators aren't
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client;available;held;total;locked\n\
+             1;1246,3456;0,0000;1246,3456;false\n\
+             2;1,5000;0,0000;1,5000;false\n"
+        );
+        assert_eq!(engine.skipped(), 1);
+
+        // a decimal comma on its own doesn't take points out
+        let mut engine = EngineBuilder::new().decimal_comma(true).build();
+        engine.process_reader(input.as_bytes()).unwrap();
+        assert_eq!(engine.accounts()[&1].total(), dec!(11.8456));
+        assert_eq!(engine.accounts()[&2].total(), dec!(1.5));
+        assert_eq!(engine.skipped(), 2);
+    }
+
+    #[test]
+    fn output_is_sorted() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 3, 1, 1.0\n\
+                     deposit, 1, 2, 3.0\n\
+                     deposit, 2, 3, 2.0\n\
+                     deposit, 4, 4, 2.0\n";
+        let engine = process_reader(input.as_bytes()).unwrap();
+        let clients = |sort_by| {
+            let options = ReportOptions {
+                sort_by,
+                ..ReportOptions::default()
+            };
+            let mut out = Vec::new();
+            engine.write_report(&options, &mut out).unwrap();
+            String::from_utf8(out)
+                .unwrap()
+                .lines()
+                .skip(1)
+                .map(|line| line.split(',').next().unwrap().to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        assert_eq!(clients(SortBy::Client), "1 2 3 4");
+        assert_eq!(clients(SortBy::Total), "3 2 4 1");
+        assert_eq!(clients(SortBy::Available), "3 2 4 1");
+    }
+
+    #[test]
+    fn filters_trim_the_report() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 1.0\n\
+                     withdrawal, 1, 2, 0.5\n\
+                     deposit, 2, 3, 2.0\n\
+                     dispute, 2, 3,\n\
+                     chargeback, 2, 3,\n\
+                     deposit, 3, 4, 3.0\n\
+                     deposit, 4, 5, 0.00001\n";
+        let engine = process_reader(input.as_bytes()).unwrap();
+        let clients = |options: ReportOptions| {
+            let mut out = Vec::new();
+            engine.write_report(&options, &mut out).unwrap();
+            String::from_utf8(out)
+                .unwrap()
+                .lines()
+                .skip(1)
+                .map(|line| line.split(',').next().unwrap().to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        let only_locked = ReportOptions {
+            only_locked: true,
+            ..ReportOptions::default()
+        };
+        assert_eq!(clients(only_locked), "2");
+        let min_total = ReportOptions {
+            min_total: Some(dec!(0.0001)),
+            ..ReportOptions::default()
+        };
+        assert_eq!(clients(min_total), "1 3");
+        let some_clients = ReportOptions {
+            clients: Some(vec![3, 1, 9]),
+            min_total: Some(dec!(1)),
+            ..ReportOptions::default()
+        };
+        assert_eq!(clients(some_clients), "3");
+    }
+
+    #[test]
+    fn partitions_keep_each_client_in_one_file() {
+        let input = "type, client, tx, amount, currency\n\
+                     deposit, 5, 1, 1.0\n\
+                     deposit, 1, 2, 1.0\n\
+                     deposit, 9, 3, 1.0\n\
+                     deposit, 2, 4, 1.0\n\
+                     deposit, 1, 5, 1.0, EUR\n";
+        let engine = process_reader(input.as_bytes()).unwrap();
+        let clients = |by| {
+            let parts = engine
+                .write_partitioned(&ReportOptions::default(), 3, by, |_| Ok(Vec::new()))
+                .unwrap();
+            parts
+                .into_iter()
+                .map(|part| {
+                    let part = String::from_utf8(part).unwrap();
+                    let clients: Vec<_> = part
+                        .lines()
+                        .skip(1)
+                        .map(|line| line.split(',').next().unwrap().to_string())
+                        .collect();
+                    clients.join(" ")
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(clients(PartitionBy::Range), ["1 1 2", "5", "9"]);
+        assert_eq!(clients(PartitionBy::Modulo), ["9", "1 1", "2 5"]);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn chunked_output_matches_row_by_row() {
+        let mut input = String::from("type, client, tx, amount\n");
+        for client in 0..10_000u32 {
+            input.push_str(&format!("deposit, {}, {}, {}.25\n", client, client, client));
+        }
+        let engine = process_reader(input.as_bytes()).unwrap();
+        let rows = rows(&engine, SortBy::Client);
+        let rows: Vec<&AccountRow> = rows.iter().collect();
+        assert!(rows.len() > 2 * CHUNK_ROWS);
+
+        for format in [OutputFormat::Csv, OutputFormat::Json, OutputFormat::Jsonl] {
+            let (mut chunked, mut plain) = (Vec::new(), Vec::new());
+            write_chunked(&rows, format, &mut chunked).unwrap();
+            let one_by_one = rows.iter().copied();
+            match format {
+                OutputFormat::Csv => write_csv(one_by_one, &mut plain),
+                OutputFormat::Json => write_json(one_by_one, &mut plain),
+                _ => write_jsonl(one_by_one, &mut plain),
+            }
+            .unwrap();
+            assert!(chunked == plain, "{} output differs", format);
+        }
+    }
+}
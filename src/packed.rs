@@ -0,0 +1,182 @@
+use crate::amount::Amount;
+use crate::currency::Currency;
+use crate::transaction::{DisputeStatus, Transaction, TransactionMap, TransactionType};
+use std::collections::HashMap;
+
+/// A stored transaction in 48 bytes rather than a `Transaction`'s 128: the `Amount`s as they are,
+/// the optional fields as sentinels, and no signature or `given` amount, neither of which is looked
+/// at again once the record is applied.
+#[derive(Debug, Clone, Copy)]
+struct PackedTransaction {
+    amount: Amount,
+    // `Amount::MIN` for none, neither can be negative
+    held: Amount,
+    seq: u64,
+    // i64::MIN for none, like the spill store has it
+    timestamp: i64,
+    currency: Currency,
+    client: u16,
+    r_type: TransactionType,
+    status: DisputeStatus,
+}
+
+impl PackedTransaction {
+    // `None` for the odd one that won't pack, one without an amount that was restored into the
+    // engine without going through `prepare`
+    fn pack(transaction: &Transaction) -> Option<PackedTransaction> {
+        let held = match transaction.held {
+            Some(held) => Some(held).filter(|&held| held != Amount::MIN)?,
+            None => Amount::MIN,
+        };
+        Some(PackedTransaction {
+            amount: transaction.amount?,
+            held,
+            seq: transaction.seq,
+            timestamp: transaction.timestamp.unwrap_or(i64::MIN),
+            currency: transaction.currency,
+            client: transaction.client,
+            r_type: transaction.r_type,
+            status: transaction.status,
+        })
+    }
+
+    fn unpack(&self, tx: u32) -> Transaction {
+        Transaction {
+            r_type: self.r_type,
+            client: self.client,
+            tx,
+            amount: Some(self.amount),
+            given: None,
+            currency: self.currency,
+            timestamp: Some(self.timestamp).filter(|&timestamp| timestamp != i64::MIN),
+            status: self.status,
+            seq: self.seq,
+            signature: None,
+            held: Some(self.held).filter(|&held| held != Amount::MIN),
+        }
+    }
+}
+
+/// Stored transactions by tx id, packed. The few that don't pack are kept as they are.
+#[derive(Debug, Default)]
+pub(crate) struct PackedMap {
+    packed: HashMap<u32, PackedTransaction>,
+    wide: TransactionMap,
+}
+
+impl PackedMap {
+    pub fn len(&self) -> usize {
+        self.packed.len() + self.wide.len()
+    }
+
+    pub fn contains(&self, tx: u32) -> bool {
+        self.packed.contains_key(&tx) || self.wide.contains_key(&tx)
+    }
+
+    pub fn get(&self, tx: u32) -> Option<Transaction> {
+        match self.packed.get(&tx) {
+            Some(packed) => Some(packed.unpack(tx)),
+            None => self.wide.get(&tx).copied(),
+        }
+    }
+
+    pub fn insert(&mut self, transaction: Transaction) {
+        let tx = transaction.tx;
+        // a tx is in one map or the other, whichever its latest version fits
+        match PackedTransaction::pack(&transaction) {
+            Some(packed) => {
+                self.packed.insert(tx, packed);
+                self.wide.remove(&tx);
+            }
+            None => {
+                self.wide.insert(tx, transaction);
+                self.packed.remove(&tx);
+            }
+        }
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = Transaction> + '_ {
+        let packed = self.packed.iter().map(|(&tx, packed)| packed.unpack(tx));
+        packed.chain(self.wide.values().copied())
+    }
+
+    #[cfg(feature = "spill")]
+    pub fn drain(&mut self) -> impl Iterator<Item = Transaction> + '_ {
+        let packed = self.packed.drain().map(|(tx, packed)| packed.unpack(tx));
+        packed.chain(self.wide.drain().map(|(_, transaction)| transaction))
+    }
+
+    pub fn into_values(self) -> impl Iterator<Item = Transaction> {
+        let packed = self
+            .packed
+            .into_iter()
+            .map(|(tx, packed)| packed.unpack(tx));
+        packed.chain(self.wide.into_values())
+    }
+
+    pub fn clear(&mut self) {
+        self.packed.clear();
+        self.wide.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use std::mem::size_of;
+
+    #[test]
+    fn packed_transactions_come_back_the_same() {
+        assert_eq!(size_of::<PackedTransaction>(), 48);
+        let deposit = Transaction {
+            r_type: TransactionType::Deposit,
+            client: 7,
+            tx: 1,
+            amount: Some(Amount::from_units(12_345)),
+            given: None,
+            currency: "EUR".parse().unwrap(),
+            timestamp: Some(1_660_000_000),
+            status: DisputeStatus::Disputed,
+            seq: 42,
+            signature: None,
+            held: Some(Amount::from_units(5_000)),
+        };
+        let mut map = PackedMap::default();
+        let transactions = [
+            deposit,
+            Transaction {
+                tx: 2,
+                timestamp: None,
+                held: None,
+                ..deposit
+            },
+            // no amount, so it isn't packed
+            Transaction {
+                tx: 3,
+                amount: None,
+                ..deposit
+            },
+        ];
+        for transaction in transactions {
+            map.insert(transaction);
+        }
+        assert_eq!((map.packed.len(), map.wide.len()), (2, 1));
+        let fields = |t: Transaction| {
+            let ids = (t.r_type, t.client, t.tx, t.currency, t.status);
+            (ids, t.amount, t.held, t.timestamp, t.seq)
+        };
+        for transaction in transactions {
+            let stored = map.get(transaction.tx).unwrap();
+            assert_eq!(fields(stored), fields(transaction));
+        }
+
+        // moves over once it fits
+        map.insert(Transaction {
+            amount: Some(Amount::from_units(10_000)),
+            ..transactions[2]
+        });
+        assert_eq!((map.packed.len(), map.wide.len()), (3, 0));
+        assert_eq!(map.get(3).unwrap().amount(), dec!(1));
+    }
+}
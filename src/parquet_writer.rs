@@ -0,0 +1,240 @@
+use crate::account::AccountRow;
+use crate::audit::AuditEntry;
+use crate::error::Error;
+use crate::utils::four_places;
+use parquet::{
+    basic::Compression,
+    data_type::Int32Type,
+    data_type::{BoolType, ByteArray, ByteArrayType, FixedLenByteArray, FixedLenByteArrayType},
+    errors::ParquetError,
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    schema::parser::parse_message_type,
+};
+use rust_decimal::Decimal;
+use std::{io, io::Write, sync::Arc};
+
+// amounts are DECIMAL(38, 4), the 16 byte big endian mantissa at four places, so nothing is lost
+// on the way into the lake: rust_decimal's 96 bit mantissa always fits
+const ACCOUNT_SCHEMA: &str = "
+message account {
+    REQUIRED INT32 client (INTEGER(16, false));
+    REQUIRED BYTE_ARRAY currency (UTF8);
+    REQUIRED FIXED_LEN_BYTE_ARRAY (16) available (DECIMAL(38, 4));
+    REQUIRED FIXED_LEN_BYTE_ARRAY (16) held (DECIMAL(38, 4));
+    REQUIRED FIXED_LEN_BYTE_ARRAY (16) total (DECIMAL(38, 4));
+    REQUIRED BOOLEAN locked;
+}";
+
+const AUDIT_SCHEMA: &str = "
+message audit_entry {
+    REQUIRED INT32 tx (INTEGER(32, false));
+    REQUIRED INT32 client (INTEGER(16, false));
+    REQUIRED BYTE_ARRAY type (UTF8);
+    REQUIRED FIXED_LEN_BYTE_ARRAY (16) amount (DECIMAL(38, 4));
+    REQUIRED FIXED_LEN_BYTE_ARRAY (16) available (DECIMAL(38, 4));
+    REQUIRED FIXED_LEN_BYTE_ARRAY (16) held (DECIMAL(38, 4));
+    REQUIRED FIXED_LEN_BYTE_ARRAY (16) total (DECIMAL(38, 4));
+    REQUIRED BOOLEAN locked;
+    REQUIRED BYTE_ARRAY currency (UTF8);
+}";
+
+/// Audit entries buffered before they're written out as one row group.
+const ROW_GROUP_ROWS: usize = 64 * 1024;
+
+/// One column of a row group, in schema order.
+enum Column {
+    Int32(Vec<i32>),
+    Text(Vec<ByteArray>),
+    Decimal(Vec<FixedLenByteArray>),
+    Bool(Vec<bool>),
+}
+
+fn text(value: impl ToString) -> ByteArray {
+    value.to_string().into_bytes().into()
+}
+
+fn decimal(value: Decimal) -> FixedLenByteArray {
+    four_places(value).mantissa().to_be_bytes().to_vec().into()
+}
+
+fn file_writer<W: Write + Send>(
+    writer: W,
+    schema: &str,
+) -> Result<SerializedFileWriter<W>, ParquetError> {
+    let schema = Arc::new(parse_message_type(schema)?);
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    SerializedFileWriter::new(writer, schema, Arc::new(properties))
+}
+
+fn write_row_group<W: Write + Send>(
+    writer: &mut SerializedFileWriter<W>,
+    columns: Vec<Column>,
+) -> Result<(), ParquetError> {
+    let mut row_group = writer.next_row_group()?;
+    for column in columns {
+        let mut out = row_group
+            .next_column()?
+            .ok_or_else(|| ParquetError::General("more columns than the schema".to_string()))?;
+        match &column {
+            Column::Int32(values) => out.typed::<Int32Type>().write_batch(values, None, None)?,
+            Column::Text(values) => out
+                .typed::<ByteArrayType>()
+                .write_batch(values, None, None)?,
+            Column::Decimal(values) => out
+                .typed::<FixedLenByteArrayType>()
+                .write_batch(values, None, None)?,
+            Column::Bool(values) => out.typed::<BoolType>().write_batch(values, None, None)?,
+        };
+        out.close()?;
+    }
+    row_group.close()?;
+    Ok(())
+}
+
+fn parquet_error(err: ParquetError) -> io::Error {
+    io::Error::other(format!("parquet: {}", err))
+}
+
+/// The account report as one Parquet file with a single row group. The currency column is always
+/// there, empty for the default currency.
+pub(crate) fn write_accounts<'a>(
+    rows: impl Iterator<Item = &'a AccountRow>,
+    mut writer: impl Write,
+) -> Result<(), Error> {
+    let rows: Vec<&AccountRow> = rows.collect();
+    let columns = vec![
+        Column::Int32(rows.iter().map(|r| r.client.into()).collect()),
+        Column::Text(
+            rows.iter()
+                .map(|r| text(r.currency.unwrap_or_default()))
+                .collect(),
+        ),
+        Column::Decimal(rows.iter().map(|r| decimal(r.available)).collect()),
+        Column::Decimal(rows.iter().map(|r| decimal(r.held)).collect()),
+        Column::Decimal(rows.iter().map(|r| decimal(r.total)).collect()),
+        Column::Bool(rows.iter().map(|r| r.locked).collect()),
+    ];
+    // the file writer wants to own a Send writer, so it goes through a buffer
+    let mut buffer = Vec::new();
+    (|| {
+        let mut file = file_writer(&mut buffer, ACCOUNT_SCHEMA)?;
+        write_row_group(&mut file, columns)?;
+        file.close().map(drop)
+    })()
+    .map_err(parquet_error)?;
+    writer.write_all(&buffer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// The `--audit-format parquet` sink. Entries are written in row groups of `ROW_GROUP_ROWS`, and
+/// the file is only readable once `finish` has written the footer.
+pub(crate) struct AuditParquet {
+    // None once finished
+    writer: Option<SerializedFileWriter<Box<dyn Write + Send>>>,
+    pending: Vec<AuditEntry>,
+}
+
+impl AuditParquet {
+    pub(crate) fn new(writer: Box<dyn Write + Send>) -> io::Result<Self> {
+        Ok(AuditParquet {
+            writer: Some(file_writer(writer, AUDIT_SCHEMA).map_err(parquet_error)?),
+            pending: Vec::new(),
+        })
+    }
+
+    pub(crate) fn write(&mut self, entry: &AuditEntry) -> io::Result<()> {
+        if self.writer.is_none() {
+            return Err(io::Error::other("audit log was already finished"));
+        }
+        self.pending.push(entry.clone());
+        if self.pending.len() >= ROW_GROUP_ROWS {
+            self.write_pending()?;
+        }
+        Ok(())
+    }
+
+    fn write_pending(&mut self) -> io::Result<()> {
+        let Some(writer) = &mut self.writer else {
+            return Ok(());
+        };
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let entries = std::mem::take(&mut self.pending);
+        let columns = vec![
+            // unsigned columns keep the bits, readers go by the annotation
+            Column::Int32(entries.iter().map(|e| e.tx as i32).collect()),
+            Column::Int32(entries.iter().map(|e| e.client.into()).collect()),
+            Column::Text(entries.iter().map(|e| text(e.r_type)).collect()),
+            Column::Decimal(entries.iter().map(|e| decimal(e.amount)).collect()),
+            Column::Decimal(entries.iter().map(|e| decimal(e.available)).collect()),
+            Column::Decimal(entries.iter().map(|e| decimal(e.held)).collect()),
+            Column::Decimal(entries.iter().map(|e| decimal(e.total)).collect()),
+            Column::Bool(entries.iter().map(|e| e.locked).collect()),
+            Column::Text(entries.iter().map(|e| text(e.currency)).collect()),
+        ];
+        write_row_group(writer, columns).map_err(parquet_error)
+    }
+
+    /// Writes what's still buffered and the footer.
+    pub(crate) fn finish(&mut self) -> io::Result<()> {
+        self.write_pending()?;
+        if let Some(writer) = self.writer.take() {
+            writer.close().map_err(parquet_error)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Engine, OutputFormat, ReportOptions};
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::{Row, RowAccessor};
+    use std::{fs, fs::File, process};
+
+    fn rows(reader: impl FileReader) -> Vec<Row> {
+        let rows = reader.get_row_iter(None).unwrap();
+        rows.map(|row| row.unwrap()).collect()
+    }
+
+    fn column(row: &Row, i: usize) -> String {
+        row.get_column_iter().nth(i).unwrap().1.to_string()
+    }
+
+    #[test]
+    fn accounts_and_audit_round_trip_through_parquet() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 2, 1, 1.5\n\
+                     deposit, 1, 2, 20.25\n\
+                     dispute, 1, 2,\n";
+        let audit = std::env::temp_dir().join(format!("audit-test-{}.parquet", process::id()));
+        let mut engine = Engine::new();
+        engine
+            .set_audit_log_parquet(File::create(&audit).unwrap())
+            .unwrap();
+        engine.process_reader(input.as_bytes()).unwrap();
+        engine.finish_audit_log().unwrap();
+        let mut out = Vec::new();
+        engine
+            .write_report(&ReportOptions::new(OutputFormat::Parquet), &mut out)
+            .unwrap();
+
+        let accounts = rows(SerializedFileReader::new(bytes::Bytes::from(out)).unwrap());
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(accounts[0].get_ushort(0).unwrap(), 1);
+        assert_eq!(column(&accounts[0], 3), "20.2500");
+        assert_eq!(column(&accounts[1], 2), "1.5000");
+        assert!(!accounts[1].get_bool(5).unwrap());
+
+        let entries = rows(SerializedFileReader::new(File::open(&audit).unwrap()).unwrap());
+        fs::remove_file(&audit).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[2].get_uint(0).unwrap(), 2);
+        assert_eq!(column(&entries[2], 2), "\"dispute\"");
+        assert_eq!(column(&entries[2], 5), "20.2500");
+    }
+}
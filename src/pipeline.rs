@@ -0,0 +1,115 @@
+use crate::engine::Engine;
+use crate::error::Error;
+use crate::reader::{RecordReader, Row};
+use crate::utils::Instant;
+use csv::StringRecord;
+use std::{io::Read, mem, sync::mpsc, thread};
+
+/// Rows in one batch going from the parser to the engine.
+const BATCH_ROWS: usize = 1024;
+/// How many batches the parser can get ahead of the engine before it has to wait.
+const QUEUE_DEPTH: usize = 8;
+
+// parsed rows with the raw fields each was parsed from, which rejections are reported with. Batches
+// go back to the parser once applied so the records' buffers get reused
+#[derive(Default)]
+struct Batch {
+    rows: Vec<Row>,
+    raws: Vec<StringRecord>,
+}
+
+impl Engine {
+    /// Like `process_reader` but parses on a thread of its own, handing the rows over in batches,
+    /// so decoding the csv overlaps with applying it. Same rows, same order, same results.
+    pub fn process_reader_pipelined(&mut self, reader: impl Read + Send) -> Result<(), Error> {
+        let started = Instant::now();
+        let mut custom_reader = RecordReader::new(reader, self.config())?;
+        let headers = custom_reader.headers().clone();
+        let (sender, batches) = mpsc::sync_channel::<csv::Result<Batch>>(QUEUE_DEPTH);
+        let (recycle, recycled) = mpsc::channel::<Batch>();
+
+        let result = thread::scope(|scope| {
+            scope.spawn(move || loop {
+                let mut batch = recycled.try_recv().unwrap_or_default();
+                batch.rows.clear();
+                let filled = fill(&mut custom_reader, &mut batch);
+                let done = !matches!(filled, Ok(true));
+                // the engine hangs up once it's stopped or failed
+                if sender.send(filled.map(|_| batch)).is_err() || done {
+                    return;
+                }
+            });
+            // `batches` is dropped on the way out, which stops the parser if it's still going
+            self.apply_batches(&headers, batches, &recycle)
+        });
+        self.counters_mut().add_time(started.elapsed());
+        self.flush_reports()?;
+        result
+    }
+
+    fn apply_batches(
+        &mut self,
+        headers: &StringRecord,
+        batches: mpsc::Receiver<csv::Result<Batch>>,
+        recycle: &mpsc::Sender<Batch>,
+    ) -> Result<(), Error> {
+        let mut last_tx = None;
+        for batch in batches {
+            let mut batch = batch?;
+            for (row, raw) in batch.rows.drain(..).zip(&batch.raws) {
+                if self.past_stop(self.applied() + self.skipped(), last_tx) {
+                    return Ok(());
+                }
+                last_tx = row.parsed.as_ref().ok().map(|record| record.tx);
+                self.apply_row(row, headers, raw)?;
+            }
+            let _ = recycle.send(batch);
+        }
+        Ok(())
+    }
+}
+
+// reads up to BATCH_ROWS rows into `batch`, false once the input has run out
+fn fill<R: Read>(reader: &mut RecordReader<R>, batch: &mut Batch) -> csv::Result<bool> {
+    while batch.rows.len() < BATCH_ROWS {
+        let Some(row) = reader.next_row()? else {
+            return Ok(false);
+        };
+        let i = batch.rows.len();
+        if i == batch.raws.len() {
+            batch.raws.push(StringRecord::new());
+        }
+        mem::swap(reader.raw_mut(), &mut batch.raws[i]);
+        batch.rows.push(row);
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{generate_transactions, process_reader, Engine, StopAt};
+
+    #[test]
+    fn pipelined_runs_end_up_like_plain_ones() {
+        let mut input = Vec::new();
+        generate_transactions(5_000, &mut input).unwrap();
+        input.extend_from_slice(b"deposit, x, 99999, 1.0\n");
+        let plain = process_reader(input.as_slice()).unwrap();
+        let mut pipelined = Engine::new();
+        pipelined
+            .process_reader_pipelined(input.as_slice())
+            .unwrap();
+        let (mut ours, mut theirs) = (Vec::new(), Vec::new());
+        pipelined.write_csv(&mut ours).unwrap();
+        plain.write_csv(&mut theirs).unwrap();
+        assert_eq!(ours, theirs);
+        assert_eq!(pipelined.stats().by_reason, plain.stats().by_reason);
+        assert_eq!(pipelined.applied(), plain.applied());
+
+        // stopping part way through a batch
+        let mut stopped = Engine::new();
+        stopped.set_stop(StopAt::Records(1500));
+        stopped.process_reader_pipelined(input.as_slice()).unwrap();
+        assert_eq!(stopped.applied() + stopped.skipped(), 1500);
+    }
+}
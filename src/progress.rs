@@ -0,0 +1,114 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use std::{
+    io::{self, Read},
+    sync::atomic::{AtomicU64, Ordering},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+// how often the rows part of the bar is recounted, the bar itself redraws on its own schedule
+const ROWS_EVERY: Duration = Duration::from_millis(200);
+
+/// `--progress`: a bar on stderr with the bytes read out of all the inputs, the rows they held so
+/// far and, when the total size is known, an ETA. Hidden when stderr isn't a terminal.
+#[derive(Clone)]
+pub struct InputProgress {
+    bar: ProgressBar,
+    rows: Arc<AtomicU64>,
+}
+
+impl InputProgress {
+    /// `total` is what all the inputs add up to on disk, `None` for stdin.
+    pub fn new(total: Option<u64>) -> Self {
+        let (bar, template) = match total {
+            Some(total) => (
+                ProgressBar::new(total),
+                "{bar:30} {bytes}/{total_bytes} {binary_bytes_per_sec} {msg} eta {eta}",
+            ),
+            None => (
+                ProgressBar::new_spinner(),
+                "{spinner} {bytes} {binary_bytes_per_sec} {msg}",
+            ),
+        };
+        bar.set_style(ProgressStyle::with_template(template).expect("valid progress template"));
+        InputProgress {
+            bar,
+            rows: Arc::default(),
+        }
+    }
+
+    /// Counts `reader`'s bytes as they're read, before any decompression, since that's what the
+    /// total is in.
+    pub fn bytes(&self, reader: impl Read + Send + 'static) -> Box<dyn Read + Send> {
+        Box::new(self.bar.wrap_read(reader))
+    }
+
+    /// Counts the lines coming out of `reader`, the decompressed input. Close enough to rows, a
+    /// quoted field with a newline in it is the only way they differ.
+    pub fn rows(&self, reader: impl Read + Send + 'static) -> Box<dyn Read + Send> {
+        Box::new(RowCounter {
+            reader,
+            progress: self.clone(),
+            shown: Instant::now(),
+        })
+    }
+
+    pub fn finish(&self) {
+        self.show_rows();
+        self.bar.finish();
+    }
+
+    fn show_rows(&self) {
+        let rows = self.rows.load(Ordering::Relaxed);
+        let secs = self.bar.elapsed().as_secs_f64();
+        let rate = if secs > 0.0 { rows as f64 / secs } else { 0.0 };
+        self.bar
+            .set_message(format!("{} rows ({:.0} rows/s)", rows, rate));
+    }
+}
+
+struct RowCounter<R> {
+    reader: R,
+    progress: InputProgress,
+    shown: Instant,
+}
+
+impl<R: Read> Read for RowCounter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        let lines = buf[..read].iter().filter(|&&byte| byte == b'\n').count();
+        self.progress
+            .rows
+            .fetch_add(lines as u64, Ordering::Relaxed);
+        if self.shown.elapsed() >= ROWS_EVERY {
+            self.progress.show_rows();
+            self.shown = Instant::now();
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_bytes_and_rows() {
+        let input: &'static [u8] = b"type,client,tx,amount\n\
+                                     deposit,1,1,1.0\n\
+                                     deposit,2,2,2.0\n";
+        let progress = InputProgress::new(Some(input.len() as u64));
+        let mut read = Vec::new();
+        progress
+            .rows(progress.bytes(input))
+            .read_to_end(&mut read)
+            .unwrap();
+        progress.finish();
+
+        assert_eq!(read, input);
+        assert_eq!(progress.bar.position(), input.len() as u64);
+        // the header is a line too
+        assert_eq!(progress.rows.load(Ordering::Relaxed), 3);
+        assert!(progress.bar.message().starts_with("3 rows ("));
+    }
+}
@@ -0,0 +1,567 @@
+use crate::admin::parse_signature;
+use crate::config::{Delimiter, EngineConfig, InputFormat, INPUT_COLUMNS};
+use crate::error::Error;
+use crate::jsonl::JsonLines;
+use crate::schema::check_header;
+use crate::transaction::{Transaction, TransactionType};
+use crate::utils::{parse_amount, parse_timestamp, AmountNotation};
+use csv::{ErrorKind, StringRecord, Trim};
+use std::{
+    collections::BTreeMap,
+    io::{self, Cursor, Read},
+};
+
+/// One data row from the input. `row` is the 1-based line number in the file (the header is line 1).
+pub(crate) struct Row {
+    pub row: u64,
+    pub parsed: Result<Transaction, csv::Error>,
+}
+
+/// The columns of a transactions csv, for inputs that don't come with a header row.
+#[cfg(any(feature = "grpc", feature = "kafka", feature = "ffi"))]
+pub(crate) fn default_headers() -> StringRecord {
+    StringRecord::from(vec![
+        "type",
+        "client",
+        "tx",
+        "amount",
+        "signature",
+        "currency",
+        "timestamp",
+    ])
+}
+
+/// A record's fields joined back up with commas, to show a row in error messages.
+pub(crate) fn csv_line(record: &StringRecord) -> String {
+    record.iter().collect::<Vec<_>>().join(",")
+}
+
+/// The separator a header line has most of, among comma, tab, semicolon and pipe. Comma if it has
+/// none of them, a one column file.
+pub(crate) fn detect_delimiter(header: &[u8]) -> u8 {
+    let line = header
+        .split(|&byte| byte == b'\n')
+        .next()
+        .unwrap_or_default();
+    // ties go to the one listed first
+    [b',', b'\t', b';', b'|']
+        .into_iter()
+        .rev()
+        .max_by_key(|&candidate| line.iter().filter(|&&byte| byte == candidate).count())
+        .filter(|&candidate| line.contains(&candidate))
+        .unwrap_or(b',')
+}
+
+// the input read up to the end of its first line, for guessing the delimiter, handed back to the csv
+// reader in front of the rest
+fn read_header_line(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut start = Vec::new();
+    let mut chunk = [0; 4096];
+    while !start.contains(&b'\n') {
+        match reader.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => start.extend_from_slice(&chunk[..n]),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(start)
+}
+
+/// The header's names, with the ones `columns` maps (from the input's name to ours) replaced.
+pub(crate) fn renamed<'a>(
+    headers: impl IntoIterator<Item = &'a str>,
+    columns: &BTreeMap<String, String>,
+) -> Vec<String> {
+    headers
+        .into_iter()
+        .map(|name| columns.get(name).map_or(name, String::as_str).to_string())
+        .collect()
+}
+
+/// Which column has the amounts and how they're written, unless they're plain decimals.
+pub(crate) fn amount_column(
+    headers: &StringRecord,
+    notation: AmountNotation,
+) -> Option<(usize, AmountNotation)> {
+    (!notation.is_plain())
+        .then(|| headers.iter().position(|header| header == "amount"))
+        .flatten()
+        .map(|i| (i, notation))
+}
+
+/// `raw` with its amount (at the column `amount_column` found) turned into plain decimal text.
+/// `None` if there's nothing to turn, the row is parsed as it is then.
+pub(crate) fn unformatted(
+    raw: &StringRecord,
+    amount: Option<(usize, AmountNotation)>,
+) -> Option<StringRecord> {
+    let (i, notation) = amount?;
+    let amount = notation.plain(raw.get(i)?)?;
+    let fields = raw.iter().enumerate();
+    let mut record: StringRecord = fields
+        .map(|(j, field)| if j == i { amount.as_str() } else { field })
+        .collect();
+    record.set_position(raw.position().cloned());
+    Some(record)
+}
+
+/// Where each `Transaction` field is among the header's columns, for the fast parser.
+#[derive(Debug, Clone, Copy)]
+struct Columns {
+    r_type: usize,
+    client: usize,
+    tx: usize,
+    amount: Option<usize>,
+    currency: Option<usize>,
+    timestamp: Option<usize>,
+    signature: Option<usize>,
+    // serde fails a row that ends before a column it doesn't know, so those rows go to serde
+    short_of: usize,
+}
+
+impl Columns {
+    // `None` unless the header names type, client and tx, and none of the columns twice. serde gets
+    // those headers to itself
+    fn find(headers: &StringRecord) -> Option<Columns> {
+        let column = |name: &str| {
+            let mut found = headers.iter().enumerate().filter(|&(_, h)| h == name);
+            match (found.next(), found.next()) {
+                (Some((i, _)), None) => Ok(Some(i)),
+                (None, _) => Ok(None),
+                (Some(_), Some(_)) => Err(()),
+            }
+        };
+        let optional = |name| column(name).ok();
+        const KNOWN: [&str; 7] = [
+            "type",
+            "client",
+            "tx",
+            "amount",
+            "currency",
+            "timestamp",
+            "signature",
+        ];
+        let short_of = headers
+            .iter()
+            .enumerate()
+            .filter(|(_, header)| !KNOWN.contains(header))
+            .last()
+            .map_or(0, |(i, _)| i + 1);
+        Some(Columns {
+            r_type: optional("type")??,
+            client: optional("client")??,
+            tx: optional("tx")??,
+            amount: optional("amount")?,
+            currency: optional("currency")?,
+            timestamp: optional("timestamp")?,
+            signature: optional("signature")?,
+            short_of,
+        })
+    }
+
+    // the fields straight off the record, no serde. `None` for anything it can't take, which is
+    // then left to serde to parse or explain
+    fn parse(&self, raw: &StringRecord) -> Option<Transaction> {
+        if raw.len() < self.short_of {
+            return None;
+        }
+        let field = |i: Option<usize>| {
+            i.and_then(|i| raw.get(i))
+                .map(str::trim)
+                .filter(|field| !field.is_empty())
+        };
+        Some(Transaction {
+            r_type: TransactionType::from_tag(raw.get(self.r_type)?),
+            client: raw.get(self.client)?.parse().ok()?,
+            tx: raw.get(self.tx)?.parse().ok()?,
+            amount: None,
+            given: field(self.amount).map(parse_amount).transpose().ok()?,
+            currency: field(self.currency)
+                .map(str::parse)
+                .transpose()
+                .ok()?
+                .unwrap_or_default(),
+            timestamp: field(self.timestamp)
+                .map(parse_timestamp)
+                .transpose()
+                .ok()?,
+            status: Default::default(),
+            seq: 0,
+            signature: field(self.signature)
+                .map(parse_signature)
+                .transpose()
+                .ok()?,
+            held: None,
+        })
+    }
+}
+
+// the input as it is, or JSON Lines turned into csv on the way in
+enum Input<R> {
+    Csv(R),
+    Jsonl(Box<JsonLines<R>>),
+}
+
+impl<R: Read> Input<R> {
+    fn new(reader: R, config: &EngineConfig) -> Self {
+        match config.input_format {
+            InputFormat::Csv => Input::Csv(reader),
+            InputFormat::Jsonl => Input::Jsonl(Box::new(JsonLines::new(reader, &config.columns))),
+        }
+    }
+}
+
+impl<R: Read> Read for Input<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Input::Csv(reader) => reader.read(buf),
+            Input::Jsonl(reader) => reader.read(buf),
+        }
+    }
+}
+
+/// Thin wrapper over csv::Reader that keeps going past bad rows. Rows that don't parse come back as
+/// an `Err` in `Row::parsed` with the raw fields still available, only real I/O failures stop it.
+pub(crate) struct RecordReader<R> {
+    reader: csv::Reader<io::Chain<Cursor<Vec<u8>>, Input<R>>>,
+    headers: StringRecord,
+    delimiter: u8,
+    raw: StringRecord,
+    // where `reader` started in the original input, non-zero when resuming part way through
+    byte_base: u64,
+    line_base: u64,
+    // set with `fast`, if the header allows it
+    columns: Option<Columns>,
+    // the amount column, set with `notation` if there is one and its amounts aren't plain
+    amount: Option<(usize, AmountNotation)>,
+}
+
+impl<R: Read> RecordReader<R> {
+    /// Reads the header line, with the config's delimiter (telling it from the line if it's
+    /// `Delimiter::Auto`), column names and parser. A header without the columns every record needs
+    /// fails here, saying what it has instead. JSON Lines input has no header, its records come
+    /// with every one of our columns.
+    pub fn new(reader: R, config: &EngineConfig) -> Result<Self, Error> {
+        let (reader, found) = Self::open(reader, config)?;
+        check_header(&found, reader.headers())?;
+        Ok(reader)
+    }
+
+    /// `new` without the header check, also handing back the header as it was in the file.
+    pub fn open(mut reader: R, config: &EngineConfig) -> csv::Result<(Self, StringRecord)> {
+        if config.input_format == InputFormat::Jsonl {
+            let headers = StringRecord::from(INPUT_COLUMNS.to_vec());
+            let reader = Self::resume(reader, config, headers.clone(), b',', 0, 0);
+            return Ok((reader, headers));
+        }
+        let delimiter = config.delimiter;
+        let start = match delimiter {
+            Delimiter::Auto => read_header_line(&mut reader)?,
+            Delimiter::Byte(_) => Vec::new(),
+        };
+        let delimiter = match delimiter {
+            Delimiter::Auto => detect_delimiter(&start),
+            Delimiter::Byte(byte) => byte,
+        };
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .delimiter(delimiter)
+            .trim(Trim::All)
+            .flexible(true)
+            .from_reader(Cursor::new(start).chain(Input::Csv(reader)));
+        let found = reader.headers()?.clone();
+        let headers = StringRecord::from(renamed(&found, &config.columns));
+        let reader = RecordReader {
+            reader,
+            headers,
+            delimiter,
+            raw: StringRecord::new(),
+            byte_base: 0,
+            line_base: 0,
+            columns: None,
+            amount: None,
+        };
+        let reader = reader
+            .fast(config.fast_parse)
+            .notation(config.amount_notation());
+        Ok((reader, found))
+    }
+
+    /// Picks up an input part way through. `reader` must already be at `byte` (the start of a
+    /// record, as given by `position`), so the header line isn't there and is passed in instead,
+    /// renamed already and along with the delimiter it was read with. The input format and parser
+    /// are the config's.
+    pub fn resume(
+        reader: R,
+        config: &EngineConfig,
+        headers: StringRecord,
+        delimiter: u8,
+        byte: u64,
+        line: u64,
+    ) -> Self {
+        let reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .delimiter(delimiter)
+            .trim(Trim::All)
+            .flexible(true)
+            .from_reader(Cursor::new(Vec::new()).chain(Input::new(reader, config)));
+        let reader = RecordReader {
+            reader,
+            headers,
+            delimiter,
+            raw: StringRecord::new(),
+            byte_base: byte,
+            line_base: line,
+            columns: None,
+            amount: None,
+        };
+        reader
+            .fast(config.fast_parse)
+            .notation(config.amount_notation())
+    }
+
+    /// Parses rows by hand instead of through serde (see `EngineConfig::fast_parse`). Rows come out
+    /// the same either way, bad ones are handed to serde so they're rejected with the same error.
+    pub fn fast(mut self, fast: bool) -> Self {
+        self.columns = fast.then(|| Columns::find(&self.headers)).flatten();
+        self
+    }
+
+    /// Turns amounts written this way into plain decimal text before they're parsed (see
+    /// `EngineConfig::formatted_amounts` and `decimal_comma`). The raw fields stay as they were,
+    /// for the error report.
+    pub(crate) fn notation(mut self, notation: AmountNotation) -> Self {
+        self.amount = amount_column(&self.headers, notation);
+        self
+    }
+
+    /// Byte offset of the next record and how many lines came before it, in the original input.
+    pub fn position(&self) -> (u64, u64) {
+        let (byte, line) = self.input_position(self.reader.position());
+        (self.byte_base + byte, self.line_base + line - 1)
+    }
+
+    // a position in what the csv reader reads as one in the input, less the bases
+    fn input_position(&self, pos: &csv::Position) -> (u64, u64) {
+        match self.reader.get_ref().get_ref().1 {
+            Input::Csv(_) => (pos.byte(), pos.line()),
+            Input::Jsonl(lines) => lines.input_position(pos.byte()),
+        }
+    }
+
+    pub fn headers(&self) -> &StringRecord {
+        &self.headers
+    }
+
+    pub fn delimiter(&self) -> u8 {
+        self.delimiter
+    }
+
+    /// The raw fields of the row most recently returned by `next_row`.
+    pub fn raw(&self) -> &StringRecord {
+        &self.raw
+    }
+
+    /// For taking the raw fields out, leaving another record's buffer to read the next row into.
+    pub fn raw_mut(&mut self) -> &mut StringRecord {
+        &mut self.raw
+    }
+
+    // the error for a JSON Lines row that wasn't a JSON object, so it isn't explained as bad csv
+    fn not_an_object(&self, row: u64) -> Option<csv::Error> {
+        let Input::Jsonl(lines) = self.reader.get_ref().get_ref().1 else {
+            return None;
+        };
+        let byte = self.raw.position()?.byte();
+        let message = format!("line {} is not a JSON object", row);
+        (!lines.is_object(byte)).then(|| io::Error::new(io::ErrorKind::InvalidData, message).into())
+    }
+
+    pub fn next_row(&mut self) -> csv::Result<Option<Row>> {
+        let pos = self.reader.position().clone();
+        if let Input::Jsonl(lines) = self.reader.get_mut().get_mut().1 {
+            lines.forget_before(pos.byte());
+        }
+        let line = self.line_base + self.input_position(&pos).1;
+        match self.reader.read_record(&mut self.raw) {
+            Ok(false) => Ok(None),
+            Ok(true) => {
+                let row = self
+                    .raw
+                    .position()
+                    .map_or(line, |pos| self.line_base + self.input_position(pos).1);
+                if let Some(err) = self.not_an_object(row) {
+                    return Ok(Some(Row {
+                        row,
+                        parsed: Err(err),
+                    }));
+                }
+                let unformatted = unformatted(&self.raw, self.amount);
+                let raw = unformatted.as_ref().unwrap_or(&self.raw);
+                let parsed = match self.columns.and_then(|columns| columns.parse(raw)) {
+                    Some(record) => Ok(record),
+                    None => raw.deserialize(Some(&self.headers)),
+                };
+                Ok(Some(Row { row, parsed }))
+            }
+            // broken utf-8 is just a bad row, keep reading. rows don't have to have as many fields
+            // as the header (the reader is flexible), whatever a short one lacks deserializes as
+            // missing and extra trailing fields are ignored
+            Err(err) if matches!(err.kind(), ErrorKind::Utf8 { .. }) => {
+                let row = err
+                    .position()
+                    .map_or(line, |pos| self.line_base + self.input_position(pos).1);
+                Ok(Some(Row {
+                    row,
+                    parsed: Err(err),
+                }))
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_all(input: &str, fast: bool) -> Vec<String> {
+        let config = EngineConfig {
+            fast_parse: fast,
+            ..EngineConfig::default()
+        };
+        let mut reader = RecordReader::new(input.as_bytes(), &config).unwrap();
+        let mut rows = Vec::new();
+        while let Some(row) = reader.next_row().unwrap() {
+            let parsed = row.parsed.map_err(|err| err.to_string());
+            rows.push(format!("{} {:?}", row.row, parsed));
+        }
+        rows
+    }
+
+    #[test]
+    fn fast_parsing_reads_the_same_rows() {
+        let input = "type, client, tx, amount, currency, timestamp, signature, note\n\
+                     deposit, 1, 1, 1.5, , , , hi\n\
+                     withdrawal, 1, 2, 0.25, eur, 2024-05-01, ,\n\
+                     dispute, 1, 1, , , 1714567800, ,\n\
+                     Deposit, 1, 3, 1.0\n\
+                     teleport, 2, 4, 1.0\n\
+                     deposit, 2, 5\n\
+                     deposit, 2\n\
+                     deposit, x, 6, 1.0\n\
+                     deposit, 2, 7, 1.0.0\n\
+                     deposit, 2, 8, 1.0, euros!, ,\n\
+                     deposit, 2, 9, 1.0, , yesterday\n\
+                     unlock, 2, 10, , , , abc\n\
+                     unlock, 2, 11, , , , 00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff\n\
+                     , 2, 12, 1.0\n\
+                     deposit, 70000, 13, 1.0\n";
+        let fast = parse_all(input, true);
+        assert_eq!(fast, parse_all(input, false));
+        assert_eq!(fast.len(), 15);
+
+        let plain = "type,client,tx,amount\ndeposit,1,1,2.0\ndispute,1,1\nresolve,1,1,\n";
+        assert_eq!(parse_all(plain, true), parse_all(plain, false));
+
+        // a header serde would refuse goes to serde
+        let twice = "type, client, tx, amount, amount\ndeposit, 1, 1, 1.0, 2.0\n";
+        assert!(
+            RecordReader::new(twice.as_bytes(), &EngineConfig::default())
+                .unwrap()
+                .fast(true)
+                .columns
+                .is_none()
+        );
+        assert_eq!(parse_all(twice, true), parse_all(twice, false));
+    }
+
+    #[test]
+    fn delimiters_are_told_from_the_header() {
+        let comma = parse_all("type, client, tx, amount\ndeposit, 1, 1, 1.5\n", false);
+        let tabs = parse_all("type\tclient\ttx\tamount\ndeposit\t1\t1\t1.5\n", false);
+        assert_eq!(tabs, comma);
+        assert_eq!(
+            parse_all("type;client;tx;amount\ndeposit;1;1;1.5\n", false),
+            comma
+        );
+
+        assert_eq!(detect_delimiter(b"type|client|tx|amount\n1,2"), b'|');
+        assert_eq!(detect_delimiter(b"type"), b',');
+        let config = EngineConfig {
+            delimiter: Delimiter::Byte(b','),
+            ..EngineConfig::default()
+        };
+        let (mut forced, _) =
+            RecordReader::open("type;client;tx\ndeposit;1;1\n".as_bytes(), &config).unwrap();
+        assert_eq!(forced.headers().len(), 1);
+        assert!(forced.next_row().unwrap().is_some());
+    }
+
+    #[test]
+    fn formatted_amounts_are_read_when_asked() {
+        let input = "type,client,tx,amount\n\
+                     deposit,1,1,\"1,234.50\"\n\
+                     deposit,1,2,$12.00\n\
+                     withdrawal,1,3,-€ 1.5\n\
+                     deposit,1,4,\"$-1,000\"\n\
+                     deposit,1,5,\"1,5\"\n\
+                     deposit,1,6,\"12,34.5\"\n\
+                     deposit,1,7,2.25\n";
+        let amounts = |config: &EngineConfig| {
+            let mut reader = RecordReader::new(input.as_bytes(), config).unwrap();
+            let mut amounts = Vec::new();
+            while let Some(row) = reader.next_row().unwrap() {
+                amounts.push(row.parsed.ok().and_then(|record| record.given));
+            }
+            amounts
+        };
+        let plain = amounts(&EngineConfig::default());
+        assert_eq!(plain.iter().flatten().count(), 1);
+        for fast in [false, true] {
+            let config = EngineConfig {
+                formatted_amounts: true,
+                fast_parse: fast,
+                ..EngineConfig::default()
+            };
+            let dec = |text: &str| Some(text.parse().unwrap());
+            assert_eq!(
+                amounts(&config),
+                [
+                    dec("1234.50"),
+                    dec("12.00"),
+                    dec("-1.5"),
+                    dec("-1000"),
+                    None,
+                    None,
+                    dec("2.25")
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn mapped_columns_read_like_ours() {
+        let config = EngineConfig::from_toml_str(
+            "[columns]\n\
+             txn_type = \"type\"\n\
+             customer_id = \"client\"\n\
+             value = \"amount\"\n",
+        )
+        .unwrap();
+        let input = "txn_type, customer_id, tx, value\ndeposit, 1, 1, 1.5\n";
+        for fast in [false, true] {
+            let config = EngineConfig {
+                fast_parse: fast,
+                ..config.clone()
+            };
+            let mut reader = RecordReader::new(input.as_bytes(), &config).unwrap();
+            assert_eq!(reader.headers(), vec!["type", "client", "tx", "amount"]);
+            let record = reader.next_row().unwrap().unwrap().parsed.unwrap();
+            assert_eq!(record.r_type, TransactionType::Deposit);
+            assert_eq!(record.client, 1);
+            assert_eq!(record.given, Some(rust_decimal::Decimal::new(15, 1)));
+        }
+    }
+}
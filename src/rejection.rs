@@ -0,0 +1,113 @@
+use std::{error::Error, fmt};
+
+/// Why the engine refused to apply a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Rejection {
+    Malformed,
+    UnknownType,
+    InvalidAmount,
+    MissingAmount,
+    AmountTooLarge,
+    DuplicateTx,
+    UnknownTx,
+    AlreadyDisputed,
+    NotDisputed,
+    OverHeld,
+    NotApplied,
+    ClientMismatch,
+    CurrencyMismatch,
+    InsufficientFunds,
+    OverWithdrawalLimit,
+    TooManyWithdrawals,
+    OverWithdrawalTotal,
+    AccountLocked,
+    WithdrawalDispute,
+    DisputeWindowClosed,
+    NotDisputable,
+    Unauthorized,
+    NotLocked,
+    BalanceOverflow,
+}
+
+impl Rejection {
+    /// Whether the record itself is bad (doesn't parse, unknown type, bad amount, points at a tx it
+    /// can't), as opposed to a valid record the ledger's state doesn't allow. These are what
+    /// `strict` runs fail on.
+    pub fn is_invalid_input(self) -> bool {
+        matches!(
+            self,
+            Rejection::Malformed
+                | Rejection::UnknownType
+                | Rejection::InvalidAmount
+                | Rejection::MissingAmount
+                | Rejection::AmountTooLarge
+                | Rejection::UnknownTx
+                | Rejection::ClientMismatch
+                | Rejection::CurrencyMismatch
+        )
+    }
+
+    /// A short snake_case name, for labels and the like where the reason sentence won't do.
+    pub fn name(self) -> &'static str {
+        match self {
+            Rejection::Malformed => "malformed",
+            Rejection::UnknownType => "unknown_type",
+            Rejection::InvalidAmount => "invalid_amount",
+            Rejection::MissingAmount => "missing_amount",
+            Rejection::AmountTooLarge => "amount_too_large",
+            Rejection::DuplicateTx => "duplicate_tx",
+            Rejection::UnknownTx => "unknown_tx",
+            Rejection::AlreadyDisputed => "already_disputed",
+            Rejection::NotDisputed => "not_disputed",
+            Rejection::OverHeld => "over_held",
+            Rejection::NotApplied => "not_applied",
+            Rejection::ClientMismatch => "client_mismatch",
+            Rejection::CurrencyMismatch => "currency_mismatch",
+            Rejection::InsufficientFunds => "insufficient_funds",
+            Rejection::OverWithdrawalLimit => "over_withdrawal_limit",
+            Rejection::TooManyWithdrawals => "too_many_withdrawals",
+            Rejection::OverWithdrawalTotal => "over_withdrawal_total",
+            Rejection::AccountLocked => "account_locked",
+            Rejection::WithdrawalDispute => "withdrawal_dispute",
+            Rejection::DisputeWindowClosed => "dispute_window_closed",
+            Rejection::NotDisputable => "not_disputable",
+            Rejection::Unauthorized => "unauthorized",
+            Rejection::NotLocked => "not_locked",
+            Rejection::BalanceOverflow => "balance_overflow",
+        }
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            Rejection::Malformed => "malformed record",
+            Rejection::UnknownType => "unknown transaction type",
+            Rejection::InvalidAmount => "amount must be greater than zero",
+            Rejection::MissingAmount => "amount is missing",
+            Rejection::AmountTooLarge => "amount is over the largest one accepted",
+            Rejection::DuplicateTx => "duplicate transaction id",
+            Rejection::UnknownTx => "referenced transaction does not exist",
+            Rejection::AlreadyDisputed => "transaction was already disputed",
+            Rejection::NotDisputed => "transaction is not under dispute",
+            Rejection::OverHeld => "dispute is for more than the account holds",
+            Rejection::NotApplied => "referenced transaction was never applied",
+            Rejection::ClientMismatch => "referenced transaction belongs to another client",
+            Rejection::CurrencyMismatch => "referenced transaction is in another currency",
+            Rejection::InsufficientFunds => "insufficient available funds",
+            Rejection::OverWithdrawalLimit => "withdrawal is over the single withdrawal limit",
+            Rejection::TooManyWithdrawals => "too many withdrawals that day",
+            Rejection::OverWithdrawalTotal => "withdrawals that day would go over the total limit",
+            Rejection::AccountLocked => "account is locked",
+            Rejection::WithdrawalDispute => "disputes on withdrawals are not allowed",
+            Rejection::DisputeWindowClosed => "transaction is too old to dispute",
+            Rejection::NotDisputable => "only deposits and withdrawals can be disputed",
+            Rejection::Unauthorized => "admin record without a valid signature",
+            Rejection::NotLocked => "account is not locked",
+            Rejection::BalanceOverflow => "balance would overflow",
+        };
+        f.write_str(reason)
+    }
+}
+
+impl Error for Rejection {}
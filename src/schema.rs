@@ -0,0 +1,319 @@
+use crate::config::{EngineConfig, INPUT_COLUMNS};
+use crate::error::Error;
+use crate::reader::RecordReader;
+use crate::utils::{parse_amount, parse_timestamp};
+use std::{fmt, io::Read};
+
+/// The columns every input needs, the rest are optional.
+const REQUIRED: [&str; 3] = ["type", "client", "tx"];
+
+// header names other systems use for our columns, lowercase without separators
+const ALIASES: [(&str, &[&str]); 3] = [
+    (
+        "type",
+        &[
+            "transactiontype",
+            "txntype",
+            "txtype",
+            "kind",
+            "rtype",
+            "recordtype",
+        ],
+    ),
+    (
+        "client",
+        &[
+            "clientid",
+            "customer",
+            "customerid",
+            "account",
+            "accountid",
+            "user",
+            "userid",
+        ],
+    ),
+    (
+        "tx",
+        &[
+            "txid",
+            "txn",
+            "txnid",
+            "transaction",
+            "transactionid",
+            "id",
+            "reference",
+        ],
+    ),
+];
+
+/// What a column's values look like, the narrowest kind all of its non-empty values fit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColumnKind {
+    /// No values at all.
+    Empty,
+    Integer,
+    Decimal,
+    /// Unix seconds or RFC 3339, like the `timestamp` column takes.
+    Timestamp,
+    Text,
+}
+
+impl ColumnKind {
+    fn of(value: &str) -> ColumnKind {
+        if value.parse::<i64>().is_ok() {
+            ColumnKind::Integer
+        } else if parse_amount(value).is_ok() {
+            ColumnKind::Decimal
+        } else if parse_timestamp(value).is_ok() {
+            ColumnKind::Timestamp
+        } else {
+            ColumnKind::Text
+        }
+    }
+
+    fn widen(self, other: ColumnKind) -> ColumnKind {
+        match (self, other) {
+            (ColumnKind::Empty, kind) | (kind, ColumnKind::Empty) => kind,
+            (ColumnKind::Integer, ColumnKind::Decimal)
+            | (ColumnKind::Decimal, ColumnKind::Integer) => ColumnKind::Decimal,
+            (a, b) if a == b => a,
+            _ => ColumnKind::Text,
+        }
+    }
+}
+
+impl fmt::Display for ColumnKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ColumnKind::Empty => "empty",
+            ColumnKind::Integer => "integer",
+            ColumnKind::Decimal => "decimal",
+            ColumnKind::Timestamp => "timestamp",
+            ColumnKind::Text => "text",
+        })
+    }
+}
+
+/// One column of an input as `infer_schema` saw it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnSchema {
+    /// The name in the file's header.
+    pub name: String,
+    /// Which of our columns it's read as, `None` if it's ignored.
+    pub reads_as: Option<&'static str>,
+    pub kind: ColumnKind,
+    /// Rows where it was empty or missing.
+    pub empty: u64,
+}
+
+/// An input's delimiter and columns, from its header and first rows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schema {
+    pub delimiter: u8,
+    pub columns: Vec<ColumnSchema>,
+    /// How many rows the kinds were inferred from.
+    pub rows: u64,
+    /// The header problem that would stop a run, if there is one.
+    pub problem: Option<String>,
+}
+
+/// Reads the header and up to `rows` rows of `reader` the way a run with `config` would, and
+/// describes its columns.
+pub fn infer_schema(reader: impl Read, config: &EngineConfig, rows: u64) -> Result<Schema, Error> {
+    let (mut reader, names) = RecordReader::open(reader, config)?;
+    let problem = check_header(&names, reader.headers())
+        .err()
+        .map(|err| err.to_string());
+    let mut columns: Vec<ColumnSchema> = names
+        .iter()
+        .zip(reader.headers())
+        .map(|(name, ours)| ColumnSchema {
+            name: name.to_string(),
+            reads_as: INPUT_COLUMNS.into_iter().find(|&column| column == ours),
+            kind: ColumnKind::Empty,
+            empty: 0,
+        })
+        .collect();
+    let mut seen = 0;
+    while seen < rows {
+        if reader.next_row()?.is_none() {
+            break;
+        }
+        seen += 1;
+        for (i, column) in columns.iter_mut().enumerate() {
+            match reader.raw().get(i).filter(|value| !value.is_empty()) {
+                Some(value) => column.kind = column.kind.widen(ColumnKind::of(value)),
+                None => column.empty += 1,
+            }
+        }
+    }
+    Ok(Schema {
+        delimiter: reader.delimiter(),
+        columns,
+        rows: seen,
+        problem,
+    })
+}
+
+impl fmt::Display for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let delimiter = match self.delimiter {
+            b'\t' => "tab".to_string(),
+            byte => format!("{:?}", byte as char),
+        };
+        writeln!(f, "delimiter: {}", delimiter)?;
+        writeln!(f, "rows read: {}", self.rows)?;
+        let width = self.columns.iter().map(|c| c.name.len()).max().unwrap_or(0);
+        for column in &self.columns {
+            let reads_as = match column.reads_as {
+                Some(ours) if ours == column.name => String::new(),
+                Some(ours) => format!(" -> {}", ours),
+                None => " (ignored)".to_string(),
+            };
+            writeln!(
+                f,
+                "  {:width$}  {:9}  {} empty{}",
+                column.name,
+                column.kind.to_string(),
+                column.empty,
+                reads_as,
+                width = width
+            )?;
+        }
+        if let Some(problem) = &self.problem {
+            writeln!(f, "problem: {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+/// Fails with what's wrong if the header (`found` as in the file, `ours` after `[columns]`) lacks
+/// a column every record needs, listing what it has and which of its columns might be the missing
+/// ones. An input without a header at all is fine, it has no rows either.
+pub(crate) fn check_header<'a>(
+    found: impl IntoIterator<Item = &'a str> + Clone,
+    ours: impl IntoIterator<Item = &'a str> + Clone,
+) -> Result<(), Error> {
+    let missing: Vec<&str> = REQUIRED
+        .into_iter()
+        .filter(|&column| !ours.clone().into_iter().any(|name| name == column))
+        .collect();
+    let found: Vec<&str> = found.into_iter().collect();
+    if missing.is_empty() || found.iter().all(|name| name.is_empty()) {
+        return Ok(());
+    }
+    // only columns we don't read already can be the missing ones
+    let mut unused: Vec<&str> = found
+        .iter()
+        .zip(ours)
+        .filter(|(_, ours)| !INPUT_COLUMNS.contains(ours))
+        .map(|(&name, _)| name)
+        .collect();
+    let mut suggestions = Vec::new();
+    for column in &missing {
+        let best = unused
+            .iter()
+            .enumerate()
+            .filter_map(|(i, name)| likeness(column, name).map(|score| (score, i)))
+            .min();
+        if let Some((_, i)) = best {
+            let mapping = format!("{}={}", unused.remove(i), column);
+            suggestions.push(match mapping.contains(char::is_whitespace) {
+                true => format!("--column '{}'", mapping),
+                false => format!("--column {}", mapping),
+            });
+        }
+    }
+
+    let mut message = format!(
+        "the header is missing {}: found {}, expected {} and optionally {}",
+        missing.join(", "),
+        found.join(", "),
+        REQUIRED.join(", "),
+        INPUT_COLUMNS[REQUIRED.len()..].join(", "),
+    );
+    if !suggestions.is_empty() {
+        message.push_str(&format!(" (maybe {})", suggestions.join(" ")));
+    }
+    Err(Error::Input(message))
+}
+
+// how much `name` looks like `column`, lower is more. `None` if it doesn't at all
+fn likeness(column: &str, name: &str) -> Option<usize> {
+    let name: String = name
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    let aliases = ALIASES
+        .iter()
+        .find(|(ours, _)| *ours == column)
+        .map_or(&[][..], |(_, aliases)| aliases);
+    if name == column {
+        Some(0)
+    } else if aliases.contains(&name.as_str()) {
+        Some(1)
+    } else {
+        let distance = edit_distance(column, &name);
+        (distance <= 2).then_some(2 + distance)
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, &b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_columns_are_named_with_likely_mappings() {
+        let input = "Transaction Type, customer_id, txid, amount, note\n\
+                     deposit, 1, 1, 1.5, hi\n";
+        let err = crate::process_reader(input.as_bytes()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the header is missing type, client, tx: found Transaction Type, customer_id, txid, \
+             amount, note, expected type, client, tx and optionally amount, currency, timestamp, \
+             signature (maybe --column 'Transaction Type=type' --column customer_id=client \
+             --column txid=tx)"
+        );
+
+        let schema = infer_schema(input.as_bytes(), &EngineConfig::default(), 10).unwrap();
+        assert_eq!(schema.rows, 1);
+        assert!(schema.problem.is_some());
+        let kinds: Vec<_> = schema
+            .columns
+            .iter()
+            .map(|c| (c.kind, c.reads_as))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (ColumnKind::Text, None),
+                (ColumnKind::Integer, None),
+                (ColumnKind::Integer, None),
+                (ColumnKind::Decimal, Some("amount")),
+                (ColumnKind::Text, None),
+            ]
+        );
+
+        let fine = "type;client;tx;amount\ndeposit;1;1;\nwithdrawal;1;2;0.5\n";
+        let schema = infer_schema(fine.as_bytes(), &EngineConfig::default(), 10).unwrap();
+        assert_eq!(schema.delimiter, b';');
+        assert_eq!(schema.problem, None);
+        assert_eq!(schema.columns[3].kind, ColumnKind::Decimal);
+        assert_eq!(schema.columns[3].empty, 1);
+    }
+}
@@ -0,0 +1,213 @@
+use crate::currency::Currency;
+use crate::engine::Engine;
+use crate::error::Error;
+use crate::output::{rows, SortBy};
+use crate::snapshot::Snapshots;
+use serde_json::{json, Value};
+use std::{
+    io::{self, Read},
+    time::Duration,
+};
+use tiny_http::{Header, Method, Response, Server};
+
+/// How long the server waits for a request before it looks at the snapshot flag again.
+const SNAPSHOT_POLL: Duration = Duration::from_millis(200);
+
+/// Runs `engine` as a long-lived HTTP service on `addr` (e.g. `127.0.0.1:8080`):
+///
+/// - `POST /transactions` applies a csv body (header row included) like one more input file and
+///   answers with how many records were applied and rejected
+/// - `GET /accounts` lists every account, by client, one row per currency like the report
+/// - `GET /accounts/:id` is one account's balance in the default currency, or in the one
+///   `?currency=EUR` names, 404 if the client was never seen
+/// - `GET /metrics` is `Engine::metrics`, for Prometheus to scrape
+/// - `POST /admin/snapshot` writes a snapshot of the accounts with `snapshots` and answers with
+///   its path, 404 without `snapshots`. Their flag gets one written too, between requests
+///
+/// Requests are handled one at a time in the order they arrive, so the ledger sees one long stream.
+/// Only returns if the listener can't be set up.
+pub fn serve(engine: &mut Engine, addr: &str, snapshots: Option<&Snapshots>) -> Result<(), Error> {
+    let server = listen(addr)?;
+    loop {
+        if let Some(snapshots) = snapshots {
+            snapshots.write_requested(engine);
+        }
+        let Some(mut request) = server.recv_timeout(SNAPSHOT_POLL)? else {
+            continue;
+        };
+        let method = request.method().clone();
+        let url = request.url().to_string();
+        let _span = tracing::info_span!("request", %method, url).entered();
+        // the one that isn't json
+        if method == Method::Get && url == "/metrics" {
+            if let Err(err) = request.respond(metrics_response(engine.metrics())) {
+                tracing::info!(error = %err, "could not respond");
+            }
+            continue;
+        }
+        let (status, body) = handle(engine, snapshots, &method, &url, request.as_reader());
+        let json = Header::from_bytes("Content-Type", "application/json").expect("valid header");
+        let response = Response::from_string(body.to_string())
+            .with_status_code(status)
+            .with_header(json);
+        // a client that hung up is no reason to stop serving the others
+        if let Err(err) = request.respond(response) {
+            tracing::info!(error = %err, "could not respond");
+        }
+    }
+}
+
+pub(crate) fn listen(addr: &str) -> Result<Server, Error> {
+    let server = Server::http(addr).map_err(|err| {
+        Error::Io(io::Error::other(format!(
+            "can't listen on {}: {}",
+            addr, err
+        )))
+    })?;
+    tracing::info!(addr, "listening");
+    Ok(server)
+}
+
+pub(crate) fn metrics_response(text: String) -> Response<io::Cursor<Vec<u8>>> {
+    let content_type =
+        Header::from_bytes("Content-Type", "text/plain; version=0.0.4").expect("valid header");
+    Response::from_string(text).with_header(content_type)
+}
+
+fn handle(
+    engine: &mut Engine,
+    snapshots: Option<&Snapshots>,
+    method: &Method,
+    url: &str,
+    body: impl Read,
+) -> (u16, Value) {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let path = path.strip_suffix('/').unwrap_or(path);
+    match (path, method) {
+        ("/transactions", Method::Post) => post_transactions(engine, body),
+        ("/accounts", Method::Get) => (200, json!(rows(engine, SortBy::Client))),
+        ("/admin/snapshot", Method::Post) => match snapshots.map(|s| s.write(engine)) {
+            Some(Ok(path)) => (200, json!({ "path": path.display().to_string() })),
+            Some(Err(err)) => (500, error(&err.to_string())),
+            None => (404, error("snapshots are off, start with --snapshot-dir")),
+        },
+        ("/transactions" | "/accounts" | "/admin/snapshot", _) => {
+            (405, error("method not allowed"))
+        }
+        _ => match path.strip_prefix("/accounts/") {
+            Some(id) if method == &Method::Get => get_account(engine, id, query),
+            Some(_) => (405, error("method not allowed")),
+            None => (404, error("not found")),
+        },
+    }
+}
+
+fn post_transactions(engine: &mut Engine, body: impl Read) -> (u16, Value) {
+    let (applied, rejected) = (engine.applied(), engine.skipped());
+    let result = engine.process_reader(body);
+    let counts = json!({
+        "applied": engine.applied() - applied,
+        "rejected": engine.skipped() - rejected,
+    });
+    match result {
+        Ok(()) => (200, counts),
+        // the rows before the bad one were still applied, so say how many
+        Err(err) => {
+            let status = match err {
+                Error::Csv(_) | Error::Parse { .. } | Error::Policy { .. } => 400,
+                _ => 500,
+            };
+            let mut body = error(&err.to_string());
+            body["applied"] = counts["applied"].clone();
+            body["rejected"] = counts["rejected"].clone();
+            (status, body)
+        }
+    }
+}
+
+fn get_account(engine: &Engine, id: &str, query: &str) -> (u16, Value) {
+    let Ok(client) = id.parse::<u16>() else {
+        return (400, error("client ids are numbers from 0 to 65535"));
+    };
+    let currency = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("currency="))
+        .unwrap_or_default();
+    let currency = match currency.parse::<Currency>() {
+        Ok(currency) => currency,
+        Err(err) => return (400, error(&err)),
+    };
+    let Some(account) = engine.account(client) else {
+        return (404, error("no such account"));
+    };
+    let rows = account.rows(!currency.is_default());
+    match rows
+        .iter()
+        .find(|row| row.currency.unwrap_or_default() == currency)
+    {
+        Some(row) => (200, json!(row)),
+        None => (404, error("no balance in that currency")),
+    }
+}
+
+fn error(message: &str) -> Value {
+    json!({ "error": message })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn posted_transactions_show_up_in_accounts() {
+        let mut engine = Engine::new();
+        let body = "type, client, tx, amount\n\
+                    deposit, 1, 1, 5.0\n\
+                    withdrawal, 1, 2, 50.0\n\
+                    deposit, 2, 3, 1.5\n";
+        let (status, counts) = handle(
+            &mut engine,
+            None,
+            &Method::Post,
+            "/transactions",
+            body.as_bytes(),
+        );
+        assert_eq!(status, 200);
+        assert_eq!(counts, json!({ "applied": 2, "rejected": 1 }));
+
+        let (status, account) = handle(&mut engine, None, &Method::Get, "/accounts/1", io::empty());
+        assert_eq!(status, 200);
+        assert_eq!(account["available"], "5.0000");
+
+        let (status, accounts) = handle(&mut engine, None, &Method::Get, "/accounts/", io::empty());
+        assert_eq!(status, 200);
+        assert_eq!(accounts.as_array().unwrap().len(), 2);
+
+        let (status, _) = handle(&mut engine, None, &Method::Get, "/accounts/9", io::empty());
+        assert_eq!(status, 404);
+        let (status, _) = handle(&mut engine, None, &Method::Delete, "/accounts", io::empty());
+        assert_eq!(status, 405);
+
+        let (status, _) = handle(
+            &mut engine,
+            None,
+            &Method::Post,
+            "/admin/snapshot",
+            io::empty(),
+        );
+        assert_eq!(status, 404);
+        let dir = std::env::temp_dir();
+        let snapshots = Snapshots::new(&dir, Default::default());
+        let (status, body) = handle(
+            &mut engine,
+            Some(&snapshots),
+            &Method::Post,
+            "/admin/snapshot",
+            io::empty(),
+        );
+        assert_eq!(status, 200);
+        let path = body["path"].as_str().unwrap();
+        assert!(std::fs::read_to_string(path).unwrap().contains("1,5.0000"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,254 @@
+use crate::engine::Engine;
+use crate::error::Error;
+use crate::error_report::RejectedRecord;
+use crate::reader::{csv_line, RecordReader};
+use crate::rejection::Rejection;
+use crate::transaction::Transaction;
+use crate::utils::Instant;
+use std::{collections::HashSet, io::Read, sync::mpsc, thread};
+
+/// How many parsed records each shard may have queued before the reader blocks.
+const SHARD_QUEUE_DEPTH: usize = 4096;
+
+/// Which shard owns a client. Every record for a client lands on the same worker so per-client
+/// ordering is preserved.
+pub fn shard_for(client: u16, shards: usize) -> usize {
+    client as usize % shards
+}
+
+impl Engine {
+    /// Like `process_reader` but spreads the work over `shards` worker threads, each with its own
+    /// AccountMap/TransactionMap, then merges the shards back into this engine.
+    ///
+    /// A dispute only sees transactions made by the same client (they live on the same shard), which
+    /// is all it's allowed to touch anyway. Tx ids are global though, so duplicates are caught by the
+    /// reader before a record is handed to a shard.
+    pub fn process_reader_sharded(
+        &mut self,
+        reader: impl Read,
+        shards: usize,
+    ) -> Result<(), Error> {
+        let started = Instant::now();
+        let shards = shards.max(1);
+        let mut custom_reader = RecordReader::new(reader, self.config())?;
+        let mut seen = self.tx_ids();
+        let parts = self.split(shards)?;
+        // workers hand their rejections back here so they end up in the one error report, and so a
+        // strict run hears about them
+        let (reject_sender, rejects) = mpsc::channel::<(RejectedRecord, Rejection)>();
+        let reporting = self.has_error_report() || self.config().strict;
+
+        thread::scope(|scope| {
+            let mut senders = Vec::with_capacity(shards);
+            let mut workers = Vec::with_capacity(shards);
+            for (index, mut shard) in parts.into_iter().enumerate() {
+                let (sender, receiver) =
+                    mpsc::sync_channel::<(u64, u64, Transaction)>(SHARD_QUEUE_DEPTH);
+                let reject_sender = reject_sender.clone();
+                senders.push(sender);
+                workers.push(scope.spawn(move || {
+                    for (row, seq, record) in receiver {
+                        let _span = tracing::debug_span!("record", row, shard = index).entered();
+                        // rejections are counted by the shard and merged below
+                        if let Err(rejection) = shard.apply_at(record, seq) {
+                            if reporting {
+                                let rejected =
+                                    RejectedRecord::from_transaction(row, &record, rejection);
+                                let _ = reject_sender.send((rejected, rejection));
+                            }
+                        }
+                    }
+                    shard
+                }));
+            }
+            drop(reject_sender);
+
+            // a worker only goes away if it panicked, which join() reports afterwards
+            let send = |row, seq, record: Transaction| {
+                let shard = shard_for(record.client, senders.len());
+                senders[shard].send((row, seq, record)).is_ok()
+            };
+            let result = self.dispatch(&mut custom_reader, &mut seen, send, &rejects);
+            // hang up so the workers drain their queues and finish
+            drop(senders);
+
+            for worker in workers {
+                let mut shard = worker
+                    .join()
+                    .map_err(|_| Error::Internal("shard worker panicked".to_string()))?;
+                shard.check_store()?;
+                self.merge(shard);
+            }
+            result?;
+            self.counters_mut().add_time(started.elapsed());
+            self.report_all(rejects.iter())?;
+            self.flush_reports()?;
+            Ok(())
+        })
+    }
+
+    /// Reader side of the sharded run: parse, catch what needs a global view, hand the rest to
+    /// `send` along with its row and seq, which goes to the owning shard and is false once it's gone.
+    /// Bad records and duplicates are caught here, where the raw fields are still around for the
+    /// error report.
+    pub(crate) fn dispatch<R: Read>(
+        &mut self,
+        custom_reader: &mut RecordReader<R>,
+        seen: &mut HashSet<u32>,
+        mut send: impl FnMut(u64, u64, Transaction) -> bool,
+        rejects: &mpsc::Receiver<(RejectedRecord, Rejection)>,
+    ) -> Result<(), Error> {
+        // what apply() would count single-threaded: every record, wherever it ends up
+        let mut seq = self.applied() + self.skipped();
+        let mut last_tx = None;
+        while !self.past_stop(seq, last_tx) {
+            let Some(row) = custom_reader.next_row()? else {
+                break;
+            };
+            if self.filters_out(&row, custom_reader.headers(), custom_reader.raw()) {
+                continue;
+            }
+            seq += 1;
+            let _span = tracing::debug_span!("record", row = row.row).entered();
+            last_tx = row.parsed.as_ref().ok().map(|record| record.tx);
+            let record = match row.parsed {
+                Ok(record) => self.prepare(record),
+                Err(err) => {
+                    self.reject_malformed(
+                        row.row,
+                        custom_reader.headers(),
+                        custom_reader.raw(),
+                        &err,
+                    )?;
+                    continue;
+                }
+            };
+            let rejection = match self.check_record(&record) {
+                Err(rejection) => Some(rejection),
+                Ok(()) if record.r_type.is_stored() && !seen.insert(record.tx) => {
+                    Some(Rejection::DuplicateTx)
+                }
+                Ok(()) => None,
+            };
+            if let Some(rejection) = rejection {
+                // never gets to apply(), so count its type here
+                self.counters_mut().record(record.r_type);
+                self.reject(&record, rejection);
+                let rejected = RejectedRecord::from_raw(
+                    row.row,
+                    custom_reader.headers(),
+                    custom_reader.raw(),
+                    rejection,
+                );
+                self.report(&rejected)?;
+                self.fail_if_strict(row.row, rejection, || csv_line(custom_reader.raw()))?;
+                continue;
+            }
+            if !send(row.row, seq - 1, record) {
+                break;
+            }
+            self.report_all(rejects.try_iter())?;
+        }
+        Ok(())
+    }
+
+    // a strict run stops at the first of these it notices, which with several shards isn't
+    // necessarily the earliest row
+    pub(crate) fn report_all(
+        &mut self,
+        rejected: impl Iterator<Item = (RejectedRecord, Rejection)>,
+    ) -> Result<(), Error> {
+        for (record, rejection) in rejected {
+            self.report(&record)?;
+            self.fail_if_strict(record.row, rejection, || record.line())?;
+        }
+        Ok(())
+    }
+}
+
+/// Convenience entry point for the sharded path, mirroring `process_reader`.
+pub fn process_reader_sharded(reader: impl Read, shards: usize) -> Result<Engine, Error> {
+    let mut engine = Engine::new();
+    engine.process_reader_sharded(reader, shards)?;
+    Ok(engine)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EngineConfig;
+    use crate::transaction::{DisputeStatus, TransactionType};
+    use crate::{admin_signature, Currency};
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn sharded_matches_single_threaded() {
+        let mut input = String::from("type, client, tx, amount, signature\n");
+        let mut tx = 0;
+        for round in 0..50 {
+            for client in 0..20u16 {
+                tx += 1;
+                input.push_str(&format!("deposit, {}, {}, {}.25\n", client, tx, round));
+                tx += 1;
+                input.push_str(&format!("withdrawal, {}, {}, 0.5\n", client, tx));
+                if round % 7 == 0 {
+                    input.push_str(&format!("dispute, {}, {},\n", client, tx - 1));
+                }
+                if round % 14 == 0 {
+                    input.push_str(&format!("chargeback, {}, {},\n", client, tx - 1));
+                }
+            }
+        }
+        // a replayed deposit from another client must still be caught across shards
+        input.push_str("deposit, 3, 1, 100.0\n");
+        // over the cap, then resent with the amount it should have had under the same tx id
+        input.push_str("deposit, 5, 5000, 2000.0\n");
+        input.push_str("deposit, 5, 5000, 5.0\n");
+        // an adjustment that isn't signed, then the signed one
+        let key = "ops";
+        let adjustment = Transaction {
+            r_type: TransactionType::Adjustment,
+            client: 6,
+            tx: 5001,
+            amount: None,
+            given: Some(Decimal::from(3)),
+            status: DisputeStatus::Normal,
+            seq: 0,
+            currency: Currency::DEFAULT,
+            timestamp: None,
+            signature: None,
+            held: None,
+        };
+        input.push_str("adjustment, 6, 5001, 3.0,\n");
+        input.push_str(&format!(
+            "adjustment, 6, 5001, 3.0, {}\n",
+            admin_signature(key, &adjustment)
+        ));
+
+        let config = EngineConfig {
+            max_amount: "1000".parse().ok(),
+            admin_key: Some(key.to_string()),
+            ..EngineConfig::default()
+        };
+        let mut single = Engine::with_config(config.clone());
+        single.process_reader(input.as_bytes()).unwrap();
+        let mut sharded = Engine::with_config(config);
+        sharded.process_reader_sharded(input.as_bytes(), 4).unwrap();
+        assert!(sharded.transaction(5000).is_some());
+        assert_eq!(sharded.accounts()[&6].total(), Decimal::from(3));
+
+        assert_eq!(single.accounts().len(), sharded.accounts().len());
+        for (client, account) in single.accounts() {
+            let other = &sharded.accounts()[&client];
+            assert_eq!(account.available(), other.available());
+            assert_eq!(account.held(), other.held());
+            assert_eq!(account.total(), other.total());
+            assert_eq!(account.locked(), other.locked());
+        }
+        assert_eq!(
+            single.transactions().count(),
+            sharded.transactions().count()
+        );
+        assert_eq!(single.skipped(), sharded.skipped());
+    }
+}
@@ -0,0 +1,116 @@
+use crate::account::{Account, AccountMap};
+use crate::engine::Engine;
+use crate::rejection::Rejection;
+use crate::transaction::Transaction;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// An engine that many threads can use at once, for a service handling requests on a pool of
+/// threads. Clones are handles to the same engine. Every call takes a lock for its duration, so
+/// records are still applied one at a time, in the order the calls got the lock. It's a mutex
+/// rather than a read-write lock because the engine's report writers can't be shared between
+/// threads, only moved. Reads copy what they need out under the lock instead, so a report is built
+/// without holding up the writers.
+#[derive(Debug, Clone, Default)]
+pub struct SharedEngine {
+    engine: Arc<Mutex<Engine>>,
+}
+
+impl SharedEngine {
+    pub fn new(engine: Engine) -> Self {
+        SharedEngine {
+            engine: Arc::new(Mutex::new(engine)),
+        }
+    }
+
+    /// `Engine::apply` under the lock.
+    pub fn apply(&self, record: Transaction) -> Result<(), Rejection> {
+        self.lock().apply(record)
+    }
+
+    /// A copy of every account as it is right now. Later records don't change it.
+    pub fn snapshot(&self) -> AccountMap {
+        self.lock().accounts()
+    }
+
+    pub fn account(&self, client: u16) -> Option<Account> {
+        self.lock().account(client)
+    }
+
+    /// The engine itself, for everything else. Other threads wait until the guard is dropped.
+    pub fn lock(&self) -> MutexGuard<'_, Engine> {
+        // a panic halfway through a record may have left the ledger inconsistent
+        self.engine.lock().expect("engine poisoned")
+    }
+
+    /// The engine back, once this is the last handle to it.
+    pub fn into_inner(self) -> Result<Engine, Self> {
+        Arc::try_unwrap(self.engine)
+            .map(|engine| engine.into_inner().expect("engine poisoned"))
+            .map_err(|engine| SharedEngine { engine })
+    }
+}
+
+impl From<Engine> for SharedEngine {
+    fn from(engine: Engine) -> Self {
+        SharedEngine::new(engine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::currency::Currency;
+    use crate::transaction::{DisputeStatus, TransactionType};
+    use rust_decimal_macros::dec;
+    use std::thread;
+
+    fn deposit(client: u16, tx: u32) -> Transaction {
+        Transaction {
+            r_type: TransactionType::Deposit,
+            client,
+            tx,
+            amount: None,
+            given: Some(dec!(1.5)),
+            currency: Currency::DEFAULT,
+            timestamp: None,
+            status: DisputeStatus::default(),
+            seq: 0,
+            signature: None,
+            held: None,
+        }
+    }
+
+    #[test]
+    fn threads_apply_to_one_ledger() {
+        let engine = SharedEngine::new(Engine::new());
+        let threads = (0..8u16)
+            .map(|t| {
+                let engine = engine.clone();
+                thread::spawn(move || {
+                    for i in 0..100u32 {
+                        engine
+                            .apply(deposit(t % 2, u32::from(t) * 100 + i))
+                            .unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        let before = engine.snapshot();
+        threads
+            .into_iter()
+            .for_each(|thread| thread.join().unwrap());
+
+        let after = engine.snapshot();
+        assert_eq!(after[&0].total(), dec!(600));
+        assert_eq!(after[&1].total(), dec!(600));
+        // a snapshot is a copy, taken at some point between the records
+        assert!(before
+            .values()
+            .all(|account| account.total().to_decimal() <= dec!(600)));
+        assert!(engine.apply(deposit(1, 5)).is_err());
+
+        let engine = engine.into_inner().unwrap();
+        assert_eq!(engine.applied(), 800);
+        assert_eq!(engine.skipped(), 1);
+    }
+}
@@ -0,0 +1,51 @@
+use crate::error::Error;
+use ed25519_dalek::pkcs8::{DecodePrivateKey, DecodePublicKey};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+/// The environment variable `--sign-key` falls back on, holding the key itself rather than a path.
+pub const SIGN_KEY_ENV: &str = "CSV_TX_RESOLVER_SIGN_KEY";
+
+/// A detached ed25519 signature over a report's bytes, the 64 raw bytes that go in its `.sig`
+/// file. `key` is a PKCS#8 PEM private key, what `openssl genpkey -algorithm ed25519` writes.
+pub fn sign_report(key: &str, report: &[u8]) -> Result<[u8; 64], Error> {
+    let key = SigningKey::from_pkcs8_pem(key)
+        .map_err(|err| Error::Config(format!("bad signing key: {}", err)))?;
+    Ok(key.sign(report).to_bytes())
+}
+
+/// Whether `signature` is a signature over `report` made with the private half of `public_key`,
+/// a PEM public key (`openssl pkey -pubout`).
+pub fn verify_report(public_key: &str, report: &[u8], signature: &[u8]) -> Result<bool, Error> {
+    let key = VerifyingKey::from_public_key_pem(public_key)
+        .map_err(|err| Error::Config(format!("bad public key: {}", err)))?;
+    let Ok(signature) = Signature::from_slice(signature) else {
+        return Ok(false);
+    };
+    Ok(key.verify(report, &signature).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_reader;
+    use ed25519_dalek::pkcs8::{spki::der::pem::LineEnding, EncodePrivateKey, EncodePublicKey};
+
+    #[test]
+    fn signed_reports_verify_until_touched() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let private = key.to_pkcs8_pem(LineEnding::LF).unwrap();
+        let public = key
+            .verifying_key()
+            .to_public_key_pem(LineEnding::LF)
+            .unwrap();
+        let engine = process_reader("type,client,tx,amount\ndeposit,1,1,2.5\n".as_bytes()).unwrap();
+        let mut report = Vec::new();
+        engine.write_csv(&mut report).unwrap();
+
+        let signature = sign_report(&private, &report).unwrap();
+        assert!(verify_report(&public, &report, &signature).unwrap());
+        let tampered = String::from_utf8(report).unwrap().replace("2.5", "9.5");
+        assert!(!verify_report(&public, tampered.as_bytes(), &signature).unwrap());
+        assert!(sign_report("not a key", b"").is_err());
+    }
+}
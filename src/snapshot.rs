@@ -0,0 +1,93 @@
+use crate::atomic_file::AtomicFile;
+use crate::engine::Engine;
+use crate::error::Error;
+use crate::output::ReportOptions;
+use chrono::{DateTime, Utc};
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+/// On-demand account reports for runs that don't end (`--follow`, `serve`): every one goes to a
+/// new file in `dir` named for when it was taken, `accounts-20261014T181100.123Z.csv`, so earlier
+/// ones are kept. Setting `flag` (from a SIGHUP handler, say) asks for one.
+#[derive(Debug, Clone)]
+pub struct Snapshots {
+    dir: PathBuf,
+    options: ReportOptions,
+    requested: Arc<AtomicBool>,
+}
+
+impl Snapshots {
+    pub fn new(dir: impl Into<PathBuf>, options: ReportOptions) -> Self {
+        Snapshots {
+            dir: dir.into(),
+            options,
+            requested: Arc::default(),
+        }
+    }
+
+    /// Set it to ask for a snapshot, `write_requested` takes it from there.
+    pub fn flag(&self) -> Arc<AtomicBool> {
+        self.requested.clone()
+    }
+
+    /// Writes `engine`'s report to a new file right away, and says where.
+    pub fn write(&self, engine: &Engine) -> Result<PathBuf, Error> {
+        let taken = DateTime::<Utc>::from(SystemTime::now()).format("%Y%m%dT%H%M%S%.3fZ");
+        let name = format!("accounts-{}.{}", taken, self.options.format);
+        let mut file = AtomicFile::create(self.dir.join(name))?;
+        engine.write_report(&self.options, &mut file)?;
+        let path = file.path().to_path_buf();
+        file.commit()?;
+        tracing::info!(path = %path.display(), "snapshot written");
+        Ok(path)
+    }
+
+    /// `write`, if the flag was set since the last call. A snapshot that can't be written is
+    /// logged rather than stopping the run.
+    pub fn write_requested(&self, engine: &Engine) -> Option<PathBuf> {
+        if !self.requested.swap(false, Ordering::Relaxed) {
+            return None;
+        }
+        self.write(engine)
+            .map_err(|err| tracing::warn!(error = %err, "could not write the snapshot"))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, process};
+
+    #[test]
+    fn snapshots_go_to_new_files_when_asked_for() {
+        let dir = std::env::temp_dir().join(format!("snapshot-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let engine =
+            crate::process_reader("type,client,tx,amount\ndeposit,1,1,2.5\n".as_bytes()).unwrap();
+        let snapshots = Snapshots::new(&dir, ReportOptions::default());
+        assert_eq!(snapshots.write_requested(&engine), None);
+
+        snapshots.flag().store(true, Ordering::Relaxed);
+        let path = snapshots.write_requested(&engine).unwrap();
+        let name = path.file_name().unwrap().to_str().unwrap();
+        assert!(
+            name.starts_with("accounts-") && name.ends_with("Z.csv"),
+            "{}",
+            name
+        );
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "client,available,held,total,locked\n1,2.5000,0.0000,2.5000,false\n"
+        );
+        // once per request
+        assert_eq!(snapshots.write_requested(&engine), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,259 @@
+use crate::account::{Account, AccountMap, Balance};
+use crate::amount::Amount;
+use crate::backend::LedgerBackend;
+use crate::currency::Currency;
+use crate::engine::Engine;
+use crate::error::Error;
+use crate::transaction::{Transaction, TransactionMap};
+use crate::utils::four_places;
+use rusqlite::{params, Connection};
+use rust_decimal::Decimal;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{collections::HashMap, io, path::Path, str::FromStr};
+
+// amounts are TEXT with four places, so nothing is lost to floats. sqlite still does arithmetic on
+// them (`SELECT sum(total) FROM accounts`), converting as it goes
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS accounts (
+    client INTEGER NOT NULL,
+    currency TEXT NOT NULL,
+    available TEXT NOT NULL,
+    held TEXT NOT NULL,
+    total TEXT NOT NULL,
+    locked INTEGER NOT NULL,
+    PRIMARY KEY (client, currency)
+);
+CREATE TABLE IF NOT EXISTS transactions (
+    tx INTEGER PRIMARY KEY,
+    client INTEGER NOT NULL,
+    type TEXT NOT NULL,
+    amount TEXT NOT NULL,
+    status TEXT NOT NULL,
+    seq INTEGER NOT NULL,
+    currency TEXT NOT NULL,
+    timestamp INTEGER,
+    held TEXT
+);
+CREATE INDEX IF NOT EXISTS transactions_by_client ON transactions (client);
+";
+
+/// The ledger in a SQLite database, one row per (client, currency) balance and per stored tx, so it survives restarts
+/// and can be queried with SQL while no run is saving.
+#[derive(Debug)]
+pub struct SqliteBackend {
+    connection: Connection,
+}
+
+impl SqliteBackend {
+    /// Opens (or creates) the database at `path` and its tables.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let connection = Connection::open(path).map_err(sqlite_error)?;
+        connection.execute_batch(SCHEMA).map_err(sqlite_error)?;
+        // databases from before clamped disputes don't have the column yet
+        let has_held: bool = connection
+            .query_row(
+                "SELECT count(*) FROM pragma_table_info('transactions') WHERE name = 'held'",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(sqlite_error)?;
+        if !has_held {
+            connection
+                .execute_batch("ALTER TABLE transactions ADD COLUMN held TEXT")
+                .map_err(sqlite_error)?;
+        }
+        Ok(SqliteBackend { connection })
+    }
+}
+
+impl LedgerBackend for SqliteBackend {
+    fn load(&mut self, engine: &mut Engine) -> Result<(), Error> {
+        let mut balances: HashMap<u16, (Vec<Balance>, bool)> = HashMap::new();
+        let mut statement = self
+            .connection
+            .prepare("SELECT client, currency, available, held, total, locked FROM accounts")
+            .map_err(sqlite_error)?;
+        let mut rows = statement.query([]).map_err(sqlite_error)?;
+        while let Some(row) = rows.next().map_err(sqlite_error)? {
+            let text = |i| row.get::<_, String>(i).map_err(sqlite_error);
+            let (account, locked) = balances
+                .entry(row.get(0).map_err(sqlite_error)?)
+                .or_default();
+            *locked |= row.get::<_, bool>(5).map_err(sqlite_error)?;
+            account.push(Balance {
+                currency: currency(&text(1)?)?,
+                available: amount(&text(2)?)?,
+                held: amount(&text(3)?)?,
+                total: amount(&text(4)?)?,
+            });
+        }
+        let accounts: AccountMap = balances
+            .into_iter()
+            .map(|(client, (balances, locked))| {
+                (client, Account::with_balances(client, balances, locked))
+            })
+            .collect();
+
+        let mut transactions = TransactionMap::new();
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT tx, client, type, amount, status, seq, currency, timestamp, held \
+                 FROM transactions",
+            )
+            .map_err(sqlite_error)?;
+        let mut rows = statement.query([]).map_err(sqlite_error)?;
+        while let Some(row) = rows.next().map_err(sqlite_error)? {
+            let text = |i| row.get::<_, String>(i).map_err(sqlite_error);
+            let tx = Transaction {
+                tx: row.get(0).map_err(sqlite_error)?,
+                client: row.get(1).map_err(sqlite_error)?,
+                r_type: from_text(&text(2)?)?,
+                amount: Some(amount(&text(3)?)?),
+                given: None,
+                status: from_text(&text(4)?)?,
+                // sqlite integers are signed
+                seq: row.get::<_, i64>(5).map_err(sqlite_error)? as u64,
+                currency: currency(&text(6)?)?,
+                timestamp: row.get(7).map_err(sqlite_error)?,
+                signature: None,
+                held: row
+                    .get::<_, Option<String>>(8)
+                    .map_err(sqlite_error)?
+                    .as_deref()
+                    .map(amount)
+                    .transpose()?,
+            };
+            transactions.insert(tx.tx, tx);
+        }
+        engine.restore(accounts, transactions);
+        Ok(())
+    }
+
+    fn save(&mut self, engine: &Engine) -> Result<(), Error> {
+        let transaction = self.connection.transaction().map_err(sqlite_error)?;
+        transaction
+            .execute_batch("DELETE FROM accounts; DELETE FROM transactions;")
+            .map_err(sqlite_error)?;
+        {
+            let mut insert = transaction
+                .prepare("INSERT INTO accounts VALUES (?1, ?2, ?3, ?4, ?5, ?6)")
+                .map_err(sqlite_error)?;
+            for row in engine.store().accounts().flat_map(|a| a.rows(true)) {
+                insert
+                    .execute(params![
+                        row.client,
+                        row.currency.unwrap_or_default().as_str(),
+                        four_places(row.available).to_string(),
+                        four_places(row.held).to_string(),
+                        four_places(row.total).to_string(),
+                        row.locked,
+                    ])
+                    .map_err(sqlite_error)?;
+            }
+            let mut insert = transaction
+                .prepare("INSERT INTO transactions VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)")
+                .map_err(sqlite_error)?;
+            for tx in engine.transactions() {
+                insert
+                    .execute(params![
+                        tx.tx,
+                        tx.client,
+                        to_text(&tx.r_type),
+                        tx.amount().to_string(),
+                        to_text(&tx.status),
+                        tx.seq as i64,
+                        tx.currency.as_str(),
+                        tx.timestamp,
+                        tx.held.map(|held| held.to_string()),
+                    ])
+                    .map_err(sqlite_error)?;
+            }
+        }
+        transaction.commit().map_err(sqlite_error)
+    }
+}
+
+// enums are stored the way serde names them (`deposit`, `charged-back`)
+fn to_text(value: &impl Serialize) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(text)) => text,
+        other => unreachable!("unit variants serialize as strings, got {:?}", other),
+    }
+}
+
+fn from_text<T: DeserializeOwned>(text: &str) -> Result<T, Error> {
+    serde_json::from_value(serde_json::Value::String(text.to_string()))
+        .map_err(|err| Error::Config(format!("bad ledger database: {}", err)))
+}
+
+fn currency(text: &str) -> Result<Currency, Error> {
+    Currency::from_str(text).map_err(|err| Error::Config(format!("bad ledger database: {}", err)))
+}
+
+fn amount(text: &str) -> Result<Amount, Error> {
+    let amount = Decimal::from_str(text)
+        .map_err(|err| Error::Config(format!("bad ledger database: {}", err)))?;
+    Amount::from_decimal(amount).ok_or_else(|| {
+        Error::Config(format!(
+            "bad ledger database: amount {} doesn't fit four places",
+            amount
+        ))
+    })
+}
+
+fn sqlite_error(err: rusqlite::Error) -> Error {
+    Error::Io(io::Error::other(format!("sqlite: {}", err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::DisputeStatus;
+    use rust_decimal_macros::dec;
+    use std::{fs, process};
+
+    #[test]
+    fn ledger_survives_a_restart_in_sqlite() {
+        let path = std::env::temp_dir().join(format!("ledger-test-{}.db", process::id()));
+        let mut backend = SqliteBackend::open(&path).unwrap();
+        let mut day_one = Engine::new();
+        backend.load(&mut day_one).unwrap();
+        day_one
+            .process_reader(
+                "type, client, tx, amount\n\
+                 deposit, 1, 1, 10.0\n\
+                 deposit, 1, 2, 2.5\n\
+                 dispute, 1, 2,\n"
+                    .as_bytes(),
+            )
+            .unwrap();
+        backend.save(&day_one).unwrap();
+        drop(backend);
+
+        let mut backend = SqliteBackend::open(&path).unwrap();
+        let held: String = backend
+            .connection
+            .query_row("SELECT held FROM accounts WHERE client = 1", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(held, "2.5000");
+
+        let mut day_two = Engine::new();
+        backend.load(&mut day_two).unwrap();
+        day_two
+            .process_reader("type, client, tx, amount\nchargeback, 1, 2,\n".as_bytes())
+            .unwrap();
+        backend.save(&day_two).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let account = &day_two.accounts()[&1];
+        assert_eq!(account.total(), dec!(10.0));
+        assert!(account.locked());
+        assert_eq!(
+            day_two.transaction(2).unwrap().status,
+            DisputeStatus::ChargedBack
+        );
+    }
+}
@@ -0,0 +1,473 @@
+use crate::account::{Account, AccountMap, Balance};
+use crate::amount::Amount;
+use crate::atomic_file::AtomicFile;
+use crate::currency::Currency;
+use crate::engine::Engine;
+use crate::error::Error;
+use crate::input::read_path;
+use crate::limits::Daily;
+use crate::transaction::{DisputeStatus, Transaction, TransactionType};
+use crate::utils::four_precision_serializer;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{btree_map::Entry, BTreeMap, HashMap, HashSet},
+    io::{self, BufReader, Read, Write},
+    path::Path,
+};
+
+/// Bumped whenever the layout below changes, so an old state file is refused instead of misread.
+const STATE_VERSION: u32 = 1;
+
+/// Everything needed to pick up where a run left off: the balances, every stored tx (disputes
+/// can reference transactions from earlier files) and what the withdrawal limits counted on each
+/// client's latest day. The run counters are per run and not kept.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct State {
+    version: u32,
+    accounts: Vec<StoredAccount>,
+    transactions: Vec<StoredTransaction>,
+    // left out when there are no withdrawal limits, older state files don't have it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    withdrawals: Vec<StoredWithdrawals>,
+}
+
+// one per (client, currency), the way the report has them. The currency is left out for the default
+// one, so files from before currencies read the same
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredAccount {
+    client: u16,
+    #[serde(default, skip_serializing_if = "Currency::is_default")]
+    currency: Currency,
+    #[serde(serialize_with = "four_precision_serializer")]
+    available: Decimal,
+    #[serde(serialize_with = "four_precision_serializer")]
+    held: Decimal,
+    #[serde(serialize_with = "four_precision_serializer")]
+    total: Decimal,
+    locked: bool,
+}
+
+// Transaction's own serde impl is shaped for the input csv (and skips the status), so stored txs get
+// their own plain representation
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredTransaction {
+    r_type: TransactionType,
+    client: u16,
+    tx: u32,
+    amount: Decimal,
+    status: DisputeStatus,
+    // older state files don't have it, those txs count as made at the start
+    #[serde(default)]
+    seq: u64,
+    #[serde(default, skip_serializing_if = "Currency::is_default")]
+    currency: Currency,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    held: Option<Decimal>,
+}
+
+// what the withdrawal limits counted for a client on its latest day, so the day's limits still hold
+// for a run that picks up on the same day
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredWithdrawals {
+    client: u16,
+    #[serde(default, skip_serializing_if = "Currency::is_default")]
+    currency: Currency,
+    // days since 1970-01-01 (UTC), none if no withdrawal had a timestamp
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    day: Option<i64>,
+    count: u64,
+    #[serde(serialize_with = "four_precision_serializer")]
+    total: Decimal,
+}
+
+impl State {
+    pub(crate) fn of(engine: &Engine) -> State {
+        State {
+            version: STATE_VERSION,
+            accounts: engine
+                .store()
+                .accounts()
+                .flat_map(|account| account.rows(true))
+                .map(|row| StoredAccount {
+                    client: row.client,
+                    currency: row.currency.unwrap_or_default(),
+                    available: row.available,
+                    held: row.held,
+                    total: row.total,
+                    locked: row.locked,
+                })
+                .collect(),
+            transactions: engine
+                .transactions()
+                .map(|tx| StoredTransaction {
+                    r_type: tx.r_type,
+                    client: tx.client,
+                    tx: tx.tx,
+                    amount: tx.amount().into(),
+                    status: tx.status,
+                    seq: tx.seq,
+                    currency: tx.currency,
+                    timestamp: tx.timestamp,
+                    held: tx.held.map(Decimal::from),
+                })
+                .collect(),
+            withdrawals: {
+                let mut withdrawals = engine
+                    .velocity()
+                    .days()
+                    .map(|(client, currency, daily)| StoredWithdrawals {
+                        client,
+                        currency,
+                        day: daily.day,
+                        count: daily.count,
+                        total: daily.total.into(),
+                    })
+                    .collect::<Vec<_>>();
+                // from a map, so put in order to save the same way every time
+                withdrawals.sort_by_key(|stored| (stored.client, stored.currency));
+                withdrawals
+            },
+        }
+    }
+
+    /// Puts the saved ledger into `engine`, replacing whatever it held.
+    pub(crate) fn restore(self, engine: &mut Engine) -> Result<(), Error> {
+        if self.version != STATE_VERSION {
+            return Err(Error::Config(format!(
+                "state version {} is not supported (expected {})",
+                self.version, STATE_VERSION
+            )));
+        }
+        let mut balances: HashMap<u16, (Vec<Balance>, bool)> = HashMap::new();
+        for stored in self.accounts {
+            let (account, locked) = balances.entry(stored.client).or_default();
+            *locked |= stored.locked;
+            account.push(Balance {
+                currency: stored.currency,
+                available: amount(stored.available)?,
+                held: amount(stored.held)?,
+                total: amount(stored.total)?,
+            });
+        }
+        let accounts: AccountMap = balances
+            .into_iter()
+            .map(|(client, (balances, locked))| {
+                (client, Account::with_balances(client, balances, locked))
+            })
+            .collect();
+        let transactions = self
+            .transactions
+            .into_iter()
+            .map(|stored| {
+                let tx = Transaction {
+                    r_type: stored.r_type,
+                    client: stored.client,
+                    tx: stored.tx,
+                    amount: Some(amount(stored.amount)?),
+                    given: None,
+                    status: stored.status,
+                    seq: stored.seq,
+                    currency: stored.currency,
+                    timestamp: stored.timestamp,
+                    // only needed to apply an admin record, not to keep it
+                    signature: None,
+                    held: stored.held.map(amount).transpose()?,
+                };
+                Ok((tx.tx, tx))
+            })
+            .collect::<Result<_, Error>>()?;
+        let days = self
+            .withdrawals
+            .into_iter()
+            .map(|stored| {
+                let daily = Daily {
+                    day: stored.day,
+                    count: stored.count,
+                    total: amount(stored.total)?,
+                };
+                Ok((stored.client, stored.currency, daily))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        engine.restore(accounts, transactions);
+        engine.velocity_mut().restore_days(days);
+        Ok(())
+    }
+}
+
+// everything `save_state` writes fits, one edited by hand might not
+// a state file of this version
+fn read_state(reader: impl Read) -> Result<State, Error> {
+    let state: State = serde_json::from_reader(BufReader::new(reader))
+        .map_err(|err| Error::Config(err.to_string()))?;
+    if state.version != STATE_VERSION {
+        return Err(Error::Config(format!(
+            "state version {} is not supported (expected {})",
+            state.version, STATE_VERSION
+        )));
+    }
+    Ok(state)
+}
+
+/// Combines two ledgers written by `save_state`, say of runs over different partitions of the
+/// clients, into one. Balances of a client in both are summed per currency, and it's locked if
+/// either has it locked. Transactions are put together, and a tx id in both is an error because
+/// it'd mean the partitions overlapped and its amount would count twice.
+pub fn merge_states(a: impl Read, b: impl Read, writer: impl Write) -> Result<(), Error> {
+    let (a, b) = (read_state(a)?, read_state(b)?);
+
+    let mut accounts: BTreeMap<(u16, Currency), StoredAccount> = BTreeMap::new();
+    for stored in a.accounts.into_iter().chain(b.accounts) {
+        match accounts.entry((stored.client, stored.currency)) {
+            Entry::Vacant(entry) => {
+                entry.insert(stored);
+            }
+            Entry::Occupied(mut entry) => {
+                let merged = entry.get_mut();
+                merged.available += stored.available;
+                merged.held += stored.held;
+                merged.total += stored.total;
+                merged.locked |= stored.locked;
+            }
+        }
+    }
+    // locking is per client, not per currency
+    let locked: HashSet<u16> = accounts
+        .values()
+        .filter(|stored| stored.locked)
+        .map(|stored| stored.client)
+        .collect();
+    for stored in accounts.values_mut() {
+        stored.locked = locked.contains(&stored.client);
+    }
+
+    let ids: HashSet<u32> = a.transactions.iter().map(|stored| stored.tx).collect();
+    let mut both: Vec<u32> = b
+        .transactions
+        .iter()
+        .map(|stored| stored.tx)
+        .filter(|tx| ids.contains(tx))
+        .collect();
+    if !both.is_empty() {
+        both.sort_unstable();
+        let count = both.len();
+        both.truncate(10);
+        let listed = both.iter().map(u32::to_string).collect::<Vec<_>>();
+        return Err(Error::Input(format!(
+            "tx ids in both states: {} ({} in all)",
+            listed.join(", "),
+            count
+        )));
+    }
+    let mut transactions = a.transactions;
+    transactions.extend(b.transactions);
+    transactions.sort_by_key(|stored| (stored.seq, stored.tx));
+
+    let mut withdrawals = a.withdrawals;
+    withdrawals.extend(b.withdrawals);
+    withdrawals.sort_by_key(|stored| (stored.client, stored.currency));
+
+    let merged = State {
+        version: STATE_VERSION,
+        accounts: accounts.into_values().collect(),
+        transactions,
+        withdrawals,
+    };
+    serde_json::to_writer(writer, &merged)?;
+    Ok(())
+}
+
+fn amount(amount: Decimal) -> Result<Amount, Error> {
+    Amount::from_decimal(amount)
+        .ok_or_else(|| Error::Config(format!("amount {} doesn't fit four places", amount)))
+}
+
+impl Engine {
+    /// Writes the ledger (accounts and stored transactions) as json.
+    pub fn save_state(&self, writer: impl Write) -> Result<(), Error> {
+        serde_json::to_writer(writer, &State::of(self))?;
+        Ok(())
+    }
+
+    /// A SHA-256 over the ledger `save_state` writes, hex encoded, with its accounts ordered by
+    /// client and currency and its transactions by tx id so it doesn't depend on how they're
+    /// stored. It covers the balances, locks and every stored transaction with its dispute status,
+    /// so an independent second run over the same input with the same config can be checked
+    /// against the first by comparing one line. The run's counters aren't part of it, and a ledger
+    /// saved and loaded again hashes the same. A `--two-pass` run keeps fewer transactions, so its
+    /// hash isn't the same as a normal run's.
+    pub fn state_hash(&self) -> Result<String, Error> {
+        let mut state = State::of(self);
+        state
+            .accounts
+            .sort_by_key(|stored| (stored.client, stored.currency));
+        state.transactions.sort_by_key(|stored| stored.tx);
+        let mut hasher = Sha256::new();
+        serde_json::to_writer(&mut hasher, &state)?;
+        Ok(hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect())
+    }
+
+    /// Replaces the ledger with one written by `save_state`. Config, warnings and the error report
+    /// are left alone.
+    pub fn load_state(&mut self, reader: impl Read) -> Result<(), Error> {
+        let state: State =
+            serde_json::from_reader(reader).map_err(|err| Error::Config(err.to_string()))?;
+        state.restore(self)
+    }
+
+    /// Saves the ledger to `path`. The old file is only replaced once the new one is fully written.
+    /// With a state key it's encrypted.
+    pub fn save_state_file(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let mut state = Vec::new();
+        self.save_state(&mut state)?;
+        let mut file = AtomicFile::create(path)?;
+        file.write_all(&self.seal(state)?)?;
+        file.commit()?;
+        Ok(())
+    }
+
+    /// Loads the ledger from `path`. A missing file is a fresh start, not an error, so the first run
+    /// of a daily job needs no special casing. An encrypted one needs the state key it was saved
+    /// with.
+    pub fn load_state_file(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        match read_path(path) {
+            Ok(saved) => self
+                .unseal(saved)
+                .and_then(|state| self.load_state(state.as_slice()))
+                .map_err(|err| match err {
+                    Error::Config(err) => {
+                        Error::Config(format!("bad state {}: {}", path.display(), err))
+                    }
+                    err => err,
+                }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_reader;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn state_carries_over_between_runs() {
+        let mut day_one = Engine::new();
+        day_one
+            .process_reader(
+                "type, client, tx, amount\n\
+                 deposit, 1, 1, 10.0\n\
+                 deposit, 1, 2, 2.5\n\
+                 dispute, 1, 2,\n"
+                    .as_bytes(),
+            )
+            .unwrap();
+        let mut saved = Vec::new();
+        day_one.save_state(&mut saved).unwrap();
+
+        let mut day_two = Engine::new();
+        day_two.load_state(saved.as_slice()).unwrap();
+        // yesterday's dispute gets charged back and yesterday's tx ids are still taken
+        day_two
+            .process_reader(
+                "type, client, tx, amount\n\
+                 withdrawal, 1, 3, 1.0\n\
+                 chargeback, 1, 2,\n\
+                 deposit, 1, 1, 5.0\n"
+                    .as_bytes(),
+            )
+            .unwrap();
+
+        let account = &day_two.accounts()[&1];
+        assert_eq!(account.available(), dec!(9.0));
+        assert_eq!(account.held(), dec!(0));
+        assert_eq!(account.total(), dec!(9.0));
+        assert!(account.locked());
+        assert_eq!(day_two.skipped(), 1);
+        assert_eq!(
+            day_two.transaction(2).unwrap().status,
+            DisputeStatus::ChargedBack
+        );
+    }
+
+    #[test]
+    fn partitioned_states_merge_into_one() {
+        let state = |input: &str| {
+            let mut saved = Vec::new();
+            process_reader(input.as_bytes())
+                .unwrap()
+                .save_state(&mut saved)
+                .unwrap();
+            saved
+        };
+        let a = state(
+            "type, client, tx, amount, currency\n\
+             deposit, 1, 1, 10.0,\n\
+             deposit, 2, 2, 4.0, EUR\n\
+             dispute, 2, 2,,EUR\n",
+        );
+        let b = state(
+            "type, client, tx, amount, currency\n\
+             deposit, 2, 3, 1.0, EUR\n\
+             deposit, 2, 4, 3.0,\n\
+             dispute, 2, 4,,\n\
+             chargeback, 2, 4,,\n",
+        );
+        let mut merged = Vec::new();
+        merge_states(a.as_slice(), b.as_slice(), &mut merged).unwrap();
+
+        let mut engine = Engine::new();
+        engine.load_state(merged.as_slice()).unwrap();
+        let mut report = Vec::new();
+        engine.write_csv(&mut report).unwrap();
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            "client,currency,available,held,total,locked\n\
+             1,,10.0000,0.0000,10.0000,false\n\
+             2,,0.0000,0.0000,0.0000,true\n\
+             2,EUR,1.0000,4.0000,5.0000,true\n"
+        );
+        // the dispute from the first state is still open in the merged one
+        assert_eq!(
+            engine.transaction(2).unwrap().status,
+            DisputeStatus::Disputed
+        );
+
+        let overlapping = state("type, client, tx, amount\ndeposit, 3, 1, 1.0\n");
+        let err = merge_states(a.as_slice(), overlapping.as_slice(), io::sink()).unwrap_err();
+        assert_eq!(err.to_string(), "tx ids in both states: 1 (1 in all)");
+    }
+
+    #[test]
+    fn same_ledger_same_hash() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 10.0\n\
+                     deposit, 2, 2, 2.5\n\
+                     dispute, 1, 1,\n";
+        let first = process_reader(input.as_bytes()).unwrap();
+        let second = process_reader(input.as_bytes()).unwrap();
+        let hash = first.state_hash().unwrap();
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, second.state_hash().unwrap());
+
+        let mut saved = Vec::new();
+        first.save_state(&mut saved).unwrap();
+        let mut loaded = Engine::new();
+        loaded.load_state(saved.as_slice()).unwrap();
+        assert_eq!(hash, loaded.state_hash().unwrap());
+
+        let resolved = format!("{}resolve, 1, 1,\n", input);
+        let other = process_reader(resolved.as_bytes()).unwrap();
+        assert_ne!(hash, other.state_hash().unwrap());
+    }
+}
@@ -0,0 +1,238 @@
+use crate::currency::Currency;
+use crate::error::Error;
+use crate::transaction::TransactionType;
+use crate::utils::{amount_deserializer, four_precision_serializer};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, collections::HashMap, io::Read, io::Write};
+
+/// What a statement line is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StatementEntry {
+    /// The balances before the client's first entry in the audit log.
+    Opening,
+    /// A deposit or withdrawal.
+    Transaction,
+    /// A dispute, resolve or chargeback.
+    Dispute,
+    /// An unlock or adjustment.
+    Admin,
+    /// The balances after the last entry.
+    Closing,
+}
+
+/// One line of a client's statement, with the balances after it. Opening and closing lines have
+/// no tx, type or amount, and there's one of each per currency.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StatementLine {
+    pub entry: StatementEntry,
+    pub tx: Option<u32>,
+    #[serde(rename = "type")]
+    pub r_type: Option<TransactionType>,
+    pub currency: Currency,
+    #[serde(serialize_with = "optional_amount")]
+    pub amount: Option<Decimal>,
+    #[serde(serialize_with = "four_precision_serializer")]
+    pub available: Decimal,
+    #[serde(serialize_with = "four_precision_serializer")]
+    pub held: Decimal,
+    #[serde(serialize_with = "four_precision_serializer")]
+    pub total: Decimal,
+    pub locked: bool,
+}
+
+fn optional_amount<S: serde::Serializer>(
+    amount: &Option<Decimal>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match amount {
+        Some(amount) => four_precision_serializer(amount, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+// a line of the audit log as `AuditEntry` writes it
+#[derive(Debug, Clone, Deserialize)]
+struct AuditRow {
+    tx: u32,
+    client: u16,
+    #[serde(rename = "type")]
+    r_type: TransactionType,
+    #[serde(deserialize_with = "amount_deserializer")]
+    amount: Option<Decimal>,
+    #[serde(deserialize_with = "amount_deserializer")]
+    available: Option<Decimal>,
+    #[serde(deserialize_with = "amount_deserializer")]
+    held: Option<Decimal>,
+    #[serde(deserialize_with = "amount_deserializer")]
+    total: Option<Decimal>,
+    locked: bool,
+    #[serde(default)]
+    currency: Currency,
+}
+
+impl AuditRow {
+    fn line(&self, entry: StatementEntry) -> StatementLine {
+        StatementLine {
+            entry,
+            tx: Some(self.tx),
+            r_type: Some(self.r_type),
+            currency: self.currency,
+            amount: self.amount,
+            available: self.available.unwrap_or_default(),
+            held: self.held.unwrap_or_default(),
+            total: self.total.unwrap_or_default(),
+            locked: self.locked,
+        }
+    }
+
+    // the balances the entry started from. The audit log doesn't say what a dispute was about, so
+    // that's `disputed`, the type of the tx it referenced
+    fn opening(&self, disputed: TransactionType) -> StatementLine {
+        let x = self.amount.unwrap_or_default();
+        let (available, held, total) = (
+            self.available.unwrap_or_default(),
+            self.held.unwrap_or_default(),
+            self.total.unwrap_or_default(),
+        );
+        let withdrawal = disputed == TransactionType::Withdrawal;
+        let (available, held, total) = match self.r_type {
+            TransactionType::Deposit | TransactionType::Adjustment => {
+                (available - x, held, total - x)
+            }
+            TransactionType::Withdrawal => (available + x, held, total + x),
+            TransactionType::Dispute if withdrawal => (available, held - x, total - x),
+            TransactionType::Dispute => (available + x, held - x, total),
+            TransactionType::Resolve if withdrawal => (available, held + x, total + x),
+            TransactionType::Resolve => (available - x, held + x, total),
+            TransactionType::Chargeback if withdrawal => (available - x, held + x, total),
+            TransactionType::Chargeback => (available, held + x, total + x),
+            TransactionType::Unlock | TransactionType::Unknown => (available, held, total),
+        };
+        let locked = match self.r_type {
+            TransactionType::Unlock => true,
+            TransactionType::Chargeback => false,
+            _ => self.locked,
+        };
+        StatementLine {
+            entry: StatementEntry::Opening,
+            tx: None,
+            r_type: None,
+            currency: self.currency,
+            amount: None,
+            available,
+            held,
+            total,
+            locked,
+        }
+    }
+}
+
+/// `client`'s statement from an `--audit` log: an opening line per currency, every applied record
+/// of theirs in order, and a closing line per currency. The opening balances are worked back from
+/// the client's first entry, so a log that started from a `--state` ledger still opens right. A
+/// dispute whose tx isn't in the log is taken to be about a deposit.
+pub fn statement(audit: impl Read, client: u16) -> Result<Vec<StatementLine>, Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(audit);
+    let mut stored = HashMap::new();
+    let mut opening = BTreeMap::new();
+    let mut closing = BTreeMap::new();
+    let mut lines = Vec::new();
+    let mut locked = false;
+    for row in reader.deserialize() {
+        let row: AuditRow = row?;
+        if row.client != client {
+            continue;
+        }
+        let entry = match row.r_type {
+            TransactionType::Deposit | TransactionType::Withdrawal => {
+                stored.insert(row.tx, row.r_type);
+                StatementEntry::Transaction
+            }
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback => {
+                StatementEntry::Dispute
+            }
+            _ => StatementEntry::Admin,
+        };
+        let disputed = stored
+            .get(&row.tx)
+            .copied()
+            .unwrap_or(TransactionType::Deposit);
+        opening
+            .entry(row.currency)
+            .or_insert_with(|| row.opening(disputed));
+        let line = row.line(entry);
+        locked = line.locked;
+        closing.insert(row.currency, line.clone());
+        lines.push(line);
+    }
+
+    let closing = closing.into_values().map(|last| StatementLine {
+        entry: StatementEntry::Closing,
+        tx: None,
+        r_type: None,
+        amount: None,
+        // the lock is the account's, not the currency's
+        locked,
+        ..last
+    });
+    Ok(opening.into_values().chain(lines).chain(closing).collect())
+}
+
+/// Writes `statement`'s lines as csv.
+pub fn write_statement(lines: &[StatementLine], writer: impl Write) -> Result<(), Error> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for line in lines {
+        writer.serialize(line)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Engine;
+    use std::{fs, process};
+
+    #[test]
+    fn statement_opens_and_closes_around_the_clients_entries() {
+        // day one is already in the ledger, the audit log only has day two
+        let mut engine = Engine::new();
+        engine
+            .process_reader("type, client, tx, amount\ndeposit, 7, 1, 10.0\n".as_bytes())
+            .unwrap();
+        let path = std::env::temp_dir().join(format!("statement-test-{}.csv", process::id()));
+        engine.set_audit_log(fs::File::create(&path).unwrap());
+        engine
+            .process_reader(
+                "type, client, tx, amount\n\
+                 dispute, 7, 1,\n\
+                 deposit, 8, 2, 1.0\n\
+                 deposit, 7, 3, 2.5\n\
+                 resolve, 7, 1,\n\
+                 withdrawal, 7, 4, 3.0\n"
+                    .as_bytes(),
+            )
+            .unwrap();
+
+        let audit = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let lines = statement(audit.as_slice(), 7).unwrap();
+        let mut out = Vec::new();
+        write_statement(&lines, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "entry,tx,type,currency,amount,available,held,total,locked\n\
+             opening,,,,,10.0000,0.0000,10.0000,false\n\
+             dispute,1,dispute,,10.0000,0.0000,10.0000,10.0000,false\n\
+             transaction,3,deposit,,2.5000,2.5000,10.0000,12.5000,false\n\
+             dispute,1,resolve,,10.0000,12.5000,0.0000,12.5000,false\n\
+             transaction,4,withdrawal,,3.0000,9.5000,0.0000,9.5000,false\n\
+             closing,,,,,9.5000,0.0000,9.5000,false\n"
+        );
+    }
+}
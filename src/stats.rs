@@ -0,0 +1,258 @@
+use crate::amount::Amount;
+use crate::currency::Currency;
+use crate::engine::Engine;
+use crate::metrics::Histogram;
+use crate::rejection::Rejection;
+use crate::transaction::TransactionType;
+use crate::utils::{to_four_precision, PRECISION};
+use rust_decimal::Decimal;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    time::Duration,
+};
+
+/// Running counts the engine keeps as it goes. Only the processing that happened in this process is
+/// counted, a run resumed from a checkpoint starts these over.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Counters {
+    by_type: HashMap<TransactionType, u64>,
+    by_reason: HashMap<Rejection, u64>,
+    overdrawn_disputes: u64,
+    clamped_disputes: u64,
+    suspicious_amounts: u64,
+    filtered_out: u64,
+    elapsed: Duration,
+    batches: Histogram,
+}
+
+impl Counters {
+    pub fn record(&mut self, r_type: TransactionType) {
+        *self.by_type.entry(r_type).or_default() += 1;
+    }
+
+    pub fn reject(&mut self, rejection: Rejection) {
+        *self.by_reason.entry(rejection).or_default() += 1;
+    }
+
+    /// A dispute that went ahead although available didn't cover it, clamped or not.
+    pub fn overdrawn_dispute(&mut self, clamped: bool) {
+        match clamped {
+            true => self.clamped_disputes += 1,
+            false => self.overdrawn_disputes += 1,
+        }
+    }
+
+    /// A record applied although its amount is over `suspicious_amount`.
+    pub fn suspicious_amount(&mut self) {
+        self.suspicious_amounts += 1;
+    }
+
+    /// A record passed over for its client (see `EngineConfig::takes_client`).
+    pub fn filter_out(&mut self) {
+        self.filtered_out += 1;
+    }
+
+    /// Time spent on one batch of input: a file, a followed chunk, a request or a Kafka batch.
+    pub fn add_time(&mut self, elapsed: Duration) {
+        self.elapsed += elapsed;
+        self.batches.observe(elapsed);
+    }
+
+    pub fn batches(&self) -> &Histogram {
+        &self.batches
+    }
+
+    pub fn merge(&mut self, other: Counters) {
+        for (r_type, count) in other.by_type {
+            *self.by_type.entry(r_type).or_default() += count;
+        }
+        for (rejection, count) in other.by_reason {
+            *self.by_reason.entry(rejection).or_default() += count;
+        }
+        self.overdrawn_disputes += other.overdrawn_disputes;
+        self.clamped_disputes += other.clamped_disputes;
+        self.suspicious_amounts += other.suspicious_amounts;
+        self.filtered_out += other.filtered_out;
+        // shards run side by side, so their time doesn't add up
+    }
+}
+
+/// Summary of a run, for reconciling batch jobs. `Display` gives the human readable version.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub records: u64,
+    pub applied: u64,
+    pub rejected: u64,
+    /// Records per transaction type, in the order the types are declared. Malformed rows have no
+    /// type so they only show up in `by_reason`.
+    pub by_type: Vec<(TransactionType, u64)>,
+    /// Rejections per reason, most common first.
+    pub by_reason: Vec<(Rejection, u64)>,
+    /// Disputes that took available below zero (see `NegativeAvailable::Allow`).
+    pub overdrawn_disputes: u64,
+    /// Disputes that held less than their amount so available stopped at zero (see
+    /// `NegativeAvailable::Clamp`).
+    pub clamped_disputes: u64,
+    /// Records applied with an amount of at least `EngineConfig::suspicious_amount`.
+    pub suspicious_amounts: u64,
+    /// Records passed over because their client isn't included or is excluded. They aren't in
+    /// `records`.
+    pub filtered_out: u64,
+    pub accounts: usize,
+    pub locked_accounts: usize,
+    /// Held in the default currency.
+    pub total_held: Decimal,
+    /// Held in every other currency, by currency.
+    pub held_by_currency: Vec<(Currency, Decimal)>,
+    /// Wall-clock time spent processing input.
+    pub elapsed: Duration,
+}
+
+impl Stats {
+    /// Records per second, 0 if nothing was timed.
+    pub fn throughput(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.records as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "records read: {}", self.records)?;
+        writeln!(f, "records applied: {}", self.applied)?;
+        writeln!(f, "records rejected: {}", self.rejected)?;
+        for (r_type, count) in &self.by_type {
+            writeln!(f, "  {}: {}", r_type, count)?;
+        }
+        if !self.by_reason.is_empty() {
+            writeln!(f, "rejected by reason:")?;
+            for (rejection, count) in &self.by_reason {
+                writeln!(f, "  {}: {}", rejection, count)?;
+            }
+        }
+        if self.overdrawn_disputes > 0 {
+            writeln!(
+                f,
+                "disputes that overdrew available: {}",
+                self.overdrawn_disputes
+            )?;
+        }
+        if self.clamped_disputes > 0 {
+            writeln!(
+                f,
+                "disputes clamped to available: {}",
+                self.clamped_disputes
+            )?;
+        }
+        if self.suspicious_amounts > 0 {
+            writeln!(f, "suspicious amounts: {}", self.suspicious_amounts)?;
+        }
+        if self.filtered_out > 0 {
+            writeln!(f, "records of other clients: {}", self.filtered_out)?;
+        }
+        writeln!(f, "accounts: {}", self.accounts)?;
+        writeln!(f, "locked accounts: {}", self.locked_accounts)?;
+        let four_places = |amount| {
+            let mut held = to_four_precision(amount);
+            held.rescale(PRECISION);
+            held
+        };
+        writeln!(f, "total held: {}", four_places(self.total_held))?;
+        for (currency, held) in &self.held_by_currency {
+            writeln!(f, "total held {}: {}", currency, four_places(*held))?;
+        }
+        writeln!(
+            f,
+            "elapsed: {:.3}s ({:.0} records/s)",
+            self.elapsed.as_secs_f64(),
+            self.throughput()
+        )
+    }
+}
+
+impl Engine {
+    pub fn stats(&self) -> Stats {
+        let counters = self.counters();
+        let mut by_type: Vec<_> = counters.by_type.iter().map(|(t, n)| (*t, *n)).collect();
+        by_type.sort();
+        let mut by_reason: Vec<_> = counters.by_reason.iter().map(|(r, n)| (*r, *n)).collect();
+        // most common first, ties in declaration order so the output is stable
+        by_reason.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        let accounts = self.accounts();
+        let accounts = accounts.values();
+        let mut held: BTreeMap<Currency, Amount> = BTreeMap::new();
+        for balance in accounts.clone().flat_map(|account| account.balances()) {
+            // saturating, a ledger restored from somewhere else could hold anything
+            let sum = held.entry(balance.currency).or_default();
+            *sum = sum.saturating_add(balance.held);
+        }
+        let total_held = held.remove(&Currency::DEFAULT).unwrap_or_default().into();
+        Stats {
+            records: self.applied() + self.skipped(),
+            applied: self.applied(),
+            rejected: self.skipped(),
+            by_type,
+            by_reason,
+            overdrawn_disputes: counters.overdrawn_disputes,
+            clamped_disputes: counters.clamped_disputes,
+            suspicious_amounts: counters.suspicious_amounts,
+            filtered_out: counters.filtered_out,
+            accounts: accounts.len(),
+            locked_accounts: accounts.clone().filter(|account| account.locked()).count(),
+            total_held,
+            held_by_currency: held
+                .into_iter()
+                .map(|(currency, held)| (currency, held.into()))
+                .collect(),
+            elapsed: counters.elapsed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{process_reader, Rejection, TransactionType};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn stats_count_types_and_reasons() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 5.0\n\
+                     deposit, 2, 2, 3.0\n\
+                     deposit, 2, 2, 3.0\n\
+                     withdrawal, 1, 3, 9.0\n\
+                     dispute, 2, 2,\n\
+                     refund, 1, 4, 1.0\n\
+                     deposit, 1, 5, lots\n";
+        let stats = process_reader(input.as_bytes()).unwrap().stats();
+
+        assert_eq!(stats.records, 7);
+        assert_eq!(stats.applied, 3);
+        assert_eq!(stats.rejected, 4);
+        assert_eq!(
+            stats.by_type,
+            [
+                (TransactionType::Deposit, 3),
+                (TransactionType::Withdrawal, 1),
+                (TransactionType::Dispute, 1),
+                (TransactionType::Unknown, 1),
+            ]
+        );
+        assert_eq!(
+            stats.by_reason,
+            [
+                (Rejection::Malformed, 1),
+                (Rejection::UnknownType, 1),
+                (Rejection::DuplicateTx, 1),
+                (Rejection::InsufficientFunds, 1),
+            ]
+        );
+        assert_eq!(stats.accounts, 2);
+        assert_eq!(stats.total_held, dec!(3.0));
+    }
+}
@@ -0,0 +1,584 @@
+use crate::account::{Account, AccountMap};
+use crate::packed::PackedMap;
+use crate::transaction::Transaction;
+use std::{fmt, io};
+
+#[cfg(feature = "spill")]
+use crate::amount::Amount;
+#[cfg(feature = "spill")]
+use crate::currency::Currency;
+#[cfg(feature = "spill")]
+use crate::transaction::{DisputeStatus, TransactionType};
+#[cfg(feature = "spill")]
+#[cfg(feature = "spill")]
+use std::{
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+/// Where the engine keeps the ledger while it runs: an account per client and the deposits and
+/// withdrawals later disputes can reference. Every record is applied with a get, a change and a
+/// put, so a store only has to hand out and take back copies. `MemoryStore` is the default, other
+/// stores (RocksDB, sled, Postgres...) go in with `Engine::with_store`.
+pub trait LedgerStore: fmt::Debug + Send {
+    fn get_account(&self, client: u16) -> Option<Account>;
+
+    fn put_account(&mut self, account: Account);
+
+    fn get_transaction(&self, tx: u32) -> Option<Transaction>;
+
+    fn put_transaction(&mut self, transaction: Transaction);
+
+    /// Every account, in no particular order.
+    fn accounts(&self) -> Box<dyn Iterator<Item = Account> + '_>;
+
+    /// Every stored transaction, in no particular order.
+    fn transactions(&self) -> Box<dyn Iterator<Item = Transaction> + '_>;
+
+    fn contains_transaction(&self, tx: u32) -> bool {
+        self.get_transaction(tx).is_some()
+    }
+
+    /// Drops everything, for swapping in a saved ledger.
+    fn clear(&mut self);
+
+    /// An empty store for one shard of a sharded run, whose state is put back in here afterwards.
+    /// In memory unless a store says otherwise.
+    fn empty_like(&self) -> io::Result<Box<dyn LedgerStore>> {
+        Ok(Box::new(MemoryStore::default()))
+    }
+
+    /// Gets and puts can't fail as far as the engine is concerned, so a store keeps its first error
+    /// until the engine asks for it here (after every record).
+    fn take_error(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Default for Box<dyn LedgerStore> {
+    fn default() -> Self {
+        Box::new(MemoryStore::default())
+    }
+}
+
+/// The default `LedgerStore`: accounts in a HashMap and transactions in a `TransactionStore`, so
+/// with a spill limit they can still go to disk.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    accounts: AccountMap,
+    transactions: TransactionStore,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_transactions(transactions: TransactionStore) -> Self {
+        MemoryStore {
+            accounts: AccountMap::new(),
+            transactions,
+        }
+    }
+}
+
+impl LedgerStore for MemoryStore {
+    fn get_account(&self, client: u16) -> Option<Account> {
+        self.accounts.get(&client).cloned()
+    }
+
+    fn put_account(&mut self, account: Account) {
+        self.accounts.insert(account.client(), account);
+    }
+
+    fn get_transaction(&self, tx: u32) -> Option<Transaction> {
+        self.transactions.get(tx)
+    }
+
+    fn put_transaction(&mut self, transaction: Transaction) {
+        self.transactions.insert(transaction);
+    }
+
+    fn accounts(&self) -> Box<dyn Iterator<Item = Account> + '_> {
+        Box::new(self.accounts.values().cloned())
+    }
+
+    fn transactions(&self) -> Box<dyn Iterator<Item = Transaction> + '_> {
+        self.transactions.iter()
+    }
+
+    fn contains_transaction(&self, tx: u32) -> bool {
+        self.transactions.contains(tx)
+    }
+
+    fn clear(&mut self) {
+        self.accounts.clear();
+        self.transactions.clear();
+    }
+
+    fn empty_like(&self) -> io::Result<Box<dyn LedgerStore>> {
+        let transactions = self.transactions.empty_like()?;
+        Ok(Box::new(MemoryStore::with_transactions(transactions)))
+    }
+
+    fn take_error(&mut self) -> io::Result<()> {
+        self.transactions.take_error()
+    }
+}
+
+/// Where the `MemoryStore` keeps deposits/withdrawals so later disputes can find them.
+///
+/// By default everything stays in memory. With a spill limit (the `spill` feature) at most that many
+/// transactions are kept in memory: once it's reached they all get moved to a temporary on-disk store
+/// and are looked up there on a miss. A tx that gets disputed is pulled back into memory, so the
+/// dispute/resolve/chargeback that usually follow don't go to disk again.
+#[derive(Debug, Default)]
+pub struct TransactionStore {
+    memory: PackedMap,
+    #[cfg(feature = "spill")]
+    spill: Option<Spill>,
+}
+
+impl TransactionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A store that keeps at most `limit` transactions in memory and spills the rest to a temporary
+    /// database under `dir` (the system temp dir if `None`), deleted again when the store is dropped.
+    #[cfg(feature = "spill")]
+    pub fn with_spill(limit: usize, dir: Option<&Path>) -> io::Result<Self> {
+        Ok(TransactionStore {
+            memory: PackedMap::default(),
+            spill: Some(Spill::open(limit.max(1), dir)?),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.memory.len() + self.on_disk()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, tx: u32) -> bool {
+        self.memory.contains(tx) || self.get_spilled(tx).is_some()
+    }
+
+    pub fn get(&self, tx: u32) -> Option<Transaction> {
+        self.memory.get(tx).or_else(|| self.get_spilled(tx))
+    }
+
+    /// Every stored transaction, in no particular order.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = Transaction> + '_> {
+        let memory = self.memory.values();
+        #[cfg(feature = "spill")]
+        if let Some(spill) = &self.spill {
+            return Box::new(memory.chain(spill.iter()));
+        }
+        Box::new(memory)
+    }
+
+    pub(crate) fn insert(&mut self, transaction: Transaction) {
+        self.make_room();
+        #[cfg(feature = "spill")]
+        if let Some(spill) = &mut self.spill {
+            // memory and disk never hold the same tx, so a replaced one has to leave the disk
+            spill.remove(transaction.tx);
+        }
+        self.memory.insert(transaction);
+    }
+
+    /// An empty store with the same spill settings (and its own temporary database).
+    pub(crate) fn empty_like(&self) -> io::Result<Self> {
+        #[cfg(feature = "spill")]
+        if let Some(spill) = &self.spill {
+            return Self::with_spill(spill.limit, Some(&spill.dir));
+        }
+        Ok(Self::new())
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.memory.clear();
+        #[cfg(feature = "spill")]
+        if let Some(spill) = &mut self.spill {
+            spill.clear();
+        }
+    }
+
+    /// Lookups can't fail as far as the engine is concerned, so a disk error is kept here until the
+    /// engine checks for it (after every record).
+    pub(crate) fn take_error(&mut self) -> io::Result<()> {
+        #[cfg(feature = "spill")]
+        if let Some(spill) = &mut self.spill {
+            if let Some(err) = spill.error.get_mut().expect("spill error poisoned").take() {
+                return Err(err.into());
+            }
+        }
+        Ok(())
+    }
+
+    fn on_disk(&self) -> usize {
+        #[cfg(feature = "spill")]
+        if let Some(spill) = &self.spill {
+            return spill.on_disk;
+        }
+        0
+    }
+
+    fn get_spilled(&self, _tx: u32) -> Option<Transaction> {
+        #[cfg(feature = "spill")]
+        if let Some(spill) = &self.spill {
+            return spill.get(_tx);
+        }
+        None
+    }
+
+    fn make_room(&mut self) {
+        #[cfg(feature = "spill")]
+        if let Some(spill) = &mut self.spill {
+            if self.memory.len() >= spill.limit {
+                spill.write_all(self.memory.drain());
+            }
+        }
+    }
+}
+
+impl IntoIterator for TransactionStore {
+    type Item = Transaction;
+    type IntoIter = Box<dyn Iterator<Item = Transaction>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let memory = self.memory.into_values();
+        #[cfg(feature = "spill")]
+        if let Some(spill) = self.spill {
+            // collected so the iterator doesn't hold on to the database
+            let spilled: Vec<Transaction> = spill.iter().collect();
+            return Box::new(memory.chain(spilled));
+        }
+        Box::new(memory)
+    }
+}
+
+#[cfg(feature = "spill")]
+static NEXT_SPILL: AtomicUsize = AtomicUsize::new(0);
+
+/// Cap on sled's own page cache, so the on-disk side doesn't quietly eat the memory we saved.
+#[cfg(feature = "spill")]
+const SPILL_CACHE_BYTES: u64 = 64 * 1024 * 1024;
+
+#[cfg(feature = "spill")]
+struct Spill {
+    db: sled::Db,
+    dir: PathBuf,
+    limit: usize,
+    on_disk: usize,
+    error: Mutex<Option<sled::Error>>,
+}
+
+#[cfg(feature = "spill")]
+impl Spill {
+    fn open(limit: usize, dir: Option<&Path>) -> io::Result<Self> {
+        let dir = dir.map_or_else(std::env::temp_dir, Path::to_path_buf);
+        let name = format!(
+            "csv_tx_resolver-spill-{}-{}",
+            process::id(),
+            NEXT_SPILL.fetch_add(1, Ordering::Relaxed)
+        );
+        let db = sled::Config::new()
+            .path(dir.join(name))
+            .temporary(true)
+            .cache_capacity(SPILL_CACHE_BYTES)
+            .open()?;
+        Ok(Spill {
+            db,
+            dir,
+            limit,
+            on_disk: 0,
+            error: Mutex::new(None),
+        })
+    }
+
+    fn get(&self, tx: u32) -> Option<Transaction> {
+        match self.db.get(tx.to_be_bytes()) {
+            Ok(value) => value.and_then(|value| decode(tx, &value)),
+            Err(err) => self.fail(err),
+        }
+    }
+
+    fn remove(&mut self, tx: u32) -> Option<Transaction> {
+        match self.db.remove(tx.to_be_bytes()) {
+            Ok(value) => {
+                let transaction = value.and_then(|value| decode(tx, &value))?;
+                self.on_disk -= 1;
+                Some(transaction)
+            }
+            Err(err) => self.fail(err),
+        }
+    }
+
+    fn write_all(&mut self, transactions: impl Iterator<Item = Transaction>) {
+        let mut batch = sled::Batch::default();
+        let mut written = 0;
+        for transaction in transactions {
+            batch.insert(&transaction.tx.to_be_bytes(), &encode(&transaction)[..]);
+            written += 1;
+        }
+        match self.db.apply_batch(batch) {
+            Ok(()) => self.on_disk += written,
+            Err(err) => {
+                self.fail::<()>(err);
+            }
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = Transaction> + '_ {
+        self.db.iter().filter_map(|entry| match entry {
+            Ok((key, value)) => {
+                let tx = u32::from_be_bytes(key.as_ref().try_into().ok()?);
+                decode(tx, &value)
+            }
+            Err(err) => self.fail(err),
+        })
+    }
+
+    fn clear(&mut self) {
+        match self.db.clear() {
+            Ok(()) => self.on_disk = 0,
+            Err(err) => {
+                self.fail::<()>(err);
+            }
+        }
+    }
+
+    // keeps the first error for take_error, the lookup itself just comes back empty
+    fn fail<T>(&self, err: sled::Error) -> Option<T> {
+        let mut error = self.error.lock().expect("spill error poisoned");
+        error.get_or_insert(err);
+        None
+    }
+}
+
+#[cfg(feature = "spill")]
+impl fmt::Debug for Spill {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Spill")
+            .field("dir", &self.dir)
+            .field("limit", &self.limit)
+            .field("on_disk", &self.on_disk)
+            .finish_non_exhaustive()
+    }
+}
+
+// on disk a tx is [type, status, client (2 bytes), amount (8 bytes), seq (8 bytes), currency (8
+// bytes), timestamp (8 bytes, i64::MIN for none), whether it's held (1 byte), held (8 bytes)],
+// keyed by its big endian id
+#[cfg(feature = "spill")]
+const ENCODED_LEN: usize = 45;
+
+#[cfg(feature = "spill")]
+fn encode(transaction: &Transaction) -> [u8; ENCODED_LEN] {
+    let mut bytes = [0; ENCODED_LEN];
+    bytes[0] = match transaction.r_type {
+        TransactionType::Deposit => 0,
+        TransactionType::Withdrawal => 1,
+        TransactionType::Dispute => 2,
+        TransactionType::Resolve => 3,
+        TransactionType::Chargeback => 4,
+        TransactionType::Unknown => 5,
+        TransactionType::Unlock => 6,
+        TransactionType::Adjustment => 7,
+    };
+    bytes[1] = match transaction.status {
+        DisputeStatus::Normal => 0,
+        DisputeStatus::Disputed => 1,
+        DisputeStatus::Resolved => 2,
+        DisputeStatus::ChargedBack => 3,
+        DisputeStatus::NotApplied => 4,
+    };
+    bytes[2..4].copy_from_slice(&transaction.client.to_le_bytes());
+    let amount = transaction.amount.unwrap_or_default();
+    bytes[4..12].copy_from_slice(&amount.units().to_le_bytes());
+    bytes[12..20].copy_from_slice(&transaction.seq.to_le_bytes());
+    bytes[20..28].copy_from_slice(&transaction.currency.to_bytes());
+    let timestamp = transaction.timestamp.unwrap_or(i64::MIN);
+    bytes[28..36].copy_from_slice(&timestamp.to_le_bytes());
+    if let Some(held) = transaction.held {
+        bytes[36] = 1;
+        bytes[37..].copy_from_slice(&held.units().to_le_bytes());
+    }
+    bytes
+}
+
+#[cfg(feature = "spill")]
+fn decode(tx: u32, bytes: &[u8]) -> Option<Transaction> {
+    let bytes: &[u8; ENCODED_LEN] = bytes.try_into().ok()?;
+    let r_type = match bytes[0] {
+        0 => TransactionType::Deposit,
+        1 => TransactionType::Withdrawal,
+        2 => TransactionType::Dispute,
+        3 => TransactionType::Resolve,
+        4 => TransactionType::Chargeback,
+        6 => TransactionType::Unlock,
+        7 => TransactionType::Adjustment,
+        _ => TransactionType::Unknown,
+    };
+    let status = match bytes[1] {
+        0 => DisputeStatus::Normal,
+        1 => DisputeStatus::Disputed,
+        2 => DisputeStatus::Resolved,
+        3 => DisputeStatus::ChargedBack,
+        _ => DisputeStatus::NotApplied,
+    };
+    Some(Transaction {
+        r_type,
+        client: u16::from_le_bytes([bytes[2], bytes[3]]),
+        tx,
+        amount: Some(Amount::from_units(i64::from_le_bytes(
+            bytes[4..12].try_into().ok()?,
+        ))),
+        given: None,
+        status,
+        seq: u64::from_le_bytes(bytes[12..20].try_into().ok()?),
+        currency: Currency::from_bytes(bytes[20..28].try_into().ok()?),
+        timestamp: Some(i64::from_le_bytes(bytes[28..36].try_into().ok()?))
+            .filter(|&timestamp| timestamp != i64::MIN),
+        signature: None,
+        held: match bytes[36] {
+            0 => None,
+            _ => Some(Amount::from_units(i64::from_le_bytes(
+                bytes[37..].try_into().ok()?,
+            ))),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::DisputeStatus;
+    use rust_decimal_macros::dec;
+
+    #[cfg(feature = "spill")]
+    fn deposit(tx: u32) -> Transaction {
+        Transaction {
+            r_type: TransactionType::Deposit,
+            client: 7,
+            tx,
+            amount: Some(Amount::from_units(12_345)),
+            given: None,
+            status: DisputeStatus::Normal,
+            seq: 0,
+            currency: Currency::DEFAULT,
+            timestamp: None,
+            signature: None,
+            held: None,
+        }
+    }
+
+    #[cfg(feature = "spill")]
+    #[test]
+    fn spilled_transactions_are_still_found() {
+        let mut store = TransactionStore::with_spill(2, None).unwrap();
+        store.insert(Transaction {
+            held: Some(Amount::from_units(5_000)),
+            ..deposit(1)
+        });
+        for tx in 2..=5 {
+            store.insert(deposit(tx));
+        }
+        assert_eq!(store.len(), 5);
+        assert!(store.memory.len() <= 2);
+        assert_eq!(store.get(1).unwrap().amount(), dec!(1.2345));
+        assert_eq!(store.get(1).unwrap().held, Some(Amount::from_units(5_000)));
+        assert_eq!(store.get(2).unwrap().held, None);
+        assert!(!store.contains(6));
+
+        // written back into memory once it's changed, and not counted twice
+        let mut disputed = store.get(1).unwrap();
+        disputed.status = DisputeStatus::Disputed;
+        store.insert(disputed);
+        assert_eq!(store.len(), 5);
+        assert_eq!(store.get(1).unwrap().status, DisputeStatus::Disputed);
+
+        let mut all: Vec<u32> = store
+            .into_iter()
+            .map(|transaction| transaction.tx)
+            .collect();
+        all.sort();
+        assert_eq!(all, [1, 2, 3, 4, 5]);
+    }
+
+    // a downstream store only needs the trait, here one that counts what the engine writes
+    #[derive(Debug, Default)]
+    struct CountingStore {
+        inner: MemoryStore,
+        account_puts: usize,
+        transaction_puts: usize,
+    }
+
+    impl LedgerStore for CountingStore {
+        fn get_account(&self, client: u16) -> Option<Account> {
+            self.inner.get_account(client)
+        }
+
+        fn put_account(&mut self, account: Account) {
+            self.account_puts += 1;
+            self.inner.put_account(account)
+        }
+
+        fn get_transaction(&self, tx: u32) -> Option<Transaction> {
+            self.inner.get_transaction(tx)
+        }
+
+        fn put_transaction(&mut self, transaction: Transaction) {
+            self.transaction_puts += 1;
+            self.inner.put_transaction(transaction)
+        }
+
+        fn accounts(&self) -> Box<dyn Iterator<Item = Account> + '_> {
+            self.inner.accounts()
+        }
+
+        fn transactions(&self) -> Box<dyn Iterator<Item = Transaction> + '_> {
+            self.inner.transactions()
+        }
+
+        fn clear(&mut self) {
+            self.inner.clear()
+        }
+    }
+
+    #[test]
+    fn the_engine_runs_on_any_ledger_store() {
+        let mut engine = crate::Engine::with_store(Default::default(), CountingStore::default());
+        engine
+            .process_reader(
+                "type, client, tx, amount\n\
+                 deposit, 1, 1, 5.0\n\
+                 dispute, 1, 1,\n\
+                 withdrawal, 1, 2, 9.0\n"
+                    .as_bytes(),
+            )
+            .unwrap();
+
+        assert_eq!(engine.account(1).unwrap().held(), dec!(5.0));
+        assert_eq!(
+            engine.transaction(1).unwrap().status,
+            DisputeStatus::Disputed
+        );
+        // the rejected withdrawal is stored too, so its tx id stays taken
+        assert_eq!(engine.transactions().count(), 2);
+        let store = format!("{:?}", engine.store());
+        assert!(
+            store.contains("account_puts: 3, transaction_puts: 3"),
+            "{}",
+            store
+        );
+    }
+}
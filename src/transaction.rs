@@ -0,0 +1,259 @@
+use crate::admin::{signature_deserializer, Signature};
+use crate::amount::Amount;
+use crate::currency::Currency;
+use crate::rejection::Rejection;
+use crate::store::LedgerStore;
+use crate::utils::{amount_deserializer, timestamp_deserializer};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionType {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    Resolve,
+    Chargeback,
+    /// Admin record: unfreezes a locked account.
+    Unlock,
+    /// Admin record: a manual correction, `amount` (positive or negative) goes straight onto
+    /// available and total.
+    Adjustment,
+    // anything we don't recognise lands here so the engine can count and skip it
+    #[serde(other)]
+    Unknown,
+}
+
+impl TransactionType {
+    /// Types that get stored under their tx id, so the id can't be reused.
+    pub fn is_stored(self) -> bool {
+        matches!(
+            self,
+            TransactionType::Deposit | TransactionType::Withdrawal | TransactionType::Adjustment
+        )
+    }
+
+    /// The type a `type` field names, exactly as serde reads it: lowercase, anything else is
+    /// `Unknown`.
+    pub(crate) fn from_tag(tag: &str) -> TransactionType {
+        match tag {
+            "deposit" => TransactionType::Deposit,
+            "withdrawal" => TransactionType::Withdrawal,
+            "dispute" => TransactionType::Dispute,
+            "resolve" => TransactionType::Resolve,
+            "chargeback" => TransactionType::Chargeback,
+            "unlock" => TransactionType::Unlock,
+            "adjustment" => TransactionType::Adjustment,
+            _ => TransactionType::Unknown,
+        }
+    }
+
+    /// Types only an admin may send, they need a valid signature.
+    pub fn is_admin(self) -> bool {
+        matches!(self, TransactionType::Unlock | TransactionType::Adjustment)
+    }
+}
+
+impl fmt::Display for TransactionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TransactionType::Deposit => "deposit",
+            TransactionType::Withdrawal => "withdrawal",
+            TransactionType::Dispute => "dispute",
+            TransactionType::Resolve => "resolve",
+            TransactionType::Chargeback => "chargeback",
+            TransactionType::Unlock => "unlock",
+            TransactionType::Adjustment => "adjustment",
+            TransactionType::Unknown => "unknown",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Where a stored deposit/withdrawal is in its dispute lifecycle.
+/// Normal -> Disputed -> Resolved | ChargedBack, and a tx only gets disputed once. NotApplied is for
+/// deposits/withdrawals that were themselves rejected, those can never be disputed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DisputeStatus {
+    #[default]
+    Normal,
+    Disputed,
+    Resolved,
+    ChargedBack,
+    NotApplied,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Transaction {
+    // I could either escape type like r#type or rename it bc it's a reserved word
+    #[serde(rename = "type")]
+    pub r_type: TransactionType,
+    pub client: u16,
+    pub tx: u32,
+    /// Only deposits, withdrawals and adjustments need one. Disputes, resolves and chargebacks often
+    /// leave the column empty or drop it altogether, `None` either way. `Engine::prepare` sets it
+    /// from `given`.
+    #[serde(skip)]
+    pub amount: Option<Amount>,
+    /// The `amount` column as it's written, which `Engine::prepare` rounds to four places into
+    /// `amount`. Only kept for the error report after that, stored transactions don't have it.
+    #[serde(rename = "amount", default, deserialize_with = "amount_deserializer")]
+    pub given: Option<Decimal>,
+    /// The optional `currency` column, the default currency when it's empty or missing.
+    #[serde(default)]
+    pub currency: Currency,
+    /// The optional `timestamp` column as unix seconds (see `timestamp_deserializer`). Only kept to
+    /// tell how old a stored tx is in days (see `EngineConfig::max_dispute_days`).
+    #[serde(default, deserialize_with = "timestamp_deserializer")]
+    pub timestamp: Option<i64>,
+    // not part of the csv, only tracked for stored transactions
+    #[serde(skip)]
+    pub status: DisputeStatus,
+    /// How many records the engine had seen before this one, set when it's applied. Only kept to
+    /// tell how old a stored tx is (see `EngineConfig::max_dispute_window`).
+    #[serde(skip)]
+    pub seq: u64,
+    /// Only admin records carry one, older files don't have the column at all.
+    #[serde(default, deserialize_with = "signature_deserializer")]
+    pub signature: Option<Signature>,
+    /// What a clamped dispute of this stored tx holds, when it's less than the amount (see
+    /// `NegativeAvailable::Clamp`). `None` is the whole amount.
+    #[serde(skip)]
+    pub held: Option<Amount>,
+}
+
+pub type TransactionMap = HashMap<u32, Transaction>;
+
+/// The largest amount a record can move, a hundred trillion. A balance can still be taken past
+/// what an `Amount` holds (about 922 trillion), that record is rejected.
+pub const MAX_AMOUNT: Amount = Amount::from_units(1_000_000_000_000_000_000);
+
+impl Transaction {
+    /// The amount, zero if there is none. Records that passed `check` and stored transactions
+    /// always have one when it matters.
+    pub(crate) fn amount(&self) -> Amount {
+        self.amount.unwrap_or(Amount::ZERO)
+    }
+
+    /// How much a dispute of this tx holds, and its resolve or chargeback releases.
+    pub(crate) fn disputed_amount(&self) -> Amount {
+        self.held.unwrap_or_else(|| self.amount())
+    }
+
+    /// Checks that only need the record itself, before any state is looked at.
+    pub(crate) fn check(&self) -> Result<(), Rejection> {
+        match (self.r_type, self.amount) {
+            (TransactionType::Unknown, _) => Err(Rejection::UnknownType),
+            (TransactionType::Deposit | TransactionType::Withdrawal, amount) => match amount {
+                None => Err(Rejection::MissingAmount),
+                // a deposit of -500 would otherwise drain the account
+                Some(amount) if amount <= Amount::ZERO => Err(Rejection::InvalidAmount),
+                Some(amount) if amount > MAX_AMOUNT => Err(Rejection::AmountTooLarge),
+                Some(_) => Ok(()),
+            },
+            (TransactionType::Adjustment, None) => Err(Rejection::MissingAmount),
+            (TransactionType::Adjustment, Some(amount)) if amount.abs() > MAX_AMOUNT => {
+                Err(Rejection::AmountTooLarge)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Stores a deposit/withdrawal so later disputes can find it. Rejected ones are kept too, so the
+    /// tx id stays used up, but they can't be disputed since no money ever moved.
+    pub(crate) fn save(&self, store: &mut dyn LedgerStore, applied: bool) -> u32 {
+        let mut stored = Transaction {
+            given: None,
+            ..*self
+        };
+        if !applied {
+            stored.status = DisputeStatus::NotApplied;
+        }
+        store.put_transaction(stored);
+        self.tx
+    }
+
+    pub(crate) fn open_dispute(&mut self) -> Result<(), Rejection> {
+        match self.status {
+            DisputeStatus::Normal => {
+                self.status = DisputeStatus::Disputed;
+                Ok(())
+            }
+            DisputeStatus::NotApplied => Err(Rejection::NotApplied),
+            _ => Err(Rejection::AlreadyDisputed),
+        }
+    }
+
+    /// Closes an open dispute as either Resolved or ChargedBack.
+    pub(crate) fn close_dispute(&mut self, outcome: DisputeStatus) -> Result<(), Rejection> {
+        match self.status {
+            DisputeStatus::Disputed => {
+                self.status = outcome;
+                Ok(())
+            }
+            _ => Err(Rejection::NotDisputed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Engine;
+
+    // read and then prepared, the way the engine gets it
+    fn record(line: &str) -> Transaction {
+        let input = format!("type, client, tx, amount\n{}\n", line);
+        let record = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .flexible(true)
+            .from_reader(input.as_bytes())
+            .deserialize()
+            .next()
+            .unwrap()
+            .unwrap();
+        Engine::new().prepare(record)
+    }
+
+    #[test]
+    fn amount_is_required_only_where_money_moves() {
+        // an empty amount and a zero one are different mistakes
+        assert_eq!(record("deposit, 1, 1,").amount, None);
+        assert_eq!(
+            record("deposit, 1, 1,").check(),
+            Err(Rejection::MissingAmount)
+        );
+        assert_eq!(record("deposit, 1, 1, 0").amount, Some(Amount::ZERO));
+        assert_eq!(
+            record("deposit, 1, 1, 0").check(),
+            Err(Rejection::InvalidAmount)
+        );
+        assert_eq!(
+            record("withdrawal, 1, 1").check(),
+            Err(Rejection::MissingAmount)
+        );
+        assert_eq!(
+            record("adjustment, 1, 1,").check(),
+            Err(Rejection::MissingAmount)
+        );
+        assert_eq!(record("deposit, 1, 1, 1.5").check(), Ok(()));
+        assert_eq!(
+            record("deposit, 1, 1, 100000000000000.0001").check(),
+            Err(Rejection::AmountTooLarge)
+        );
+        assert_eq!(
+            record("adjustment, 1, 1, -1000000000000000000").check(),
+            Err(Rejection::AmountTooLarge)
+        );
+
+        // the dispute family goes without, whether the column is empty or missing
+        for line in ["dispute, 1, 1,", "resolve, 1, 1", "chargeback, 1, 1,"] {
+            assert_eq!(record(line).amount, None);
+            assert_eq!(record(line).check(), Ok(()));
+            assert_eq!(record(line).amount(), Amount::ZERO);
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use crate::engine::Engine;
+use crate::error::Error;
+use crate::reader::RecordReader;
+use crate::transaction::TransactionType;
+use std::io::Read;
+
+impl Engine {
+    /// The first pass of a two-pass run: reads `reader` for the tx ids disputes, resolves and
+    /// chargebacks refer to. From then on only those deposits, withdrawals and adjustments are
+    /// stored, the rest are remembered by id alone (to still catch duplicates), so the second pass
+    /// over the same input needs a fraction of the memory when disputes are rare. Results are the
+    /// same as a one-pass run's.
+    ///
+    /// Call it for every input before applying any: a dispute in a later file can refer to a tx in
+    /// an earlier one. Rows that don't parse are left for the second pass to reject.
+    pub fn scan_disputes(&mut self, reader: impl Read) -> Result<(), Error> {
+        let mut custom_reader = RecordReader::new(reader, self.config())?;
+        let disputed = self.dispute_index_mut();
+        while let Some(row) = custom_reader.next_row()? {
+            let Ok(record) = row.parsed else {
+                continue;
+            };
+            if matches!(
+                record.r_type,
+                TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback
+            ) {
+                disputed.insert(record.tx);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `scan_disputes` has run, or a checkpoint of a two-pass run was loaded.
+    pub fn scanned_disputes(&self) -> bool {
+        self.dispute_index().0.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{generate_transactions, process_reader, Engine};
+
+    #[test]
+    fn two_passes_end_up_like_one() {
+        let mut input = Vec::new();
+        generate_transactions(5_000, &mut input).unwrap();
+        // a replayed deposit that was never disputed is still a duplicate
+        input.extend_from_slice(b"deposit, 1, 1, 1.0\ndispute, 1, 99999,\n");
+        let one_pass = process_reader(input.as_slice()).unwrap();
+
+        let mut two_pass = Engine::new();
+        two_pass.scan_disputes(input.as_slice()).unwrap();
+        two_pass.process_reader(input.as_slice()).unwrap();
+        let (mut ours, mut theirs) = (Vec::new(), Vec::new());
+        two_pass.write_csv(&mut ours).unwrap();
+        one_pass.write_csv(&mut theirs).unwrap();
+        assert_eq!(ours, theirs);
+        assert_eq!(two_pass.stats().by_reason, one_pass.stats().by_reason);
+        assert!(two_pass.transactions().count() * 10 < one_pass.transactions().count());
+
+        // resumed from a checkpoint, with the index that was saved in it
+        let path = std::env::temp_dir().join(format!("two-pass-test-{}", std::process::id()));
+        let mut interrupted = Engine::new();
+        interrupted.set_checkpoints(&path, 2_000);
+        interrupted.scan_disputes(input.as_slice()).unwrap();
+        interrupted
+            .process_input(0, input.as_slice(), None)
+            .unwrap();
+        let mut resumed = Engine::new();
+        let progress = resumed.load_checkpoint(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(resumed.scanned_disputes());
+        resumed
+            .process_input(0, input.as_slice(), progress.as_ref())
+            .unwrap();
+        let mut resumed_csv = Vec::new();
+        resumed.write_csv(&mut resumed_csv).unwrap();
+        assert_eq!(resumed_csv, theirs);
+        assert_eq!(resumed.skipped(), one_pass.skipped());
+
+        let mut sharded = Engine::new();
+        sharded.scan_disputes(input.as_slice()).unwrap();
+        sharded.process_reader_sharded(input.as_slice(), 3).unwrap();
+        assert_eq!(sharded.stats().by_reason, one_pass.stats().by_reason);
+    }
+}
@@ -0,0 +1,246 @@
+use chrono::{DateTime, NaiveDate};
+use rust_decimal::prelude::*;
+use serde::{de, Deserializer, Serializer};
+use std::{fmt, marker::PhantomData};
+
+// wasm32-unknown-unknown has no clock of its own, `std::time::Instant::now` panics there
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub(crate) use std::time::Instant;
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub(crate) use web_time::Instant;
+
+// I should assume up to 4 precision. If given more than 4 precision, drop the extra.
+pub const PRECISION: u32 = 4;
+
+/// Chops a decimal down to four places without rounding up.
+pub fn to_four_precision(value: Decimal) -> Decimal {
+    round_to_precision(value, RoundingStrategy::ToZero)
+}
+
+/// Brings a decimal down to four places with the given strategy.
+pub fn round_to_precision(value: Decimal, strategy: RoundingStrategy) -> Decimal {
+    value.round_dp_with_strategy(PRECISION, strategy)
+}
+
+/// Hands an optional text field to `parse`, trimmed, as the `&str` the deserializer already has:
+/// csv rows are read into a reused record, so this doesn't allocate per row the way going through
+/// a `String` does. A missing or empty field is `None`.
+pub(crate) fn deserialize_text<'de, D, T>(
+    deserializer: D,
+    parse: impl FnOnce(&str) -> Result<T, String>,
+) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_option(Text(parse, PhantomData))
+}
+
+struct Text<F, T>(F, PhantomData<T>);
+
+impl<'de, F, T> de::Visitor<'de> for Text<F, T>
+where
+    F: FnOnce(&str) -> Result<T, String>,
+{
+    type Value = Option<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a string")
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_str(self)
+    }
+
+    // borrowed and owned strings both end up here
+    fn visit_str<E: de::Error>(self, given: &str) -> Result<Self::Value, E> {
+        match given.trim() {
+            "" => Ok(None),
+            raw => (self.0)(raw).map(Some).map_err(E::custom),
+        }
+    }
+}
+
+/// Parses the raw amount string exactly, an empty one is `None`. Bringing it down to four places is
+/// up to the engine, since how to round is a policy (see `EngineConfig::rounding`).
+pub fn amount_deserializer<'de, D>(deserializer: D) -> Result<Option<Decimal>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_text(deserializer, parse_amount)
+}
+
+// parse the raw string straight into a Decimal so nothing goes through f64
+pub(crate) fn parse_amount(raw: &str) -> Result<Decimal, String> {
+    Decimal::from_str(raw).map_err(|err| format!("invalid amount {:?}: {}", raw, err))
+}
+
+// what `unformat_amount` takes off either end
+const CURRENCY_SYMBOLS: [char; 5] = ['$', '€', '£', '¥', '₹'];
+
+/// How the input writes amounts when it isn't plain decimal text, from
+/// `EngineConfig::formatted_amounts` and `EngineConfig::decimal_comma`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct AmountNotation {
+    pub formatted: bool,
+    pub decimal_comma: bool,
+}
+
+impl AmountNotation {
+    pub fn is_plain(self) -> bool {
+        self == AmountNotation::default()
+    }
+
+    /// `raw` as plain decimal text, `None` if there's nothing to change or it isn't written this
+    /// way (and then fails to parse as it is).
+    pub fn plain(self, raw: &str) -> Option<String> {
+        match self {
+            AmountNotation {
+                formatted: true,
+                decimal_comma,
+            } => unformat_amount(raw, decimal_comma),
+            // `12,3456`, a point as well is more than a decimal comma
+            AmountNotation {
+                formatted: false,
+                decimal_comma: true,
+            } => {
+                (raw.matches(',').count() == 1 && !raw.contains('.')).then(|| raw.replace(',', "."))
+            }
+            AmountNotation { .. } => None,
+        }
+    }
+}
+
+// an amount written for people, `$1,234.50` or `-€12.00` (`1.234,50` with a decimal comma), as
+// plain decimal text. The separators have to be between groups of three digits, so `1,5` without a
+// decimal comma is left alone
+fn unformat_amount(raw: &str, decimal_comma: bool) -> Option<String> {
+    let (separator, point) = match decimal_comma {
+        true => ('.', ','),
+        false => (',', '.'),
+    };
+    let raw = raw.trim();
+    let (mut sign, rest) = match raw.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", raw.strip_prefix('+').unwrap_or(raw)),
+    };
+    let mut rest = rest.trim_start_matches(CURRENCY_SYMBOLS).trim_start();
+    // the sign can come after the symbol too, `$-12.00`
+    if let ("", Some(unsigned)) = (sign, rest.strip_prefix('-')) {
+        (sign, rest) = ("-", unsigned);
+    }
+    let rest = rest.trim_end_matches(CURRENCY_SYMBOLS).trim_end();
+    let (whole, fraction) = match rest.split_once(point) {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (rest, None),
+    };
+    let digits = |group: &str| !group.is_empty() && group.bytes().all(|b| b.is_ascii_digit());
+    let mut groups = whole.split(separator);
+    let first = groups.next().filter(|first| digits(first))?;
+    let mut grouped = false;
+    for group in groups {
+        if first.len() > 3 || group.len() != 3 || !digits(group) {
+            return None;
+        }
+        grouped = true;
+    }
+    let mut plain = format!("{}{}", sign, whole.replace(separator, ""));
+    if let Some(fraction) = fraction {
+        plain.push('.');
+        plain.push_str(fraction);
+    }
+    (grouped || plain != raw).then_some(plain)
+}
+
+/// Reads the optional `timestamp` column as unix seconds. It takes RFC 3339
+/// (`2024-05-01T12:30:00Z`), a plain date (`2024-05-01`, midnight UTC) or the unix seconds
+/// themselves. An empty one is `None`.
+pub fn timestamp_deserializer<'de, D>(deserializer: D) -> Result<Option<i64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_text(deserializer, parse_timestamp)
+}
+
+pub(crate) fn parse_timestamp(raw: &str) -> Result<i64, String> {
+    if let Ok(seconds) = raw.parse::<i64>() {
+        return Ok(seconds);
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(raw) {
+        return Ok(time.timestamp());
+    }
+    NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|midnight| midnight.and_utc().timestamp())
+        .ok_or_else(|| format!("invalid timestamp {:?}", raw))
+}
+
+pub fn four_precision_serializer<S>(data: &Decimal, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_str(&four_places(*data))
+}
+
+// always exactly four places, e.g. 1.5 -> 1.5000
+pub(crate) fn four_places(value: Decimal) -> Decimal {
+    let mut chopped = to_four_precision(value);
+    chopped.rescale(PRECISION);
+    chopped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn extra_precision_is_dropped() {
+        assert_eq!(to_four_precision(dec!(1.123456)), dec!(1.1234));
+        assert_eq!(to_four_precision(dec!(3.00000001)), dec!(3.0000));
+        assert_eq!(to_four_precision(dec!(-2.98769)), dec!(-2.9876));
+    }
+
+    #[test]
+    fn rounding_strategy_is_respected() {
+        let half_even = RoundingStrategy::MidpointNearestEven;
+        assert_eq!(round_to_precision(dec!(1.00005), half_even), dec!(1.0000));
+        assert_eq!(round_to_precision(dec!(1.00015), half_even), dec!(1.0002));
+        let half_up = RoundingStrategy::MidpointAwayFromZero;
+        assert_eq!(round_to_precision(dec!(1.00005), half_up), dec!(1.0001));
+    }
+
+    #[test]
+    fn fields_parse_borrowed_or_owned() {
+        #[derive(serde::Deserialize)]
+        struct Row {
+            #[serde(default, deserialize_with = "amount_deserializer")]
+            amount: Option<Decimal>,
+        }
+        let headers = csv::StringRecord::from(vec!["amount"]);
+        let amount = |field: &str| {
+            let row: Row = csv::StringRecord::from(vec![field])
+                .deserialize(Some(&headers))
+                .unwrap();
+            row.amount
+        };
+        assert_eq!(amount(" 1.5 "), Some(dec!(1.5)));
+        assert_eq!(amount(""), None);
+        assert_eq!(amount("  "), None);
+
+        // an escaped json string can't be borrowed, so it comes in owned
+        let row: Row = serde_json::from_str(r#"{"amount": "\u0032.25"}"#).unwrap();
+        assert_eq!(row.amount, Some(dec!(2.25)));
+        let row: Row = serde_json::from_str(r#"{"amount": null}"#).unwrap();
+        assert_eq!(row.amount, None);
+        assert!(serde_json::from_str::<Row>(r#"{"amount": "x"}"#).is_err());
+    }
+}
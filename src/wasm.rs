@@ -0,0 +1,37 @@
+use crate::engine::process_reader;
+use crate::error::Error;
+use wasm_bindgen::prelude::*;
+
+/// Resolves a csv of transactions into the csv of accounts, exactly like the batch tool with its
+/// defaults does, for JavaScript. A bad header or a failed run throws with the message the tool
+/// would print.
+#[wasm_bindgen]
+pub fn resolve(csv: &str) -> Result<String, JsError> {
+    resolve_csv(csv).map_err(|err| JsError::new(&err.to_string()))
+}
+
+// JsError only exists in a JavaScript runtime, so the tests go through here
+fn resolve_csv(csv: &str) -> Result<String, Error> {
+    let engine = process_reader(csv.as_bytes())?;
+    let mut out = Vec::new();
+    engine.write_csv(&mut out)?;
+    String::from_utf8(out).map_err(|err| Error::Internal(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_runs_the_batch_logic() {
+        let input = "type, client, tx, amount\n\
+                     deposit, 1, 1, 2.0\n\
+                     withdrawal, 1, 2, 0.5\n\
+                     dispute, 1, 1,\n";
+        assert_eq!(
+            resolve_csv(input).unwrap(),
+            "client,available,held,total,locked\n1,-0.5000,2.0000,1.5000,false\n"
+        );
+        assert!(resolve_csv("kind, customer\n").is_err());
+    }
+}
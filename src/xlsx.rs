@@ -0,0 +1,78 @@
+use calamine::{Data, Reader, Xlsx};
+use std::io::{self, Cursor, Read};
+
+/// The first sheet of an `.xlsx` workbook as csv, to be read like any other input: a header row
+/// with the usual columns, then a record per row. Numbers come out the shortest way that reads
+/// back the same (`1.1`, `42`), date cells as RFC 3339 in UTC and rows left empty are dropped.
+/// A workbook is a zip that can't be read front to back, so it's loaded whole.
+pub(crate) fn sheet_to_csv(mut reader: impl Read) -> io::Result<Vec<u8>> {
+    let mut workbook = Vec::new();
+    reader.read_to_end(&mut workbook)?;
+    let mut workbook = Xlsx::new(Cursor::new(workbook)).map_err(invalid)?;
+    let sheet = workbook
+        .worksheet_range_at(0)
+        .ok_or_else(|| invalid("the workbook has no sheets"))?
+        .map_err(invalid)?;
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in sheet.rows() {
+        if row.iter().all(|cell| *cell == Data::Empty) {
+            continue;
+        }
+        writer.write_record(row.iter().map(cell))?;
+    }
+    writer
+        .into_inner()
+        .map_err(|err| io::Error::new(err.error().kind(), err.to_string()))
+}
+
+fn cell(cell: &Data) -> String {
+    match cell {
+        Data::DateTime(when) => match when.as_datetime() {
+            Some(when) => when.format("%Y-%m-%dT%H:%M:%S%.fZ").to_string(),
+            None => when.to_string(),
+        },
+        // error cells like `#VALUE!` stay as they are, and make the row malformed
+        other => other.to_string(),
+    }
+}
+
+fn invalid(err: impl ToString) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("not a readable .xlsx: {}", err.to_string()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{open_input, process_reader};
+
+    #[test]
+    fn spreadsheets_read_like_the_same_csv() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/data/transactions.test.xlsx");
+        let mut csv = String::new();
+        std::io::Read::read_to_string(&mut open_input(path).unwrap(), &mut csv).unwrap();
+        assert_eq!(
+            csv,
+            "type,client,tx,amount,timestamp\n\
+             deposit,1,1,1.5,2024-05-01T00:00:00Z\n\
+             deposit,2,2,2,2024-05-01T12:00:00Z\n\
+             deposit,1,3,2.25,\n\
+             withdrawal,1,4,1.1,\n\
+             dispute,1,1,,\n\
+             resolve,1,1,,\n\
+             withdrawal,2,5,3,\n"
+        );
+
+        let engine = process_reader(open_input(path).unwrap()).unwrap();
+        let mut report = Vec::new();
+        engine.write_csv(&mut report).unwrap();
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            "client,available,held,total,locked\n\
+             1,2.6500,0.0000,2.6500,false\n\
+             2,2.0000,0.0000,2.0000,false\n"
+        );
+        assert_eq!(engine.skipped(), 1);
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    fs,
+    process::{self, Command},
+};
+
+const BIN: &str = env!("CARGO_BIN_EXE_csv_tx_resolver");
+
+#[test]
+fn bench_generate_is_generate_with_its_rows() {
+    let hidden = Command::new(BIN)
+        .args(["--bench-generate", "10"])
+        .output()
+        .unwrap();
+    let generate = Command::new(BIN)
+        .args(["generate", "--rows", "10"])
+        .output()
+        .unwrap();
+    assert!(hidden.status.success(), "{:?}", hidden);
+    assert!(generate.status.success(), "{:?}", generate);
+    assert_eq!(hidden.stdout, generate.stdout);
+    // the header and ten records
+    assert_eq!(
+        String::from_utf8(hidden.stdout).unwrap().lines().count(),
+        11
+    );
+}
+
+fn temp_path(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("cli-test-{}-{}", process::id(), name));
+    path.to_str().unwrap().to_string()
+}
+
+fn exit_code(args: &[&str]) -> i32 {
+    let output = Command::new(BIN).args(args).output().unwrap();
+    output.status.code().unwrap()
+}
+
+#[test]
+fn exit_codes_tell_how_the_run_ended() {
+    let clean = temp_path("clean.csv");
+    let bad_row = temp_path("bad-row.csv");
+    let negative = temp_path("negative.csv");
+    let strict_parsing = temp_path("strict.toml");
+    let bad_config = temp_path("bad.toml");
+    fs::write(&clean, "type,client,tx,amount\ndeposit,1,1,2.0\n").unwrap();
+    fs::write(&bad_row, "type,client,tx,amount\ndeposit,1,1,lots\n").unwrap();
+    fs::write(&negative, "type,client,tx,amount\ndeposit,1,1,-5.0\n").unwrap();
+    fs::write(&strict_parsing, "parsing = \"strict\"\n").unwrap();
+    fs::write(&bad_config, "no_such_key = 1\n").unwrap();
+    let missing_dir = format!("{}/accounts.csv", temp_path("missing"));
+
+    assert_eq!(exit_code(&[&clean]), 0);
+    // rejected records, the run still finished
+    assert_eq!(exit_code(&["data/transactions.csv"]), 1);
+    assert_eq!(exit_code(&["--no-such-flag"]), 2);
+    assert_eq!(exit_code(&[&bad_row, "--config", &strict_parsing]), 3);
+    assert_eq!(exit_code(&[&negative, "--strict"]), 4);
+    assert_eq!(exit_code(&[&clean, "-o", &missing_dir]), 5);
+    assert_eq!(exit_code(&[&clean, "--config", &bad_config]), 6);
+
+    for path in [clean, bad_row, negative, strict_parsing, bad_config] {
+        fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn validate_lists_every_problem_and_writes_no_report() {
+    let clean = temp_path("validate-clean.csv");
+    let malformed = temp_path("validate-malformed.csv");
+    let errors = temp_path("validate-errors.csv");
+    fs::write(&clean, "type,client,tx,amount\ndeposit,1,1,2.0\n").unwrap();
+    fs::write(
+        &malformed,
+        "type,client,tx,amount\n\
+         deposit,1,1,2.0\n\
+         deposit,1,2,lots\n\
+         withdrawal,1,3\n",
+    )
+    .unwrap();
+    let validate = |args: &[&str]| {
+        Command::new(BIN)
+            .arg("validate")
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let output = validate(&[&clean]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "1 records ok\n");
+
+    // the bundled example has a withdrawal past available and a dispute of another client's tx
+    let output = validate(&["data/transactions.csv"]);
+    assert_eq!(output.status.code(), Some(1));
+    let listed = String::from_utf8(output.stdout).unwrap();
+    assert!(listed.starts_with("row,type,client,tx,amount,reason\n6,withdrawal,2,5,"));
+
+    // every bad row by its row number, and no accounts
+    let output = validate(&[&malformed]);
+    assert_eq!(output.status.code(), Some(1));
+    let listed = String::from_utf8(output.stdout).unwrap();
+    let rows = listed.lines().skip(1).collect::<Vec<_>>();
+    assert_eq!(rows.len(), 2);
+    assert!(rows[0].starts_with("3,deposit,1,2,lots,"));
+    assert_eq!(rows[1], "4,withdrawal,1,3,,amount is missing");
+    assert!(!listed.contains("available"));
+    let summary = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(summary, "2 of 3 records rejected\n");
+
+    // with --errors the list goes there and stdout stays empty
+    let output = validate(&[&malformed, "--errors", &errors]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+    assert_eq!(fs::read_to_string(&errors).unwrap(), listed);
+
+    for path in [clean, malformed, errors] {
+        fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn rejections_are_logged_with_a_v() {
+    let run = |args: &[&str]| {
+        let output = Command::new(BIN).args(args).output().unwrap();
+        String::from_utf8(output.stderr).unwrap()
+    };
+    assert_eq!(run(&["data/transactions.csv"]), "");
+    let logged = run(&["-v", "data/transactions.csv"]);
+    assert_eq!(logged.matches("rejected").count(), 2);
+    assert!(logged.contains("insufficient available funds"));
+    assert!(!logged.contains("applied"));
+    // every record with -vv
+    assert!(run(&["-vv", "data/transactions.csv"]).contains("applied"));
+}
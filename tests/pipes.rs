@@ -0,0 +1,111 @@
+#![cfg(unix)]
+
+use std::{
+    fs,
+    io::{Read, Write},
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    thread,
+};
+
+const BIN: &str = env!("CARGO_BIN_EXE_csv_tx_resolver");
+
+const INPUT: &str = "type,client,tx,amount\n\
+                     deposit,1,1,2.5\n\
+                     deposit,2,2,1.0\n\
+                     withdrawal,1,3,0.5\n";
+
+const REPORT: &str = "client,available,held,total,locked\n\
+                      1,2.0000,0.0000,2.0000,false\n\
+                      2,1.0000,0.0000,1.0000,false\n";
+
+fn mkfifo(path: &Path) {
+    let _ = fs::remove_file(path);
+    let status = Command::new("mkfifo").arg(path).status().unwrap();
+    assert!(status.success());
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("pipes-test-{}-{}", process::id(), name))
+}
+
+#[test]
+fn named_pipes_in_and_out() {
+    let input = temp_path("in.fifo");
+    let output = temp_path("out.fifo");
+    mkfifo(&input);
+    mkfifo(&output);
+
+    // opening a fifo blocks until the other end is open too, so both ends are threads
+    let writer = {
+        let input = input.clone();
+        thread::spawn(move || fs::write(input, INPUT).unwrap())
+    };
+    let reader = {
+        let output = output.clone();
+        thread::spawn(move || fs::read_to_string(output).unwrap())
+    };
+    let status = Command::new(BIN)
+        .arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .status()
+        .unwrap();
+    writer.join().unwrap();
+    let report = reader.join().unwrap();
+
+    assert!(status.success());
+    assert_eq!(report, REPORT);
+    // written through rather than replaced by a renamed file
+    assert!(fs::metadata(&output).unwrap().file_type().is_fifo());
+    fs::remove_file(&input).unwrap();
+    fs::remove_file(&output).unwrap();
+}
+
+#[test]
+fn stdin_to_stdout_and_a_reader_going_away() {
+    let mut child = Command::new(BIN)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(INPUT.as_bytes())
+        .unwrap();
+    let mut report = String::new();
+    child
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_string(&mut report)
+        .unwrap();
+    assert!(child.wait().unwrap().success());
+    assert_eq!(report, REPORT);
+
+    // like `| head -c 1`, a report far bigger than the pipe ends without an error message
+    let input = (0..20_000).fold(String::from(INPUT), |mut input, tx| {
+        input.push_str(&format!("deposit,{},{},1.0\n", tx % 60_000, tx + 10));
+        input
+    });
+    let mut child = Command::new(BIN)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = thread::spawn(move || {
+        let _ = stdin.write_all(input.as_bytes());
+    });
+    let mut first = [0; 1];
+    child.stdout.take().unwrap().read_exact(&mut first).unwrap();
+    writer.join().unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(first, *b"c");
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+}
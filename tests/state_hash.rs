@@ -0,0 +1,64 @@
+use std::{
+    fs,
+    path::PathBuf,
+    process::{self, Command},
+};
+
+const BIN: &str = env!("CARGO_BIN_EXE_csv_tx_resolver");
+
+const INPUT: &str = "type,client,tx,amount\n\
+                     deposit,1,1,10.0\n\
+                     deposit,2,2,4.0\n";
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("state-hash-test-{}-{}", process::id(), name))
+}
+
+// the report on stdout and the hash written next to it
+fn resolve(name: &str, extra: &[&str]) -> (String, String) {
+    let input = temp_path(&format!("{}.csv", name));
+    let hash = temp_path(&format!("{}.hash", name));
+    fs::write(&input, INPUT).unwrap();
+    let _ = fs::remove_file(&hash);
+    let output = Command::new(BIN)
+        .arg(&input)
+        .arg("--state-hash")
+        .arg(&hash)
+        .args(extra)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let report = String::from_utf8(output.stdout).unwrap();
+    let written = fs::read_to_string(&hash).unwrap_or_default();
+    fs::remove_file(&input).unwrap();
+    let _ = fs::remove_file(&hash);
+    (report, written)
+}
+
+#[test]
+fn hash_is_taken_after_accrual() {
+    let config = temp_path("accrual.toml");
+    fs::write(&config, "[accrual]\nmaintenance_fee = 2.5\n").unwrap();
+
+    let (plain_report, plain_hash) = resolve("plain", &[]);
+    let (accrued_report, accrued_hash) =
+        resolve("accrued", &["--config", config.to_str().unwrap()]);
+    fs::remove_file(&config).unwrap();
+
+    assert!(plain_report.contains("1,10.0000,0.0000,10.0000,false"));
+    assert!(accrued_report.contains("1,7.5000,0.0000,7.5000,false"));
+    assert_eq!(plain_hash.trim().len(), 64);
+    assert_eq!(accrued_hash.trim().len(), 64);
+    assert_ne!(plain_hash, accrued_hash);
+
+    // the same run again gets the same one
+    let (_, again) = resolve("plain-again", &[]);
+    assert_eq!(plain_hash, again);
+}
+
+#[test]
+fn no_hash_for_a_replay_that_stopped_partway() {
+    let (report, hash) = resolve("stopped", &["--until-tx", "1"]);
+    assert!(report.contains("1,10.0000"));
+    assert_eq!(hash, "");
+}